@@ -0,0 +1,136 @@
+//! How long translator takes, and how much memory it needs, to get through
+//! a large hacker file: `cargo bench --bench large_file`. The blocks are in
+//! a language with no executor, so translator only reads and skips them,
+//! and what's measured is the parsing. The same file also goes through the
+//! extraction translator had before it streamed its input, which read the
+//! whole file and split it into lines first. `TRANSLATOR_BENCH_MB` sets the
+//! file's size, 200 MB by default.
+
+use std::env;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// Lines of code in each block, besides its number.
+const BLOCK_LINES: usize = 2000;
+
+/// Set to the file for the bench to run the old extraction on, as a
+/// process of its own, so its memory is measured apart from the rest.
+const OLD: &str = "TRANSLATOR_BENCH_OLD";
+
+fn main() {
+    if let Some(path) = env::var_os(OLD) {
+        let content = fs::read_to_string(path).expect("read the file");
+        println!("{} blocks", extract_blocks(&content).len());
+        return;
+    }
+    let megabytes: usize = env::var("TRANSLATOR_BENCH_MB").ok().and_then(|mb| mb.parse().ok()).unwrap_or(200);
+    let dir = tempfile::tempdir().expect("temp dir");
+    let path = dir.path().join("large.hacker");
+    let mut file = BufWriter::new(File::create(&path).expect("create the file"));
+    let line = format!("    {}\n", "x".repeat(75));
+    let mut written = 0;
+    let mut blocks = 0;
+    while written < megabytes << 20 {
+        blocks += 1;
+        // Numbered, so no block is a duplicate of another.
+        let directive = format!("Block {}.\n\n|> translator:benchmark (\n    // {}\n", blocks, blocks);
+        file.write_all(directive.as_bytes()).unwrap();
+        for _ in 0..BLOCK_LINES {
+            file.write_all(line.as_bytes()).unwrap();
+        }
+        file.write_all(b")\n\n").unwrap();
+        written += directive.len() + BLOCK_LINES * line.len() + 3;
+    }
+    file.flush().unwrap();
+    drop(file);
+    println!("{} MB, {} blocks", written >> 20, blocks);
+
+    let mut streaming = Command::new(env!("CARGO_BIN_EXE_translator"));
+    streaming.args(["--yes", "--no-plugins", "--quiet", "--no-cache"]).arg(&path);
+    report("streaming", measure(&mut streaming));
+    let mut old = Command::new(env::current_exe().expect("the bench's own path"));
+    old.env(OLD, &path);
+    report("read_to_string + lines", measure(&mut old));
+}
+
+fn report(case: &str, (elapsed, peak): (Duration, Option<u64>)) {
+    match peak {
+        Some(peak) => println!("{:<24} {:>8.2?}  peak RSS {} MB", case, elapsed, peak >> 20),
+        None => println!("{:<24} {:>8.2?}", case, elapsed),
+    }
+}
+
+/// How long `command` took, and the most memory it had resident at once,
+/// in bytes.
+fn measure(command: &mut Command) -> (Duration, Option<u64>) {
+    let start = Instant::now();
+    let child = command.stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null()).spawn().expect("start the case");
+    let (exited, peak) = wait(child);
+    let elapsed = start.elapsed();
+    assert!(exited, "the case was killed or failed");
+    (elapsed, peak)
+}
+
+/// Waits for `child`, with its own resource usage rather than that of all
+/// the children so far.
+#[cfg(unix)]
+fn wait(child: std::process::Child) -> (bool, Option<u64>) {
+    let mut status = 0;
+    // wait4 only writes to the struct it is given.
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    let pid = unsafe { libc::wait4(child.id() as libc::pid_t, &mut status, 0, &mut usage) };
+    assert_eq!(pid, child.id() as libc::pid_t, "wait4: {}", std::io::Error::last_os_error());
+    let exited = libc::WIFEXITED(status) && libc::WEXITSTATUS(status) <= 2;
+    // macOS counts bytes, the others kilobytes.
+    let peak = u64::try_from(usage.ru_maxrss).ok().map(|peak| if cfg!(target_os = "macos") { peak } else { peak * 1024 });
+    (exited, peak)
+}
+
+#[cfg(not(unix))]
+fn wait(mut child: std::process::Child) -> (bool, Option<u64>) {
+    let status = child.wait().expect("wait for the case");
+    (status.code().is_some_and(|code| code <= 2), None)
+}
+
+/// The extraction of translator's first version: the whole file in memory,
+/// a slice for each of its lines, and the blocks' code copied out.
+fn extract_blocks(content: &str) -> Vec<(String, String)> {
+    let mut blocks = Vec::new();
+    let lines: Vec<&str> = content.lines().collect();
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i].trim();
+        if line.starts_with("|> translator:") {
+            let parts: Vec<&str> = line.splitn(2, ':').collect();
+            if parts.len() == 2 {
+                let lang = parts[1].trim().split('(').next().unwrap_or("").trim().to_string();
+                if !lang.is_empty() {
+                    let mut code = String::new();
+                    i += 1;
+                    let mut depth = 1;
+                    while i < lines.len() && depth > 0 {
+                        let code_line = lines[i];
+                        for c in code_line.chars() {
+                            if c == '(' {
+                                depth += 1;
+                            } else if c == ')' {
+                                depth -= 1;
+                            }
+                        }
+                        code.push_str(code_line);
+                        code.push('\n');
+                        i += 1;
+                    }
+                    if depth == 0 {
+                        blocks.push((lang, code.trim().to_string()));
+                    }
+                    continue;
+                }
+            }
+        }
+        i += 1;
+    }
+    blocks
+}
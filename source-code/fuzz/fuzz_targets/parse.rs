@@ -0,0 +1,29 @@
+//! Feeds arbitrary bytes to the hacker file parser, which must never panic:
+//! `cargo +nightly fuzz run parse` from `source-code`.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+// translator is only a binary, so the parser and what it uses are compiled
+// in here from its sources.
+#[allow(dead_code)]
+#[path = "../../src/detect.rs"]
+mod detect;
+#[allow(dead_code)]
+#[path = "../../src/encoding.rs"]
+mod encoding;
+#[allow(dead_code)]
+#[path = "../../src/languages.rs"]
+mod languages;
+#[allow(dead_code)]
+#[path = "../../src/parser.rs"]
+mod parser;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(input) = std::str::from_utf8(data) {
+        let (blocks, _) = parser::parse(input);
+        let directives = input.lines().filter(|line| line.trim().starts_with("|> translator:")).count();
+        assert!(blocks.len() <= directives);
+    }
+});
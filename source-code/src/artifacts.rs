@@ -0,0 +1,113 @@
+//! Files blocks leave behind, copied out by `artifacts="chart.svg,out/*.csv"`
+//! before the run directory goes. Patterns are relative to the directory
+//! the block's program ran in; `*` and `?` match within one path
+//! component, and only files are copied.
+
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use crate::parser::Block;
+
+/// The directory under `--artifacts-dir` a block's files go to: its name,
+/// or `block-N`.
+pub fn block_dir(block: &Block) -> String {
+    match block.name() {
+        Some(name) => name.replace(['/', '\\'], "_"),
+        None => format!("block-{}", block.index),
+    }
+}
+
+/// The comma-separated patterns of `artifacts=`, or the first that reaches
+/// outside the block's directory.
+pub fn patterns(value: &str) -> Result<Vec<&str>, String> {
+    let patterns: Vec<&str> = value.split(',').map(str::trim).filter(|p| !p.is_empty()).collect();
+    for pattern in &patterns {
+        if !Path::new(pattern).components().all(|c| matches!(c, Component::Normal(_) | Component::CurDir)) {
+            return Err(format!("artifacts={} isn't a path inside the block's directory", pattern));
+        }
+    }
+    Ok(patterns)
+}
+
+/// Copies the files `patterns` match in `workdir` to the same relative
+/// paths under `dest`. Returns where they went, and the patterns that
+/// matched nothing.
+pub fn collect(patterns: &[&str], workdir: &Path, dest: &Path) -> io::Result<(Vec<PathBuf>, Vec<String>)> {
+    let mut copied = Vec::new();
+    let mut unmatched = Vec::new();
+    for pattern in patterns {
+        let matches = matching(workdir, Path::new(pattern))?;
+        if matches.is_empty() {
+            unmatched.push(pattern.to_string());
+        }
+        for path in matches {
+            let target = dest.join(path.strip_prefix(workdir).unwrap_or(&path));
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::copy(&path, &target)?;
+            copied.push(target);
+        }
+    }
+    Ok((copied, unmatched))
+}
+
+/// The files under `base` that `pattern` matches, in sorted order.
+fn matching(base: &Path, pattern: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut candidates = vec![base.to_path_buf()];
+    for component in pattern.components() {
+        let Component::Normal(part) = component else {
+            continue;
+        };
+        let part = part.to_string_lossy();
+        let mut next = Vec::new();
+        for dir in candidates {
+            if !part.contains(['*', '?']) {
+                let path = dir.join(&*part);
+                if path.exists() {
+                    next.push(path);
+                }
+                continue;
+            }
+            let Ok(entries) = fs::read_dir(&dir) else {
+                continue;
+            };
+            for entry in entries {
+                let entry = entry?;
+                if wildcard(&part, &entry.file_name().to_string_lossy()) {
+                    next.push(entry.path());
+                }
+            }
+        }
+        next.sort();
+        candidates = next;
+    }
+    Ok(candidates.into_iter().filter(|path| path.is_file()).collect())
+}
+
+/// Whether `name` matches `pattern`, where `*` stands for any run of
+/// characters and `?` for one.
+fn wildcard(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // Where the last `*` was, and how much of the name it has taken.
+    let mut star = None;
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, n));
+            p += 1;
+        } else if let Some((star_p, star_n)) = star {
+            p = star_p + 1;
+            n = star_n + 1;
+            star = Some((star_p, star_n + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
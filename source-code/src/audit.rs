@@ -0,0 +1,115 @@
+//! `--audit-log`: a line of JSON for every process translator starts for a
+//! block, appended as soon as the process is done. It says what ran, where
+//! and on behalf of which block, and how it ended, but never what the
+//! process printed; reports are for that.
+//!
+//! ```text
+//! {"time":"2024-05-01T09:30:12.042Z","file":"demo.hacker","blocks":[{"index":2,"lang":"rust","name":null,"start_line":8,"end_line":14,"code_sha256":"3f1a…"}],"step":"compile","argv":["rustc","main.rs","-o","out/block-3f1a…"],"cwd":"/tmp/.tmpX/2","exit_code":0,"signal":null,"error":null,"duration_ms":412}
+//! ```
+
+use std::env;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::{Command, ExitStatus};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::parser::Block;
+use crate::report;
+use crate::sandbox::Step;
+use crate::signal;
+
+/// The log, shared by every block of a run.
+pub struct AuditLog {
+    file: Mutex<File>,
+}
+
+#[derive(Serialize)]
+struct Entry<'a> {
+    time: String,
+    file: String,
+    /// The blocks the process worked on: one, except for a batch compile.
+    blocks: Vec<BlockId<'a>>,
+    step: Step,
+    argv: Vec<String>,
+    cwd: Option<String>,
+    exit_code: Option<i32>,
+    signal: Option<i32>,
+    /// Why the process couldn't be started, or waited for.
+    error: Option<&'a str>,
+    duration_ms: u128,
+}
+
+#[derive(Serialize)]
+struct BlockId<'a> {
+    index: usize,
+    lang: &'a str,
+    name: Option<&'a str>,
+    start_line: usize,
+    end_line: usize,
+    /// Of the block's code, as written to its source file.
+    code_sha256: String,
+}
+
+impl AuditLog {
+    /// Opens `path` for appending, creating it if need be.
+    pub fn open(path: &Path) -> io::Result<AuditLog> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(AuditLog { file: Mutex::new(file) })
+    }
+
+    /// Records that `command`, started at `started` for a `step` of `blocks`
+    /// of `file`, ended with `outcome`.
+    pub fn record(
+        &self,
+        file: &Path,
+        blocks: &[&Block],
+        step: Step,
+        command: &Command,
+        started: SystemTime,
+        outcome: Result<ExitStatus, &str>,
+    ) -> io::Result<()> {
+        let duration = started.elapsed().unwrap_or_default();
+        let mut argv = vec![command.get_program().to_string_lossy().into_owned()];
+        argv.extend(command.get_args().map(|arg| arg.to_string_lossy().into_owned()));
+        let cwd = match command.get_current_dir() {
+            Some(dir) => Some(dir.to_path_buf()),
+            None => env::current_dir().ok(),
+        };
+        let entry = Entry {
+            time: report::rfc3339(started),
+            file: file.display().to_string(),
+            blocks: blocks.iter().map(|block| BlockId::of(block)).collect(),
+            step,
+            argv,
+            cwd: cwd.map(|dir| dir.display().to_string()),
+            exit_code: outcome.ok().and_then(|status| status.code()),
+            signal: outcome.ok().as_ref().and_then(signal::of),
+            error: outcome.err(),
+            duration_ms: duration.as_millis(),
+        };
+        let mut line = serde_json::to_string(&entry).map_err(io::Error::other)?;
+        line.push('\n');
+        // One write per entry, so that runs sharing the log don't interleave.
+        let mut file = self.file.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        file.write_all(line.as_bytes())?;
+        file.flush()
+    }
+}
+
+impl<'a> BlockId<'a> {
+    fn of(block: &'a Block) -> Self {
+        BlockId {
+            index: block.index,
+            lang: &block.lang,
+            name: block.name(),
+            start_line: block.span.start_line,
+            end_line: block.span.end_line,
+            code_sha256: Sha256::digest(block.code.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect(),
+        }
+    }
+}
@@ -0,0 +1,520 @@
+//! Where a block's compile and run steps execute. Natively they run as
+//! built; in a container each step becomes a `docker run` (or `podman run`,
+//! whose command line is the same) of the language's image. The block's
+//! directory and the cache are mounted at their own paths, so the commands'
+//! arguments stay valid inside the container.
+//!
+//! Over SSH the block's directory is copied to a temporary directory on the
+//! remote machine before each step, and the step runs there with its paths
+//! rewritten. All blocks share one connection through OpenSSH's
+//! `ControlMaster`; everything else comes from the user's ssh config.
+//!
+//! In WSL the steps run through `wsl.exe` in a login shell of the default
+//! distribution, which sees the Windows drives, so the block's directory is
+//! used where it is, under the path `wslpath` gives it. On Windows, blocks
+//! whose toolchain isn't installed natively go there too when WSL is.
+
+use std::cell::OnceCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+
+use serde::Deserialize;
+
+use crate::flags;
+use crate::parser::Block;
+use crate::tools;
+
+/// The engines `--backend` takes.
+pub const ENGINES: &[&str] = &["docker", "podman"];
+
+/// The image for each built-in language when `translator.toml` has none.
+const DEFAULT_IMAGES: &[(&str, &str)] = &[
+    ("rust", "rust:1"),
+    ("java", "eclipse-temurin:21"),
+    ("python", "python:3"),
+    ("go", "golang:1"),
+];
+
+/// The `[container]` table of `translator.toml`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    /// Image per language, e.g. `rust = "rust:1.79"`.
+    pub images: BTreeMap<String, String>,
+    /// `--memory` for each container.
+    pub memory: String,
+    /// `--cpus` for each container.
+    pub cpus: String,
+    /// `--pids-limit` for each container.
+    pub pids: u32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            images: BTreeMap::new(),
+            memory: "2g".to_string(),
+            cpus: "2".to_string(),
+            pids: 512,
+        }
+    }
+}
+
+/// What `--backend` asked for.
+#[derive(Clone, Debug, Default)]
+pub enum Kind {
+    #[default]
+    Native,
+    Container {
+        engine: String,
+    },
+    /// `ssh://[user@]host[:port]`.
+    Ssh {
+        destination: String,
+        port: Option<String>,
+    },
+    Wsl,
+}
+
+impl Kind {
+    /// Parses a `--backend` value.
+    pub fn parse(value: &str) -> Option<Kind> {
+        if value == "native" {
+            return Some(Kind::Native);
+        }
+        if value == "wsl" {
+            return Some(Kind::Wsl);
+        }
+        if ENGINES.contains(&value) {
+            return Some(Kind::Container {
+                engine: value.to_string(),
+            });
+        }
+        let address = value.strip_prefix("ssh://")?.trim_end_matches('/');
+        let (destination, port) = match address.rsplit_once(':') {
+            Some((destination, port)) if port.bytes().all(|b| b.is_ascii_digit()) => (destination, Some(port)),
+            _ => (address, None),
+        };
+        let host = destination.rsplit('@').next().unwrap_or("");
+        (!host.is_empty() && !host.contains('/')).then(|| Kind::Ssh {
+            destination: destination.to_string(),
+            port: port.map(str::to_string),
+        })
+    }
+}
+
+/// Where one block's steps run.
+pub enum Backend {
+    Native,
+    Container(Container),
+    Ssh(Remote),
+    Wsl(Wsl),
+}
+
+pub struct Container {
+    pub engine: String,
+    pub image: String,
+    settings: Settings,
+    /// Host paths mounted at the same path inside.
+    mounts: Vec<PathBuf>,
+}
+
+impl Backend {
+    /// The backend for `block`: a container when `--backend` names an engine
+    /// or the block asks for an image with `container=`, else native.
+    /// `dir` is the block's directory and `cache` the cache directory, if
+    /// any; both are mounted into containers.
+    pub fn for_block(
+        block: &Block,
+        kind: &Kind,
+        settings: &Settings,
+        dir: &Path,
+        cache: Option<&Path>,
+    ) -> Result<Backend, String> {
+        let engine = match kind {
+            Kind::Container { engine } => Some(engine.as_str()),
+            Kind::Ssh { .. } if block.params.get("container").is_none() => {
+                return Ok(Backend::Ssh(Remote::new(kind, dir)));
+            }
+            Kind::Wsl if block.params.get("container").is_none() => {
+                return Wsl::new(dir).map(Backend::Wsl).map_err(|e| e.to_string());
+            }
+            Kind::Ssh { .. } | Kind::Wsl | Kind::Native => None,
+        };
+        let image = match (block.params.get("container"), engine) {
+            (Some(image), _) => image.to_string(),
+            (None, Some(_)) => image_for(&block.lang, settings).ok_or_else(|| {
+                format!(
+                    "no container image for {} blocks; add `{} = \"IMAGE\"` to [container.images] in translator.toml or use container=IMAGE",
+                    block.lang, block.lang
+                )
+            })?,
+            (None, None) => return Ok(Backend::Native),
+        };
+        let mut mounts = vec![dir.to_path_buf()];
+        mounts.extend(cache.map(Path::to_path_buf));
+        Ok(Backend::Container(Container {
+            engine: engine.unwrap_or("docker").to_string(),
+            image,
+            settings: settings.clone(),
+            mounts,
+        }))
+    }
+
+    /// What identifies the toolchain in cache keys when it isn't the
+    /// native one: the image it comes from.
+    pub fn toolchain(&self) -> Option<String> {
+        match self {
+            Backend::Native | Backend::Ssh(_) | Backend::Wsl(_) => None,
+            Backend::Container(container) => Some(format!("{} image {}", container.engine, container.image)),
+        }
+    }
+
+    /// `command` as it runs on this backend, or `None` to run it as it is.
+    /// Only `network` steps, which fetch dependencies, get a network in a
+    /// container. Fails when a remote machine can't be reached.
+    pub fn wrap(&self, command: &Command, network: bool) -> io::Result<Option<Command>> {
+        match self {
+            Backend::Native => Ok(None),
+            Backend::Container(container) => Ok(Some(container.wrap(command, network))),
+            Backend::Ssh(remote) => remote.wrap(command).map(Some),
+            Backend::Wsl(wsl) => Ok(Some(wsl.wrap(command))),
+        }
+    }
+}
+
+impl Container {
+    fn wrap(&self, command: &Command, network: bool) -> Command {
+        let mut run = Command::new(&self.engine);
+        run.args(["run", "--rm", "-i"]);
+        if !network {
+            run.args(["--network", "none"]);
+        }
+        run.arg(format!("--memory={}", self.settings.memory))
+            .arg(format!("--cpus={}", self.settings.cpus))
+            .arg(format!("--pids-limit={}", self.settings.pids));
+        for mount in &self.mounts {
+            let mut volume = mount.clone().into_os_string();
+            volume.push(":");
+            volume.push(mount);
+            run.arg("-v").arg(volume);
+        }
+        // Files the steps write into the mounts stay the user's.
+        #[cfg(unix)]
+        if let Some(owner) = self.mounts.first().and_then(|dir| owner(dir)) {
+            run.arg("--user").arg(owner);
+        }
+        // Some images set no HOME for an arbitrary user, and go and pip want
+        // one.
+        run.arg("-e").arg("HOME=/tmp");
+        for (key, value) in command.get_envs() {
+            if let Some(value) = value {
+                let mut pair = key.to_os_string();
+                pair.push("=");
+                pair.push(value);
+                run.arg("-e").arg(pair);
+            }
+        }
+        let workdir = command.get_current_dir().unwrap_or(&self.mounts[0]);
+        run.arg("-w").arg(workdir);
+        run.arg(&self.image).arg(command.get_program()).args(command.get_args());
+        run
+    }
+
+    /// Pulls the image unless it is there already. Each image is checked
+    /// once per process.
+    pub fn ensure_image(&self) -> Result<(), ImageError> {
+        static PRESENT: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+        let key = format!("{} {}", self.engine, self.image);
+        if PRESENT.lock().unwrap_or_else(|e| e.into_inner()).contains(&key) {
+            return Ok(());
+        }
+        let quiet = |command: &mut Command| {
+            command
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::piped())
+                .output()
+        };
+        let inspect = quiet(Command::new(&self.engine).args(["image", "inspect"]).arg(&self.image));
+        let present = match inspect {
+            Ok(output) => output.status.success(),
+            Err(e) => return Err(ImageError::Engine(e)),
+        };
+        if !present {
+            let pull = quiet(Command::new(&self.engine).arg("pull").arg(&self.image)).map_err(ImageError::Engine)?;
+            if !pull.status.success() {
+                return Err(ImageError::Pull(String::from_utf8_lossy(&pull.stderr).into_owned()));
+            }
+        }
+        PRESENT.lock().unwrap_or_else(|e| e.into_inner()).insert(key);
+        Ok(())
+    }
+}
+
+/// A remote machine the block runs on, with the temporary directory the
+/// block's directory is copied to, created on first use and removed when
+/// the block is done.
+pub struct Remote {
+    destination: String,
+    port: Option<String>,
+    local: PathBuf,
+    dir: OnceCell<String>,
+}
+
+/// The exit status of `ssh` when the connection, not the command, failed.
+pub const SSH_FAILED: i32 = 255;
+
+impl Remote {
+    fn new(kind: &Kind, local: &Path) -> Remote {
+        let Kind::Ssh { destination, port } = kind else {
+            unreachable!("only built for --backend ssh://");
+        };
+        Remote {
+            destination: destination.clone(),
+            port: port.clone(),
+            local: local.to_path_buf(),
+            dir: OnceCell::new(),
+        }
+    }
+
+    pub fn destination(&self) -> &str {
+        &self.destination
+    }
+
+    /// `ssh` to the machine through the shared connection, ready for the
+    /// remote command line.
+    fn ssh(&self) -> Command {
+        let mut control_path = env::temp_dir().join("translator-ssh-").into_os_string();
+        control_path.push("%C");
+        let mut option = std::ffi::OsString::from("ControlPath=");
+        option.push(control_path);
+        let mut ssh = Command::new("ssh");
+        ssh.args(["-o", "ControlMaster=auto", "-o", "ControlPersist=60", "-o"])
+            .arg(option);
+        if let Some(port) = &self.port {
+            ssh.arg("-p").arg(port);
+        }
+        ssh.arg(&self.destination).arg("--");
+        ssh
+    }
+
+    /// Runs a command line on the machine for translator's own purposes.
+    fn run(&self, script: &str, input: Option<Stdio>) -> io::Result<String> {
+        let mut ssh = self.ssh();
+        ssh.arg(script)
+            .stdin(input.unwrap_or_else(Stdio::null))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        let output = ssh.output().map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => io::Error::new(e.kind(), "`ssh` was not found; the ssh backend needs it"),
+            _ => e,
+        })?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(io::Error::other(format!("ssh {}: {}", self.destination, stderr.trim())));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+
+    fn dir(&self) -> io::Result<&str> {
+        if let Some(dir) = self.dir.get() {
+            return Ok(dir);
+        }
+        let dir = self.run("mktemp -d /tmp/translator-XXXXXXXX", None)?;
+        Ok(self.dir.get_or_init(|| dir))
+    }
+
+    /// Copies the block's directory over what is there already.
+    fn upload(&self, dir: &str) -> io::Result<()> {
+        let mut tar = Command::new("tar")
+            .arg("-C")
+            .arg(&self.local)
+            .args(["-cf", "-", "."])
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let archive = tar.stdout.take().expect("stdout is piped");
+        let copied = self.run(&format!("tar -xf - -C {}", quote(dir)), Some(archive.into()));
+        tar.wait()?;
+        copied.map(drop)
+    }
+
+    fn wrap(&self, command: &Command) -> io::Result<Command> {
+        let dir = self.dir()?;
+        self.upload(dir)?;
+        let local = self.local.to_string_lossy();
+        let remote = |value: &std::ffi::OsStr| value.to_string_lossy().replace(local.as_ref(), dir);
+        let cwd = command.get_current_dir().map_or_else(|| dir.to_string(), |cwd| remote(cwd.as_os_str()));
+        let mut words = vec!["env".to_string()];
+        for (key, value) in command.get_envs() {
+            if let Some(value) = value {
+                words.push(format!("{}={}", key.to_string_lossy(), remote(value)));
+            }
+        }
+        words.push(remote(command.get_program()));
+        words.extend(command.get_args().map(remote));
+        // Without `exec` the remote shell turns death by a signal into an
+        // exit status, which ssh passes on where it would otherwise report
+        // its own failure.
+        let mut ssh = self.ssh();
+        ssh.arg(format!("cd {} && {}", quote(&cwd), flags::display(&words)));
+        Ok(ssh)
+    }
+}
+
+impl Drop for Remote {
+    fn drop(&mut self) {
+        if let Some(dir) = self.dir.get() {
+            let _ = self.run(&format!("rm -rf {}", quote(dir)), None);
+        }
+    }
+}
+
+/// How WSL is started.
+pub const WSL: &str = "wsl.exe";
+
+/// The exit status of `wsl.exe` when WSL itself, not the command, failed.
+pub const WSL_FAILED: i32 = -1;
+
+/// A block's directory in the default WSL distribution.
+pub struct Wsl {
+    local: PathBuf,
+    /// `local` as WSL sees it.
+    linux: String,
+}
+
+impl Wsl {
+    /// WSL for the block in `dir`. Fails when WSL can't tell where that is.
+    pub fn new(dir: &Path) -> io::Result<Wsl> {
+        Ok(Wsl {
+            local: dir.to_path_buf(),
+            linux: linux_path(dir)?,
+        })
+    }
+
+    /// Whether `wsl.exe` is there to start.
+    pub fn available() -> bool {
+        tools::locate(Path::new(WSL), None).is_some()
+    }
+
+    /// `wsl.exe` running `command` in a login shell, so that toolchains set
+    /// up in the user's profile are on its `PATH`.
+    fn wrap(&self, command: &Command) -> Command {
+        let linux = |value: &std::ffi::OsStr| match Path::new(value).strip_prefix(&self.local) {
+            Ok(rest) => linux_join(&self.linux, rest),
+            Err(_) => value.to_string_lossy().into_owned(),
+        };
+        let cwd = command.get_current_dir().map_or_else(|| self.linux.clone(), |cwd| linux(cwd.as_os_str()));
+        let mut words = vec!["env".to_string()];
+        for (key, value) in command.get_envs() {
+            if let Some(value) = value {
+                words.push(format!("{}={}", key.to_string_lossy(), linux(value)));
+            }
+        }
+        words.push(linux(command.get_program()));
+        words.extend(command.get_args().map(linux));
+        // As over ssh, `exec` keeps the shell from turning a signal into an
+        // exit status of its own.
+        let mut wsl = Command::new(WSL);
+        wsl.args(["-e", "bash", "-lc"])
+            .arg(format!("cd {} && exec {}", quote(&cwd), flags::display(&words)));
+        wsl
+    }
+
+    /// `text` with the block's directory in it as Windows knows it, for
+    /// compiler messages.
+    pub fn to_windows(&self, text: &str) -> String {
+        text.replace(&self.linux, &self.local.to_string_lossy())
+    }
+}
+
+/// `path` as WSL sees it: the root, such as `C:\`, as `wslpath` translates
+/// it, which is asked once per root and process, and the rest joined to it.
+fn linux_path(path: &Path) -> io::Result<String> {
+    static ROOTS: Mutex<BTreeMap<PathBuf, String>> = Mutex::new(BTreeMap::new());
+    let root: PathBuf = path
+        .components()
+        .take_while(|component| matches!(component, std::path::Component::Prefix(_) | std::path::Component::RootDir))
+        .collect();
+    let rest = path.strip_prefix(&root).unwrap_or(path);
+    let mut roots = ROOTS.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(linux) = roots.get(&root) {
+        return Ok(linux_join(linux, rest));
+    }
+    let output = Command::new(WSL)
+        .args(["-e", "wslpath", "-a", "-u"])
+        .arg(&root)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => io::Error::new(e.kind(), "`wsl.exe` was not found; the wsl backend needs WSL"),
+            _ => e,
+        })?;
+    if !output.status.success() {
+        // wsl.exe's own messages are UTF-16.
+        let stderr = String::from_utf8_lossy(&output.stderr).replace('\0', "");
+        return Err(io::Error::other(format!("wslpath {}: {}", root.display(), stderr.trim())));
+    }
+    let linux = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    let joined = linux_join(&linux, rest);
+    roots.insert(root, linux);
+    Ok(joined)
+}
+
+/// `rest`, a relative path on this machine, under `base` in WSL.
+fn linux_join(base: &str, rest: &Path) -> String {
+    let mut joined = base.trim_end_matches('/').to_string();
+    for component in rest.components() {
+        joined.push('/');
+        joined.push_str(&component.as_os_str().to_string_lossy());
+    }
+    if joined.is_empty() {
+        joined.push('/');
+    }
+    joined
+}
+
+/// `word` quoted for the remote shell.
+fn quote(word: &str) -> String {
+    flags::display(&[word.to_string()])
+}
+
+pub enum ImageError {
+    /// The engine couldn't be started.
+    Engine(std::io::Error),
+    /// The engine ran but couldn't pull the image; its stderr.
+    Pull(String),
+}
+
+/// The exit status `docker run` uses when the container couldn't be created
+/// or started.
+pub const ENGINE_FAILED: i32 = 125;
+
+/// Whether a remote step failed because `env` couldn't find its program.
+pub fn remote_program_missing(status: Option<i32>, stderr: &str) -> bool {
+    status == Some(127) && stderr.starts_with("env: ")
+}
+
+/// Whether a step's stderr says the program wasn't in the image.
+pub fn program_missing(status: Option<i32>, stderr: &str) -> bool {
+    matches!(status, Some(126 | 127)) && stderr.contains("executable file not found")
+}
+
+fn image_for(lang: &str, settings: &Settings) -> Option<String> {
+    let default = || {
+        let (_, image) = DEFAULT_IMAGES.iter().find(|(image_lang, _)| *image_lang == lang)?;
+        Some(image.to_string())
+    };
+    settings.images.get(lang).cloned().or_else(default)
+}
+
+#[cfg(unix)]
+fn owner(dir: &Path) -> Option<String> {
+    use std::os::unix::fs::MetadataExt;
+    let metadata = dir.metadata().ok()?;
+    Some(format!("{}:{}", metadata.uid(), metadata.gid()))
+}
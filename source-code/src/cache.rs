@@ -0,0 +1,124 @@
+//! Compiled artifacts kept between runs, so unchanged rust, java and go
+//! blocks skip their compiler.
+//!
+//! Each entry is a directory named after a hash of the language, the
+//! compiler's version and the code. It is built in a staging directory next
+//! to it and renamed into place once the compiler succeeds, so an entry that
+//! exists is always complete.
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+use tempfile::TempDir;
+
+pub struct Cache {
+    dir: PathBuf,
+}
+
+/// An entry that doesn't exist yet: compile into [`Pending::dir`], then
+/// [`Pending::commit`] it.
+pub struct Pending {
+    staging: TempDir,
+    entry: PathBuf,
+}
+
+pub enum Lookup {
+    Hit(PathBuf),
+    Miss(Pending),
+}
+
+impl Cache {
+    pub fn new(dir: PathBuf) -> Self {
+        Cache { dir }
+    }
+
+    /// `$XDG_CACHE_HOME/translator`, falling back to `~/.cache/translator`.
+    pub fn default_dir() -> Option<PathBuf> {
+        let base = env::var_os("XDG_CACHE_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))?;
+        Some(base.join("translator"))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Finds the entry for `code` built by the compiler of the given
+    /// `version`. `extra` holds anything else the artifacts depend on.
+    pub fn lookup(&self, lang: &str, version: &str, code: &str, extra: &[&str]) -> io::Result<Lookup> {
+        let mut parts = vec![lang, version, code];
+        parts.extend(extra);
+        self.entry(self.dir.join("compiled").join(lang), &parts)
+    }
+
+    /// Finds the virtualenv for `key`, which names the interpreter and the
+    /// requirements installed into it.
+    pub fn venv(&self, key: &[&str]) -> io::Result<Lookup> {
+        self.entry(self.dir.join("venvs"), key)
+    }
+
+    /// Finds the npm packages for `key`, which names the package manager,
+    /// node's version and the packages installed.
+    pub fn node_modules(&self, key: &[&str]) -> io::Result<Lookup> {
+        self.entry(self.dir.join("npm"), key)
+    }
+
+    fn entry(&self, parent: PathBuf, key: &[&str]) -> io::Result<Lookup> {
+        let entry = parent.join(hash(key));
+        if entry.is_dir() {
+            return Ok(Lookup::Hit(entry));
+        }
+        fs::create_dir_all(&parent)?;
+        let staging = tempfile::Builder::new().prefix(".staging-").tempdir_in(&parent)?;
+        Ok(Lookup::Miss(Pending { staging, entry }))
+    }
+
+    /// Where `tool`'s lock files for the dependencies described by `key` are
+    /// kept, so blocks with the same dependencies resolve them once and then
+    /// build from the tool's own download cache.
+    pub fn lock_dir(&self, tool: &str, key: &str) -> PathBuf {
+        self.dir.join("locks").join(tool).join(hash(&[key]))
+    }
+
+    /// Removes every entry.
+    pub fn clear(&self) -> io::Result<()> {
+        match fs::remove_dir_all(&self.dir) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    }
+}
+
+/// A hex SHA-256 of `parts`, which can't run into each other.
+pub fn hash(parts: &[&str]) -> String {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(part.len().to_le_bytes());
+        hasher.update(part.as_bytes());
+    }
+    hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+impl Pending {
+    pub fn dir(&self) -> &Path {
+        self.staging.path()
+    }
+
+    /// Moves the compiled artifacts into place and returns where they are.
+    /// If a concurrent run got there first, its entry is used instead.
+    pub fn commit(self) -> io::Result<PathBuf> {
+        let staging = self.staging.keep();
+        if let Err(e) = fs::rename(&staging, &self.entry) {
+            let _ = fs::remove_dir_all(&staging);
+            if !self.entry.is_dir() {
+                return Err(e);
+            }
+        }
+        Ok(self.entry)
+    }
+}
@@ -0,0 +1,207 @@
+//! Captured program output. A stream stays in memory until it grows past a
+//! threshold; after that it goes to a file, and only its head and tail are
+//! kept in memory for display.
+
+use std::borrow::Cow;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Spill streams that grow past this many bytes, by default.
+pub const DEFAULT_THRESHOLD: usize = 1024 * 1024;
+
+/// How much of the start and of the end of a spilled stream stays in memory.
+const KEEP: usize = 32 * 1024;
+
+#[derive(Clone, Debug)]
+pub enum Captured {
+    Inline(Vec<u8>),
+    /// Shared, since reused results of identical blocks point at the same file.
+    Spilled(Arc<Spilled>),
+}
+
+#[derive(Debug)]
+pub struct Spilled {
+    pub path: PathBuf,
+    pub len: u64,
+    /// Whether the file outlives the run, so reports can point at it.
+    pub kept: bool,
+    head: Vec<u8>,
+    tail: Vec<u8>,
+}
+
+impl Default for Captured {
+    fn default() -> Self {
+        Captured::Inline(Vec::new())
+    }
+}
+
+impl From<Vec<u8>> for Captured {
+    fn from(bytes: Vec<u8>) -> Self {
+        Captured::Inline(bytes)
+    }
+}
+
+impl Captured {
+    pub fn spilled(&self) -> Option<&Spilled> {
+        match self {
+            Captured::Inline(_) => None,
+            Captured::Spilled(spilled) => Some(spilled),
+        }
+    }
+
+    /// The whole output. A spilled stream is read back from its file, so
+    /// this is for when nothing less will do.
+    pub fn bytes(&self) -> io::Result<Cow<'_, [u8]>> {
+        match self {
+            Captured::Inline(bytes) => Ok(Cow::Borrowed(bytes)),
+            Captured::Spilled(spilled) => fs::read(&spilled.path).map(Cow::Owned),
+        }
+    }
+
+    /// The output as text for display: all of it when it is in memory,
+    /// otherwise its head and tail around a note saying what was left out.
+    pub fn preview(&self) -> Cow<'_, str> {
+        match self {
+            Captured::Inline(bytes) => String::from_utf8_lossy(bytes),
+            Captured::Spilled(spilled) => {
+                let omitted = spilled.len - (spilled.head.len() + spilled.tail.len()) as u64;
+                let mut text = String::from_utf8_lossy(&spilled.head).into_owned();
+                if !text.ends_with('\n') {
+                    text.push('\n');
+                }
+                let note = if spilled.kept {
+                    format!("[... {} bytes omitted, all of it is in {} ...]\n", omitted, spilled.path.display())
+                } else {
+                    format!("[... {} bytes omitted, {} bytes in total ...]\n", omitted, spilled.len)
+                };
+                text.push_str(&note);
+                text.push_str(&String::from_utf8_lossy(&spilled.tail));
+                Cow::Owned(text)
+            }
+        }
+    }
+
+    /// Whether the output is exactly `expected`, comparing a spilled stream
+    /// piece by piece rather than reading it whole.
+    pub fn equals(&self, expected: &[u8]) -> io::Result<bool> {
+        let spilled = match self {
+            Captured::Inline(bytes) => return Ok(bytes == expected),
+            Captured::Spilled(spilled) if spilled.len != expected.len() as u64 => return Ok(false),
+            Captured::Spilled(spilled) => spilled,
+        };
+        let mut file = BufReader::new(File::open(&spilled.path)?);
+        let mut buf = [0; 8192];
+        let mut rest = expected;
+        loop {
+            let n = file.read(&mut buf)?;
+            if n == 0 {
+                return Ok(rest.is_empty());
+            }
+            if n > rest.len() || buf[..n] != rest[..n] {
+                return Ok(false);
+            }
+            rest = &rest[n..];
+        }
+    }
+
+    /// Writes the whole output to `path`.
+    pub fn write_to(&self, path: &Path) -> io::Result<()> {
+        match self {
+            Captured::Inline(bytes) => fs::write(path, bytes),
+            Captured::Spilled(spilled) => fs::copy(&spilled.path, path).map(drop),
+        }
+    }
+
+    /// Moves a spilled stream's file to `path`, which outlives the run
+    /// directory. Output kept in memory stays there.
+    pub fn persist(&mut self, path: PathBuf) -> io::Result<()> {
+        let Captured::Spilled(spilled) = self else {
+            return Ok(());
+        };
+        // Renaming fails across filesystems; copying always works.
+        if fs::rename(&spilled.path, &path).is_err() {
+            fs::copy(&spilled.path, &path)?;
+        }
+        *self = Captured::Spilled(Arc::new(Spilled {
+            path,
+            len: spilled.len,
+            kept: true,
+            head: spilled.head.clone(),
+            tail: spilled.tail.clone(),
+        }));
+        Ok(())
+    }
+}
+
+/// Collects one stream as it is read, spilling it to `path` past the
+/// threshold.
+pub struct Capture {
+    path: PathBuf,
+    threshold: usize,
+    buf: Vec<u8>,
+    spill: Option<Spill>,
+}
+
+struct Spill {
+    file: File,
+    len: u64,
+    head: Vec<u8>,
+    tail: Vec<u8>,
+}
+
+impl Capture {
+    /// A `threshold` of 0 keeps everything in memory.
+    pub fn new(path: PathBuf, threshold: usize) -> Self {
+        Capture {
+            path,
+            threshold,
+            buf: Vec::new(),
+            spill: None,
+        }
+    }
+
+    pub fn push(&mut self, chunk: &[u8]) -> io::Result<()> {
+        if self.spill.is_none() {
+            self.buf.extend_from_slice(chunk);
+            if self.threshold == 0 || self.buf.len() <= self.threshold {
+                return Ok(());
+            }
+            let mut file = File::create(&self.path)?;
+            file.write_all(&self.buf)?;
+            let buf = std::mem::take(&mut self.buf);
+            self.spill = Some(Spill {
+                file,
+                len: buf.len() as u64,
+                head: buf[..KEEP.min(buf.len())].to_vec(),
+                tail: buf[buf.len().saturating_sub(KEEP)..].to_vec(),
+            });
+            return Ok(());
+        }
+        let spill = self.spill.as_mut().expect("checked above");
+        spill.file.write_all(chunk)?;
+        spill.len += chunk.len() as u64;
+        spill.tail.extend_from_slice(chunk);
+        let excess = spill.tail.len().saturating_sub(KEEP);
+        spill.tail.drain(..excess);
+        Ok(())
+    }
+
+    pub fn finish(self) -> io::Result<Captured> {
+        let Some(mut spill) = self.spill else {
+            return Ok(Captured::Inline(self.buf));
+        };
+        spill.file.flush()?;
+        // The tail starts after the head, so nothing shows up twice.
+        let overlap = (spill.head.len() as u64 + spill.tail.len() as u64).saturating_sub(spill.len);
+        spill.tail.drain(..overlap as usize);
+        Ok(Captured::Spilled(Arc::new(Spilled {
+            path: self.path,
+            len: spill.len,
+            kept: false,
+            head: spill.head,
+            tail: spill.tail,
+        })))
+    }
+}
@@ -0,0 +1,156 @@
+//! `--changed-only[=BASE]`: running only the files, and with
+//! `--changed-blocks` only the blocks, that `git diff BASE...HEAD` touches.
+//! Everything else is reported as skipped, so a green run says how much of
+//! it didn't run. Where git can't tell, e.g. for a file outside a
+//! repository, everything runs, with a warning.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+use crate::parser::Block;
+
+/// What changed in one file.
+#[derive(Clone, Debug)]
+pub enum Change {
+    /// git couldn't say, so all of its blocks run.
+    Unknown,
+    Unchanged,
+    /// The file changed, and all of its blocks run.
+    Changed,
+    /// The file changed in these lines, and only the blocks that touch them
+    /// run.
+    Lines(Vec<RangeInclusive<usize>>),
+}
+
+impl Change {
+    /// Whether `block` counts as changed.
+    pub fn touches(&self, block: &Block) -> bool {
+        match self {
+            Change::Unknown | Change::Changed => true,
+            Change::Unchanged => false,
+            Change::Lines(lines) => lines
+                .iter()
+                .any(|lines| *lines.start() <= block.span.end_line && block.span.start_line <= *lines.end()),
+        }
+    }
+}
+
+/// What changed in each of the files being run.
+pub struct Changes {
+    pub base: String,
+    files: HashMap<String, Change>,
+}
+
+impl Changes {
+    /// Asks git what changed in `files` since `base`, down to the lines
+    /// with `lines`. Problems that make it run everything are passed to
+    /// `warn`.
+    pub fn find(files: &[String], base: &str, lines: bool, warn: &mut dyn FnMut(String)) -> Changes {
+        let mut changes = Changes {
+            base: base.to_string(),
+            files: HashMap::new(),
+        };
+        if let Err(e) = git(Path::new("."), &["--version"]) {
+            warn(format!("--changed-only: can't run git ({}); running every block", e));
+            return changes;
+        }
+        // Every repository is asked once, whichever of its files are run.
+        let mut repositories: HashMap<PathBuf, Result<Vec<PathBuf>, ()>> = HashMap::new();
+        for file in files {
+            let (repository, file_path) = match locate(Path::new(file)) {
+                Ok(located) => located,
+                Err(problem) => {
+                    warn(format!("--changed-only: {}; running all of its blocks", problem));
+                    continue;
+                }
+            };
+            let changed = repositories.entry(repository.clone()).or_insert_with(|| {
+                let output = git(&repository, &["diff", "--name-only", "-z", &format!("{}...HEAD", base)]);
+                let output = output.map_err(|problem| {
+                    warn(format!("--changed-only: {}: {}; running every block there", repository.display(), problem));
+                })?;
+                let names = String::from_utf8_lossy(&output.stdout).into_owned();
+                Ok(names.split('\0').filter(|name| !name.is_empty()).map(|name| repository.join(name)).collect())
+            });
+            let change = match &changed {
+                Err(()) => Change::Unknown,
+                Ok(changed) if !changed.contains(&file_path) => Change::Unchanged,
+                Ok(_) if !lines => Change::Changed,
+                Ok(_) => match changed_lines(&repository, &file_path, base) {
+                    Ok(lines) => Change::Lines(lines),
+                    Err(problem) => {
+                        warn(format!("--changed-only: {}: {}; running all of its blocks", file, problem));
+                        Change::Changed
+                    }
+                },
+            };
+            changes.files.insert(file.clone(), change);
+        }
+        changes
+    }
+
+    pub fn of(&self, file: &str) -> &Change {
+        self.files.get(file).unwrap_or(&Change::Unknown)
+    }
+}
+
+/// The top of the repository `file` is in, and the file's path beneath it
+/// as git names it, both canonical.
+fn locate(file: &Path) -> Result<(PathBuf, PathBuf), String> {
+    let file = fs::canonicalize(file).map_err(|e| format!("{}: {}", file.display(), e))?;
+    let dir = file.parent().unwrap_or(Path::new("."));
+    let output = git(dir, &["rev-parse", "--show-toplevel"])
+        .map_err(|_| format!("{} isn't in a git repository", file.display()))?;
+    let top = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    let top = fs::canonicalize(&top).map_err(|e| format!("{}: {}", top, e))?;
+    Ok((top, file))
+}
+
+/// The lines of `file`, as it is at HEAD, that differ from `base`. A line
+/// that was only deleted counts as a change to the lines on either side.
+fn changed_lines(repository: &Path, file: &Path, base: &str) -> Result<Vec<RangeInclusive<usize>>, String> {
+    let path = file.to_string_lossy();
+    let output = git(repository, &["diff", "--unified=0", "--no-color", "--no-ext-diff", &format!("{}...HEAD", base), "--", &path])?;
+    let diff = String::from_utf8_lossy(&output.stdout);
+    let mut lines = Vec::new();
+    // `@@ -12,3 +14,5 @@`: the new side starts at 14 and is 5 lines long.
+    for hunk in diff.lines().filter_map(|line| line.strip_prefix("@@ -")) {
+        let Some(new) = hunk.split(' ').nth(1).and_then(|new| new.strip_prefix('+')) else {
+            continue;
+        };
+        let (start, count) = match new.split_once(',') {
+            Some((start, count)) => (start.parse::<usize>(), count.parse::<usize>()),
+            None => (new.parse::<usize>(), Ok(1)),
+        };
+        let (Ok(start), Ok(count)) = (start, count) else {
+            continue;
+        };
+        lines.push(if count == 0 { start..=start + 1 } else { start..=start + count - 1 });
+    }
+    Ok(lines)
+}
+
+/// Runs git in `dir`. Fails with what git said, if it ran.
+fn git(dir: &Path, args: &[&str]) -> Result<Output, String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => "git isn't installed".to_string(),
+            _ => e.to_string(),
+        })?;
+    if output.status.success() {
+        Ok(output)
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let problem = stderr.lines().next().unwrap_or_default().trim().trim_end_matches('.');
+        Err(format!("git {} failed: {}", args[0], problem))
+    }
+}
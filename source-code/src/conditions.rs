@@ -0,0 +1,195 @@
+//! Conditions on whether a block runs at all: `if-os=linux`, `if-env=CI`
+//! and `if-tool=docker`, each negated by a leading `!`, as in
+//! `if-os=!windows`. A block runs only if all of its conditions hold, and is
+//! skipped, with the first that doesn't as the reason, otherwise.
+
+use std::env;
+use std::fmt;
+use std::path::Path;
+
+use crate::parser::Params;
+use crate::python;
+use crate::tools::{self, Tools};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Test {
+    /// The operating system, as Rust names it (`linux`, `macos`,
+    /// `windows`, ...), or `unix` for any Unix.
+    Os,
+    /// The environment variable is set to something.
+    Env,
+    /// The program is configured in `[tools]` or on the `PATH`. `python`
+    /// is whichever interpreter python blocks would run with.
+    Tool,
+}
+
+/// One `if-*` parameter.
+#[derive(Clone, Debug)]
+pub struct Condition {
+    test: Test,
+    negated: bool,
+    value: String,
+}
+
+impl Condition {
+    /// The conditions among `params`, in the order they were written.
+    pub fn all(params: &Params) -> Vec<Condition> {
+        params
+            .iter()
+            .filter_map(|(key, value)| {
+                let test = match key {
+                    "if-os" => Test::Os,
+                    "if-env" => Test::Env,
+                    "if-tool" => Test::Tool,
+                    _ => return None,
+                };
+                let (negated, value) = match value.strip_prefix('!') {
+                    Some(value) => (true, value),
+                    None => (false, value),
+                };
+                Some(Condition {
+                    test,
+                    negated,
+                    value: value.trim().to_string(),
+                })
+            })
+            .collect()
+    }
+
+    fn holds(&self, tools: &Tools) -> bool {
+        let found = match self.test {
+            Test::Os => self.value == env::consts::OS || self.value == env::consts::FAMILY,
+            Test::Env => env::var_os(&self.value).is_some_and(|value| !value.is_empty()),
+            Test::Tool if self.value == "python" => python::interpreter(tools).is_ok(),
+            Test::Tool => tools.get(&self.value).is_some() || tools::locate(Path::new(&self.value), None).is_some(),
+        };
+        found != self.negated
+    }
+
+    /// Why the block is skipped when this doesn't hold.
+    pub fn reason(&self) -> String {
+        let not = if self.negated { "" } else { "n't" };
+        let what = match self.test {
+            Test::Os if self.negated => format!("running on {}", self.value),
+            Test::Os => format!("not running on {}", self.value),
+            Test::Env => format!("{} is{} set", self.value, not),
+            Test::Tool => format!("{} is{} installed", self.value, not),
+        };
+        format!("{}: {}", self, what)
+    }
+
+    /// The condition as a test for a POSIX shell, for exported scripts,
+    /// which run on whatever machine they are copied to.
+    pub fn shell(&self) -> String {
+        let quoted = |word: &str| format!("'{}'", word.replace('\'', "'\\''"));
+        let test = match self.test {
+            // What `uname -s` says on each system; Windows is one of the
+            // ports of the shell.
+            Test::Os => {
+                let pattern = match self.value.as_str() {
+                    "linux" => "Linux",
+                    "macos" => "Darwin",
+                    "windows" => "MINGW*|MSYS*|CYGWIN*",
+                    "unix" => "Linux|Darwin|*BSD|DragonFly|SunOS",
+                    "freebsd" => "FreeBSD",
+                    "openbsd" => "OpenBSD",
+                    "netbsd" => "NetBSD",
+                    "solaris" | "illumos" => "SunOS",
+                    _ => "''",
+                };
+                format!("case $(uname -s) in {}) true ;; *) false ;; esac", pattern)
+            }
+            Test::Env => format!("[ -n \"$(printenv {} 2>/dev/null)\" ]", quoted(&self.value)),
+            Test::Tool => format!("command -v {} >/dev/null 2>&1", quoted(&self.value)),
+        };
+        if self.negated {
+            format!("! {{ {}; }}", test)
+        } else {
+            test
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let key = match self.test {
+            Test::Os => "if-os",
+            Test::Env => "if-env",
+            Test::Tool => "if-tool",
+        };
+        write!(f, "{}={}{}", key, if self.negated { "!" } else { "" }, self.value)
+    }
+}
+
+/// The first of `params`' conditions that doesn't hold, if any.
+pub fn unmet(params: &Params, tools: &Tools) -> Option<Condition> {
+    Condition::all(params).into_iter().find(|condition| !condition.holds(tools))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env::consts::{FAMILY, OS};
+
+    use super::*;
+    use crate::parser;
+
+    fn params(directive: &str) -> Params {
+        parser::header(directive).expect("valid directive").1
+    }
+
+    #[test]
+    fn first_unmet_condition() {
+        let exe = env::current_exe().unwrap().display().to_string();
+        let unset = "TRANSLATOR_TEST_NEVER_SET";
+        let cases: Vec<(String, Option<String>)> = vec![
+            ("rust".into(), None),
+            ("rust(name=demo, timeout=5)".into(), None),
+            (format!("rust(if-os={})", OS), None),
+            (format!("rust(if-os={})", FAMILY), None),
+            (format!("rust(if-os=!{})", OS), Some(format!("if-os=!{}: running on {}", OS, OS))),
+            ("rust(if-os=plan9)".into(), Some("if-os=plan9: not running on plan9".into())),
+            ("rust(if-os=!plan9)".into(), None),
+            ("rust(if-env=PATH)".into(), None),
+            ("rust(if-env=!PATH)".into(), Some("if-env=!PATH: PATH is set".into())),
+            (format!("rust(if-env={})", unset), Some(format!("if-env={}: {} isn't set", unset, unset))),
+            (format!("rust(if-env=!{})", unset), None),
+            (format!("rust(if-tool={})", exe), None),
+            (format!("rust(if-tool=!{})", exe), Some(format!("if-tool=!{}: {} is installed", exe, exe))),
+            ("rust(if-tool=translator-no-such-tool)".into(), Some("if-tool=translator-no-such-tool: translator-no-such-tool isn't installed".into())),
+            ("rust(if-tool=!translator-no-such-tool)".into(), None),
+            (format!("rust(if-os={}, if-env=PATH)", OS), None),
+            (
+                format!("rust(name=demo, if-os={}, if-env={}, if-tool=translator-no-such-tool)", OS, unset),
+                Some(format!("if-env={}: {} isn't set", unset, unset)),
+            ),
+        ];
+        for (directive, reason) in cases {
+            let unmet = unmet(&params(&directive), &Tools::none());
+            assert_eq!(unmet.map(|condition| condition.reason()), reason, "{}", directive);
+        }
+    }
+
+    #[test]
+    fn configured_tool_is_installed() {
+        let tools = Tools::none().with(vec![("docker", env::current_exe().unwrap())], tools::Source::Env("TRANSLATOR_DOCKER"));
+        assert!(unmet(&params("rust(if-tool=docker)"), &tools).is_none());
+        assert!(unmet(&params("rust(if-tool=!docker)"), &tools).is_some());
+    }
+
+    #[test]
+    fn as_shell_tests() {
+        let cases = [
+            ("if-os=macos", "case $(uname -s) in Darwin) true ;; *) false ;; esac"),
+            ("if-os=plan9", "case $(uname -s) in '') true ;; *) false ;; esac"),
+            ("if-env=CI", "[ -n \"$(printenv 'CI' 2>/dev/null)\" ]"),
+            ("if-env=!CI", "! { [ -n \"$(printenv 'CI' 2>/dev/null)\" ]; }"),
+            ("if-tool=it's", "command -v 'it'\\''s' >/dev/null 2>&1"),
+        ];
+        for (param, shell) in cases {
+            let conditions = Condition::all(&params(&format!("rust({})", param)));
+            assert_eq!(conditions.len(), 1, "{}", param);
+            assert_eq!(conditions[0].to_string(), param);
+            assert_eq!(conditions[0].shell(), shell, "{}", param);
+        }
+    }
+}
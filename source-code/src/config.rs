@@ -0,0 +1,172 @@
+//! `translator.toml`: settings for every run in a project, so they don't
+//! have to be repeated on each command line. The file is `--config PATH`, or
+//! else the nearest `translator.toml` in the working directory or one of its
+//! parents. Every setting is optional.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::backend;
+use crate::parser;
+
+pub const FILE_NAME: &str = "translator.toml";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Interpreter for python blocks, e.g. `/usr/bin/python3.12`. The same
+    /// as `python` in `[tools]`, which wins.
+    pub python: Option<String>,
+    /// What directive lines start with instead of `|>`, for hacker files
+    /// inside formats where `|>` means something else.
+    pub marker: Option<String>,
+    /// What every compile and run step is started through, like
+    /// `--sandbox-cmd`, which wins.
+    pub sandbox_cmd: Option<String>,
+    /// Directories whose hacker files run without asking, relative to the
+    /// file, like a `.translator-trust` in each.
+    #[serde(default)]
+    pub trusted: Vec<String>,
+    /// Programs to use instead of the ones on the `PATH`.
+    #[serde(default)]
+    pub tools: ToolPaths,
+    /// Limits and images for the container backend.
+    #[serde(default)]
+    pub container: backend::Settings,
+    /// Toolchains for `version=`, by language and version.
+    #[serde(default)]
+    pub versions: Versions,
+    /// The file the settings came from.
+    #[serde(skip)]
+    pub path: Option<PathBuf>,
+}
+
+/// The `[tools]` table, one entry per program in [`crate::tools::TOOLS`].
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ToolPaths {
+    pub rustc: Option<String>,
+    pub cargo: Option<String>,
+    pub javac: Option<String>,
+    pub java: Option<String>,
+    pub jshell: Option<String>,
+    pub python: Option<String>,
+    pub go: Option<String>,
+}
+
+/// The `[versions]` table: for each language, versions mapped to the
+/// interpreter or compiler (python, go) or the JDK's home (java).
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Versions {
+    #[serde(default)]
+    pub python: BTreeMap<String, String>,
+    #[serde(default)]
+    pub java: BTreeMap<String, String>,
+    #[serde(default)]
+    pub go: BTreeMap<String, String>,
+}
+
+impl Versions {
+    pub fn get(&self, lang: &str) -> Option<&BTreeMap<String, String>> {
+        match lang {
+            "python" => Some(&self.python),
+            "java" => Some(&self.java),
+            "go" => Some(&self.go),
+            _ => None,
+        }
+    }
+}
+
+impl Config {
+    /// Loads `path`, or the nearest `translator.toml` when it is `None`.
+    /// Without a file the defaults apply.
+    pub fn load(path: Option<&Path>) -> io::Result<Config> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => match find()? {
+                Some(path) => path,
+                None => return Ok(Config::default()),
+            },
+        };
+        let text = fs::read_to_string(&path).map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+        let mut config: Config = toml::from_str(&text).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", path.display(), e.message()),
+            )
+        })?;
+        config.path = Some(path);
+        Ok(config)
+    }
+
+    /// The configured program for `tool`.
+    pub fn tool(&self, tool: &str) -> Option<&str> {
+        let paths = &self.tools;
+        let path = match tool {
+            "rustc" => &paths.rustc,
+            "cargo" => &paths.cargo,
+            "javac" => &paths.javac,
+            "java" => &paths.java,
+            "jshell" => &paths.jshell,
+            "python" => return paths.python.as_deref().or(self.python.as_deref()),
+            "go" => &paths.go,
+            _ => return None,
+        };
+        path.as_deref()
+    }
+}
+
+fn find() -> io::Result<Option<PathBuf>> {
+    find_from(&env::current_dir()?)
+}
+
+/// The nearest `translator.toml` in `dir` or one of its parents.
+fn find_from(dir: &Path) -> io::Result<Option<PathBuf>> {
+    Ok(dir
+        .ancestors()
+        .map(|dir| dir.join(FILE_NAME))
+        .find(|path| path.is_file()))
+}
+
+/// The marker directive lines start with in `file`. `--marker` wins; then,
+/// unless `--config` named the settings for every file, comes the
+/// `translator.toml` nearest to the file rather than to the working
+/// directory, since the marker belongs to the file's format and files from
+/// different projects can be run together. `config` is what was loaded for
+/// the run.
+pub fn marker(file: &Path, flag: Option<&str>, config: &Config, config_given: bool) -> io::Result<String> {
+    if let Some(marker) = flag {
+        return Ok(marker.to_string());
+    }
+    if config_given {
+        return checked_marker(config.path.as_deref().unwrap_or(Path::new(FILE_NAME)), config.marker.clone());
+    }
+    // Resolved, so `..` doesn't lead through the working directory.
+    let file = fs::canonicalize(file).or_else(|_| env::current_dir().map(|cwd| cwd.join(file)))?;
+    let Some(path) = find_from(file.parent().unwrap_or(&file))? else {
+        return Ok(parser::MARKER.to_string());
+    };
+    let marker = if config.path.as_deref() == Some(path.as_path()) {
+        config.marker.clone()
+    } else {
+        Config::load(Some(&path))?.marker
+    };
+    checked_marker(&path, marker)
+}
+
+fn checked_marker(path: &Path, marker: Option<String>) -> io::Result<String> {
+    match marker {
+        Some(marker) if marker.trim().is_empty() || marker.trim() != marker => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: marker can't be empty or start or end with whitespace", path.display()),
+        )),
+        Some(marker) => Ok(marker),
+        None => Ok(parser::MARKER.to_string()),
+    }
+}
@@ -0,0 +1,64 @@
+//! Where translator's own output goes: the process's stdout and stderr, or,
+//! for a daemon request, the client that sent it.
+
+use std::io::{self, IsTerminal, Write};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::report;
+
+type Sink = Arc<Mutex<dyn Write + Send>>;
+
+#[derive(Clone)]
+pub struct Console {
+    out: Sink,
+    err: Sink,
+    /// Whether the error stream shows ANSI colors.
+    pub color: bool,
+    /// Whether the user can be asked questions: stdin, stdout and stderr
+    /// are all terminals.
+    pub interactive: bool,
+}
+
+impl Console {
+    pub fn stdio() -> Self {
+        let interactive = io::stdin().is_terminal() && io::stdout().is_terminal() && io::stderr().is_terminal();
+        Console {
+            interactive,
+            ..Console::new(io::stdout(), io::stderr(), report::stderr_color())
+        }
+    }
+
+    pub fn new(out: impl Write + Send + 'static, err: impl Write + Send + 'static, color: bool) -> Self {
+        Console {
+            out: Arc::new(Mutex::new(out)),
+            err: Arc::new(Mutex::new(err)),
+            color,
+            interactive: false,
+        }
+    }
+
+    pub fn out(&self) -> MutexGuard<'_, dyn Write + Send + 'static> {
+        self.out.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn err(&self) -> MutexGuard<'_, dyn Write + Send + 'static> {
+        self.err.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// The output stream as an owned writer, for reporters that hold one.
+    pub fn out_writer(&self) -> Out {
+        Out(self.out.clone())
+    }
+}
+
+pub struct Out(Sink);
+
+impl Write for Out {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).flush()
+    }
+}
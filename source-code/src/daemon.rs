@@ -0,0 +1,301 @@
+//! `translator daemon` keeps one process alive between runs, so toolchain
+//! version probes and any other per-process setup are paid once. `translator
+//! client` sends it a command line over a unix socket and replays the output.
+//!
+//! The protocol is one JSON line per message. The client sends a `Request`;
+//! the daemon answers with `Frame`s, the last of which is always `exit`.
+//! Requests are served one at a time: a run changes the working directory,
+//! which is process-wide, so later clients wait in the listen queue.
+
+use std::env;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::cache::Cache;
+
+/// How long the daemon waits for a request before exiting, by default.
+const IDLE_TIMEOUT_SECS: u64 = 600;
+
+#[derive(Serialize, Deserialize)]
+struct Request {
+    /// The command line, as it would follow `translator`.
+    args: Vec<String>,
+    cwd: PathBuf,
+    /// Whether the client's stderr shows colors.
+    color: bool,
+    #[serde(default)]
+    shutdown: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "frame", rename_all = "snake_case")]
+enum Frame {
+    /// Base64, since output need not be UTF-8.
+    Stdout { data: String },
+    Stderr { data: String },
+    Exit { code: u8 },
+}
+
+/// `$XDG_RUNTIME_DIR/translator.sock`, falling back to the cache directory
+/// and then the temporary directory.
+fn default_socket() -> PathBuf {
+    if let Some(dir) = env::var_os("XDG_RUNTIME_DIR").filter(|dir| !dir.is_empty()) {
+        return PathBuf::from(dir).join("translator.sock");
+    }
+    match Cache::default_dir() {
+        Some(dir) => dir.join("daemon.sock"),
+        None => env::temp_dir().join("translator-daemon.sock"),
+    }
+}
+
+#[cfg(unix)]
+pub use unix::{client, serve};
+
+#[cfg(not(unix))]
+pub fn serve(_args: &[String]) -> u8 {
+    eprintln!("translator daemon needs unix domain sockets, which this platform lacks");
+    1
+}
+
+#[cfg(not(unix))]
+pub fn client(_args: &[String]) -> u8 {
+    serve(&[])
+}
+
+#[cfg(unix)]
+mod unix {
+    use std::env;
+    use std::fs;
+    use std::io::{self, BufRead, BufReader, ErrorKind, Write};
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::panic::{self, AssertUnwindSafe};
+    use std::path::{Path, PathBuf};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use base64::Engine;
+
+    use super::{default_socket, Frame, Request, IDLE_TIMEOUT_SECS};
+    use crate::console::Console;
+    use crate::report;
+
+    /// How often the accept loop checks the idle timeout.
+    const POLL: Duration = Duration::from_millis(100);
+
+    pub fn serve(args: &[String]) -> u8 {
+        let mut socket = default_socket();
+        let mut idle_timeout = Some(Duration::from_secs(IDLE_TIMEOUT_SECS));
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match (arg.as_str(), args.next()) {
+                ("--socket", Some(path)) => socket = PathBuf::from(path),
+                ("--idle-timeout", Some(secs)) => match secs.parse() {
+                    Ok(0) => idle_timeout = None,
+                    Ok(secs) => idle_timeout = Some(Duration::from_secs(secs)),
+                    Err(_) => return fail(format!("--idle-timeout expects seconds, got {}", secs)),
+                },
+                _ => return fail(format!("Unknown daemon option: {}", arg)),
+            }
+        }
+        match listen(&socket, idle_timeout) {
+            Ok(()) => 0,
+            Err(e) => fail(format!("Error: {}: {}", socket.display(), e)),
+        }
+    }
+
+    fn fail(message: String) -> u8 {
+        eprintln!("{}", message);
+        1
+    }
+
+    fn listen(socket: &Path, idle_timeout: Option<Duration>) -> io::Result<()> {
+        // Runs move the working directory around; the path must not depend on it.
+        let home = env::current_dir()?;
+        let socket = &home.join(socket);
+        if UnixStream::connect(socket).is_ok() {
+            return Err(io::Error::new(ErrorKind::AddrInUse, "a daemon is already listening"));
+        }
+        // Nothing answered, so the file is left over from a daemon that died.
+        if socket.exists() {
+            fs::remove_file(socket)?;
+        }
+        if let Some(parent) = socket.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let listener = UnixListener::bind(socket)?;
+        listener.set_nonblocking(true)?;
+        eprintln!("Listening on {}", socket.display());
+        let mut idle = Instant::now();
+        let result = loop {
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    let shutdown = handle(stream);
+                    env::set_current_dir(&home)?;
+                    if shutdown {
+                        eprintln!("Shutting down");
+                        break Ok(());
+                    }
+                    idle = Instant::now();
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                    if idle_timeout.is_some_and(|timeout| idle.elapsed() >= timeout) {
+                        eprintln!("Idle for {}s, exiting", idle.elapsed().as_secs());
+                        break Ok(());
+                    }
+                    thread::sleep(POLL);
+                }
+                Err(e) => break Err(e),
+            }
+        };
+        let _ = fs::remove_file(socket);
+        result
+    }
+
+    /// Serves one client and reports whether it asked the daemon to stop.
+    /// A client that goes away mid-run only ends its own request.
+    fn handle(stream: UnixStream) -> bool {
+        let _ = stream.set_nonblocking(false);
+        let mut line = String::new();
+        let request = BufReader::new(&stream)
+            .read_line(&mut line)
+            .map_err(|e| e.to_string())
+            .and_then(|_| serde_json::from_str::<Request>(&line).map_err(|e| e.to_string()));
+        let stream = Arc::new(Mutex::new(stream));
+        let request = match request {
+            Ok(request) => request,
+            Err(e) => {
+                let _ = send(&stream, &Frame::Stderr {
+                    data: encode(format!("translator daemon: bad request: {}\n", e).as_bytes()),
+                });
+                let _ = send(&stream, &Frame::Exit { code: 1 });
+                return false;
+            }
+        };
+        if request.shutdown {
+            let _ = send(&stream, &Frame::Exit { code: 0 });
+            return true;
+        }
+        let code = match env::set_current_dir(&request.cwd) {
+            Ok(()) => {
+                let console = Console::new(
+                    FrameWriter {
+                        stream: stream.clone(),
+                        stderr: false,
+                    },
+                    FrameWriter {
+                        stream: stream.clone(),
+                        stderr: true,
+                    },
+                    request.color,
+                );
+                // A bug in one run shouldn't take down the daemon for everyone.
+                panic::catch_unwind(AssertUnwindSafe(|| crate::run(request.args, console))).unwrap_or(101)
+            }
+            Err(e) => {
+                let message = format!("translator daemon: {}: {}\n", request.cwd.display(), e);
+                let _ = send(&stream, &Frame::Stderr {
+                    data: encode(message.as_bytes()),
+                });
+                1
+            }
+        };
+        let _ = send(&stream, &Frame::Exit { code });
+        false
+    }
+
+    fn encode(bytes: &[u8]) -> String {
+        base64::engine::general_purpose::STANDARD.encode(bytes)
+    }
+
+    fn send(stream: &Mutex<UnixStream>, frame: &Frame) -> io::Result<()> {
+        let mut line = serde_json::to_vec(frame)?;
+        line.push(b'\n');
+        stream.lock().unwrap_or_else(|e| e.into_inner()).write_all(&line)
+    }
+
+    /// One of a request's output streams, sent to the client as frames.
+    struct FrameWriter {
+        stream: Arc<Mutex<UnixStream>>,
+        stderr: bool,
+    }
+
+    impl Write for FrameWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let data = encode(buf);
+            let frame = if self.stderr {
+                Frame::Stderr { data }
+            } else {
+                Frame::Stdout { data }
+            };
+            send(&self.stream, &frame)?;
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    pub fn client(args: &[String]) -> u8 {
+        let mut socket = default_socket();
+        let mut shutdown = false;
+        let mut rest = args;
+        loop {
+            match rest {
+                [flag, path, tail @ ..] if flag == "--socket" => {
+                    socket = PathBuf::from(path);
+                    rest = tail;
+                }
+                [flag, tail @ ..] if flag == "--shutdown" => {
+                    shutdown = true;
+                    rest = tail;
+                }
+                _ => break,
+            }
+        }
+        match request(&socket, rest, shutdown) {
+            Ok(code) => code,
+            Err(e) if matches!(e.kind(), ErrorKind::NotFound | ErrorKind::ConnectionRefused) => fail(format!(
+                "No daemon is listening on {}; start one with `translator daemon`",
+                socket.display()
+            )),
+            Err(e) => fail(format!("Error: {}: {}", socket.display(), e)),
+        }
+    }
+
+    fn request(socket: &Path, args: &[String], shutdown: bool) -> io::Result<u8> {
+        let mut stream = UnixStream::connect(socket)?;
+        let request = Request {
+            args: args.to_vec(),
+            cwd: env::current_dir()?,
+            color: report::stderr_color(),
+            shutdown,
+        };
+        let mut line = serde_json::to_vec(&request)?;
+        line.push(b'\n');
+        stream.write_all(&line)?;
+        let (mut stdout, mut stderr) = (io::stdout(), io::stderr());
+        for line in BufReader::new(stream).lines() {
+            let frame: Frame = serde_json::from_str(&line?)?;
+            let decode = |data: String| {
+                base64::engine::general_purpose::STANDARD
+                    .decode(data)
+                    .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+            };
+            match frame {
+                Frame::Stdout { data } => stdout.write_all(&decode(data)?)?,
+                Frame::Stderr { data } => stderr.write_all(&decode(data)?)?,
+                Frame::Exit { code } => {
+                    stdout.flush()?;
+                    return Ok(code);
+                }
+            }
+        }
+        Err(io::Error::new(
+            ErrorKind::UnexpectedEof,
+            "the daemon closed the connection before the run finished",
+        ))
+    }
+}
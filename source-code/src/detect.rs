@@ -0,0 +1,186 @@
+//! Guessing the language of `|> translator:auto (` blocks from their code.
+//! A shebang line decides; otherwise every clue found in the code counts
+//! for its language, and the language with clearly the most wins. Blocks
+//! whose clues point several ways aren't guessed at.
+
+use std::cmp::Reverse;
+
+use crate::languages;
+
+/// The language that asks for a guess.
+pub const AUTO: &str = "auto";
+
+/// What each clue is worth for its language. Clues match at the start of
+/// a word, so `def ` doesn't match `undef `.
+const CLUES: &[(&str, &str, u32)] = &[
+    ("rust", "fn main", 3),
+    ("rust", "println!", 3),
+    ("rust", "print!", 2),
+    ("rust", "use std::", 3),
+    ("rust", "let mut ", 2),
+    ("rust", "impl ", 1),
+    ("rust", "&str", 1),
+    ("go", "package main", 3),
+    ("go", "func main", 3),
+    ("go", "fmt.", 2),
+    ("go", "func ", 1),
+    ("go", ":=", 1),
+    ("java", "public class", 3),
+    ("java", "System.out", 3),
+    ("java", "public static void main", 3),
+    ("java", "String[]", 1),
+    ("python", "def ", 2),
+    ("python", "print(", 2),
+    ("python", "elif ", 2),
+    ("python", "import ", 1),
+    ("python", "self.", 1),
+    ("python", "__name__", 2),
+];
+
+/// Python's clues only count in code without braces around blocks, since
+/// the others have `print(` and `import ` too.
+const BRACELESS: &str = "python";
+
+/// How much of the clues' total the winner needs.
+const MAJORITY: f64 = 2.0 / 3.0;
+
+/// A language the code looks like.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Guess {
+    pub lang: String,
+    /// The winner's share of what all the clues were worth, from 0 to 1.
+    pub confidence: f64,
+    /// What gave it away.
+    pub clues: Vec<&'static str>,
+}
+
+/// The language of `code`, or the languages it could be, best first, when
+/// no one of them is clearly ahead. None at all means no clue matched.
+pub fn detect(code: &str) -> Result<Guess, Vec<&'static str>> {
+    if let Some(lang) = shebang(code) {
+        return Ok(Guess {
+            lang,
+            confidence: 1.0,
+            clues: vec!["#!"],
+        });
+    }
+    let braces = code.lines().any(|line| line.trim_end().ends_with('{'));
+    let mut scores: Vec<(&'static str, u32, Vec<&'static str>)> = Vec::new();
+    for &(lang, clue, weight) in CLUES {
+        if (lang == BRACELESS && braces) || !contains_word(code, clue) {
+            continue;
+        }
+        match scores.iter_mut().find(|(scored, _, _)| *scored == lang) {
+            Some((_, score, clues)) => {
+                *score += weight;
+                clues.push(clue.trim_end());
+            }
+            None => scores.push((lang, weight, vec![clue.trim_end()])),
+        }
+    }
+    // Ties keep the table's order.
+    scores.sort_by_key(|(_, score, _)| Reverse(*score));
+    let total: u32 = scores.iter().map(|(_, score, _)| score).sum();
+    match scores.first() {
+        Some((lang, score, clues)) if f64::from(*score) / f64::from(total) >= MAJORITY => Ok(Guess {
+            lang: lang.to_string(),
+            confidence: f64::from(*score) / f64::from(total),
+            clues: clues.clone(),
+        }),
+        _ => Err(scores.into_iter().map(|(lang, _, _)| lang).collect()),
+    }
+}
+
+/// Why an `auto` block can't run: `could not detect language, candidates:
+/// rust, go`.
+pub fn failure(code: &str) -> String {
+    match detect(code) {
+        Err(candidates) if candidates.is_empty() => {
+            "could not detect language; name it in the directive, as in translator:rust".to_string()
+        }
+        Err(candidates) => format!("could not detect language, candidates: {}", candidates.join(", ")),
+        // Guesses are only made for the directives of hacker files.
+        Ok(_) => "auto only picks a language for blocks in hacker files".to_string(),
+    }
+}
+
+/// The language a `#!` first line names, as in `#!/usr/bin/env python3`.
+/// Interpreters translator doesn't know are left to plugins.
+fn shebang(code: &str) -> Option<String> {
+    let line = code.lines().next()?.strip_prefix("#!")?;
+    let mut words = line.split_whitespace();
+    let mut program = words.next()?.rsplit('/').next()?;
+    if program == "env" {
+        program = words.find(|word| !word.starts_with('-'))?;
+    }
+    (!program.is_empty()).then(|| languages::canonical(program))
+}
+
+/// Whether `clue` appears in `code` where a word starts.
+fn contains_word(code: &str, clue: &str) -> bool {
+    code.match_indices(clue).any(|(at, _)| {
+        let before = code[..at].chars().next_back();
+        !before.is_some_and(|c| c.is_alphanumeric() || c == '_')
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn guesses() {
+        let cases: &[(&str, Result<&str, &[&str]>)] = &[
+            ("fn main() {\n    println!(\"hi\");\n}", Ok("rust")),
+            ("use std::collections::HashMap;\nlet mut map = HashMap::new();", Ok("rust")),
+            ("package main\n\nimport \"fmt\"\n\nfunc main() {\n\tfmt.Println(1)\n}", Ok("go")),
+            ("public class Main {\n    public static void main(String[] args) {\n        System.out.println(1);\n    }\n}", Ok("java")),
+            ("System.out.println(x);", Ok("java")),
+            ("def greet(name):\n    print(name)\n\ngreet('x')", Ok("python")),
+            ("import os\nif __name__ == '__main__':\n    print(os.getcwd())", Ok("python")),
+            ("x := 1", Ok("go")),
+            // `import ` counts for python, but not enough to beat rust.
+            ("use std::io;\nimport os", Ok("rust")),
+            // Clues in comments count like any other.
+            ("// println!", Ok("rust")),
+            // A shebang decides, whatever the code looks like.
+            ("#!/usr/bin/env python3\nfn main() {}", Ok("python")),
+            ("#!/usr/bin/python\nx = 1", Ok("python")),
+            ("#!/usr/bin/env -S ruby -w\nputs 1", Ok("ruby")),
+            ("#!/bin/bash\necho hi", Ok("bash")),
+            // Clues for several languages, none clearly ahead.
+            ("fn main() {}\nfunc main() {}", Err(&["go", "rust"])),
+            ("let mut x = 5\ndef f():", Err(&["rust", "python"])),
+            ("package main\npublic class Main", Err(&["go", "java"])),
+            ("fmt.Println(1)\nprint(1)", Err(&["go", "python"])),
+            // No clue at all.
+            ("", Err(&[])),
+            ("x = 1", Err(&[])),
+            ("#!\nx = 1", Err(&[])),
+            ("#undef def_value", Err(&[])),
+            // Braces rule python out, even in a dict literal.
+            ("if x {\n    print(x)\n}", Err(&[])),
+            ("def f():\n    return {\n    }", Err(&[])),
+        ];
+        for (code, expected) in cases {
+            let guess = detect(code).map(|guess| guess.lang);
+            assert_eq!(guess, expected.map(str::to_string).map_err(<[&str]>::to_vec), "{:?}", code);
+        }
+    }
+
+    #[test]
+    fn confidence_and_clues() {
+        let guess = detect("use std::io;\nimport os").unwrap();
+        assert_eq!(guess.confidence, 0.75);
+        assert_eq!(guess.clues, ["use std::"]);
+        let guess = detect("fn main() {\n    let mut n = 1;\n    println!(\"{}\", n);\n}").unwrap();
+        assert_eq!((guess.confidence, guess.clues), (1.0, vec!["fn main", "println!", "let mut"]));
+        assert_eq!(detect("#!/bin/sh\n").unwrap().clues, ["#!"]);
+    }
+
+    #[test]
+    fn failures() {
+        assert_eq!(failure("x = 1"), "could not detect language; name it in the directive, as in translator:rust");
+        assert_eq!(failure("fn main() {}\nfunc main() {}"), "could not detect language, candidates: go, rust");
+    }
+}
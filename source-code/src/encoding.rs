@@ -0,0 +1,320 @@
+//! How hacker files are decoded, from `--encoding`. By default they have to
+//! be UTF-8. `--encoding latin1`, or any other ASCII-compatible encoding
+//! encoding_rs has a label for, decodes them from that instead, and
+//! `--encoding auto` takes UTF-8 but replaces invalid bytes rather than
+//! refusing the file. A UTF-8 byte order mark is skipped in every mode.
+//!
+//! What blocks' programs and compilers print is expected to be UTF-8 too,
+//! except on Windows, where older tools write the console's code page,
+//! cp1252 or cp936 say. There output that isn't UTF-8 is decoded from the
+//! code page, or from `--output-encoding`, unless it starts with a byte
+//! order mark: Windows tools that write UTF-16 put one first.
+
+use std::borrow::Cow;
+
+use encoding_rs::{Decoder, Encoding};
+
+pub const BOM: &[u8] = b"\xEF\xBB\xBF";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FileEncoding {
+    /// UTF-8, and nothing else.
+    #[default]
+    Utf8,
+    /// UTF-8, with each invalid sequence replaced by U+FFFD.
+    Auto,
+    /// A legacy encoding such as windows-1252, which is what `latin1` means
+    /// to encoding_rs as it does to browsers.
+    Other(&'static Encoding),
+}
+
+/// A line as the parser sees it.
+pub struct Decoded<'a> {
+    pub text: Cow<'a, str>,
+    /// Where in the line invalid bytes were replaced.
+    pub replaced: Vec<usize>,
+}
+
+impl FileEncoding {
+    pub fn parse(label: &str) -> Result<FileEncoding, String> {
+        match label {
+            "utf8" | "utf-8" => return Ok(FileEncoding::Utf8),
+            "auto" => return Ok(FileEncoding::Auto),
+            _ => {}
+        }
+        match Encoding::for_label(label.as_bytes()) {
+            Some(encoding) if encoding == encoding_rs::UTF_8 => Ok(FileEncoding::Utf8),
+            // Lines are split at `\n` bytes before they are decoded.
+            Some(encoding) if encoding.is_ascii_compatible() => Ok(FileEncoding::Other(encoding)),
+            Some(encoding) => Err(format!("{} isn't supported: hacker files need an ASCII-compatible encoding", encoding.name())),
+            None => Err(format!("Unknown encoding: {}; use utf8, latin1, auto or another encoding label", label)),
+        }
+    }
+
+    /// Decodes one line of a file.
+    pub fn decode<'a>(&self, raw: &'a [u8]) -> Result<Decoded<'a>, std::str::Utf8Error> {
+        match self {
+            FileEncoding::Utf8 => Ok(Decoded {
+                text: Cow::Borrowed(std::str::from_utf8(raw)?),
+                replaced: Vec::new(),
+            }),
+            FileEncoding::Auto => Ok(lossy(raw)),
+            FileEncoding::Other(encoding) => Ok(Decoded {
+                text: encoding.decode_without_bom_handling(raw).0,
+                replaced: Vec::new(),
+            }),
+        }
+    }
+
+    /// `text` as it is written back into a file of this encoding.
+    pub fn encode<'a>(&self, text: &'a str) -> Cow<'a, [u8]> {
+        match self {
+            FileEncoding::Utf8 | FileEncoding::Auto => Cow::Borrowed(text.as_bytes()),
+            FileEncoding::Other(encoding) => encoding.encode(text).0,
+        }
+    }
+}
+
+/// `raw` as UTF-8, with every invalid sequence replaced by U+FFFD.
+fn lossy(raw: &[u8]) -> Decoded<'_> {
+    let mut replaced = Vec::new();
+    let mut text = String::new();
+    let mut rest = raw;
+    loop {
+        match std::str::from_utf8(rest) {
+            Ok(valid) if replaced.is_empty() => {
+                return Decoded {
+                    text: Cow::Borrowed(valid),
+                    replaced,
+                }
+            }
+            Ok(valid) => {
+                text.push_str(valid);
+                return Decoded {
+                    text: Cow::Owned(text),
+                    replaced,
+                };
+            }
+            Err(e) => {
+                let (valid, invalid) = rest.split_at(e.valid_up_to());
+                text.push_str(std::str::from_utf8(valid).expect("checked by from_utf8"));
+                text.push(char::REPLACEMENT_CHARACTER);
+                replaced.push(raw.len() - rest.len() + valid.len());
+                rest = &invalid[e.error_len().unwrap_or(invalid.len())..];
+            }
+        }
+    }
+}
+
+/// How the output of the steps is decoded.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputEncoding {
+    /// Taken as UTF-8, with invalid bytes replaced where it is shown.
+    #[default]
+    Utf8,
+    /// UTF-8 while it is, and from this encoding as soon as it isn't.
+    Legacy(&'static Encoding),
+}
+
+impl OutputEncoding {
+    /// `auto` for the console's code page, `utf8`, or an encoding label.
+    pub fn parse(label: &str) -> Result<OutputEncoding, String> {
+        match label {
+            "auto" => return Ok(OutputEncoding::console()),
+            "utf8" | "utf-8" => return Ok(OutputEncoding::Utf8),
+            _ => {}
+        }
+        match Encoding::for_label(label.as_bytes()) {
+            Some(encoding) if encoding == encoding_rs::UTF_8 => Ok(OutputEncoding::Utf8),
+            Some(encoding) => Ok(OutputEncoding::Legacy(encoding)),
+            None => Err(format!("Unknown encoding: {}; use auto, utf8 or another encoding label", label)),
+        }
+    }
+
+    /// The console's output code page on Windows, or the ANSI one without
+    /// a console; UTF-8 elsewhere.
+    #[cfg(windows)]
+    pub fn console() -> OutputEncoding {
+        use windows_sys::Win32::Globalization::GetACP;
+        use windows_sys::Win32::System::Console::GetConsoleOutputCP;
+        let page = match unsafe { GetConsoleOutputCP() } {
+            0 => unsafe { GetACP() },
+            page => page,
+        };
+        match code_page(page) {
+            Some(encoding) if encoding != encoding_rs::UTF_8 => OutputEncoding::Legacy(encoding),
+            _ => OutputEncoding::Utf8,
+        }
+    }
+
+    #[cfg(not(windows))]
+    pub fn console() -> OutputEncoding {
+        OutputEncoding::Utf8
+    }
+
+    /// All of a step's output in `raw`, as UTF-8.
+    pub fn decode(self, raw: Vec<u8>) -> Vec<u8> {
+        match self {
+            OutputEncoding::Legacy(encoding) if std::str::from_utf8(&raw).is_err() => {
+                encoding.decode(&raw).0.into_owned().into_bytes()
+            }
+            _ => raw,
+        }
+    }
+
+    /// Decodes a stream of output a chunk at a time.
+    pub fn decoder(self) -> OutputDecoder {
+        OutputDecoder {
+            encoding: self,
+            legacy: None,
+            pending: Vec::new(),
+            started: false,
+        }
+    }
+}
+
+/// The encoding encoding_rs has for a Windows code page; DOS code pages
+/// other than 866 aren't among them.
+#[cfg(windows)]
+fn code_page(page: u32) -> Option<&'static Encoding> {
+    let label = match page {
+        65001 => "utf-8",
+        932 => "shift_jis",
+        936 => "gbk",
+        949 => "euc-kr",
+        950 => "big5",
+        20866 => "koi8-r",
+        28591 => "iso-8859-1",
+        page => return Encoding::for_label(format!("windows-{}", page).as_bytes()).or_else(|| Encoding::for_label(format!("cp{}", page).as_bytes())),
+    };
+    Encoding::for_label(label.as_bytes())
+}
+
+/// A stream's decoding: as long as it is valid UTF-8 the output passes
+/// through unchanged, which is what tools that know better write even on
+/// Windows.
+pub struct OutputDecoder {
+    encoding: OutputEncoding,
+    /// Set once the stream turned out not to be UTF-8.
+    legacy: Option<Decoder>,
+    /// The start of a UTF-8 sequence the chunk ended in.
+    pending: Vec<u8>,
+    /// Whether any output has passed through, after which a byte order
+    /// mark is just bytes.
+    started: bool,
+}
+
+impl OutputDecoder {
+    /// The UTF-8 of `chunk`, as far as it is complete.
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<u8> {
+        let OutputEncoding::Legacy(encoding) = self.encoding else {
+            return chunk.to_vec();
+        };
+        if let Some(decoder) = &mut self.legacy {
+            return decode_chunk(decoder, chunk, false);
+        }
+        self.pending.extend_from_slice(chunk);
+        let passed = match std::str::from_utf8(&self.pending) {
+            Ok(_) => std::mem::take(&mut self.pending),
+            Err(e) if e.error_len().is_none() => {
+                let rest = self.pending.split_off(e.valid_up_to());
+                std::mem::replace(&mut self.pending, rest)
+            }
+            Err(_) => {
+                let pending = std::mem::take(&mut self.pending);
+                let decoder = self.legacy.insert(legacy_decoder(encoding, self.started));
+                return decode_chunk(decoder, &pending, false);
+            }
+        };
+        self.started |= !passed.is_empty();
+        passed
+    }
+
+    /// Whatever is left once the stream has ended.
+    pub fn finish(&mut self) -> Vec<u8> {
+        let pending = std::mem::take(&mut self.pending);
+        if self.legacy.is_none() {
+            match self.encoding {
+                // A sequence cut off at the end isn't UTF-8 after all.
+                OutputEncoding::Legacy(encoding) if !pending.is_empty() => {
+                    self.legacy = Some(legacy_decoder(encoding, self.started));
+                }
+                _ => return pending,
+            }
+        }
+        let decoder = self.legacy.as_mut().expect("set above");
+        decode_chunk(decoder, &pending, true)
+    }
+}
+
+/// A decoder from `encoding` for the rest of a stream, or from the encoding
+/// of the byte order mark the stream starts with.
+fn legacy_decoder(encoding: &'static Encoding, started: bool) -> Decoder {
+    if started {
+        encoding.new_decoder_without_bom_handling()
+    } else {
+        encoding.new_decoder()
+    }
+}
+
+fn decode_chunk(decoder: &mut Decoder, chunk: &[u8], last: bool) -> Vec<u8> {
+    let capacity = decoder.max_utf8_buffer_length(chunk.len()).unwrap_or(chunk.len() * 3 + 16);
+    let mut text = String::with_capacity(capacity);
+    let _ = decoder.decode_to_string(chunk, &mut text, last);
+    text.into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn latin1() -> OutputEncoding {
+        OutputEncoding::parse("latin1").unwrap()
+    }
+
+    /// `raw` through a decoder, `size` bytes at a time.
+    fn streamed(encoding: OutputEncoding, raw: &[u8], size: usize) -> Vec<u8> {
+        let mut decoder = encoding.decoder();
+        let mut text: Vec<u8> = raw.chunks(size).flat_map(|chunk| decoder.push(chunk)).collect();
+        text.extend(decoder.finish());
+        text
+    }
+
+    #[test]
+    fn output() {
+        let cases: &[(&[u8], &str)] = &[
+            (b"plain\n", "plain\n"),
+            ("caf\u{e9} \u{4e2d}\n".as_bytes(), "caf\u{e9} \u{4e2d}\n"),
+            (b"caf\xe9\n", "caf\u{e9}\n"),
+            (b"\x80 5\n", "\u{20ac} 5\n"),
+            (b"\xff\xfeh\x00\xe9\x00\n\x00", "h\u{e9}\n"),
+            (b"\xfe\xff\x00h\x00\xe9\x00\n", "h\u{e9}\n"),
+            (b"a\xff\xfe", "a\u{ff}\u{fe}"),
+        ];
+        for (raw, text) in cases {
+            assert_eq!(latin1().decode(raw.to_vec()), text.as_bytes(), "{:?}", raw);
+            for size in 1..=raw.len() {
+                assert_eq!(streamed(latin1(), raw, size), text.as_bytes(), "{:?} in chunks of {}", raw, size);
+            }
+        }
+    }
+
+    #[test]
+    fn utf8_output_is_left_alone() {
+        for raw in [&b"caf\xe9\n"[..], b"\xff\xfeh\x00"] {
+            assert_eq!(OutputEncoding::Utf8.decode(raw.to_vec()), raw);
+            assert_eq!(streamed(OutputEncoding::Utf8, raw, 1), raw);
+        }
+    }
+
+    #[test]
+    fn files() {
+        let latin1 = FileEncoding::parse("latin1").unwrap();
+        assert_eq!(latin1.decode(b"caf\xe9").unwrap().text, "caf\u{e9}");
+        assert_eq!(latin1.encode("caf\u{e9}").as_ref(), b"caf\xe9");
+        assert!(FileEncoding::Utf8.decode(b"caf\xe9").is_err());
+        let auto = FileEncoding::Auto.decode(b"caf\xe9!").unwrap();
+        assert_eq!((auto.text.as_ref(), auto.replaced), ("caf\u{fffd}!", vec![3]));
+        assert!(FileEncoding::parse("utf-16").is_err());
+    }
+}
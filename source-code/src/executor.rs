@@ -0,0 +1,1802 @@
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::env;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Output, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+use crate::audit::AuditLog;
+use crate::backend::{self, Backend, Container, ImageError, Wsl};
+use crate::cache::{self, Cache, Lookup};
+use crate::capture::{Capture, Captured};
+use crate::encoding::OutputEncoding;
+use crate::java_batch::Compiled;
+use crate::parser::Block;
+use crate::sandbox::{self, Landlock, Limits, Network, Step, User, Wrapper};
+use crate::tools::Tools;
+use crate::usage::{self, Usage};
+use crate::{artifacts, detect, flags, gomod, java_class, jshell, languages, manifest, npm, plugin, python, rundir, signal, toolchain, versions, Options};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Passed,
+    Failed,
+    Skipped,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    /// No built-in executor or plugin handles the language.
+    Unsupported,
+    /// `--allow-lang` doesn't include the language.
+    NotAllowed,
+    /// A compiler, interpreter or the built program could not be started.
+    Spawn,
+    /// The compiler or interpreter the block needs isn't installed.
+    ToolchainMissing,
+    /// The compiler rejected the code, or the interpreter did before running
+    /// it, e.g. with a python `SyntaxError`.
+    CompileError,
+    /// The block's program exited unsuccessfully, or a step that isn't
+    /// about compiling it did.
+    NonZeroExit,
+    /// A step was killed by a signal, e.g. a segfault.
+    Signal,
+    /// Preparing the block failed, e.g. writing its source file.
+    Io,
+    /// The block ran but its stdout differs from its `|> expect:` section.
+    ExpectMismatch,
+    /// A directive parameter has a value the executor can't use.
+    InvalidParam,
+    /// The code lacks something its executor needs, e.g. a java class.
+    InvalidSource,
+    /// The container engine couldn't create or start the block's container.
+    Container,
+    /// The remote machine of `--backend ssh://` couldn't be reached.
+    Remote,
+    /// The block's container image couldn't be pulled.
+    ImagePull,
+    /// The block's third-party dependencies couldn't be resolved.
+    Dependency,
+    /// The compiler printed warnings and `--deny-warnings` was given.
+    CompilerWarnings,
+    /// The block's directory grew beyond `--max-disk` and `--deny-disk`
+    /// was given.
+    DiskUsage,
+    /// A block named in its `needs=` failed or was skipped, so it wasn't run.
+    DependencyFailed,
+    /// One of the block's `if-os`, `if-env` or `if-tool` conditions doesn't
+    /// hold, so it wasn't run.
+    ConditionUnmet,
+    /// A pattern in `artifacts=` matched no file and `--strict` was given.
+    MissingArtifact,
+    /// `--changed-only` left the block out, since git has no changes to it.
+    Unchanged,
+    /// `--replay=strict` has no recorded result for the block, which would
+    /// have to run.
+    NotRecorded,
+    /// The plugin's result file isn't what the plugin protocol allows.
+    PluginProtocol,
+}
+
+#[derive(Debug)]
+pub struct ExecError {
+    pub kind: ErrorKind,
+    pub message: String,
+    /// What the failing step printed, when it got as far as running.
+    pub output: Option<RunOutput>,
+}
+
+impl ExecError {
+    /// Starting `command` failed. A program looked up on the `PATH` that
+    /// isn't there is a missing toolchain.
+    pub fn spawn(command: &Command, e: io::Error) -> Self {
+        let program = command.get_program();
+        let on_path = Path::new(program).components().count() == 1;
+        if e.kind() == io::ErrorKind::NotFound && on_path {
+            return ExecError::toolchain_missing(format!("`{}` was not found", program.to_string_lossy()));
+        }
+        ExecError {
+            kind: ErrorKind::Spawn,
+            message: format!("{}: {}", program.to_string_lossy(), e),
+            output: None,
+        }
+    }
+
+    pub fn invalid_param(message: String) -> Self {
+        ExecError {
+            kind: ErrorKind::InvalidParam,
+            message,
+            output: None,
+        }
+    }
+
+    pub fn toolchain_missing(problem: String) -> Self {
+        ExecError {
+            kind: ErrorKind::ToolchainMissing,
+            message: format!("{}; run `translator doctor` to check the toolchains", problem),
+            output: None,
+        }
+    }
+}
+
+impl From<io::Error> for ExecError {
+    fn from(e: io::Error) -> Self {
+        ExecError {
+            kind: ErrorKind::Io,
+            message: e.to_string(),
+            output: None,
+        }
+    }
+}
+
+/// Time spent in each phase of a block. Interpreted languages only run.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Timings {
+    pub compile: Option<Duration>,
+    pub run: Option<Duration>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+/// What a finished step printed, like [`Output`] but with streams that may
+/// have been spilled to disk.
+#[derive(Debug)]
+pub struct RunOutput {
+    pub status: ExitStatus,
+    pub stdout: Captured,
+    pub stderr: Captured,
+}
+
+impl From<Output> for RunOutput {
+    fn from(output: Output) -> Self {
+        RunOutput {
+            status: output.status,
+            stdout: output.stdout.into(),
+            stderr: output.stderr.into(),
+        }
+    }
+}
+
+/// Receives the block's program output as it is produced.
+pub type OutputSink<'a> = &'a mut dyn FnMut(Stream, &[u8]);
+
+/// Looks at each line of a program's stdout before anything else does,
+/// and may rewrite it. Returns the stream the line belongs on, or `None` to
+/// drop it.
+pub type LineFilter<'a> = &'a mut dyn FnMut(&mut Vec<u8>) -> Option<Stream>;
+
+/// A step as [`record_steps`] writes it down instead of running it.
+pub struct Recorded {
+    pub step: Step,
+    pub program: OsString,
+    pub args: Vec<OsString>,
+    /// The variables the step sets, or removes when `None`.
+    pub envs: Vec<(OsString, Option<OsString>)>,
+    pub cwd: Option<PathBuf>,
+    /// What the step is given on stdin, if anything.
+    pub input: Option<Vec<u8>>,
+}
+
+impl Recorded {
+    fn of(command: &Command, step: Step, input: Option<&[u8]>) -> Self {
+        Recorded {
+            step,
+            program: command.get_program().to_os_string(),
+            args: command.get_args().map(OsString::from).collect(),
+            envs: command.get_envs().map(|(key, value)| (key.to_os_string(), value.map(OsString::from))).collect(),
+            cwd: command.get_current_dir().map(Path::to_path_buf),
+            input: input.map(<[u8]>::to_vec),
+        }
+    }
+}
+
+/// Runs the steps of a block, timing each phase. Output of the run phase is
+/// passed to the sink as it arrives; compilers are only captured.
+pub struct Phases<'a> {
+    pub timings: Timings,
+    sink: OutputSink<'a>,
+    cache: Option<&'a Cache>,
+    tools: Cow<'a, Tools>,
+    /// Where the steps run.
+    backend: Backend,
+    /// The block's compilation, when it was done ahead of time.
+    prebuilt: Option<&'a Compiled>,
+    /// What the block's parameters add to the compiler's arguments.
+    compiler_flags: Vec<String>,
+    /// The edition, release or go version the block is compiled as, when
+    /// it isn't the compiler's default.
+    language_version: Option<String>,
+    /// The version of the toolchain picked by `version=`.
+    toolchain_version: Option<String>,
+    /// What the compiler printed while succeeding.
+    compiler_warnings: String,
+    /// Whatever else outside the code its compilation depends on, such as
+    /// stamps of the files it reads, so the cache notices when it changes.
+    inputs: Vec<String>,
+    /// The hacker file's directory; paths in parameters are relative to it.
+    source_dir: &'a Path,
+    /// Where run output beyond `spill_threshold` bytes goes.
+    dir: &'a Path,
+    spill_threshold: usize,
+    /// Whether programs read translator's stdin rather than a closed one.
+    inherit_stdin: bool,
+    /// Which steps may use the network.
+    network: Network,
+    /// Who the program runs as, from `--run-as`.
+    run_as: Option<&'a User>,
+    /// What the steps may touch on the filesystem, from `--sandbox fs`.
+    landlock: Option<&'a Landlock>,
+    /// The cache entries the block's program needs, such as its build or
+    /// virtualenv, which are all of the cache `--sandbox fs` lets it read.
+    entries: Vec<PathBuf>,
+    limits: Limits,
+    /// What native steps are started through, from `--sandbox-cmd`.
+    wrapper: Option<&'a Wrapper>,
+    /// Prints a `--verbose` message.
+    log: &'a dyn Fn(fmt::Arguments),
+    /// What native steps' output is decoded from, from `--output-encoding`.
+    output_encoding: OutputEncoding,
+    /// Where every step's process is recorded, from `--audit-log`, with the
+    /// hacker file and the block it is for.
+    audit: Option<(&'a AuditLog, &'a Path, &'a Block)>,
+    /// Where the steps are written down instead of run, as if each of them
+    /// succeeded without printing anything.
+    recording: Option<&'a RefCell<Vec<Recorded>>>,
+    /// The directory the block's program ran in, once it has.
+    workdir: Option<PathBuf>,
+    /// What the block's program used, once it has run.
+    usage: Option<Usage>,
+    /// Problems the steps reported that didn't fail the block, such as a
+    /// plugin's warnings.
+    pub warnings: Vec<String>,
+}
+
+impl Phases<'_> {
+    /// Lets the block's program read `path`, a cache entry it uses, under
+    /// `--sandbox fs`.
+    pub fn uses(&mut self, path: &Path) {
+        self.entries.push(path.to_path_buf());
+    }
+
+    /// Runs `command` on the block's backend, like [`capture`]. Only steps
+    /// that fetch dependencies get a network in a container.
+    fn capture(&self, command: &mut Command, step: Step) -> Result<Output, ExecError> {
+        if let Some(steps) = self.recording {
+            steps.borrow_mut().push(Recorded::of(command, step, None));
+            return Ok(Output {
+                status: ExitStatus::default(),
+                stdout: Vec::new(),
+                stderr: Vec::new(),
+            });
+        }
+        let started = SystemTime::now();
+        let mut wrapped = self.wrap(command, step)?;
+        let spawned = wrapped.as_mut().unwrap_or(&mut *command);
+        let output = capture(spawned);
+        self.audit(spawned, step, started, output.as_ref().map(|output| output.status))?;
+        let mut output = output?;
+        let encoding = self.output_encoding();
+        output.stdout = encoding.decode(output.stdout);
+        output.stderr = encoding.decode(output.stderr);
+        // Compilers in WSL name the block's files by their Linux paths.
+        if let Backend::Wsl(wsl) = &self.backend {
+            output.stdout = wsl.to_windows(&String::from_utf8_lossy(&output.stdout)).into_bytes();
+            output.stderr = wsl.to_windows(&String::from_utf8_lossy(&output.stderr)).into_bytes();
+        }
+        self.check_backend(command, output.status.code(), &String::from_utf8_lossy(&output.stderr))?;
+        Ok(output)
+    }
+
+    /// What the steps' output is decoded from. Containers and remote
+    /// machines are taken to write UTF-8.
+    fn output_encoding(&self) -> OutputEncoding {
+        match self.backend {
+            Backend::Native => self.output_encoding,
+            _ => OutputEncoding::Utf8,
+        }
+    }
+
+    /// `command` as it runs on the backend or through `--sandbox-cmd`, or
+    /// `None` to run it as it is, confined the way `--no-network`,
+    /// `--run-as` and `--sandbox` say.
+    fn wrap(&self, command: &mut Command, step: Step) -> Result<Option<Command>, ExecError> {
+        let network = self.network.allows(step);
+        let wrapped = self.backend.wrap(command, step == Step::Fetch && network).map_err(|e| ExecError {
+            kind: ErrorKind::Remote,
+            message: e.to_string(),
+            output: None,
+        })?;
+        if wrapped.is_some() {
+            return Ok(wrapped);
+        }
+        let mut wrapped = self.wrapper.map(|wrapper| wrapper.wrap(command, self.dir, self.cache.map(Cache::dir)));
+        if let Some(wrapped) = &wrapped {
+            let mut argv = vec![wrapped.get_program().to_string_lossy().into_owned()];
+            argv.extend(wrapped.get_args().map(|arg| arg.to_string_lossy().into_owned()));
+            (self.log)(format_args!("Wrapped: {}", flags::display(&argv)));
+        }
+        // The wrapper is confined in the program's place, which it passes on.
+        let command = wrapped.as_mut().unwrap_or(command);
+        if !network {
+            sandbox::isolate_network(command)?;
+        }
+        let user = self.run_as.filter(|_| step == Step::Run);
+        if let Some(user) = user {
+            user.hand_over(self.dir)?;
+            user.apply(command);
+        }
+        if let Some(landlock) = self.landlock {
+            landlock.apply(command, step, &self.tools, self.dir, &self.entries)?;
+        }
+        self.limits.apply(command, step, user);
+        Ok(wrapped)
+    }
+
+    /// Adds `command`, which a `step` started at `started`, to the audit log
+    /// if there is one.
+    fn audit(&self, command: &Command, step: Step, started: SystemTime, outcome: Result<ExitStatus, &ExecError>) -> Result<(), ExecError> {
+        let Some((log, file, block)) = self.audit else {
+            return Ok(());
+        };
+        log.record(file, &[block], step, command, started, outcome.map_err(|e| e.message.as_str()))
+            .map_err(|e| io::Error::new(e.kind(), format!("couldn't write the audit log: {}", e)).into())
+    }
+
+    /// Tells the container engine's, ssh's or WSL's failures apart from the
+    /// step's own.
+    fn check_backend(&self, command: &Command, status: Option<i32>, stderr: &str) -> Result<(), ExecError> {
+        let container = match &self.backend {
+            Backend::Native => return Ok(()),
+            Backend::Ssh(remote) if status == Some(backend::SSH_FAILED) => {
+                return Err(ExecError {
+                    kind: ErrorKind::Remote,
+                    message: format!("ssh {}: {}", remote.destination(), stderr.trim()),
+                    output: None,
+                })
+            }
+            Backend::Ssh(remote) if backend::remote_program_missing(status, stderr) => {
+                let program = command.get_program().to_string_lossy();
+                return Err(ExecError {
+                    kind: ErrorKind::ToolchainMissing,
+                    message: format!("`{}` isn't installed on {}", program, remote.destination()),
+                    output: None,
+                });
+            }
+            Backend::Ssh(_) => return Ok(()),
+            Backend::Wsl(_) if status == Some(backend::WSL_FAILED) => {
+                return Err(ExecError {
+                    kind: ErrorKind::Remote,
+                    message: format!("{}: {}", backend::WSL, stderr.replace('\0', "").trim()),
+                    output: None,
+                })
+            }
+            Backend::Wsl(_) if backend::remote_program_missing(status, stderr) => {
+                let program = command.get_program().to_string_lossy();
+                return Err(ExecError {
+                    kind: ErrorKind::ToolchainMissing,
+                    message: format!("`{}` isn't installed in WSL", program),
+                    output: None,
+                });
+            }
+            Backend::Wsl(_) => return Ok(()),
+            Backend::Container(container) => container,
+        };
+        if backend::program_missing(status, stderr) {
+            let program = command.get_program().to_string_lossy();
+            return Err(ExecError {
+                kind: ErrorKind::ToolchainMissing,
+                message: format!("`{}` isn't in the image {}", program, container.image),
+                output: None,
+            });
+        }
+        if status == Some(backend::ENGINE_FAILED) {
+            return Err(ExecError {
+                kind: ErrorKind::Container,
+                message: format!("{} couldn't run the container: {}", container.engine, stderr.trim()),
+                output: None,
+            });
+        }
+        Ok(())
+    }
+
+    /// Runs `command` as (part of) the compile phase. What it prints when it
+    /// succeeds is kept as warnings.
+    fn compile(&mut self, command: &mut Command) -> Result<(), ExecError> {
+        let started = Instant::now();
+        let output = self.capture(command, Step::Compile);
+        *self.timings.compile.get_or_insert(Duration::ZERO) += started.elapsed();
+        let output = output?;
+        if output.status.success() {
+            self.compiler_warnings.push_str(&String::from_utf8_lossy(&output.stderr));
+            Ok(())
+        } else {
+            let mut e = failed(output.into());
+            e.kind = ErrorKind::CompileError;
+            Err(e)
+        }
+    }
+
+    /// Runs `command` as a step that fetches or installs the block's
+    /// dependencies. It counts towards the compile phase, but its failures
+    /// are [`ErrorKind::Dependency`] rather than compile errors.
+    fn resolve(&mut self, command: &mut Command) -> Result<Output, ExecError> {
+        let started = Instant::now();
+        let output = self.capture(command, Step::Fetch);
+        *self.timings.compile.get_or_insert(Duration::ZERO) += started.elapsed();
+        let output = output?;
+        if output.status.success() {
+            Ok(output)
+        } else {
+            let mut e = failed(output.into());
+            e.kind = ErrorKind::Dependency;
+            Err(e)
+        }
+    }
+
+    /// Compiles `code` with the command `compile` returns for an output
+    /// directory and the block's compiler flags, and returns the directory
+    /// holding the artifacts. With a cache, an earlier run's artifacts are
+    /// reused and the compiler isn't started at all.
+    fn build(
+        &mut self,
+        lang: &str,
+        code: &str,
+        dir: &Path,
+        compile: impl FnOnce(&Path, &[String]) -> Command,
+    ) -> Result<PathBuf, ExecError> {
+        if let Some(prebuilt) = self.prebuilt.take() {
+            self.timings.compile = Some(prebuilt.duration);
+            self.compiler_warnings.push_str(&prebuilt.warnings);
+            return match &prebuilt.error {
+                None => {
+                    self.uses(&prebuilt.dir);
+                    Ok(prebuilt.dir.clone())
+                }
+                Some(message) => Err(ExecError {
+                    kind: ErrorKind::CompileError,
+                    message: message.clone(),
+                    output: None,
+                }),
+            };
+        }
+        // A cache that can't be used, e.g. read-only, just means compiling.
+        // Without the compiler's version nothing is cached either.
+        let flags = self.compiler_flags.clone();
+        let extra: Vec<&str> = flags.iter().chain(&self.inputs).map(String::as_str).collect();
+        let version = self.backend.toolchain().or_else(|| toolchain::version(lang, &self.tools));
+        let lookup = self.cache.zip(version);
+        let lookup = lookup.and_then(|(cache, version)| cache.lookup(lang, &version, code, &extra).ok());
+        match lookup {
+            // The warnings are kept with the artifacts, so a cached build
+            // shows them as well.
+            Some(Lookup::Hit(entry)) => {
+                if let Ok(warnings) = fs::read_to_string(entry.join(WARNINGS_FILE)) {
+                    self.compiler_warnings.push_str(&warnings);
+                }
+                self.uses(&entry);
+                Ok(entry)
+            }
+            Some(Lookup::Miss(pending)) => {
+                let before = self.compiler_warnings.len();
+                self.compile(&mut compile(pending.dir(), &flags))?;
+                save_warnings(pending.dir(), &self.compiler_warnings[before..])?;
+                let entry = pending.commit()?;
+                self.uses(&entry);
+                Ok(entry)
+            }
+            None => {
+                let out = dir.join("out");
+                fs::create_dir(&out)?;
+                self.compile(&mut compile(&out, &flags))?;
+                Ok(out)
+            }
+        }
+    }
+
+    /// What to call the program built from `key`: a name of its own, even
+    /// where builds share a directory, with `.exe` on Windows.
+    fn program_name(&mut self, key: &str) -> String {
+        let name = format!("block-{}{}", &cache::hash(&[key])[..12], env::consts::EXE_SUFFIX);
+        // Cache entries from before programs had these names hold others.
+        self.inputs.push(name.clone());
+        name
+    }
+
+    /// Runs the block's program, which is the run phase.
+    pub fn run(&mut self, command: &mut Command) -> Result<RunOutput, ExecError> {
+        self.run_with_input(command, None)
+    }
+
+    /// Like [`Phases::run`], writing `input` to the program's stdin.
+    pub fn run_with_input(&mut self, command: &mut Command, input: Option<&[u8]>) -> Result<RunOutput, ExecError> {
+        self.run_filtered(command, input, None)
+    }
+
+    /// Like [`Phases::run_with_input`], passing stdout through `filter` a
+    /// line at a time.
+    fn run_filtered(
+        &mut self,
+        command: &mut Command,
+        input: Option<&[u8]>,
+        filter: Option<LineFilter>,
+    ) -> Result<RunOutput, ExecError> {
+        if let Some(steps) = self.recording {
+            steps.borrow_mut().push(Recorded::of(command, Step::Run, input));
+            return Ok(RunOutput {
+                status: ExitStatus::default(),
+                stdout: Vec::new().into(),
+                stderr: Vec::new().into(),
+            });
+        }
+        let started = Instant::now();
+        let spawned_at = SystemTime::now();
+        if matches!(self.backend, Backend::Native) {
+            self.workdir = command.get_current_dir().map(Path::to_path_buf).or_else(|| env::current_dir().ok());
+        }
+        let captures = [Stream::Stdout, Stream::Stderr].map(|stream| {
+            let name = match stream {
+                Stream::Stdout => "stdout.log",
+                Stream::Stderr => "stderr.log",
+            };
+            Capture::new(self.dir.join(name), self.spill_threshold)
+        });
+        let mut wrapped = self.wrap(command, Step::Run)?;
+        let spawned = wrapped.as_mut().unwrap_or(&mut *command);
+        // A program reading the terminal has to stay in translator's process
+        // group, which the terminal lets read it.
+        let group = matches!(self.backend, Backend::Native) && !self.inherit_stdin;
+        if group {
+            sandbox::own_group(spawned);
+        }
+        let stdin = match input {
+            Some(input) => Input::Bytes(input),
+            None if self.inherit_stdin => Input::Inherited,
+            None => Input::Closed,
+        };
+        let encoding = self.output_encoding();
+        let output = stream(spawned, stdin, group, encoding, &mut *self.sink, filter, captures);
+        *self.timings.run.get_or_insert(Duration::ZERO) += started.elapsed();
+        if let Ok((_, used)) = &output {
+            // Elsewhere the process is ssh, WSL or the container engine's
+            // client rather than the program.
+            let used = if matches!(self.backend, Backend::Native) { *used } else { Usage::default() };
+            self.usage = Some(self.usage.map_or(used, |usage| usage.and(used)));
+        }
+        self.audit(spawned, Step::Run, spawned_at, output.as_ref().map(|(output, _)| output.status))?;
+        let (output, _) = output?;
+        self.check_backend(command, output.status.code(), &output.stderr.preview())?;
+        Ok(output)
+    }
+}
+
+/// The block whose result a duplicate reuses.
+#[derive(Clone, Debug, Serialize)]
+pub struct Origin {
+    pub file: String,
+    pub index: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct ExecutionResult {
+    pub status: Status,
+    pub exit_code: Option<i32>,
+    /// The signal that killed the program, in which case there is no
+    /// exit code.
+    pub signal: Option<i32>,
+    pub duration: Duration,
+    pub timings: Timings,
+    pub stdout: Captured,
+    pub stderr: Captured,
+    pub error: Option<(ErrorKind, String)>,
+    /// Set when the block wasn't run because an identical one already was.
+    pub duplicate_of: Option<Origin>,
+    /// Arguments the block's parameters added to its compiler's.
+    pub compiler_flags: Vec<String>,
+    /// The edition, release or go version the block was compiled as.
+    pub language_version: Option<String>,
+    /// The toolchain version `version=` asked for and got.
+    pub toolchain_version: Option<String>,
+    /// What the compiler printed although it succeeded.
+    pub compiler_warnings: Option<String>,
+    /// Problems with the block that didn't stop it from running.
+    pub warnings: Vec<String>,
+    /// What the block's directory held once the block was done, in bytes.
+    pub disk_bytes: Option<u64>,
+    /// Where the files `artifacts=` matched were copied to.
+    pub artifacts: Vec<PathBuf>,
+    /// What the block's program used, with `--rusage`, when it ran.
+    pub usage: Option<Usage>,
+    /// Set when the result came from the document's recording, with
+    /// `--replay`, rather than from running the block.
+    pub replayed: bool,
+}
+
+impl ExecutionResult {
+    fn new(outcome: Result<RunOutput, ExecError>, duration: Duration, timings: Timings) -> Self {
+        let outcome = outcome.and_then(|output| {
+            if output.status.success() {
+                Ok(output)
+            } else {
+                Err(failed(output))
+            }
+        });
+        match outcome {
+            Ok(output) => ExecutionResult {
+                status: Status::Passed,
+                exit_code: output.status.code(),
+                signal: None,
+                duration,
+                timings,
+                stdout: output.stdout,
+                stderr: output.stderr,
+                error: None,
+                duplicate_of: None,
+                compiler_flags: Vec::new(),
+                language_version: None,
+                toolchain_version: None,
+                compiler_warnings: None,
+                warnings: Vec::new(),
+                disk_bytes: None,
+                artifacts: Vec::new(),
+                usage: None,
+                replayed: false,
+            },
+            Err(e) => {
+                let status = match e.kind {
+                    ErrorKind::Unsupported
+                    | ErrorKind::NotAllowed
+                    | ErrorKind::DependencyFailed
+                    | ErrorKind::ConditionUnmet
+                    | ErrorKind::Unchanged => Status::Skipped,
+                    _ => Status::Failed,
+                };
+                let (exit_code, signal, stdout, stderr) = match e.output {
+                    Some(output) => (output.status.code(), signal::of(&output.status), output.stdout, output.stderr),
+                    None => (None, None, Captured::default(), Captured::default()),
+                };
+                ExecutionResult {
+                    status,
+                    exit_code,
+                    signal,
+                    duration,
+                    timings,
+                    stdout,
+                    stderr,
+                    error: Some((e.kind, e.message)),
+                    duplicate_of: None,
+                    compiler_flags: Vec::new(),
+                    language_version: None,
+                    toolchain_version: None,
+                    compiler_warnings: None,
+                    warnings: Vec::new(),
+                    disk_bytes: None,
+                    artifacts: Vec::new(),
+                    usage: None,
+                    replayed: false,
+                }
+            }
+        }
+    }
+}
+
+impl ExecutionResult {
+    /// The result of a block that wasn't run at all, because of `error`.
+    pub fn not_run(error: ExecError) -> Self {
+        ExecutionResult::new(Err(error), Duration::ZERO, Timings::default())
+    }
+
+    /// This result, reused for an identical block. It took no time itself.
+    pub fn duplicate(&self, origin: Origin) -> Self {
+        ExecutionResult {
+            duration: Duration::ZERO,
+            timings: Timings::default(),
+            usage: None,
+            duplicate_of: Some(origin),
+            ..self.clone()
+        }
+    }
+
+    /// `cached (duplicate of block 3)`, for results of duplicates, where
+    /// blocks in other files are named with their file, and `replayed` for
+    /// results from `--replay`.
+    pub fn reuse_note(&self, file: &str) -> Option<String> {
+        if self.replayed {
+            return Some("replayed".to_string());
+        }
+        let origin = self.duplicate_of.as_ref()?;
+        Some(if origin.file == file {
+            format!("cached (duplicate of block {})", origin.index)
+        } else {
+            format!("cached (duplicate of {} block {})", origin.file, origin.index)
+        })
+    }
+}
+
+/// The file in a cache entry with what its compiler warned about.
+pub const WARNINGS_FILE: &str = ".compiler-warnings";
+
+/// Keeps `warnings` with the artifacts in `dir`, if there are any.
+pub fn save_warnings(dir: &Path, warnings: &str) -> io::Result<()> {
+    if warnings.is_empty() {
+        return Ok(());
+    }
+    fs::write(dir.join(WARNINGS_FILE), warnings)
+}
+
+/// What each built-in language's programs fail with when they read past the
+/// end of their input: python's `input()`, a java `Scanner`, rust's
+/// `read_exact` and go's `fmt.Scan` with the error turned into a panic.
+const STDIN_EOF: &[(&str, &str)] = &[
+    ("python", "EOFError"),
+    ("java", "java.util.NoSuchElementException"),
+    ("rust", "UnexpectedEof"),
+    ("go", "panic: EOF"),
+    ("go", "panic: unexpected EOF"),
+];
+
+/// Whether a `lang` block's failure looks like the program wanted input.
+fn read_past_stdin(lang: &str, message: &str) -> bool {
+    STDIN_EOF.iter().any(|&(eof_lang, eof)| eof_lang == lang && message.contains(eof))
+}
+
+/// Turns an unsuccessful step into an error carrying its stderr as message,
+/// followed by the signal that killed it if one did.
+fn failed(output: RunOutput) -> ExecError {
+    let mut message = output.stderr.preview().into_owned();
+    let kind = match signal::of(&output.status) {
+        Some(signal) => {
+            if !message.is_empty() && !message.ends_with('\n') {
+                message.push('\n');
+            }
+            message.push_str(&signal::describe(signal));
+            ErrorKind::Signal
+        }
+        None => ErrorKind::NonZeroExit,
+    };
+    if message.trim().is_empty() {
+        message = format!("process {}", output.status);
+    }
+    ExecError {
+        kind,
+        message,
+        output: Some(output),
+    }
+}
+
+/// The program `block` needs that isn't installed natively, when that means
+/// running it in WSL: on Windows, with WSL there and the block running as
+/// it is. A block that asked for a sandbox, a network of its own or a
+/// version of its toolchain fails instead.
+fn wsl_fallback(block: &Block, opts: &Options, p: &Phases) -> Option<&'static str> {
+    if !cfg!(windows) || !matches!(p.backend, Backend::Native) || !matches!(opts.backend, backend::Kind::Native) {
+        return None;
+    }
+    if opts.wrapper.is_some() || opts.landlock.is_some() || opts.network != Network::All {
+        return None;
+    }
+    if versions::requested(block).ok().flatten().is_some() {
+        return None;
+    }
+    let missing = toolchain::required(block).into_iter().find(|tool| !toolchain::installed(tool, &p.tools))?;
+    Wsl::available().then_some(missing)
+}
+
+/// Runs `command` to completion with piped stdout and stderr, and no stdin.
+fn capture(command: &mut Command) -> Result<Output, ExecError> {
+    command
+        .stdin(Stdio::null())
+        .stderr(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| ExecError::spawn(command, e))?
+        .wait_with_output()
+        .map_err(ExecError::from)
+}
+
+/// What a program reads.
+enum Input<'a> {
+    Closed,
+    /// Translator's own stdin.
+    Inherited,
+    Bytes(&'a [u8]),
+}
+
+/// Runs `command` to completion like [`capture`], handing each chunk of
+/// output, decoded from `encoding`, to `sink` as soon as it is read and
+/// collecting stdout and stderr in `captures`. With a `filter`, stdout is
+/// passed on in whole lines. With `group`, the program leads a process
+/// group, which is killed as soon as the program exits. Returns what the
+/// program used along with its output.
+fn stream(
+    command: &mut Command,
+    input: Input,
+    group: bool,
+    encoding: OutputEncoding,
+    sink: OutputSink,
+    mut filter: Option<LineFilter>,
+    captures: [Capture; 2],
+) -> Result<(RunOutput, Usage), ExecError> {
+    let (stdin, input) = match input {
+        Input::Closed => (Stdio::null(), None),
+        Input::Inherited => (Stdio::inherit(), None),
+        Input::Bytes(input) => (Stdio::piped(), Some(input)),
+    };
+    let mut child = command
+        .stdin(stdin)
+        .stderr(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| ExecError::spawn(command, e))?;
+    let group = group.then(|| sandbox::Group::new(&child));
+    // Feed stdin from a separate thread so a program that writes a lot before
+    // it finishes reading can't deadlock against us. A program is free to
+    // ignore its input and exit early; a broken pipe is not an error.
+    let writer = input.map(|input| {
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        let input = input.to_vec();
+        thread::spawn(move || {
+            let _ = stdin.write_all(&input);
+        })
+    });
+    let (tx, rx) = mpsc::channel();
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let stderr = child.stderr.take().expect("stderr is piped");
+    let readers = [
+        forward(Box::new(stdout), Stream::Stdout, tx.clone()),
+        forward(Box::new(stderr), Stream::Stderr, tx),
+    ];
+    // What the program started may hold on to its pipes, so they only
+    // close once the group is gone along with the program.
+    let waiter = thread::spawn(move || {
+        let status = usage::wait(&mut child);
+        drop(group);
+        status
+    });
+    let [mut out, mut err] = captures;
+    let mut spill_error = None;
+    let mut deliver = |stream, chunk: &[u8]| {
+        sink(stream, chunk);
+        let capture = match stream {
+            Stream::Stdout => &mut out,
+            Stream::Stderr => &mut err,
+        };
+        // Keep draining the pipes so the program isn't blocked on a full one.
+        if let Err(e) = capture.push(chunk) {
+            spill_error.get_or_insert(e);
+        }
+    };
+    let mut partial = Vec::new();
+    let mut decoders = [encoding.decoder(), encoding.decoder()];
+    let mut received = rx.into_iter();
+    // Once the pipes are closed, what the decoders still hold comes last.
+    let mut ended = [Stream::Stdout, Stream::Stderr].into_iter();
+    loop {
+        let (stream, chunk) = match received.next() {
+            Some((stream, chunk)) => (stream, decoders[stream as usize].push(&chunk)),
+            None => match ended.next() {
+                Some(stream) => (stream, decoders[stream as usize].finish()),
+                None => break,
+            },
+        };
+        if chunk.is_empty() {
+            continue;
+        }
+        match (stream, filter.as_mut()) {
+            (Stream::Stdout, Some(filter)) => {
+                partial.extend_from_slice(&chunk);
+                while let Some(end) = partial.iter().position(|&b| b == b'\n') {
+                    let mut line: Vec<u8> = partial.drain(..=end).collect();
+                    if let Some(stream) = filter(&mut line) {
+                        deliver(stream, &line);
+                    }
+                }
+            }
+            _ => deliver(stream, &chunk),
+        }
+    }
+    if let Some(filter) = filter.as_mut().filter(|_| !partial.is_empty()) {
+        if let Some(stream) = filter(&mut partial) {
+            deliver(stream, &partial);
+        }
+    }
+    let read = readers.map(|reader| reader.join().expect("reader thread doesn't panic"));
+    if let Some(writer) = writer {
+        let _ = writer.join();
+    }
+    // Reap the program before anything can fail, so that the block's
+    // directory, with the program in it, outlives it.
+    let (status, usage) = waiter.join().expect("waiter thread doesn't panic")?;
+    for result in read {
+        result?;
+    }
+    if let Some(e) = spill_error {
+        return Err(e.into());
+    }
+    let output = RunOutput {
+        status,
+        stdout: out.finish()?,
+        stderr: err.finish()?,
+    };
+    Ok((output, usage))
+}
+
+/// Reads `pipe` until it closes, sending every chunk down `tx`.
+fn forward(
+    mut pipe: Box<dyn Read + Send>,
+    stream: Stream,
+    tx: mpsc::Sender<(Stream, Vec<u8>)>,
+) -> thread::JoinHandle<io::Result<()>> {
+    thread::spawn(move || {
+        let mut buf = [0; 8192];
+        loop {
+            match pipe.read(&mut buf) {
+                Ok(0) => return Ok(()),
+                Ok(n) => {
+                    // The receiver only goes away once both pipes are done.
+                    let _ = tx.send((stream, buf[..n].to_vec()));
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+    })
+}
+
+/// Runs `block` in `dir`, a directory of its own.
+pub fn execute_code(
+    block: &Block,
+    opts: &Options,
+    source: &Path,
+    dir: &Path,
+    prebuilt: Option<&Compiled>,
+    sink: OutputSink,
+) -> ExecutionResult {
+    let started = Instant::now();
+    let log = |message: fmt::Arguments| opts.log(message);
+    let mut phases = phases(block, opts, source, dir, prebuilt, sink, &log);
+    let outcome = dispatch(block, opts, dir, &mut phases);
+    let mut result = ExecutionResult::new(outcome, started.elapsed(), phases.timings);
+    result.compiler_flags = phases.compiler_flags;
+    result.language_version = phases.language_version;
+    result.toolchain_version = phases.toolchain_version;
+    result.usage = phases.usage.filter(|_| opts.rusage);
+    result.warnings = phases.warnings;
+    if let Some((ErrorKind::NonZeroExit, message)) = &mut result.error {
+        if !opts.inherit_stdin && read_past_stdin(&block.lang, message) {
+            *message = format!("{}\n(stdin is closed; --inherit-stdin gives blocks translator's stdin)\n", message.trim_end());
+        }
+    }
+    if !phases.compiler_warnings.trim().is_empty() {
+        let warnings = phases.compiler_warnings;
+        if opts.deny_warnings && result.status == Status::Passed {
+            result.status = Status::Failed;
+            result.error = Some((
+                ErrorKind::CompilerWarnings,
+                format!("the compiler printed warnings, denied by --deny-warnings:\n{}", warnings),
+            ));
+        }
+        result.compiler_warnings = Some(warnings);
+    }
+    // What the block left behind is measured even when it failed, since
+    // a full disk is a likely reason.
+    if let Ok(bytes) = rundir::size(dir) {
+        opts.log(format_args!("Disk usage: {} in {}", megabytes(bytes), dir.display()));
+        if let Some(max) = opts.max_disk.filter(|&max| bytes > max) {
+            let problem = format!("the block's directory holds {}, over --max-disk {}", megabytes(bytes), megabytes(max));
+            if opts.deny_disk && result.status == Status::Passed {
+                result.status = Status::Failed;
+                result.error = Some((ErrorKind::DiskUsage, problem));
+            } else {
+                result.warnings.push(problem);
+            }
+        }
+        result.disk_bytes = Some(bytes);
+    }
+    if let Some(patterns) = block.params.get("artifacts") {
+        match &phases.workdir {
+            Some(workdir) => collect_artifacts(block, opts, patterns, workdir, &mut result),
+            None if !matches!(phases.backend, Backend::Native) => {
+                result.warnings.push("artifacts= only collects files from blocks run natively".to_string())
+            }
+            // The block failed before its program ran.
+            None => {}
+        }
+    }
+    result
+}
+
+/// Copies the files `patterns` match in `workdir` out to `--artifacts-dir`.
+/// Patterns that match nothing are warnings, or failures under `--strict`.
+fn collect_artifacts(block: &Block, opts: &Options, patterns: &str, workdir: &Path, result: &mut ExecutionResult) {
+    let patterns = match artifacts::patterns(patterns) {
+        Ok(patterns) => patterns,
+        Err(problem) => {
+            if result.status == Status::Passed {
+                result.status = Status::Failed;
+                result.error = Some((ErrorKind::InvalidParam, problem));
+            } else {
+                result.warnings.push(problem);
+            }
+            return;
+        }
+    };
+    let dest = opts.artifacts_dir.join(artifacts::block_dir(block));
+    match artifacts::collect(&patterns, workdir, &dest) {
+        Ok((copied, unmatched)) => {
+            opts.log(format_args!("Copied {} artifacts to {}", copied.len(), dest.display()));
+            result.artifacts = copied;
+            if unmatched.is_empty() {
+                return;
+            }
+            let problem = format!("artifacts={} matched nothing in {}", unmatched.join(","), workdir.display());
+            if opts.strict && result.status == Status::Passed {
+                result.status = Status::Failed;
+                result.error = Some((ErrorKind::MissingArtifact, problem));
+            } else {
+                result.warnings.push(problem);
+            }
+        }
+        Err(e) if result.status == Status::Passed => {
+            result.status = Status::Failed;
+            result.error = Some((ErrorKind::Io, format!("couldn't copy the artifacts to {}: {}", dest.display(), e)));
+        }
+        Err(e) => result.warnings.push(format!("couldn't copy the artifacts to {}: {}", dest.display(), e)),
+    }
+}
+
+/// The steps `block` would take, written down rather than run, for
+/// `translator export-script`. Nothing is cached, so every step is there.
+pub fn record_steps(block: &Block, opts: &Options, source: &Path, dir: &Path) -> Result<Vec<Recorded>, ExecError> {
+    let steps = RefCell::new(Vec::new());
+    let log = |message: fmt::Arguments| opts.log(message);
+    let mut sink = |_, _: &[u8]| {};
+    let mut phases = phases(block, opts, source, dir, None, &mut sink, &log);
+    phases.cache = None;
+    phases.recording = Some(&steps);
+    dispatch(block, opts, dir, &mut phases)?;
+    drop(phases);
+    Ok(steps.into_inner())
+}
+
+fn phases<'a>(
+    block: &'a Block,
+    opts: &'a Options,
+    source: &'a Path,
+    dir: &'a Path,
+    prebuilt: Option<&'a Compiled>,
+    sink: OutputSink<'a>,
+    log: &'a dyn Fn(fmt::Arguments),
+) -> Phases<'a> {
+    Phases {
+        timings: Timings::default(),
+        sink,
+        cache: opts.cache.as_ref(),
+        tools: Cow::Borrowed(&opts.tools),
+        backend: Backend::Native,
+        prebuilt,
+        compiler_flags: Vec::new(),
+        language_version: None,
+        toolchain_version: None,
+        compiler_warnings: String::new(),
+        inputs: Vec::new(),
+        source_dir: source.parent().filter(|d| !d.as_os_str().is_empty()).unwrap_or(Path::new(".")),
+        dir,
+        spill_threshold: opts.spill_threshold,
+        inherit_stdin: opts.inherit_stdin,
+        network: opts.network,
+        run_as: opts.run_as.as_ref(),
+        landlock: opts.landlock.as_ref(),
+        entries: Vec::new(),
+        limits: opts.limits,
+        wrapper: opts.wrapper.as_ref(),
+        log,
+        output_encoding: opts.output_encoding,
+        audit: opts.audit.as_ref().map(|log| (log, source, block)),
+        recording: None,
+        workdir: None,
+        usage: None,
+        warnings: Vec::new(),
+    }
+}
+
+/// `12.5 MB`, in units of 2^20 bytes.
+fn megabytes(bytes: u64) -> String {
+    format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+}
+
+fn dispatch(block: &Block, opts: &Options, dir: &Path, p: &mut Phases) -> Result<RunOutput, ExecError> {
+    // Without --strict, empty blocks are skipped before they get here.
+    if block.is_empty() {
+        return Err(ExecError {
+            kind: ErrorKind::InvalidSource,
+            message: "the block is empty".to_string(),
+            output: None,
+        });
+    }
+    // The parser names the language of the `auto` blocks it could tell.
+    if block.lang == detect::AUTO {
+        return Err(ExecError {
+            kind: ErrorKind::InvalidSource,
+            message: detect::failure(&block.code),
+            output: None,
+        });
+    }
+    let code = block.code.as_str();
+    if languages::COMPILED.contains(&block.lang.as_str()) {
+        p.compiler_flags = flags::for_block(block).map_err(|message| ExecError {
+            kind: ErrorKind::InvalidParam,
+            message,
+            output: None,
+        })?;
+        if !p.compiler_flags.is_empty() {
+            opts.log(format_args!("Compiler flags: {}", flags::display(&p.compiler_flags)));
+        }
+        // Checked along with the flags.
+        p.language_version = flags::language_version(block).ok().flatten().map(str::to_string);
+        let param = flags::VERSION_PARAMS.iter().find(|(lang, _)| *lang == block.lang);
+        if let (Some(version), Some((_, param))) = (&p.language_version, param) {
+            opts.log(format_args!("Language version: {}={}", param, version));
+        }
+    }
+    opts.log(format_args!("Block dir: {}", dir.display()));
+    let cache_dir = opts.cache.as_ref().map(Cache::dir);
+    p.backend =
+        Backend::for_block(block, &opts.backend, &opts.config.container, dir, cache_dir).map_err(|message| ExecError {
+            kind: ErrorKind::InvalidParam,
+            message,
+            output: None,
+        })?;
+    if let Some(tool) = wsl_fallback(block, opts, p) {
+        opts.log(format_args!("{} isn't installed here, so the block runs in WSL", tool));
+        p.backend = Backend::Wsl(Wsl::new(dir)?);
+    }
+    match &p.backend {
+        Backend::Native => {}
+        Backend::Container(container) => {
+            opts.log(format_args!("Running in {} image {}", container.engine, container.image));
+            ensure_image(container)?;
+        }
+        // The cache is on this machine, where the remote steps can't reach
+        // it.
+        Backend::Ssh(remote) => {
+            opts.log(format_args!("Running on {}", remote.destination()));
+            p.cache = None;
+        }
+        // Toolchains in WSL have versions of their own, which the cache
+        // can't tell from the native ones.
+        Backend::Wsl(_) => {
+            opts.log(format_args!("Running in WSL"));
+            p.cache = None;
+        }
+    }
+    if !matches!(p.backend, Backend::Native) {
+        if !languages::BUILTIN.contains(&block.lang.as_str()) {
+            return Err(ExecError {
+                kind: ErrorKind::Unsupported,
+                message: format!("{} blocks need a plugin, and plugins only run natively", block.lang),
+                output: None,
+            });
+        }
+        // Overrides name programs on this machine, not on the backend.
+        static ELSEWHERE: Tools = Tools::none();
+        p.tools = Cow::Borrowed(&ELSEWHERE);
+    }
+    let invalid = |message| ExecError {
+        kind: ErrorKind::InvalidParam,
+        message,
+        output: None,
+    };
+    if let Some(version) = versions::requested(block).map_err(invalid)? {
+        if !matches!(p.backend, Backend::Native) {
+            return Err(invalid(
+                "version= picks a toolchain on this machine; choose the image with container= instead".to_string(),
+            ));
+        }
+        let pinned = versions::locate(&block.lang, version, &opts.tools, &opts.config).map_err(|message| ExecError {
+            kind: ErrorKind::ToolchainMissing,
+            message,
+            output: None,
+        })?;
+        match &pinned.program {
+            Some(program) => opts.log(format_args!("Using {} {} from {}", block.lang, pinned.version, program.display())),
+            None => opts.log(format_args!("Using {} {}", block.lang, pinned.version)),
+        }
+        p.toolchain_version = Some(pinned.version);
+        p.tools = Cow::Owned(pinned.tools);
+    }
+    match block.lang.as_str() {
+        "rust" if manifest::cargo_mode(block) => execute_cargo(block, dir, p),
+        "rust" => execute_rust(code, dir, p),
+        "java" => execute_java(block, opts, dir, p),
+        "python" => execute_python(block, opts, dir, p),
+        "go" => execute_go(block, opts, dir, p),
+        lang => match opts.plugins.then(|| plugin::find(lang)).flatten() {
+            Some(path) => {
+                opts.log(format_args!("Using plugin {}", path.display()));
+                let node_modules = npm_packages(block, opts, dir, p)?;
+                plugin::execute(&path, block, opts, dir, node_modules.as_deref(), p)
+            }
+            None if npm::LANGUAGES.contains(&lang) => Err(ExecError {
+                kind: ErrorKind::Unsupported,
+                message: format!(
+                    "Unsupported language: {}; it runs through a plugin, such as examples/plugins/translator-exec-javascript on PATH",
+                    lang
+                ),
+                output: None,
+            }),
+            None => Err(ExecError {
+                kind: ErrorKind::Unsupported,
+                message: format!("Unsupported language: {}", lang),
+                output: None,
+            }),
+        },
+    }
+}
+
+/// Pulls the container's image if needed, reporting why it can't be.
+fn ensure_image(container: &Container) -> Result<(), ExecError> {
+    container.ensure_image().map_err(|e| match e {
+        ImageError::Engine(e) if e.kind() == io::ErrorKind::NotFound => ExecError {
+            kind: ErrorKind::ToolchainMissing,
+            message: format!("`{}` was not found; the container backend needs it", container.engine),
+            output: None,
+        },
+        ImageError::Engine(e) => ExecError {
+            kind: ErrorKind::Container,
+            message: format!("{}: {}", container.engine, e),
+            output: None,
+        },
+        ImageError::Pull(stderr) => ExecError {
+            kind: ErrorKind::ImagePull,
+            message: format!("couldn't pull {}: {}", container.image, stderr.trim()),
+            output: None,
+        },
+    })
+}
+
+fn execute_rust(code: &str, dir: &Path, p: &mut Phases) -> Result<RunOutput, ExecError> {
+    let file_path = dir.join("main.rs");
+    fs::write(&file_path, code)?;
+    let program = p.program_name(code);
+    let mut rustc = p.tools.command("rustc");
+    let out = p.build("rust", code, dir, |out, flags| {
+        rustc.args(flags).arg(&file_path).arg("-o").arg(out.join(&program));
+        rustc
+    })?;
+    p.run(&mut Command::new(out.join(&program)))
+}
+
+/// Cargo's summary lines, which add nothing to rustc's own diagnostics.
+fn cargo_chatter(line: &str) -> bool {
+    line.starts_with("error: could not compile `block`")
+        || line.starts_with("warning: `block` (bin \"block\") generated")
+}
+
+/// Builds the block as a cargo package. Packages share one target directory
+/// in the cache, so dependencies and incremental state survive between
+/// blocks and runs.
+fn execute_cargo(block: &Block, dir: &Path, p: &mut Phases) -> Result<RunOutput, ExecError> {
+    let manifest = manifest::generate(block).map_err(|message| ExecError {
+        kind: ErrorKind::InvalidSource,
+        message,
+        output: None,
+    })?;
+    fs::create_dir(dir.join("src"))?;
+    fs::write(dir.join("src").join("main.rs"), &block.code)?;
+    fs::write(dir.join("Cargo.toml"), &manifest)?;
+    if p.language_version.is_none() {
+        p.language_version = manifest::edition(&manifest);
+    }
+    let (target, lock) = match p.cache {
+        Some(cache) => (
+            cache.dir().join("cargo-target"),
+            Some(cache.lock_dir("cargo", &manifest).join("Cargo.lock")),
+        ),
+        None => (dir.join("target"), None),
+    };
+    // A lock file from an earlier block with the same manifest spares cargo
+    // the registry; a missing one just means resolving again.
+    if let Some(lock) = &lock {
+        let _ = fs::copy(lock, dir.join("Cargo.lock"));
+    }
+    // The image's cargo home isn't writable for the user the container
+    // runs as, so the registry goes with the target directory.
+    let cargo_home = match p.backend {
+        Backend::Container(_) => Some(target.with_file_name("cargo-home")),
+        Backend::Native | Backend::Ssh(_) | Backend::Wsl(_) => None,
+    };
+    let tools = p.tools.clone();
+    let cargo = || {
+        let mut cargo = tools.command("cargo");
+        cargo.current_dir(dir).env("CARGO_TARGET_DIR", &target);
+        if let Some(home) = &cargo_home {
+            cargo.env("CARGO_HOME", home);
+        }
+        cargo
+    };
+    // Fetching first keeps registry failures apart from compile errors,
+    // and is the only step with a network in a container.
+    if manifest::has_dependencies(&manifest) {
+        p.resolve(cargo().arg("fetch").arg("--quiet"))?;
+    }
+    // `cargo rustc` hands the flags to the block's crate only, so the shared
+    // dependencies don't get rebuilt for every new set of flags.
+    let mut cargo = cargo();
+    cargo.arg("rustc").arg("--quiet");
+    if !p.compiler_flags.is_empty() {
+        cargo.arg("--").args(&p.compiler_flags);
+    }
+    let before = p.compiler_warnings.len();
+    let compiled = p.compile(&mut cargo);
+    let warnings: String = p.compiler_warnings[before..]
+        .lines()
+        .filter(|line| !cargo_chatter(line))
+        .flat_map(|line| [line, "\n"])
+        .collect();
+    p.compiler_warnings.truncate(before);
+    p.compiler_warnings.push_str(&warnings);
+    if let Err(mut e) = compiled {
+        e.message = e
+            .message
+            .lines()
+            .filter(|line| !cargo_chatter(line))
+            .flat_map(|line| [line, "\n"])
+            .collect();
+        if let Some(output) = &mut e.output {
+            output.stderr = e.message.clone().into_bytes().into();
+        }
+        return Err(e);
+    }
+    if let Some(lock) = &lock {
+        if !lock.exists() {
+            let saved = lock.parent().map_or(Ok(()), fs::create_dir_all);
+            let _ = saved.and_then(|()| fs::copy(dir.join("Cargo.lock"), lock));
+        }
+    }
+    p.uses(&target.join("debug"));
+    p.run(Command::new(target.join("debug").join(manifest::PACKAGE)).current_dir(dir))
+}
+
+fn execute_java(block: &Block, opts: &Options, dir: &Path, p: &mut Phases) -> Result<RunOutput, ExecError> {
+    let code = block.code.as_str();
+    let jvm_flags = flags::jvm_flags(block).map_err(ExecError::invalid_param)?;
+    if !jvm_flags.is_empty() {
+        opts.log(format_args!("JVM flags: {}", flags::display(&jvm_flags)));
+    }
+    let runtime_env = runtime_env(block, opts)?;
+    let snippet = jshell::is_snippet(block);
+    if snippet {
+        match execute_jshell(block, &jvm_flags, &runtime_env, dir, p) {
+            Err(e) if e.kind == ErrorKind::ToolchainMissing => {
+                opts.log(format_args!("jshell isn't available; compiling the snippet with javac"));
+            }
+            result => return result,
+        }
+    }
+    let class = java_class::detect(code).map_err(|message| ExecError {
+        kind: ErrorKind::InvalidSource,
+        message: if snippet {
+            format!("{}; jshell, which runs code without a class, isn't available", message)
+        } else {
+            message
+        },
+        output: None,
+    })?;
+    let main = match block.params.get("main") {
+        Some(main) if !java_class::is_qualified_name(main) => {
+            return Err(ExecError {
+                kind: ErrorKind::InvalidParam,
+                message: format!("main={} isn't a class name like com.example.App", main),
+                output: None,
+            })
+        }
+        Some(main) => main.to_string(),
+        None => class.qualified(),
+    };
+    let classpath = java_classpath(block, p)?;
+    // javac wants each public type in a file of its own name.
+    let sources = java_class::split(code).unwrap_or_else(|| vec![(class.file_name(), code.to_string())]);
+    let mut files = Vec::new();
+    for (name, source) in sources {
+        let path = dir.join(name);
+        fs::write(&path, source)?;
+        files.push(path);
+    }
+    if let Some(classpath) = &classpath {
+        p.compiler_flags.extend(["-cp".to_string(), classpath.clone()]);
+    }
+    // Classes only run with --enable-preview if javac compiled them with it,
+    // which it only does for an explicit release.
+    if jvm_flags.iter().any(|flag| flag == "--enable-preview") && !p.compiler_flags.iter().any(|f| f == "--enable-preview") {
+        if p.language_version.is_none() {
+            let javac = toolchain::version("java", &p.tools).filter(|_| matches!(p.backend, Backend::Native));
+            let release = javac.and_then(|line| versions::number("java", &line));
+            let release = release.as_deref().and_then(|version| version.split('.').next()).ok_or_else(|| {
+                ExecError::invalid_param("jvm_flags=--enable-preview needs release= here, as javac's own release isn't known".to_string())
+            })?;
+            p.compiler_flags.extend(["--release".to_string(), release.to_string()]);
+        }
+        p.compiler_flags.push("--enable-preview".to_string());
+        opts.log(format_args!("Compiler flags: {}", flags::display(&p.compiler_flags)));
+    }
+    // With a classpath or another entry point, "class not found" needs to
+    // say where java looked.
+    let explained = |mut e: ExecError, classpath: &str| {
+        if block.params.get("classpath").is_some() || block.params.get("main").is_some() {
+            e.message = format!("{}\nclasspath: {}", e.message.trim_end(), classpath);
+        }
+        e
+    };
+    let mut javac = p.tools.command("javac");
+    let out = p
+        .build("java", code, dir, |out, flags| {
+            javac.args(flags).arg("-d").arg(out).args(&files);
+            javac
+        })
+        .map_err(|e| explained(e, classpath.as_deref().unwrap_or("")))?;
+    let run_classpath = env::join_paths([out.into_os_string()].into_iter().chain(classpath.map(Into::into)))
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let mut java = p.tools.command("java");
+    java.args(&jvm_flags).arg("-cp").arg(&run_classpath).arg(&main).envs(runtime_env);
+    let output = p.run(&mut java)?;
+    if output.status.success() {
+        Ok(output)
+    } else {
+        Err(explained(failed(output), &run_classpath.to_string_lossy()))
+    }
+}
+
+/// The block's `runtime_env=`, for the step that runs its program.
+fn runtime_env(block: &Block, opts: &Options) -> Result<Vec<(String, String)>, ExecError> {
+    let vars = flags::runtime_env(block).map_err(ExecError::invalid_param)?;
+    if !vars.is_empty() {
+        let shown: Vec<String> = vars.iter().map(|(name, value)| format!("{}={}", name, value)).collect();
+        opts.log(format_args!("Runtime env: {}", flags::display(&shown)));
+    }
+    Ok(vars)
+}
+
+/// Runs a java block as snippets in jshell. It fails when one of them
+/// doesn't compile or throws, although jshell itself carries on and exits
+/// successfully.
+fn execute_jshell(
+    block: &Block,
+    jvm_flags: &[String],
+    runtime_env: &[(String, String)],
+    dir: &Path,
+    p: &mut Phases,
+) -> Result<RunOutput, ExecError> {
+    let mut command = p.tools.command("jshell");
+    command.current_dir(dir).args(["-q", "--execution", "local"]);
+    // jshell keeps its settings with java's preferences in the home
+    // directory, which the filesystem sandbox doesn't let it write.
+    if p.landlock.is_some() {
+        command.arg(format!("-J-Djava.util.prefs.userRoot={}", dir.join("java-prefs").display()));
+    }
+    for flag in &p.compiler_flags {
+        command.arg(format!("-C{}", flag));
+    }
+    // With local execution the snippets run in jshell's own JVM, so that is
+    // the one that gets the flags; --enable-preview is one jshell has
+    // itself, for compiling too.
+    for flag in jvm_flags {
+        match flag.as_str() {
+            "--enable-preview" => command.arg(flag),
+            _ => command.arg(format!("-J{}", flag)),
+        };
+    }
+    command.envs(runtime_env.iter().map(|(name, value)| (name, value)));
+    if let Some(classpath) = java_classpath(block, p)? {
+        command.arg("--class-path").arg(classpath);
+    }
+    let mut sorted = jshell::Output::default();
+    let input = jshell::input(&block.code);
+    let output = p.run_filtered(&mut command, Some(input.as_bytes()), Some(&mut |line| sorted.route(line)))?;
+    if sorted.failed {
+        let mut e = failed(output);
+        if sorted.rejected {
+            e.kind = ErrorKind::CompileError;
+        }
+        return Err(e);
+    }
+    Ok(output)
+}
+
+/// The block's `classpath=`, with relative entries resolved against the
+/// hacker file's directory. Entries ending in `*` stand for every jar in a
+/// directory, as they do for javac and java; any other has to exist.
+fn java_classpath(block: &Block, p: &mut Phases) -> Result<Option<String>, ExecError> {
+    let Some(value) = block.params.get("classpath") else {
+        return Ok(None);
+    };
+    let invalid = |message| ExecError {
+        kind: ErrorKind::InvalidParam,
+        message,
+        output: None,
+    };
+    if !matches!(p.backend, Backend::Native) {
+        return Err(invalid("classpath= only works with the native backend".to_string()));
+    }
+    let mut entries = Vec::new();
+    for entry in env::split_paths(value).filter(|entry| !entry.as_os_str().is_empty()) {
+        let path = p.source_dir.join(&entry);
+        let wildcard = entry.file_name().is_some_and(|name| name == "*");
+        // The directory of a wildcard entry changes when jars come and go.
+        let stamped = if wildcard { path.parent().unwrap_or(&path) } else { &path };
+        let meta = fs::metadata(stamped).map_err(|e| {
+            invalid(format!("classpath entry {} ({}): {}", entry.display(), stamped.display(), e))
+        })?;
+        let modified = meta.modified().ok().and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok());
+        p.inputs.push(format!("{} {} {:?}", stamped.display(), meta.len(), modified));
+        entries.push(path);
+    }
+    let joined = env::join_paths(&entries).map_err(|e| invalid(format!("classpath={}: {}", value, e)))?;
+    Ok(Some(joined.to_string_lossy().into_owned()))
+}
+
+fn execute_python(block: &Block, opts: &Options, dir: &Path, p: &mut Phases) -> Result<RunOutput, ExecError> {
+    let interpreter = match p.backend {
+        Backend::Native => python::interpreter(&p.tools).map_err(ExecError::toolchain_missing)?,
+        Backend::Container(_) | Backend::Ssh(_) | Backend::Wsl(_) => python::Interpreter {
+            program: "python3".to_string(),
+            args: Vec::new(),
+        },
+    };
+    let requirements = python::requirements(block);
+    let mut command = if requirements.is_empty() {
+        interpreter.command()
+    } else {
+        let venv = python_venv(&interpreter, &requirements, opts, dir, p)?;
+        Command::new(python::venv_python(&venv))
+    };
+    if python::auto_print(block, opts.repl_semantics) {
+        command.arg("-c").arg(python::DRIVER);
+    } else {
+        command.arg("-c");
+    }
+    let output = p.run(command.arg(&block.code))?;
+    if !output.status.success() && python::is_syntax_error(&output.stderr.preview()) {
+        let mut e = failed(output);
+        e.kind = ErrorKind::CompileError;
+        return Err(e);
+    }
+    Ok(output)
+}
+
+/// The virtualenv with `requirements` installed. With a cache, each set of
+/// requirements gets one venv per interpreter, shared by every block and run
+/// that asks for it; without one the venv lives in the block's directory.
+fn python_venv(
+    interpreter: &python::Interpreter,
+    requirements: &[String],
+    opts: &Options,
+    dir: &Path,
+    p: &mut Phases,
+) -> Result<PathBuf, ExecError> {
+    let identity = p.capture(interpreter.command().arg("-c").arg(python::IDENTITY), Step::Compile)?;
+    let identity = String::from_utf8_lossy(&identity.stdout);
+    let mut key = vec![identity.as_ref()];
+    key.extend(requirements.iter().map(String::as_str));
+    // A cache that can't be used just means a venv for this block alone.
+    let (venv, pending) = match opts.cache.as_ref().map(|cache| cache.venv(&key)) {
+        Some(Ok(Lookup::Hit(venv))) => {
+            opts.log(format_args!("Using virtualenv {}", venv.display()));
+            p.uses(&venv);
+            return Ok(venv);
+        }
+        Some(Ok(Lookup::Miss(pending))) => (pending.dir().to_path_buf(), Some(pending)),
+        _ => (dir.join("venv"), None),
+    };
+    opts.log(format_args!("Installing {} into {}", requirements.join(" "), venv.display()));
+    p.resolve(interpreter.command().arg("-m").arg("venv").arg(&venv))?;
+    let install = p.resolve(
+        Command::new(python::venv_python(&venv))
+            .args(["-m", "pip", "install", "--disable-pip-version-check", "--no-input", "--quiet"])
+            .args(requirements),
+    )?;
+    for stream in [&install.stdout, &install.stderr] {
+        let text = String::from_utf8_lossy(stream);
+        for line in text.lines() {
+            opts.log(format_args!("pip: {}", line));
+        }
+    }
+    let venv = match pending {
+        Some(pending) => pending.commit()?,
+        None => venv,
+    };
+    p.uses(&venv);
+    Ok(venv)
+}
+
+/// The `node_modules` directory with the packages of a javascript or
+/// typescript block's `deps=` installed, or `None` if it has none. With a
+/// cache, each set of packages is installed once per package manager and
+/// node version; without one they go in the block's directory.
+fn npm_packages(block: &Block, opts: &Options, dir: &Path, p: &mut Phases) -> Result<Option<PathBuf>, ExecError> {
+    let packages = npm::packages(block);
+    if packages.is_empty() {
+        return Ok(None);
+    }
+    let manager = npm::manager();
+    let node = p.capture(p.tools.command("node").arg("--version"), Step::Compile)?;
+    let node = String::from_utf8_lossy(&node.stdout);
+    let mut key = vec![manager.as_str(), node.trim()];
+    key.extend(packages.iter().map(String::as_str));
+    let (install_dir, pending) = match opts.cache.as_ref().map(|cache| cache.node_modules(&key)) {
+        Some(Ok(Lookup::Hit(installed))) => {
+            opts.log(format_args!("Using npm packages in {}", installed.display()));
+            p.uses(&installed);
+            return Ok(Some(installed.join("node_modules")));
+        }
+        Some(Ok(Lookup::Miss(pending))) => (pending.dir().to_path_buf(), Some(pending)),
+        _ => (dir.join("deps"), None),
+    };
+    fs::create_dir_all(&install_dir)?;
+    fs::write(install_dir.join("package.json"), npm::PACKAGE_JSON)?;
+    opts.log(format_args!("Installing {} into {}", packages.join(" "), install_dir.display()));
+    let install = p.resolve(
+        p.tools
+            .command(&manager)
+            .current_dir(&install_dir)
+            .args(npm::install_args(&manager))
+            .args(&packages),
+    )?;
+    let name = Path::new(&manager).file_stem().unwrap_or_default().to_string_lossy();
+    for stream in [&install.stdout, &install.stderr] {
+        for line in String::from_utf8_lossy(stream).lines() {
+            opts.log(format_args!("{}: {}", name, line));
+        }
+    }
+    let installed = match pending {
+        Some(pending) => pending.commit()?,
+        None => install_dir,
+    };
+    p.uses(&installed);
+    Ok(Some(installed.join("node_modules")))
+}
+
+fn execute_go(block: &Block, opts: &Options, dir: &Path, p: &mut Phases) -> Result<RunOutput, ExecError> {
+    let code = block.code.as_str();
+    let goflags = flags::goflags(block).map_err(ExecError::invalid_param)?;
+    if let Some(goflags) = &goflags {
+        opts.log(format_args!("GOFLAGS: {}", goflags));
+        p.inputs.push(format!("GOFLAGS={}", goflags));
+    }
+    let runtime_env = runtime_env(block, opts)?;
+    let file_path = dir.join("main.go");
+    fs::write(&file_path, code)?;
+    // A container forgets its own caches, so without the cache they live in
+    // the block's directory.
+    let go_caches = match (p.cache, &p.backend) {
+        (Some(cache), _) => Some(cache.dir().join("go")),
+        (None, Backend::Container(_)) => Some(dir.join("go")),
+        // A remote machine, or WSL, keeps go's default caches between
+        // blocks.
+        (None, Backend::Native | Backend::Ssh(_) | Backend::Wsl(_)) => None,
+    };
+    let tools = p.tools.clone();
+    let go_mod = gomod::go_mod(block);
+    // The go.mod is generated, so go may update it while building rather
+    // than stop at `-mod=readonly`, or at a `-mod=vendor` with no vendor
+    // directory, inherited from the environment.
+    let mod_flag = go_mod.as_ref().map(|_| "-mod=mod");
+    // Every block builds in a fresh temp dir; a stable build and module
+    // cache keeps go from recompiling the standard library and downloading
+    // modules each time. Caches the user chose themselves are left alone.
+    let go = || {
+        let mut go = tools.command("go");
+        go.current_dir(dir);
+        // The block's flags come after any the environment already has.
+        if mod_flag.is_some() || goflags.is_some() {
+            let inherited = env::var("GOFLAGS").ok();
+            let all: Vec<&str> = [inherited.as_deref(), mod_flag, goflags.as_deref()].into_iter().flatten().collect();
+            go.env("GOFLAGS", all.join(" ").trim());
+        }
+        if let Some(caches) = &go_caches {
+            for (var, sub) in [("GOCACHE", "build"), ("GOMODCACHE", "mod")] {
+                if env::var_os(var).is_none() {
+                    go.env(var, caches.join(sub));
+                }
+            }
+        }
+        go
+    };
+    // A block with a module is built as a package, and its artifacts depend
+    // on the versions resolved as well as on the code.
+    let (key, target) = match &go_mod {
+        Some(go_mod) => {
+            let go_sum = resolve_go_modules(block, go_mod, dir, &go, p)?;
+            (format!("{}\n{}", code, go_sum), PathBuf::from("."))
+        }
+        None => (code.to_string(), file_path),
+    };
+    let program = p.program_name(&key);
+    let out = p.build("go", &key, dir, |out, flags| {
+        let mut go = go();
+        go.arg("build").args(flags).arg("-o").arg(out.join(&program)).arg(&target);
+        go
+    })?;
+    p.run(Command::new(out.join(&program)).envs(runtime_env))
+}
+
+/// Writes the block's `go.mod` and `go.sum`, reusing an earlier resolution
+/// of the same dependencies when the cache has one, and returns `go.sum`.
+fn resolve_go_modules(
+    block: &Block,
+    go_mod: &str,
+    dir: &Path,
+    go: &dyn Fn() -> Command,
+    p: &mut Phases,
+) -> Result<String, ExecError> {
+    let deps = gomod::deps(block);
+    let mut imports = gomod::third_party_imports(&block.code);
+    imports.sort();
+    let lock = p
+        .cache
+        .map(|cache| cache.lock_dir("go", &[go_mod, &deps.join(" "), &imports.join(" ")].join("\n")));
+    const FILES: [&str; 2] = ["go.mod", "go.sum"];
+    if let Some(lock) = &lock {
+        if FILES.iter().all(|file| fs::copy(lock.join(file), dir.join(file)).is_ok()) {
+            return Ok(fs::read_to_string(dir.join("go.sum"))?);
+        }
+    }
+    fs::write(dir.join("go.mod"), go_mod)?;
+    if !deps.is_empty() {
+        p.resolve(go().arg("get").args(&deps))?;
+    }
+    p.resolve(go().arg("mod").arg("tidy"))?;
+    // `go mod tidy` leaves no go.sum behind when nothing needed one.
+    let go_sum = fs::read_to_string(dir.join("go.sum")).unwrap_or_default();
+    if let Some(lock) = &lock {
+        let saved = fs::create_dir_all(lock).and_then(|()| {
+            fs::write(lock.join("go.sum"), &go_sum)?;
+            fs::copy(dir.join("go.mod"), lock.join("go.mod")).map(drop)
+        });
+        // Without a saved resolution the next run just resolves again.
+        let _ = saved;
+    }
+    Ok(go_sum)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reading_past_stdin() {
+        let cases = [
+            ("python", "Traceback (most recent call last):\n  File \"<string>\", line 1\nEOFError: EOF when reading a line\n", true),
+            ("java", "Exception in thread \"main\" java.util.NoSuchElementException: No line found\n", true),
+            ("rust", "called `Result::unwrap()` on an `Err` value: Error { kind: UnexpectedEof, message: \"failed to fill whole buffer\" }\n", true),
+            ("go", "panic: EOF\n\ngoroutine 1 [running]:\n", true),
+            ("go", "panic: unexpected EOF\n", true),
+            // Only the language's own message counts.
+            ("python", "panic: EOF\n", false),
+            ("rust", "EOFError\n", false),
+            ("sh", "EOFError\n", false),
+            // EOF on its own is too common to mean stdin.
+            ("python", "ValueError: unexpected EOF in the config file\n", false),
+            ("go", "parse error: EOF\n", false),
+            ("java", "Exception in thread \"main\" java.io.EOFException\n", false),
+        ];
+        for (lang, message, expected) in cases {
+            assert_eq!(read_past_stdin(lang, message), expected, "{}: {}", lang, message);
+        }
+    }
+}
@@ -0,0 +1,281 @@
+//! Comparison of a block's stdout with its `|> expect:` section, rendered as
+//! a unified diff when they differ.
+
+use std::borrow::Cow;
+
+use crate::executor::{ErrorKind, ExecutionResult, Status};
+use crate::parser::Block;
+
+/// Beyond this many edits the diff gives up on finding a minimal script and
+/// shows the whole of both sides, keeping memory bounded for huge outputs.
+const MAX_EDITS: usize = 4000;
+
+/// How line endings are compared with an expect section, from
+/// `--expect-newlines`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Newlines {
+    /// Byte for byte.
+    #[default]
+    Exact,
+    /// Newlines at the very end don't matter, so output that doesn't end in
+    /// one matches a section, which always does.
+    Trailing,
+    /// As with `Trailing`, and CRLF is the same as LF.
+    Lenient,
+}
+
+impl Newlines {
+    pub fn parse(value: &str) -> Option<Newlines> {
+        match value {
+            "exact" => Some(Newlines::Exact),
+            "trailing" => Some(Newlines::Trailing),
+            "lenient" => Some(Newlines::Lenient),
+            _ => None,
+        }
+    }
+
+    /// `text` with the differences that don't count taken out.
+    fn normalize(self, text: &[u8]) -> Cow<'_, [u8]> {
+        let mut text = Cow::Borrowed(text);
+        if self == Newlines::Lenient && text.windows(2).any(|pair| pair == b"\r\n") {
+            let mut lf = Vec::with_capacity(text.len());
+            for (i, &b) in text.iter().enumerate() {
+                if !(b == b'\r' && text.get(i + 1) == Some(&b'\n')) {
+                    lf.push(b);
+                }
+            }
+            text = Cow::Owned(lf);
+        }
+        if self != Newlines::Exact {
+            let end = text.iter().rposition(|&b| b != b'\n').map_or(0, |i| i + 1);
+            match &mut text {
+                Cow::Borrowed(bytes) => *bytes = &bytes[..end],
+                Cow::Owned(bytes) => bytes.truncate(end),
+            }
+        }
+        text
+    }
+}
+
+/// Fails `result` with a diff when the block passed but printed something
+/// other than what its expect section says, as far as `newlines` can tell.
+pub fn check(block: &Block, result: &mut ExecutionResult, context: usize, newlines: Newlines) {
+    let Some(expected) = &block.expected else {
+        return;
+    };
+    if result.status != Status::Passed {
+        return;
+    }
+    let expected_bytes = expected.text.as_bytes();
+    let matches = match newlines {
+        Newlines::Exact => result.stdout.equals(expected_bytes),
+        _ => result
+            .stdout
+            .bytes()
+            .map(|actual| newlines.normalize(&actual) == newlines.normalize(expected_bytes)),
+    };
+    match matches {
+        Ok(true) => return,
+        Ok(false) => {}
+        Err(e) => {
+            result.status = Status::Failed;
+            result.error = Some((ErrorKind::Io, format!("can't read the output back: {}", e)));
+            return;
+        }
+    }
+    // Spilled output is only diffed as far as it is in memory.
+    let actual = result.stdout.preview();
+    result.status = Status::Failed;
+    result.error = Some((
+        ErrorKind::ExpectMismatch,
+        format!(
+            "output doesn't match the expected output:\n{}",
+            unified_diff(&expected.text, &actual, context)
+        ),
+    ));
+}
+
+/// Renders `output` as the body of an expect section. The section format
+/// has no way to say "no trailing newline", so one is always added. Empty
+/// lines get what `indent` has besides whitespace, such as the `:` of an
+/// org results section.
+pub fn indent(output: &[u8], indent: &str) -> String {
+    let output = String::from_utf8_lossy(output);
+    let mut body = String::new();
+    for line in output.lines() {
+        if line.is_empty() {
+            body.push_str(indent.trim_end());
+        } else {
+            body.push_str(indent);
+            body.push_str(line);
+        }
+        body.push('\n');
+    }
+    body
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Line<'a> {
+    text: &'a str,
+    newline: bool,
+}
+
+fn split(text: &str) -> Vec<Line<'_>> {
+    text.split_inclusive('\n')
+        .map(|line| match line.strip_suffix('\n') {
+            Some(text) => Line { text, newline: true },
+            None => Line { text: line, newline: false },
+        })
+        .collect()
+}
+
+/// Edit operations with the line positions on both sides.
+#[derive(Clone, Copy)]
+enum Op {
+    Equal(usize, usize),
+    Delete(usize, usize),
+    Insert(usize, usize),
+}
+
+/// Myers' O((N+M)D) diff over lines.
+fn diff(a: &[Line], b: &[Line]) -> Vec<Op> {
+    let (n, m) = (a.len(), b.len());
+    let offset = n + m + 1;
+    let mut v = vec![0usize; 2 * offset + 1];
+    let mut trace = Vec::new();
+    let mut found = false;
+    'search: for d in 0..=(n + m).min(MAX_EDITS) {
+        // Only diagonals -d..=d can be reached with d edits, so that band is
+        // all the backtracking needs; cloning all of `v` would be O(N*D).
+        trace.push(v[offset - d..=offset + d].to_vec());
+        for k in (0..=2 * d).step_by(2) {
+            let k = offset + k - d;
+            let mut x = if k == offset - d || (k != offset + d && v[k - 1] < v[k + 1]) {
+                v[k + 1]
+            } else {
+                v[k - 1] + 1
+            };
+            let mut y = x + offset - k;
+            while x < n && y < m && a[x] == b[y] {
+                x += 1;
+                y += 1;
+            }
+            v[k] = x;
+            if x >= n && y >= m {
+                found = true;
+                break 'search;
+            }
+        }
+    }
+    if !found {
+        let mut ops: Vec<Op> = (0..n).map(|x| Op::Delete(x, 0)).collect();
+        ops.extend((0..m).map(|y| Op::Insert(n, y)));
+        return ops;
+    }
+    // Walk the trace backwards to recover the edit script.
+    let mut ops = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, band) in trace.iter().enumerate().rev() {
+        let v = |k: usize| band[k + d - offset];
+        let k = offset + x - y;
+        let prev_k = if d == 0 {
+            k
+        } else if k == offset - d || (k != offset + d && v(k - 1) < v(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = if d == 0 { 0 } else { v(prev_k) };
+        let prev_y = prev_x + offset - prev_k;
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+            ops.push(Op::Equal(x, y));
+        }
+        if d > 0 {
+            if x == prev_x {
+                ops.push(Op::Insert(prev_x, prev_y));
+            } else {
+                ops.push(Op::Delete(prev_x, prev_y));
+            }
+        }
+        x = prev_x;
+        y = prev_y;
+    }
+    ops.reverse();
+    ops
+}
+
+/// Shows whitespace that would otherwise be invisible in a changed line.
+fn visible(line: &Line) -> String {
+    let (text, cr) = match line.text.strip_suffix('\r') {
+        Some(text) => (text, true),
+        None => (line.text, false),
+    };
+    let trimmed = text.trim_end_matches([' ', '\t']);
+    let mut shown = trimmed.replace('\r', "^M");
+    for c in text[trimmed.len()..].chars() {
+        shown.push(if c == '\t' { '→' } else { '·' });
+    }
+    if cr {
+        shown.push_str("^M");
+    }
+    shown
+}
+
+/// Expected output is the `-` side, actual output the `+` side.
+pub fn unified_diff(expected: &str, actual: &str, context: usize) -> String {
+    let a = split(expected);
+    let b = split(actual);
+    let ops = diff(&a, &b);
+    let changed: Vec<usize> = ops
+        .iter()
+        .enumerate()
+        .filter(|(_, op)| !matches!(op, Op::Equal(..)))
+        .map(|(i, _)| i)
+        .collect();
+    let mut out = String::from("--- expected\n+++ actual\n");
+    let mut i = 0;
+    while i < changed.len() {
+        // Grow the hunk while the next change is within reach of its context.
+        let start = changed[i].saturating_sub(context);
+        let mut end = changed[i];
+        while i + 1 < changed.len() && changed[i + 1] <= end + 2 * context + 1 {
+            i += 1;
+            end = changed[i];
+        }
+        let end = (end + context + 1).min(ops.len());
+        i += 1;
+        let hunk = &ops[start..end];
+        let (a_start, b_start) = match hunk[0] {
+            Op::Equal(x, y) | Op::Delete(x, y) | Op::Insert(x, y) => (x, y),
+        };
+        let a_len = hunk.iter().filter(|op| !matches!(op, Op::Insert(..))).count();
+        let b_len = hunk.iter().filter(|op| !matches!(op, Op::Delete(..))).count();
+        let position = |start: usize, len: usize| match len {
+            0 => format!("{},0", start),
+            1 => format!("{}", start + 1),
+            len => format!("{},{}", start + 1, len),
+        };
+        out.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            position(a_start, a_len),
+            position(b_start, b_len)
+        ));
+        for op in hunk {
+            let (sign, line) = match *op {
+                Op::Equal(x, _) => (' ', a[x]),
+                Op::Delete(x, _) => ('-', a[x]),
+                Op::Insert(_, y) => ('+', b[y]),
+            };
+            let text = if sign == ' ' { line.text.to_string() } else { visible(&line) };
+            out.push(sign);
+            out.push_str(&text);
+            out.push('\n');
+            if !line.newline {
+                out.push_str("\\ No newline at end of output\n");
+            }
+        }
+    }
+    out
+}
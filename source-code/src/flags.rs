@@ -0,0 +1,249 @@
+//! Extra compiler arguments from a block's `flags=` and `opt=` parameters
+//! and from the language version it asks for with `edition=` (rust),
+//! `release=` (java) or `go=` (go), and what `jvm_flags=`, `goflags=` and
+//! `runtime_env=` add to the other steps.
+
+use crate::manifest;
+use crate::parser::Block;
+
+const RUST_EDITIONS: &[&str] = &["2015", "2018", "2021", "2024"];
+const RUST_OPT_LEVELS: &[&str] = &["0", "1", "2", "3", "s", "z"];
+/// The oldest release `javac --release` has ever taken.
+const JAVA_OLDEST_RELEASE: u32 = 8;
+
+/// Options that would move the build output away from where the executors
+/// look for it, by language. An option listed here is refused both on its own
+/// and with its value attached (`-o=x`).
+const OUTPUT_OPTIONS: &[(&str, &[&str])] = &[
+    ("rust", &["-o", "--out-dir", "--emit"]),
+    ("go", &["-o", "--o"]),
+    ("java", &["-d", "-s", "-h"]),
+];
+
+/// The arguments a compiled block adds to its compiler's command line, in
+/// order. Fails with a message for values the parameters can't take.
+pub fn for_block(block: &Block) -> Result<Vec<String>, String> {
+    let mut args = Vec::new();
+    if block.lang == "rust" {
+        if let Some(opt) = block.params.get("opt") {
+            if !RUST_OPT_LEVELS.contains(&opt) {
+                return Err(format!("opt={} isn't an optimization level; use 0-3, s or z", opt));
+            }
+            args.push("-C".to_string());
+            args.push(format!("opt-level={}", opt));
+        }
+    }
+    match (block.lang.as_str(), language_version(block)?) {
+        // Cargo mode sets the edition in the manifest instead.
+        ("rust", Some(edition)) if !manifest::cargo_mode(block) => {
+            args.push("--edition".to_string());
+            args.push(edition.to_string());
+        }
+        ("java", Some(release)) => {
+            args.push("--release".to_string());
+            args.push(release.to_string());
+        }
+        ("go", Some(version)) => args.push(format!("-gcflags=-lang=go{}", version)),
+        _ => {}
+    }
+    if let Some(flags) = block.params.get("flags") {
+        let flags = split(flags)?;
+        let forbidden = OUTPUT_OPTIONS
+            .iter()
+            .find(|(lang, _)| *lang == block.lang)
+            .map_or(&[][..], |(_, options)| options);
+        for flag in &flags {
+            // rustc also takes the short form `-oFILE`.
+            let short = block.lang == "rust" && flag.starts_with("-o");
+            if let Some(option) = forbidden.iter().find(|option| short && **option == "-o" || sets_option(flag, option)) {
+                return Err(format!(
+                    "flags can't include {}: translator decides where the build output goes",
+                    option
+                ));
+            }
+        }
+        args.extend(flags);
+    }
+    Ok(args)
+}
+
+/// Options java gets from the executor, which decides where the classes
+/// are and which one runs.
+const JVM_OPTIONS: &[&str] = &["-cp", "-classpath", "--class-path", "-jar", "-m", "--module"];
+
+/// The JVM options of a java block's `jvm_flags="-Xmx64m --enable-preview"`,
+/// which go between `java` and the class it runs.
+pub fn jvm_flags(block: &Block) -> Result<Vec<String>, String> {
+    let Some(flags) = block.params.get("jvm_flags") else {
+        return Ok(Vec::new());
+    };
+    let flags = split(flags)?;
+    if let Some(option) = JVM_OPTIONS.iter().find(|option| flags.iter().any(|flag| sets_option(flag, option))) {
+        return Err(format!(
+            "jvm_flags can't include {}: translator decides the classpath and the class to run; use classpath= or main=",
+            option
+        ));
+    }
+    Ok(flags)
+}
+
+/// The value of `GOFLAGS` for a go block's `goflags="-race -tags=demo"`,
+/// which applies to every go command the block runs.
+pub fn goflags(block: &Block) -> Result<Option<String>, String> {
+    let Some(flags) = block.params.get("goflags") else {
+        return Ok(None);
+    };
+    let flags = split(flags)?;
+    let forbidden = OUTPUT_OPTIONS.iter().find(|(lang, _)| *lang == "go").map_or(&[][..], |(_, options)| options);
+    if let Some(option) = forbidden.iter().find(|option| flags.iter().any(|flag| sets_option(flag, option))) {
+        return Err(format!("goflags can't include {}: translator decides where the build output goes", option));
+    }
+    // GOFLAGS is split on spaces, with no quoting.
+    if let Some(flag) = flags.iter().find(|flag| flag.contains(char::is_whitespace)) {
+        return Err(format!("goflags can't include `{}`: GOFLAGS has no way to quote a space", flag));
+    }
+    Ok(Some(flags.join(" ")))
+}
+
+/// The variables a block's `runtime_env="GODEBUG=gctrace=1 GOMAXPROCS=2"`
+/// sets for its program, but not for its compiler.
+pub fn runtime_env(block: &Block) -> Result<Vec<(String, String)>, String> {
+    let Some(vars) = block.params.get("runtime_env") else {
+        return Ok(Vec::new());
+    };
+    split(vars)?
+        .into_iter()
+        .map(|var| match var.split_once('=') {
+            Some((name, value)) if !name.is_empty() && !name.contains(char::is_whitespace) => {
+                Ok((name.to_string(), value.to_string()))
+            }
+            _ => Err(format!("runtime_env entry `{}` isn't NAME=VALUE", var)),
+        })
+        .collect()
+}
+
+/// The parameter that sets the language version, by language.
+pub const VERSION_PARAMS: &[(&str, &str)] = &[("rust", "edition"), ("java", "release"), ("go", "go")];
+
+/// The language version the block asks for: rust's `edition=`, java's
+/// `release=` or go's `go=`, checked against the values the compiler takes.
+pub fn language_version(block: &Block) -> Result<Option<&str>, String> {
+    match block.lang.as_str() {
+        "rust" => rust_edition(block),
+        "java" => match block.params.get("release") {
+            Some(release) if !release.parse::<u32>().is_ok_and(|n| n >= JAVA_OLDEST_RELEASE) => Err(format!(
+                "release={} isn't a java release; use {} or later, e.g. 17 or 21",
+                release, JAVA_OLDEST_RELEASE
+            )),
+            release => Ok(release),
+        },
+        "go" => match block.params.get("go") {
+            Some(version) if !is_go_version(version) => Err(format!(
+                "go={} isn't a go language version; use 1.N, e.g. 1.21",
+                version
+            )),
+            version => Ok(version),
+        },
+        _ => Ok(None),
+    }
+}
+
+fn is_go_version(version: &str) -> bool {
+    version
+        .strip_prefix("1.")
+        .is_some_and(|minor| !minor.is_empty() && minor.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// The block's `edition=`, checked against the editions rustc knows.
+pub fn rust_edition(block: &Block) -> Result<Option<&str>, String> {
+    match block.params.get("edition") {
+        Some(edition) if !RUST_EDITIONS.contains(&edition) => Err(format!(
+            "edition={} isn't a rust edition; use one of {}",
+            edition,
+            RUST_EDITIONS.join(", ")
+        )),
+        edition => Ok(edition),
+    }
+}
+
+fn sets_option(flag: &str, option: &str) -> bool {
+    flag.strip_prefix(option)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('='))
+}
+
+/// Splits `flags` into arguments the way a POSIX shell would, minus every
+/// kind of expansion: whitespace separates, quotes group and backslashes
+/// escape. Redirections, pipes and substitutions are refused rather than
+/// passed on, since there is no shell to give them their meaning.
+pub fn split(flags: &str) -> Result<Vec<String>, String> {
+    let mut args = Vec::new();
+    let mut current: Option<String> = None;
+    let mut chars = flags.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => args.extend(current.take()),
+            '\'' => {
+                let word = current.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err("unterminated ' in flags".to_string()),
+                    }
+                }
+            }
+            '"' => {
+                let word = current.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\')) => word.push(c),
+                            Some(c) => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                            None => return Err("unterminated \" in flags".to_string()),
+                        },
+                        Some(c @ ('$' | '`')) => return Err(unsupported(c)),
+                        Some(c) => word.push(c),
+                        None => return Err("unterminated \" in flags".to_string()),
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                Some(c) => current.get_or_insert_with(String::new).push(c),
+                None => return Err("flags end in a lone \\".to_string()),
+            },
+            '>' | '<' | '|' | ';' | '&' | '$' | '`' | '(' | ')' => return Err(unsupported(c)),
+            c => current.get_or_insert_with(String::new).push(c),
+        }
+    }
+    args.extend(current);
+    Ok(args)
+}
+
+fn unsupported(c: char) -> String {
+    format!(
+        "flags can't use the shell syntax `{}`: they are passed to the compiler directly; put it in single quotes to pass it literally",
+        c
+    )
+}
+
+/// `args` as they could be typed into a shell, for logs.
+pub fn display(args: &[String]) -> String {
+    args.iter()
+        .map(|arg| {
+            let plain = !arg.is_empty()
+                && arg
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "-_=.,:/+@%".contains(c));
+            if plain {
+                arg.clone()
+            } else {
+                format!("'{}'", arg.replace('\'', "'\\''"))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
@@ -0,0 +1,79 @@
+//! Modules for go blocks that import packages from outside the standard
+//! library. Such a block can't be built on its own, so it gets a `go.mod`:
+//! the modules listed in a `deps="github.com/google/uuid@v1.6.0"` parameter
+//! are fetched with `go get`, and `go mod tidy` resolves the rest from the
+//! imports.
+
+use crate::parser::Block;
+
+/// The module path every block is built as.
+pub const MODULE: &str = "block";
+
+/// The block's `go.mod` before resolution, or `None` when it only imports
+/// the standard library and builds without one.
+pub fn go_mod(block: &Block) -> Option<String> {
+    let needed = block.params.get("deps").is_some() || !third_party_imports(&block.code).is_empty();
+    needed.then(|| format!("module {}\n", MODULE))
+}
+
+/// The `module@version` arguments for `go get`, from `deps=`. A module
+/// without a version gets the latest.
+pub fn deps(block: &Block) -> Vec<String> {
+    let Some(deps) = block.params.get("deps") else {
+        return Vec::new();
+    };
+    deps.split(',')
+        .map(str::trim)
+        .filter(|dep| !dep.is_empty())
+        .map(|dep| if dep.contains('@') { dep.to_string() } else { format!("{}@latest", dep) })
+        .collect()
+}
+
+/// The imports that come from modules rather than the standard library.
+pub fn third_party_imports(code: &str) -> Vec<String> {
+    imports(code).into_iter().filter(|path| third_party(path)).collect()
+}
+
+/// Standard library paths never have a dot in their first element; module
+/// paths start with a domain.
+fn third_party(path: &str) -> bool {
+    path.split('/').next().is_some_and(|first| first.contains('.'))
+}
+
+/// The paths the code imports, from both `import "fmt"` and the
+/// parenthesised form, aliases and all.
+fn imports(code: &str) -> Vec<String> {
+    let mut paths = Vec::new();
+    let mut grouped = false;
+    for line in code.lines() {
+        let line = line.split("//").next().unwrap_or("").trim();
+        if grouped {
+            if line.starts_with(')') {
+                grouped = false;
+            } else {
+                paths.extend(quoted(line));
+            }
+        } else if let Some(rest) = line.strip_prefix("import") {
+            let rest = rest.trim_start();
+            if let Some(group) = rest.strip_prefix('(') {
+                grouped = !group.contains(')');
+                paths.extend(quoted(group));
+            } else {
+                paths.extend(quoted(rest));
+            }
+        } else if line.starts_with("func ") || line.starts_with("type ") || line.starts_with("var ") {
+            // Imports all come before the first declaration.
+            break;
+        }
+    }
+    paths
+}
+
+/// The import path in a spec like `u "github.com/google/uuid"`.
+fn quoted(spec: &str) -> Option<String> {
+    let start = spec.find(['"', '`'])?;
+    let quote = spec[start..].chars().next()?;
+    let rest = &spec[start + 1..];
+    let end = rest.find(quote)?;
+    Some(rest[..end].to_string())
+}
@@ -0,0 +1,162 @@
+//! Jupyter notebooks as input. The code cells of a `.ipynb` file are its
+//! blocks, in cell order, in the language of the notebook's kernel; a cell
+//! whose first line is a `%%translator rust` magic is in that language
+//! instead, for notebooks that mix them. Markdown and raw cells are skipped.
+//! Cells are numbered from 1, and a block's "lines" are its cell's number,
+//! so messages point at `demo.ipynb:3` for the third cell.
+//!
+//! With `--inline-output`, each cell's result goes into its `outputs`, the
+//! way Jupyter stores them, so the notebook still opens there.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::executor::{ExecutionResult, Status};
+use crate::languages;
+use crate::parser::{self, Block, Span};
+
+/// The magic that picks a cell's language, with parameters like a
+/// directive's: `%%translator rust(name=demo)`.
+const MAGIC: &str = "%%translator";
+
+/// What a cell's outputs are replaced with, by cell number.
+pub type Outputs = Vec<(usize, Vec<Value>)>;
+
+/// The blocks of the notebook at `path`. Fails on anything that isn't a
+/// notebook, naming the cell at fault.
+pub fn blocks(path: &Path) -> io::Result<Vec<Block>> {
+    let notebook = read(path)?;
+    let kernel = notebook["metadata"]["kernelspec"]["language"]
+        .as_str()
+        .or(notebook["metadata"]["language_info"]["name"].as_str())
+        .map(languages::canonical);
+    let mut blocks = Vec::new();
+    for (number, cell) in cells(&notebook)?.iter().enumerate().map(|(i, cell)| (i + 1, cell)) {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, format!("cell {}: {}", number, message));
+        let kind = cell["cell_type"].as_str().ok_or_else(|| invalid("it has no cell_type".to_string()))?;
+        if kind != "code" {
+            continue;
+        }
+        let source = source(&cell["source"]).ok_or_else(|| invalid("its source isn't text".to_string()))?;
+        let (first, rest) = source.split_once('\n').unwrap_or((&source, ""));
+        let magic = first.trim().strip_prefix(MAGIC).filter(|spec| spec.is_empty() || spec.starts_with(char::is_whitespace));
+        let (lang, params, code) = match magic {
+            Some(spec) => {
+                let (lang, params) = parser::header(spec).map_err(|e| invalid(format!("{}: {}", MAGIC, e)))?;
+                (lang, params, rest)
+            }
+            None => match &kernel {
+                Some(lang) => (lang.clone(), Default::default(), source.as_str()),
+                None => {
+                    return Err(invalid(format!(
+                        "the notebook doesn't say what language it is in; start the cell with `{} LANG`",
+                        MAGIC
+                    )))
+                }
+            },
+        };
+        let mut code = code.to_string();
+        if !code.is_empty() && !code.ends_with('\n') {
+            code.push('\n');
+        }
+        blocks.push(Block {
+            index: blocks.len() + 1,
+            lang,
+            params,
+            code,
+            span: Span {
+                start_line: number,
+                end_line: number,
+                start: 0,
+                end: 0,
+            },
+            indent: String::new(),
+            crlf: false,
+            expected: None,
+            recorded: None,
+        });
+    }
+    Ok(blocks)
+}
+
+/// The outputs Jupyter would show for `result`: what the block printed, and
+/// why it failed. Skipped blocks keep the outputs they had.
+pub fn outputs(result: &ExecutionResult) -> Option<Vec<Value>> {
+    if result.status == Status::Skipped {
+        return None;
+    }
+    let error = result.error.as_ref().filter(|_| result.status == Status::Failed);
+    let mut outputs = Vec::new();
+    for (name, captured) in [("stdout", &result.stdout), ("stderr", &result.stderr)] {
+        let text = String::from_utf8_lossy(&captured.bytes().ok()?).into_owned();
+        // A runtime error's message is often the stderr itself.
+        let repeated = error.is_some_and(|(_, message)| message.trim() == text.trim());
+        if !text.is_empty() && !repeated {
+            outputs.push(json!({"output_type": "stream", "name": name, "text": lines(&text)}));
+        }
+    }
+    if let Some((kind, message)) = error {
+        outputs.push(json!({
+            "output_type": "error",
+            "ename": kind,
+            "evalue": message.lines().next().unwrap_or_default(),
+            "traceback": message.lines().collect::<Vec<_>>(),
+        }));
+    }
+    Some(outputs)
+}
+
+/// Writes the notebook at `source` to `dest` with the cells in `outputs`
+/// replaced and numbered in the order they ran. Like Jupyter, it indents by
+/// one space and sorts keys.
+pub fn write(source: &Path, dest: &Path, outputs: Outputs) -> io::Result<()> {
+    let mut notebook = read(source)?;
+    let cells = notebook["cells"].as_array_mut().expect("read checked the cells");
+    for (count, (number, outputs)) in outputs.into_iter().enumerate() {
+        let cell = &mut cells[number - 1];
+        cell["outputs"] = Value::Array(outputs);
+        cell["execution_count"] = json!(count + 1);
+    }
+    let mut text = Vec::new();
+    let mut serializer = serde_json::Serializer::with_formatter(&mut text, serde_json::ser::PrettyFormatter::with_indent(b" "));
+    notebook.serialize(&mut serializer).map_err(io::Error::other)?;
+    text.push(b'\n');
+    let dir = dest.parent().filter(|d| !d.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let mut temp = tempfile::NamedTempFile::new_in(dir)?;
+    temp.write_all(&text)?;
+    fs::set_permissions(temp.path(), fs::metadata(source)?.permissions())?;
+    temp.persist(dest).map_err(|e| e.error)?;
+    Ok(())
+}
+
+fn read(path: &Path) -> io::Result<Value> {
+    let notebook: Value = serde_json::from_slice(&fs::read(path)?)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("not a notebook: {}", e)))?;
+    cells(&notebook)?;
+    Ok(notebook)
+}
+
+fn cells(notebook: &Value) -> io::Result<&Vec<Value>> {
+    notebook["cells"].as_array().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "not a notebook: it has no list of cells (nbformat 4)")
+    })
+}
+
+/// A cell's source, which notebooks store as one string or a list of lines.
+fn source(value: &Value) -> Option<String> {
+    match value {
+        Value::String(text) => Some(text.clone()),
+        Value::Array(lines) => lines.iter().map(Value::as_str).collect(),
+        _ => None,
+    }
+}
+
+/// `text` as a list of lines, each keeping its newline, as notebooks store
+/// multi-line strings.
+fn lines(text: &str) -> Vec<&str> {
+    text.split_inclusive('\n').collect()
+}
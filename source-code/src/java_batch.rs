@@ -0,0 +1,177 @@
+//! Compiling a file's java blocks together to pay for javac's JVM once.
+//!
+//! Blocks tend to declare classes of the same name, so they can't share one
+//! javac invocation. Instead a small helper, started with java's source
+//! launcher, runs the system compiler once per block in a single JVM, each
+//! into its own output directory. A block that fails to compile only fails itself.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::{Duration, SystemTime};
+
+use crate::cache::Lookup;
+use crate::sandbox::Step;
+use crate::{backend, executor, flags, java_class, jshell, toolchain};
+use crate::parser::Block;
+use crate::Options;
+
+const HELPER: &str = r#"
+import java.io.ByteArrayOutputStream;
+import java.nio.file.Files;
+import java.nio.file.Paths;
+import javax.tools.JavaCompiler;
+import javax.tools.ToolProvider;
+
+public class Batch {
+    public static void main(String[] args) throws Exception {
+        JavaCompiler javac = ToolProvider.getSystemJavaCompiler();
+        for (int i = 0; i + 1 < args.length; i += 2) {
+            ByteArrayOutputStream diagnostics = new ByteArrayOutputStream();
+            long started = System.nanoTime();
+            int status = javac.run(null, diagnostics, diagnostics, "-d", args[i + 1], args[i]);
+            long elapsed = System.nanoTime() - started;
+            Files.write(Paths.get(args[i] + ".log"), diagnostics.toByteArray());
+            System.out.println(status + " " + elapsed);
+        }
+    }
+}
+"#;
+
+/// A block's compilation done by the batch.
+pub struct Compiled {
+    /// Where the class files are, when it succeeded.
+    pub dir: PathBuf,
+    /// javac's diagnostics when it failed.
+    pub error: Option<String>,
+    /// javac's diagnostics when it succeeded.
+    pub warnings: String,
+    pub duration: Duration,
+}
+
+pub struct Batch {
+    compiled: HashMap<usize, Compiled>,
+}
+
+impl Batch {
+    pub fn get(&self, block: &Block) -> Option<&Compiled> {
+        self.compiled.get(&block.index)
+    }
+}
+
+struct Job {
+    index: usize,
+    source: PathBuf,
+    out: PathBuf,
+    pending: Option<crate::cache::Pending>,
+}
+
+/// Compiles the java blocks among `blocks` that aren't cached yet. Returns
+/// `None` when there aren't at least two of them, or when the helper can't
+/// run at all; those blocks are then compiled one by one as usual.
+/// The sources and, without a cache, the class files go in `file_dir`.
+pub fn compile(file: &Path, blocks: &[Block], opts: &Options, file_dir: &Path) -> Option<Batch> {
+    // The helper runs on this machine, not in a container, nor through
+    // --sandbox-cmd.
+    if !matches!(opts.backend, backend::Kind::Native) || opts.wrapper.is_some() {
+        return None;
+    }
+    let dir = file_dir.join("java-batch");
+    let mut jobs = Vec::new();
+    // Blocks with their own javac arguments, a classpath or several files
+    // are compiled on their own, and snippets aren't compiled at all.
+    let plain = |b: &&Block| {
+        b.lang == "java"
+            && !jshell::is_snippet(b)
+            && b.params.get("container").is_none()
+            && b.params.get("classpath").is_none()
+            && b.params.get("version").is_none()
+            && flags::jvm_flags(b).is_ok_and(|flags| !flags.iter().any(|flag| flag == "--enable-preview"))
+            && flags::for_block(b).is_ok_and(|args| args.is_empty())
+            && java_class::split(&b.code).is_none()
+    };
+    for block in blocks.iter().filter(plain) {
+        // Blocks without a class are left for the executor to report.
+        let Ok(class) = java_class::detect(&block.code) else {
+            continue;
+        };
+        let lookup = opts.cache.as_ref().zip(toolchain::version("java", &opts.tools));
+        let lookup = lookup.and_then(|(cache, version)| cache.lookup("java", &version, &block.code, &[]).ok());
+        let (out, pending) = match lookup {
+            Some(Lookup::Hit(_)) => continue,
+            Some(Lookup::Miss(pending)) => (pending.dir().to_path_buf(), Some(pending)),
+            None => (dir.join(format!("out-{}", block.index)), None),
+        };
+        let source_dir = dir.join(format!("block-{}", block.index));
+        fs::create_dir_all(&source_dir).ok()?;
+        fs::create_dir_all(&out).ok()?;
+        let source = source_dir.join(class.file_name());
+        fs::write(&source, &block.code).ok()?;
+        jobs.push(Job {
+            index: block.index,
+            source,
+            out,
+            pending,
+        });
+    }
+    if jobs.len() < 2 {
+        return None;
+    }
+    let helper = dir.join("Batch.java");
+    fs::write(&helper, HELPER).ok()?;
+    let mut command = opts.tools.command("java");
+    command.arg(&helper);
+    for job in &jobs {
+        command.arg(&job.source).arg(&job.out);
+    }
+    let started = SystemTime::now();
+    let output = command.stdin(Stdio::null()).stderr(Stdio::null()).output();
+    if let Some(log) = &opts.audit {
+        let batched: Vec<&Block> = blocks.iter().filter(|b| jobs.iter().any(|job| job.index == b.index)).collect();
+        let outcome = output.as_ref().map(|output| output.status).map_err(|e| e.to_string());
+        // Blocks left to compile on their own run into the same problem.
+        log.record(file, &batched, Step::Compile, &command, started, outcome.as_ref().copied().map_err(String::as_str)).ok()?;
+    }
+    let output = output.ok()?;
+    let statuses: Vec<(i32, u64)> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let (status, nanos) = line.split_once(' ')?;
+            Some((status.parse().ok()?, nanos.parse().ok()?))
+        })
+        .collect();
+    if statuses.len() != jobs.len() {
+        return None;
+    }
+    opts.log(format_args!("Compiled {} java blocks in one JVM", jobs.len()));
+    let mut compiled = HashMap::new();
+    for (job, (status, nanos)) in jobs.into_iter().zip(statuses) {
+        let mut log = job.source.into_os_string();
+        log.push(".log");
+        let diagnostics = fs::read(log).map(|raw| opts.output_encoding.decode(raw)).unwrap_or_default();
+        let diagnostics = String::from_utf8_lossy(&diagnostics).into_owned();
+        let (dir, error, warnings) = if status != 0 {
+            (job.out, Some(diagnostics), String::new())
+        } else {
+            let committed = job
+                .pending
+                .map(|pending| executor::save_warnings(pending.dir(), &diagnostics).and_then(|()| pending.commit()));
+            match committed {
+                Some(Ok(entry)) => (entry, None, diagnostics),
+                Some(Err(e)) => (job.out, Some(e.to_string()), String::new()),
+                None => (job.out, None, diagnostics),
+            }
+        };
+        compiled.insert(
+            job.index,
+            Compiled {
+                dir,
+                error,
+                warnings,
+                duration: Duration::from_nanos(nanos),
+            },
+        );
+    }
+    Some(Batch { compiled })
+}
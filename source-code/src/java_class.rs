@@ -0,0 +1,276 @@
+//! Working out which class a java block runs. javac insists that a public
+//! class lives in a file of the same name, and `java` needs the class's full
+//! name, package included, so neither can just be `Main`.
+
+/// The class a java block is compiled as and run with.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MainClass {
+    pub package: Option<String>,
+    pub name: String,
+}
+
+impl MainClass {
+    /// Where javac wants the source: the class name plus `.java`. The
+    /// package only matters for the class files, which `-d` lays out.
+    pub fn file_name(&self) -> String {
+        format!("{}.java", self.name)
+    }
+
+    /// The name `java` runs, e.g. `demo.app.Main`.
+    pub fn qualified(&self) -> String {
+        match &self.package {
+            Some(package) => format!("{}.{}", package, self.name),
+            None => self.name.clone(),
+        }
+    }
+}
+
+const TYPE_KEYWORDS: &[&str] = &["class", "interface", "enum", "record"];
+const MODIFIERS: &[&str] = &["public", "final", "abstract", "sealed", "non-sealed", "strictfp", "static"];
+
+/// A top-level type declaration: its name, whether it is public, and the
+/// index of its keyword among the top-level tokens.
+struct Declaration<'a> {
+    name: &'a str,
+    public: bool,
+    keyword: usize,
+}
+
+fn declarations<'a>(tokens: &[(usize, &'a str)]) -> Vec<Declaration<'a>> {
+    let mut found = Vec::new();
+    for (i, &(_, token)) in tokens.iter().enumerate() {
+        if !TYPE_KEYWORDS.contains(&token) {
+            continue;
+        }
+        let Some(&(_, name)) = tokens.get(i + 1).filter(|(_, name)| is_identifier(name)) else {
+            continue;
+        };
+        // `@interface` declares an annotation; `record` is only a keyword
+        // when a component list follows the name.
+        let previous = i.checked_sub(1).map(|p| tokens[p].1);
+        if previous == Some("@") || previous == Some(".") {
+            continue;
+        }
+        if token == "record" && !matches!(tokens.get(i + 2), Some((_, "(")) | Some((_, "<"))) {
+            continue;
+        }
+        let public = tokens[..i]
+            .iter()
+            .rev()
+            .take_while(|(_, token)| MODIFIERS.contains(token))
+            .any(|(_, token)| *token == "public");
+        found.push(Declaration { name, public, keyword: i });
+    }
+    found
+}
+
+/// Finds the block's public top-level class, interface, enum or record, or
+/// failing that the first top-level one of any visibility.
+pub fn detect(code: &str) -> Result<MainClass, String> {
+    let tokens = top_level_tokens(code);
+    let declarations = declarations(&tokens);
+    let before_types = declarations.first().map_or(tokens.len(), |first| first.keyword);
+    let package = tokens[..before_types]
+        .iter()
+        .position(|(_, token)| *token == "package")
+        .map(|i| {
+            tokens[i + 1..]
+                .iter()
+                .map(|(_, token)| *token)
+                .take_while(|token| *token != ";")
+                .collect::<String>()
+        })
+        .filter(|name| !name.is_empty());
+    let main = declarations.iter().find(|d| d.public).or(declarations.first());
+    match main {
+        Some(declaration) => Ok(MainClass {
+            package,
+            name: declaration.name.to_string(),
+        }),
+        None => Err("the java block doesn't declare a class; put the code in a class \
+                     with a `public static void main(String[] args)` method"
+            .to_string()),
+    }
+}
+
+/// The sources javac is given for a block that declares several top-level
+/// types, one file per type, named after it and each with the block's
+/// package and imports. `None` when there is only one type, and the block
+/// compiles as a single file.
+pub fn split(code: &str) -> Option<Vec<(String, String)>> {
+    let tokens = top_level_tokens(code);
+    let declarations = declarations(&tokens);
+    if declarations.len() < 2 {
+        return None;
+    }
+    // A declaration starts after the `;` or `}` that ends whatever comes
+    // before it, so its modifiers, annotations and comments go with it.
+    let starts: Vec<usize> = declarations
+        .iter()
+        .map(|d| {
+            tokens[..d.keyword]
+                .iter()
+                .rev()
+                .find(|(_, token)| *token == ";" || *token == "}")
+                .map_or(0, |(at, _)| at + 1)
+        })
+        .collect();
+    let header = &code[..starts[0]];
+    let files = declarations
+        .iter()
+        .enumerate()
+        .map(|(i, d)| {
+            let end = starts.get(i + 1).copied().unwrap_or(code.len());
+            let body = code[starts[i]..end].trim_start_matches(['\r', '\n']);
+            (format!("{}.java", d.name), format!("{}{}", header, body))
+        })
+        .collect();
+    Some(files)
+}
+
+/// Whether `name` is a class name `java` can run, like `com.example.App`.
+pub fn is_qualified_name(name: &str) -> bool {
+    name.split('.').all(|part| {
+        is_identifier(part) && part.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '$')
+    })
+}
+
+fn is_identifier(token: &str) -> bool {
+    token
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_alphabetic() || c == '_' || c == '$')
+}
+
+/// The tokens outside any braces, with their offsets and with comments and
+/// literals left out: identifiers and keywords whole, everything else a
+/// character at a time. The braces around each top-level body are tokens
+/// too.
+fn top_level_tokens(code: &str) -> Vec<(usize, &str)> {
+    let bytes = code.as_bytes();
+    let mut tokens = Vec::new();
+    let mut depth = 0usize;
+    let mut i = 0;
+    while i < bytes.len() {
+        let rest = &code[i..];
+        let c = rest.chars().next().expect("i is on a char boundary");
+        if rest.starts_with("//") {
+            i += rest.find('\n').unwrap_or(rest.len());
+        } else if let Some(comment) = rest.strip_prefix("/*") {
+            i += comment.find("*/").map_or(rest.len(), |end| end + 4);
+        } else if let Some(body) = rest.strip_prefix("\"\"\"") {
+            i += 3 + skip_literal(body, "\"\"\"");
+        } else if c == '"' || c == '\'' {
+            i += 1 + skip_literal(&rest[1..], if c == '"' { "\"" } else { "'" });
+        } else if c == '{' {
+            if depth == 0 {
+                tokens.push((i, "{"));
+            }
+            depth += 1;
+            i += 1;
+        } else if c == '}' {
+            depth = depth.saturating_sub(1);
+            if depth == 0 {
+                tokens.push((i, "}"));
+            }
+            i += 1;
+        } else if c.is_alphanumeric() || c == '_' || c == '$' {
+            let len = rest
+                .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '$'))
+                .unwrap_or(rest.len());
+            // `non-sealed` is the one keyword with a dash in it.
+            let len = if rest.starts_with("non-sealed") { "non-sealed".len() } else { len };
+            if depth == 0 {
+                tokens.push((i, &rest[..len]));
+            }
+            i += len;
+        } else {
+            if depth == 0 && !c.is_whitespace() {
+                tokens.push((i, &rest[..c.len_utf8()]));
+            }
+            i += c.len_utf8();
+        }
+    }
+    tokens
+}
+
+/// The length of a literal's body up to and including `end`, with
+/// backslash escapes skipped.
+fn skip_literal(body: &str, end: &str) -> usize {
+    let mut chars = body.char_indices();
+    while let Some((idx, c)) = chars.next() {
+        if c == '\\' {
+            chars.next();
+        } else if body[idx..].starts_with(end) {
+            return idx + end.len();
+        }
+    }
+    body.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn main_class() {
+        let cases: &[(&str, Option<&str>, &str)] = &[
+            ("public class Foo { public static void main(String[] args) {} }", None, "Foo"),
+            ("class Main {}", None, "Main"),
+            ("package demo.app;\n\npublic class App {}", Some("demo.app"), "App"),
+            ("package demo . app ;\nclass App {}", Some("demo.app"), "App"),
+            ("package demo;\nimport java.util.*;\n@Deprecated public final class App {}", Some("demo"), "App"),
+            ("public record Point(int x, int y) { public static void main(String[] a) {} }", None, "Point"),
+            ("record Pair<A, B>(A a, B b) {}", None, "Pair"),
+            ("record Point(int x) {}\npublic class Main {}", None, "Main"),
+            ("class record {}", None, "record"),
+            ("public enum Color { RED }", None, "Color"),
+            ("public interface Shape {}", None, "Shape"),
+            ("sealed interface Shape permits Circle {}\nnon-sealed class Circle implements Shape {}\npublic final class Main {}", None, "Main"),
+            ("class First {}\nclass Second {}", None, "First"),
+            ("class Outer { public class Inner {} }", None, "Outer"),
+            ("@interface Note {}\nclass Main {}", None, "Main"),
+            ("// public class Commented {}\n/* public class Hidden {} */\nclass Real {}", None, "Real"),
+            ("class Main { String brace = \"}\"; char c = '{'; }\npublic class After {}", None, "After"),
+            ("class Main { String text = \"\"\"\n    } public class Inside {\n    \"\"\"; }", None, "Main"),
+            ("class Main { void f() { Object o = String.class; } }", None, "Main"),
+            ("class Ünïcode {}", None, "Ünïcode"),
+        ];
+        for (code, package, name) in cases {
+            let expected = MainClass {
+                package: package.map(str::to_string),
+                name: name.to_string(),
+            };
+            assert_eq!(detect(code).as_ref(), Ok(&expected), "{}", code);
+        }
+    }
+
+    #[test]
+    fn no_class() {
+        for code in ["", "System.out.println(1);", "package demo;", "@interface Note {}", "// class Main {}"] {
+            assert!(detect(code).is_err(), "{}", code);
+        }
+    }
+
+    #[test]
+    fn qualified() {
+        let class = detect("package a.b;\npublic class C {}").unwrap();
+        assert_eq!((class.qualified(), class.file_name()), ("a.b.C".to_string(), "C.java".to_string()));
+        for (name, valid) in [("Main", true), ("com.example.App", true), ("a.$b", true), ("a..b", false), ("1a", false), ("a.b-c", false)] {
+            assert_eq!(is_qualified_name(name), valid, "{}", name);
+        }
+    }
+
+    #[test]
+    fn split_by_type() {
+        assert_eq!(split("public class Main {}"), None);
+        let files = split("package p;\nimport java.util.List;\n\nclass Helper {}\n// entry\npublic class Main {}\n").unwrap();
+        assert_eq!(
+            files,
+            [
+                ("Helper.java".to_string(), "package p;\nimport java.util.List;class Helper {}".to_string()),
+                ("Main.java".to_string(), "package p;\nimport java.util.List;// entry\npublic class Main {}\n".to_string()),
+            ]
+        );
+    }
+}
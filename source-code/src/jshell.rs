@@ -0,0 +1,66 @@
+//! Java snippets: blocks without a class, run statement by statement in
+//! jshell instead of being compiled. jshell prints the value of each
+//! expression, as `$1 ==> 7`, and that is kept; its prompts go, and its
+//! feedback about errors and exceptions becomes the block's stderr.
+
+use crate::executor::Stream;
+use crate::java_class;
+use crate::parser::Block;
+
+/// A feedback mode like jshell's `concise`, which is silent about
+/// declarations, but without prompts. jshell still prompts for these lines
+/// themselves, before the mode is in effect.
+const SETUP: &str = "/set mode translator concise -quiet\n\
+                     /set prompt translator \"\" \"\"\n\
+                     /set feedback translator\n";
+
+const PROMPT: &[u8] = b"jshell> ";
+
+/// Lines of feedback start with this; anything else is the snippets' output.
+const FEEDBACK: &[u8] = b"|  ";
+
+/// Whether `block` runs in jshell: it says `snippet`, or it doesn't say
+/// otherwise and declares no class.
+pub fn is_snippet(block: &Block) -> bool {
+    match block.params.get("snippet") {
+        Some(value) => value == "true",
+        None => java_class::detect(&block.code).is_err(),
+    }
+}
+
+/// What jshell reads on stdin for `code`.
+pub fn input(code: &str) -> String {
+    let newline = if code.ends_with('\n') { "" } else { "\n" };
+    format!("{}{}{}/exit\n", SETUP, code, newline)
+}
+
+/// Sorts jshell's stdout: see [`Output::route`].
+#[derive(Default)]
+pub struct Output {
+    /// Whether a snippet didn't compile or threw.
+    pub failed: bool,
+    /// Whether a snippet didn't compile.
+    pub rejected: bool,
+}
+
+impl Output {
+    /// Strips prompts from `line` and tells feedback from output. Feedback
+    /// goes to stderr, and errors and exceptions in it mark the run failed.
+    pub fn route(&mut self, line: &mut Vec<u8>) -> Option<Stream> {
+        let prompts = line.chunks(PROMPT.len()).take_while(|chunk| *chunk == PROMPT).count();
+        line.drain(..prompts * PROMPT.len());
+        if line.is_empty() {
+            return None;
+        }
+        let Some(feedback) = line.strip_prefix(FEEDBACK) else {
+            return Some(Stream::Stdout);
+        };
+        if feedback.starts_with(b"Error:") {
+            self.failed = true;
+            self.rejected = true;
+        } else if feedback.starts_with(b"Exception ") {
+            self.failed = true;
+        }
+        Some(Stream::Stderr)
+    }
+}
@@ -0,0 +1,26 @@
+//! Language names: the built-in executors and the aliases accepted for them.
+
+pub const BUILTIN: &[&str] = &["rust", "java", "python", "go"];
+
+/// The built-in languages with a compile step, which take compiler flags.
+pub const COMPILED: &[&str] = &["rust", "java", "go"];
+
+const ALIASES: &[(&str, &str)] = &[
+    ("rs", "rust"),
+    ("py", "python"),
+    ("python3", "python"),
+    ("golang", "go"),
+];
+
+/// Maps a language as written in a directive to the name translator uses
+/// for it everywhere else. Unknown languages (plugins) pass through as-is.
+pub fn canonical(lang: &str) -> String {
+    let lower = lang.to_ascii_lowercase();
+    if let Some((_, name)) = ALIASES.iter().find(|(alias, _)| *alias == lower) {
+        return name.to_string();
+    }
+    if BUILTIN.contains(&lower.as_str()) {
+        return lower;
+    }
+    lang.to_string()
+}
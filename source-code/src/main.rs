@@ -1,173 +1,1441 @@
+mod artifacts;
+mod audit;
+mod backend;
+mod cache;
+mod capture;
+mod changes;
+mod conditions;
+mod config;
+mod console;
+mod daemon;
+mod detect;
+mod encoding;
+mod executor;
+mod expect;
+mod flags;
+mod gomod;
+mod ipynb;
+mod java_batch;
+mod java_class;
+mod jshell;
+mod languages;
+mod manifest;
+mod needs;
+mod notebook;
+mod npm;
+mod org;
+mod parser;
+mod plugin;
+mod python;
+mod replay;
+mod report;
+mod rundir;
+mod sandbox;
+mod script;
+mod signal;
+mod toolchain;
+mod tools;
+mod trust;
+mod usage;
+mod versions;
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::env;
-use std::fs;
-use std::io;
-use std::process::{Command, Stdio};
-use tempfile::{tempdir, TempDir};
-
-fn main() -> io::Result<()> {
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
-        eprintln!("Usage: translator <hacker_file> [--verbose]");
-        std::process::exit(1);
-    }
-    let file_path = &args[1];
-    let verbose = args.len() > 2 && args[2] == "--verbose";
-    let content = fs::read_to_string(file_path)?;
-    let blocks = extract_blocks(&content, verbose);
-    for (lang, code) in blocks {
-        if verbose {
-            println!("Executing {} code:\n{}", lang, code);
-        }
-        match execute_code(&lang, &code, verbose) {
-            Ok(output) => println!("[{}] Output:\n{}", lang, output),
-            Err(e) => eprintln!("[{}] Error: {}", lang, e),
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::Instant;
+
+use console::Console;
+use executor::{ErrorKind, ExecutionResult, Origin, Status};
+use parser::{Block, Parsed, Syntax};
+use report::{Reporter, Summary};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Human,
+    Json,
+    Tap,
+    Ndjson,
+    GhAnnotations,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ReportKind {
+    Junit,
+    Markdown,
+    Html,
+}
+
+struct Options {
+    verbose: bool,
+    quiet: bool,
+    /// Print each block's stdout as it is, and nothing else.
+    raw: bool,
+    /// Start every line of a block's output with the block's name.
+    prefix: bool,
+    timings: bool,
+    /// Report the peak memory and CPU time of each block's program.
+    rusage: bool,
+    plugins: bool,
+    format: Format,
+    output: Option<PathBuf>,
+    reports: Vec<(ReportKind, PathBuf)>,
+    /// Where the summary goes once the run is over, from `--notify-url`.
+    notify_url: Option<String>,
+    /// What is run with the summary once the run is over, from
+    /// `--notify-cmd`.
+    notify_cmd: Option<Vec<String>>,
+    /// What git says changed in the files, from `--changed-only`.
+    changes: Option<changes::Changes>,
+    /// Keep blocks' results next to their files, from `--record`.
+    record: bool,
+    /// Reuse the results kept by `--record`, from `--replay`.
+    replay: Option<replay::Mode>,
+    report_max_lines: usize,
+    diff_context: usize,
+    /// How line endings are compared with expect sections.
+    expect_newlines: expect::Newlines,
+    update_expected: bool,
+    write_actual: Option<PathBuf>,
+    /// Where `artifacts=` copies blocks' files to.
+    artifacts_dir: PathBuf,
+    inline_output: bool,
+    in_place: bool,
+    header_format: Option<String>,
+    /// How hacker files are decoded, from `--encoding`.
+    encoding: encoding::FileEncoding,
+    /// What programs' output is decoded from, from `--output-encoding`.
+    output_encoding: encoding::OutputEncoding,
+    /// What directive lines start with in every file, from `--marker`.
+    marker: Option<String>,
+    /// What every file is read as, from `--input-format`, instead of what
+    /// its extension says.
+    input_format: Option<Syntax>,
+    dedup: bool,
+    /// Fail empty blocks instead of skipping them.
+    strict: bool,
+    /// Fail blocks whose compiler printed warnings.
+    deny_warnings: bool,
+    /// Give blocks translator's stdin instead of a closed one.
+    inherit_stdin: bool,
+    /// Which steps may use the network, from `--no-network`.
+    network: sandbox::Network,
+    /// Who blocks' programs run as, from `--run-as`.
+    run_as: Option<sandbox::User>,
+    /// Confines the steps to a few directories, from `--sandbox fs`.
+    landlock: Option<sandbox::Landlock>,
+    /// What native steps are started through, from `--sandbox-cmd`.
+    wrapper: Option<sandbox::Wrapper>,
+    /// From `--max-file-size` and `--max-compile-file-size`.
+    limits: sandbox::Limits,
+    /// How many bytes a block's directory may hold, from `--max-disk`.
+    max_disk: Option<u64>,
+    /// Fail blocks over `max_disk` rather than warn.
+    deny_disk: bool,
+    /// Run files outside trusted directories without asking.
+    yes: bool,
+    /// The only languages blocks may be in, from `--allow-lang`.
+    allow_lang: Option<BTreeSet<String>>,
+    /// `--set` variables, which beat the files' `|> set:` lines.
+    variables: BTreeMap<String, String>,
+    /// Where every process started for a block is recorded.
+    audit: Option<audit::AuditLog>,
+    /// Print the value of python blocks that are a single expression.
+    repl_semantics: bool,
+    keep_temp: bool,
+    /// Output beyond this many bytes goes to disk; 0 keeps it all in memory.
+    spill_threshold: usize,
+    /// Where spilled output is kept after the run, if anywhere.
+    spill_dir: Option<PathBuf>,
+    config: config::Config,
+    /// Whether `config` is from `--config` rather than found.
+    config_given: bool,
+    /// Programs overridden by the environment or `translator.toml`.
+    tools: tools::Tools,
+    /// Where blocks run, from `--backend`.
+    backend: backend::Kind,
+    /// Where compiled artifacts are kept; `None` with `--no-cache`.
+    cache: Option<cache::Cache>,
+    console: Console,
+}
+
+impl Options {
+    /// Whether `--allow-lang` lets blocks in `lang` run.
+    fn language_allowed(&self, lang: &str) -> bool {
+        self.allow_lang.as_ref().is_none_or(|allowed| allowed.contains(lang))
+    }
+
+    /// What directive lines start with in `file`.
+    fn marker(&self, file: &Path) -> io::Result<String> {
+        let marker = config::marker(file, self.marker.as_deref(), &self.config, self.config_given)?;
+        if marker != parser::MARKER {
+            self.log(format_args!("{}: directives start with {}", file.display(), marker));
+        }
+        Ok(marker)
+    }
+
+    /// What `file` is read as.
+    fn syntax(&self, file: &Path) -> Syntax {
+        self.input_format.unwrap_or_else(|| Syntax::of(file))
+    }
+
+    /// Prints a `--verbose` message. They go to stderr whenever stdout
+    /// carries a machine-readable format or raw output.
+    fn log(&self, message: fmt::Arguments) {
+        if !self.verbose {
+            return;
+        }
+        // Logging is best effort; a closed stream shows up in the results.
+        let _ = match self.format {
+            Format::Human if !self.raw => writeln!(self.console.out(), "{}", message),
+            _ => writeln!(self.console.err(), "{}", message),
+        };
+    }
+}
+
+/// Why an invocation stopped early.
+enum CliError {
+    /// Bad arguments: the message, if any, is printed above the usage.
+    Usage(String),
+    Io(io::Error),
+}
+
+impl From<io::Error> for CliError {
+    fn from(e: io::Error) -> Self {
+        CliError::Io(e)
+    }
+}
+
+fn usage_error(message: impl Into<String>) -> CliError {
+    CliError::Usage(message.into())
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let code = match args.first().map(String::as_str) {
+        Some("daemon") => daemon::serve(&args[1..]),
+        Some("client") => daemon::client(&args[1..]),
+        _ => run(args, Console::stdio()),
+    };
+    ExitCode::from(code)
+}
+
+/// Runs one command line, writing to `console`, and returns the exit code.
+/// The daemon calls this for every request it serves.
+fn run(args: Vec<String>, console: Console) -> u8 {
+    match try_run(args, console.clone()) {
+        Ok(code) => code,
+        Err(CliError::Usage(message)) => {
+            let mut err = console.err();
+            if !message.is_empty() {
+                let _ = writeln!(err, "{}", message);
+            }
+            let _ = write!(err, "{}", USAGE);
+            1
+        }
+        Err(CliError::Io(e)) => {
+            let _ = writeln!(console.err(), "Error: {}", e);
+            1
+        }
+    }
+}
+
+fn try_run(args: Vec<String>, console: Console) -> Result<u8, CliError> {
+    let mut opts = Options {
+        verbose: false,
+        quiet: false,
+        raw: false,
+        prefix: false,
+        timings: false,
+        rusage: false,
+        plugins: true,
+        format: Format::Human,
+        output: None,
+        reports: Vec::new(),
+        notify_url: None,
+        notify_cmd: None,
+        changes: None,
+        record: false,
+        replay: None,
+        report_max_lines: 100,
+        diff_context: 3,
+        expect_newlines: expect::Newlines::Exact,
+        update_expected: false,
+        write_actual: None,
+        artifacts_dir: PathBuf::from("translator-artifacts"),
+        inline_output: false,
+        in_place: false,
+        header_format: None,
+        dedup: true,
+        strict: false,
+        deny_warnings: false,
+        inherit_stdin: false,
+        network: sandbox::Network::All,
+        run_as: None,
+        landlock: None,
+        wrapper: None,
+        limits: sandbox::Limits::default(),
+        max_disk: None,
+        deny_disk: false,
+        yes: false,
+        allow_lang: None,
+        variables: BTreeMap::new(),
+        audit: None,
+        repl_semantics: false,
+        encoding: encoding::FileEncoding::Utf8,
+        output_encoding: encoding::OutputEncoding::console(),
+        marker: None,
+        input_format: None,
+        keep_temp: false,
+        spill_threshold: capture::DEFAULT_THRESHOLD,
+        spill_dir: None,
+        config: config::Config::default(),
+        config_given: false,
+        tools: tools::Tools::default(),
+        backend: backend::Kind::Native,
+        cache: None,
+        console,
+    };
+    let mut cache_dir = cache::Cache::default_dir();
+    let mut no_cache = false;
+    let mut config_path = None;
+    let mut dependency_network = false;
+    let mut run_as = None;
+    let mut allow_root = false;
+    let mut audit_log = None;
+    let mut max_procs = None;
+    let mut sandbox_cmd = None;
+    // Whether `--sandbox fs` was given, and whether with `=best-effort`.
+    let mut sandbox_fs = None;
+    let mut format_given = false;
+    // The base of --changed-only, and whether --changed-blocks was given.
+    let mut changed_only = None;
+    let mut changed_blocks = false;
+    // For export-script.
+    let mut out = None;
+    let mut fail_fast = false;
+    let mut positional = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let (flag, inline) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => (flag.to_string(), Some(value.to_string())),
+            _ => (arg.clone(), None),
+        };
+        let mut value = || {
+            inline
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| usage_error(format!("Missing value for {}", flag)))
+        };
+        match flag.as_str() {
+            "--verbose" => opts.verbose = true,
+            "--quiet" => opts.quiet = true,
+            "--raw" => opts.raw = true,
+            "--prefix" => opts.prefix = true,
+            "--timings" => opts.timings = true,
+            "--rusage" => opts.rusage = true,
+            "--no-plugins" => opts.plugins = false,
+            "--format" => {
+                format_given = true;
+                opts.format = match value()?.as_str() {
+                    "human" => Format::Human,
+                    "json" => Format::Json,
+                    "tap" => Format::Tap,
+                    "ndjson" => Format::Ndjson,
+                    "gh-annotations" => Format::GhAnnotations,
+                    other => return Err(usage_error(format!("Unknown format: {}", other))),
+                }
+            }
+            "--output" => opts.output = Some(PathBuf::from(value()?)),
+            "--report" => {
+                let spec = value()?;
+                let Some((kind, path)) = spec.split_once('=') else {
+                    return Err(usage_error(format!("--report expects KIND=PATH, got {}", spec)));
+                };
+                let kind = match kind {
+                    "junit" => ReportKind::Junit,
+                    "markdown" => ReportKind::Markdown,
+                    "html" => ReportKind::Html,
+                    other => return Err(usage_error(format!("Unknown report kind: {}", other))),
+                };
+                opts.reports.push((kind, PathBuf::from(path)));
+            }
+            "--changed-only" => changed_only = Some(inline.clone().unwrap_or_else(|| "origin/main".to_string())),
+            "--changed-blocks" => changed_blocks = true,
+            "--record" => opts.record = true,
+            "--replay" => {
+                opts.replay = match inline.as_deref() {
+                    None => Some(replay::Mode::Run),
+                    Some("strict") => Some(replay::Mode::Strict),
+                    Some(other) => return Err(usage_error(format!("--replay expects nothing or =strict, got {}", other))),
+                }
+            }
+            "--notify-url" => {
+                let url = value()?;
+                if !url.starts_with("http://") && !url.starts_with("https://") {
+                    return Err(usage_error(format!("--notify-url expects an http:// or https:// URL, got {}", url)));
+                }
+                opts.notify_url = Some(url);
+            }
+            "--notify-cmd" => {
+                let words = flags::split(&value()?).map_err(|problem| usage_error(format!("--notify-cmd: {}", problem)))?;
+                if words.is_empty() {
+                    return Err(usage_error("--notify-cmd: the command is empty"));
+                }
+                opts.notify_cmd = Some(words);
+            }
+            "--report-max-lines" => opts.report_max_lines = number(&flag, &value()?)?,
+            "--diff-context" => opts.diff_context = number(&flag, &value()?)?,
+            "--expect-newlines" => {
+                let value = value()?;
+                opts.expect_newlines = expect::Newlines::parse(&value)
+                    .ok_or_else(|| usage_error(format!("--expect-newlines expects exact, trailing or lenient, got {}", value)))?;
+            }
+            "--update-expected" => opts.update_expected = true,
+            "--write-actual" => opts.write_actual = Some(PathBuf::from(value()?)),
+            "--artifacts-dir" => opts.artifacts_dir = PathBuf::from(value()?),
+            "--inline-output" => opts.inline_output = true,
+            "--in-place" => opts.in_place = true,
+            "--header-format" => opts.header_format = Some(value()?),
+            "--encoding" => opts.encoding = encoding::FileEncoding::parse(&value()?).map_err(usage_error)?,
+            "--output-encoding" => opts.output_encoding = encoding::OutputEncoding::parse(&value()?).map_err(usage_error)?,
+            "--marker" => {
+                let value = value()?;
+                if value.trim().is_empty() || value.trim() != value {
+                    return Err(usage_error("--marker can't be empty or start or end with whitespace"));
+                }
+                opts.marker = Some(value);
+            }
+            "--input-format" => {
+                let value = value()?;
+                opts.input_format =
+                    Some(Syntax::parse(&value).ok_or_else(|| usage_error(format!("Unknown input format: {}", value)))?);
+            }
+            "--cache-dir" => cache_dir = Some(PathBuf::from(value()?)),
+            "--no-cache" => no_cache = true,
+            "--no-dedup" => opts.dedup = false,
+            "--strict" => opts.strict = true,
+            "--deny-warnings" => opts.deny_warnings = true,
+            "--inherit-stdin" => opts.inherit_stdin = true,
+            "--no-network" => opts.network = sandbox::Network::None,
+            "--allow-dependency-network" => dependency_network = true,
+            "--run-as" => run_as = Some(value()?),
+            "--allow-root" => allow_root = true,
+            "--out" => out = Some(PathBuf::from(value()?)),
+            "--fail-fast" => fail_fast = true,
+            "--max-file-size" => opts.limits.file_size = Some(megabytes(&flag, &value()?)?),
+            "--max-compile-file-size" => opts.limits.compile_file_size = Some(megabytes(&flag, &value()?)?),
+            "--max-procs" => max_procs = Some(number(&flag, &value()?)? as u64),
+            "--max-disk" => opts.max_disk = Some(megabytes(&flag, &value()?)?),
+            "--deny-disk" => opts.deny_disk = true,
+            "--yes" | "-y" => opts.yes = true,
+            "--audit-log" => audit_log = Some(PathBuf::from(value()?)),
+            "--allow-lang" => {
+                let allowed = opts.allow_lang.get_or_insert_with(BTreeSet::new);
+                allowed.extend(value()?.split(',').map(str::trim).filter(|lang| !lang.is_empty()).map(languages::canonical));
+            }
+            "--set" => {
+                let value = value()?;
+                let Some((name, value)) = value.split_once('=') else {
+                    return Err(usage_error(format!("--set expects NAME=VALUE, got {}", value)));
+                };
+                opts.variables.insert(name.trim().to_string(), value.to_string());
+            }
+            "--sandbox-cmd" => sandbox_cmd = Some(value()?),
+            "--sandbox" => {
+                let value = value()?;
+                sandbox_fs = match value.as_str() {
+                    "fs" => Some(false),
+                    "fs=best-effort" => Some(true),
+                    _ => return Err(usage_error(format!("--sandbox expects fs or fs=best-effort, got {}", value))),
+                };
+            }
+            "--repl-semantics" => opts.repl_semantics = true,
+            "--config" => config_path = Some(PathBuf::from(value()?)),
+            "--keep-temp" => opts.keep_temp = true,
+            "--spill-threshold" => opts.spill_threshold = number(&flag, &value()?)?,
+            "--spill-dir" => opts.spill_dir = Some(PathBuf::from(value()?)),
+            "--backend" => {
+                let value = value()?;
+                opts.backend = backend::Kind::parse(&value)
+                    .ok_or_else(|| usage_error(format!("Unknown backend: {}", value)))?;
+            }
+            _ if arg.starts_with("--") => return Err(usage_error(format!("Unknown option: {}", arg))),
+            _ => positional.push(arg),
+        }
+    }
+    opts.config = config::Config::load(config_path.as_deref())?;
+    opts.config_given = config_path.is_some();
+    opts.tools = tools::Tools::resolve(&opts.config)?;
+    for &(tool, _) in tools::TOOLS {
+        if let Some((path, source)) = opts.tools.get(tool) {
+            opts.log(format_args!("Using {} for {} (from {})", path.display(), tool, source));
+        }
+    }
+    if !format_given && env::var("GITHUB_ACTIONS").as_deref() == Ok("true") {
+        opts.format = Format::GhAnnotations;
+    }
+    if !no_cache {
+        opts.cache = cache_dir.map(cache::Cache::new);
+    }
+    if positional.first().map(String::as_str) == Some("cache") {
+        match positional.get(1).map(String::as_str) {
+            Some("clear") if positional.len() == 2 => {}
+            Some("verify") if positional.len() > 2 => return Ok(verify_recordings(&positional[2..], &opts)?),
+            _ => return Err(usage_error("")),
+        }
+        if let Some(cache) = &opts.cache {
+            cache.clear()?;
+            writeln!(opts.console.out(), "Cleared {}", cache.dir().display())?;
+        }
+        return Ok(0);
+    }
+    if positional.first().map(String::as_str) == Some("doctor") {
+        return doctor(&positional[1..], &opts);
+    }
+    if positional.first().map(String::as_str) == Some("list-languages") {
+        list_languages(&opts)?;
+        return Ok(0);
+    }
+    let export = positional.first().map(String::as_str) == Some("export-script");
+    if export {
+        positional.remove(0);
+    } else if out.is_some() || fail_fast {
+        return Err(usage_error("--out and --fail-fast only apply to export-script"));
+    }
+    if positional.first().map(String::as_str) == Some("run") && !export {
+        positional.remove(0);
+    }
+    if positional.is_empty() {
+        return Err(usage_error(""));
+    }
+    if opts.in_place && !opts.inline_output {
+        return Err(usage_error("--in-place only applies to --inline-output"));
+    }
+    if changed_blocks && changed_only.is_none() {
+        return Err(usage_error("--changed-blocks only applies to --changed-only"));
+    }
+    if opts.output.is_some() && opts.format != Format::Json {
+        return Err(usage_error("--output is only supported with --format json"));
+    }
+    if opts.raw && opts.format != Format::Human {
+        return Err(usage_error("--raw only applies to --format human"));
+    }
+    if dependency_network {
+        if opts.network != sandbox::Network::None {
+            return Err(usage_error("--allow-dependency-network only applies to --no-network"));
+        }
+        opts.network = sandbox::Network::Dependencies;
+    }
+    if opts.network != sandbox::Network::All {
+        match opts.backend {
+            backend::Kind::Ssh { .. } => return Err(usage_error("--no-network can't isolate blocks on a remote machine")),
+            backend::Kind::Wsl => return Err(usage_error("--no-network can't isolate blocks in WSL")),
+            backend::Kind::Container { .. } => {}
+            backend::Kind::Native => sandbox::check_network()?,
+        }
+    }
+    if let Some(name) = run_as {
+        if !matches!(opts.backend, backend::Kind::Native) {
+            return Err(usage_error("--run-as only applies to --backend native"));
+        }
+        let user = sandbox::User::lookup(&name)?;
+        if user.is_root() && !allow_root {
+            return Err(usage_error(format!(
+                "--run-as {} would run blocks as root; pass --allow-root to do that anyway",
+                name
+            )));
+        }
+        // The user couldn't read the cache, which stays translator's.
+        if opts.cache.take().is_some() {
+            opts.log(format_args!("Not using the cache, since blocks run as {}", user.name));
+        }
+        opts.run_as = Some(user);
+    } else if allow_root {
+        return Err(usage_error("--allow-root only applies to --run-as"));
+    }
+    if opts.limits.compile_file_size.is_none() {
+        opts.limits.compile_file_size = opts.limits.file_size.map(|size| size.saturating_mul(4));
+    }
+    if !opts.limits.is_empty() && cfg!(not(unix)) {
+        return Err(usage_error("--max-file-size only works on Unix"));
+    }
+    opts.limits.procs = match max_procs {
+        Some(_) if cfg!(not(unix)) => return Err(usage_error("--max-procs only works on Unix")),
+        Some(0) => None,
+        Some(procs) => Some(procs),
+        None => cfg!(unix).then_some(sandbox::DEFAULT_PROCS),
+    };
+    if opts.deny_disk && opts.max_disk.is_none() {
+        return Err(usage_error("--deny-disk only applies to --max-disk"));
+    }
+    if let Some(best_effort) = sandbox_fs {
+        if let backend::Kind::Ssh { .. } = opts.backend {
+            return Err(usage_error("--sandbox fs can't confine blocks on a remote machine"));
+        }
+        if let backend::Kind::Wsl = opts.backend {
+            return Err(usage_error("--sandbox fs can't confine blocks in WSL"));
+        }
+        match sandbox::Landlock::new(opts.cache.as_ref().map(cache::Cache::dir)) {
+            Ok(landlock) => opts.landlock = Some(landlock),
+            Err(problem) if best_effort => {
+                writeln!(opts.console.err(), "warning: {}; running blocks without the filesystem sandbox", problem)?
+            }
+            Err(problem) => return Err(io::Error::new(io::ErrorKind::Unsupported, format!("--sandbox fs: {}", problem)).into()),
+        }
+    }
+    if let Some(template) = sandbox_cmd.as_deref().or(opts.config.sandbox_cmd.as_deref()) {
+        if !matches!(opts.backend, backend::Kind::Native) {
+            return Err(usage_error("--sandbox-cmd only applies to --backend native"));
+        }
+        // Landlock would keep a wrapper like bubblewrap from mounting
+        // anything.
+        if opts.landlock.is_some() {
+            return Err(usage_error("--sandbox-cmd and --sandbox fs can't be combined"));
+        }
+        let wrapper = sandbox::Wrapper::new(template)
+            .map_err(|problem| io::Error::new(io::ErrorKind::InvalidInput, format!("--sandbox-cmd: {}", problem)))?;
+        opts.wrapper = Some(wrapper);
+    }
+    if let Some(path) = audit_log {
+        let log = audit::AuditLog::open(&path)
+            .map_err(|e| io::Error::new(e.kind(), format!("--audit-log {}: {}", path.display(), e)))?;
+        opts.audit = Some(log);
+    }
+    if export {
+        if !matches!(opts.backend, backend::Kind::Native) {
+            return Err(usage_error("export-script only applies to --backend native"));
+        }
+        let sandboxed = opts.network != sandbox::Network::All || opts.run_as.is_some();
+        if sandboxed || opts.landlock.is_some() || opts.wrapper.is_some() {
+            return Err(usage_error("export-script can't sandbox blocks; the script runs them as they are"));
+        }
+        if changed_only.is_some() {
+            return Err(usage_error("--changed-only doesn't apply to export-script, whose script runs every block"));
+        }
+        if opts.record || opts.replay.is_some() {
+            return Err(usage_error("--record and --replay don't apply to export-script, whose script runs every block"));
+        }
+        let script = script::export(&positional, &opts, fail_fast)?;
+        match out {
+            Some(path) => write_script(&path, &script)
+                .map_err(|e| io::Error::new(e.kind(), format!("--out {}: {}", path.display(), e)))?,
+            None => opts.console.out().write_all(script.as_bytes())?,
+        }
+        return Ok(0);
+    }
+    if let Some(base) = changed_only {
+        let mut warnings = Vec::new();
+        opts.changes = Some(changes::Changes::find(&positional, &base, changed_blocks, &mut |warning| warnings.push(warning)));
+        for warning in warnings {
+            writeln!(opts.console.err(), "warning: {}", warning)?;
+        }
+    }
+    let default_header = report::Human::default_header(opts.timings, opts.rusage);
+    let template = opts.header_format.as_deref().unwrap_or(&default_header);
+    let header = report::Header::parse(template).map_err(|e| usage_error(format!("--header-format: {}", e)))?;
+    let console = &opts.console;
+    let human = |stderr_only| report::Human {
+        console: console.clone(),
+        quiet: opts.quiet,
+        raw: opts.raw,
+        timings: opts.timings,
+        stderr_only,
+        header,
+        prefix: opts.prefix.then(report::Prefix::default),
+        stdout_color: report::stdout_color(),
+    };
+    let mut reporters: Vec<Box<dyn Reporter>> = match opts.format {
+        Format::Human => vec![Box::new(human(false))],
+        Format::Json => vec![Box::new(report::Json::new(opts.output.as_deref(), console, opts.tools.clone())?)],
+        Format::Tap => vec![Box::new(report::Tap::new(console.clone())?)],
+        Format::Ndjson => vec![Box::new(report::Ndjson::new(&positional, console.clone())?)],
+        // The annotations own stdout; the usual console output moves to stderr.
+        Format::GhAnnotations => vec![
+            Box::new(report::GhAnnotations {
+                console: console.clone(),
+            }),
+            Box::new(human(true)),
+        ],
+    };
+    for (kind, path) in &opts.reports {
+        reporters.push(match kind {
+            ReportKind::Junit => Box::new(report::Junit::new(path.clone())),
+            ReportKind::Markdown => Box::new(report::Markdown::new(path.clone(), opts.report_max_lines)),
+            ReportKind::Html => Box::new(report::Html::new(path.clone(), opts.report_max_lines, opts.tools.clone())),
+        });
+    }
+    if opts.notify_url.is_some() || opts.notify_cmd.is_some() {
+        reporters.push(Box::new(report::Notify::new(
+            opts.notify_url.clone(),
+            opts.notify_cmd.clone(),
+            console.clone(),
+            opts.tools.clone(),
+        )));
+    }
+    let started = Instant::now();
+    let mut summary = Summary::default();
+    let mut seen = Seen::new();
+    let run_dir = rundir::RunDir::new()?;
+    opts.log(format_args!("Run dir: {}", run_dir.path().display()));
+    if opts.run_as.is_some() {
+        sandbox::allow_traverse(run_dir.path())?;
+    }
+    let outcome = positional
+        .iter()
+        .try_for_each(|file_path| run_file(file_path, &opts, &run_dir, &mut reporters, &mut summary, &mut seen));
+    if opts.keep_temp {
+        writeln!(opts.console.err(), "Kept the run directory {}", run_dir.keep().display())?;
+    }
+    outcome?;
+    summary.duration = started.elapsed();
+    for reporter in &mut reporters {
+        reporter.finish(&summary)?;
+    }
+    Ok(if summary.compile_errors > 0 {
+        2
+    } else if summary.crashed > 0 {
+        3
+    } else if summary.failed > 0 {
+        1
+    } else {
+        0
+    })
+}
+
+/// Results of the blocks run so far, for reuse by identical blocks, keyed by
+/// a hash of the block so a long file's code isn't all kept.
+type Seen = HashMap<String, (Origin, ExecutionResult)>;
+
+/// Parameters that suggest a block affects more than its own output, or
+/// depends on files next to its hacker file, so running it again isn't the
+/// same as reusing its result.
+const SIDE_EFFECTS: &[&str] = &["workspace", "pipe", "args", "env", "classpath", "needs", "artifacts"];
+
+fn dedup_key(block: &Block, opts: &Options) -> Option<String> {
+    if !opts.dedup || SIDE_EFFECTS.iter().any(|key| block.params.get(key).is_some()) {
+        return None;
+    }
+    Some(replay::key(block))
+}
+
+fn run_file(
+    file_path: &str,
+    opts: &Options,
+    run_dir: &rundir::RunDir,
+    reporters: &mut [Box<dyn Reporter>],
+    summary: &mut Summary,
+    seen: &mut Seen,
+) -> io::Result<()> {
+    // A first pass picks out the java blocks so they can be compiled
+    // together; everything else runs as soon as it has been read.
+    let marker = opts.marker(Path::new(file_path))?;
+    let syntax = opts.syntax(Path::new(file_path));
+    // An org file's results sections are its expected output, which
+    // --update-expected rewrites.
+    if syntax == Syntax::Org && opts.inline_output {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("{}: --inline-output doesn't apply to org files", file_path),
+        ));
+    }
+    let mut java_blocks = Vec::new();
+    // What would run, by language, for the trust prompt.
+    let mut runnable = BTreeMap::new();
+    let mut graph = needs::Graph::default();
+    let change = opts.changes.as_ref().map(|changes| changes.of(file_path));
+    let mut touched = HashSet::new();
+    parse_file(Path::new(file_path), opts, &marker, |parsed| {
+        if let Parsed::Block(block) = parsed {
+            graph.add(&block);
+            if change.is_some_and(|change| change.touches(&block)) {
+                touched.insert(block.index);
+            }
+            if (block.is_empty() && !opts.strict) || !opts.language_allowed(&block.lang) {
+                return Ok(());
+            }
+            *runnable.entry(block.lang.clone()).or_insert(0) += 1;
+            if block.lang == "java" && !block.is_empty() {
+                java_blocks.push(*block);
+            }
+        }
+        Ok(())
+    })
+    .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", file_path, e)))?;
+    graph.check(file_path)?;
+    // What a changed block needs runs as well.
+    let unchanged = opts.changes.as_ref().zip(change).map(|(changes, change)| {
+        let reason = match change {
+            changes::Change::Unchanged => format!("{} hasn't changed since {} (--changed-only)", file_path, changes.base),
+            _ => format!("none of its lines changed since {} (--changed-blocks)", changes.base),
+        };
+        (graph.with_prerequisites(touched), reason)
+    });
+    if let Some((selected, _)) = &unchanged {
+        java_blocks.retain(|block| selected.contains(&block.index));
+    }
+    let recording = if opts.record || opts.replay.is_some() {
+        Some(replay::Recording::load(Path::new(file_path))?)
+    } else {
+        None
+    };
+    // Replayed blocks aren't compiled either.
+    if let (Some(recording), Some(_)) = (&recording, opts.replay) {
+        java_blocks.retain(|block| !recording.contains(&replay::key(block)));
+    }
+    let trusted = opts.yes || runnable.is_empty() || trust::is_trusted(Path::new(file_path), &opts.config)?;
+    if !trusted && !trust::confirm(file_path, &runnable, &opts.console)? {
+        return Err(io::Error::new(io::ErrorKind::Interrupted, format!("not running {}", file_path)));
+    }
+    for reporter in reporters.iter_mut() {
+        reporter.file_started(file_path)?;
+    }
+    let dir = run_dir.file_dir(file_path)?;
+    let mut run = FileRun {
+        file_path,
+        opts,
+        java: java_batch::compile(Path::new(file_path), &java_blocks, opts, &dir),
+        dir,
+        encoding: opts.encoding,
+        marker: &marker,
+        syntax,
+        reporters,
+        summary,
+        seen,
+        updates: Vec::new(),
+        recorded: Vec::new(),
+        cells: Vec::new(),
+        unchanged,
+        recording,
+        keys: Vec::new(),
+    };
+    drop(java_blocks);
+    let mut order = needs::Order::new(graph);
+    let encoding = parse_file(Path::new(file_path), opts, &marker, |parsed| match parsed {
+        Parsed::Diagnostic(diagnostic) if diagnostic.kind == parser::DiagnosticKind::DetectedLanguage => {
+            opts.log(format_args!("{}:{}: {}", file_path, diagnostic.span.start_line, diagnostic.message));
+            Ok(())
+        }
+        Parsed::Diagnostic(diagnostic) => {
+            writeln!(
+                opts.console.err(),
+                "{}:{}: warning: {}",
+                file_path, diagnostic.span.start_line, diagnostic.message
+            )
+        }
+        Parsed::Block(block) => order.push(*block, &mut |block, needed| run.block(block, &needed)),
+    })?;
+    let FileRun {
+        mut updates,
+        recorded,
+        cells,
+        recording,
+        keys,
+        ..
+    } = run;
+    let path = Path::new(file_path);
+    if let Some(recording) = recording.filter(|_| opts.record) {
+        recording.save(&keys)?;
+        opts.log(format_args!("Recorded the results in {}", recording.path().display()));
+    }
+    if syntax == Syntax::Notebook {
+        if opts.inline_output {
+            let out = if opts.in_place { path.to_path_buf() } else { notebook::out_path(path) };
+            ipynb::write(path, &out, cells)?;
+            opts.log(format_args!("Wrote {}", out.display()));
+        }
+    } else if opts.inline_output && !opts.in_place {
+        // Expect updates still go to the hacker file; the copy gets both.
+        // The copy is written first, while the offsets still match.
+        let out = notebook::out_path(path);
+        let mut all = updates.clone();
+        all.extend(recorded);
+        rewrite(path, &out, all, encoding)?;
+        opts.log(format_args!("Wrote {}", out.display()));
+        if !updates.is_empty() {
+            rewrite(path, path, updates, encoding)?;
+        }
+    } else {
+        updates.extend(recorded);
+        if !updates.is_empty() {
+            rewrite(path, path, updates, encoding)?;
         }
     }
     Ok(())
 }
 
-fn extract_blocks(content: &str, verbose: bool) -> Vec<(String, String)> {
-    let mut blocks = Vec::new();
-    let lines: Vec<&str> = content.lines().collect();
-    let mut i = 0;
-    while i < lines.len() {
-        let line = lines[i].trim();
-        if line.starts_with("|> translator:") {
-            let parts: Vec<&str> = line.splitn(2, ':').collect();
-            if parts.len() == 2 {
-                let lang = parts[1].trim().split('(').next().unwrap_or("").trim().to_string();
-                if !lang.is_empty() {
-                    let mut code = String::new();
-                    i += 1;
-                    let mut depth = 1;
-                    while i < lines.len() && depth > 0 {
-                        let code_line = lines[i];
-                        for c in code_line.chars() {
-                            if c == '(' {
-                                depth += 1;
-                            } else if c == ')' {
-                                depth -= 1;
-                            }
+/// Hands what the parser makes of `path`, a hacker file, an org file or a
+/// notebook, to `f` as it goes, and returns the file's encoding. Fails at
+/// the first block that uses an undefined variable.
+fn parse_file(
+    path: &Path,
+    opts: &Options,
+    marker: &str,
+    mut f: impl FnMut(Parsed) -> io::Result<()>,
+) -> io::Result<encoding::FileEncoding> {
+    let reader = || File::open(path).map(BufReader::new);
+    match opts.syntax(path) {
+        Syntax::Hacker => {
+            let parser = parser::Parser::new(marker).with_variables(&opts.variables);
+            parser::parse_reader(reader()?, opts.encoding, parser, |parsed| match parsed {
+                Parsed::Diagnostic(diagnostic) if diagnostic.kind == parser::DiagnosticKind::UndefinedVariable => Err(
+                    io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", diagnostic.span.start_line, diagnostic.message)),
+                ),
+                parsed => f(parsed),
+            })
+        }
+        Syntax::Org => parser::parse_reader(reader()?, opts.encoding, org::OrgParser::new(), f),
+        Syntax::Notebook => {
+            for block in ipynb::blocks(path)? {
+                f(Parsed::Block(Box::new(block)))?;
+            }
+            Ok(encoding::FileEncoding::Utf8)
+        }
+    }
+}
+
+/// The state of running one hacker file, block by block.
+struct FileRun<'a> {
+    file_path: &'a str,
+    opts: &'a Options,
+    reporters: &'a mut [Box<dyn Reporter>],
+    summary: &'a mut Summary,
+    seen: &'a mut Seen,
+    /// The file's directory in the run directory.
+    dir: PathBuf,
+    java: Option<java_batch::Batch>,
+    encoding: encoding::FileEncoding,
+    /// What the file's directive lines start with.
+    marker: &'a str,
+    syntax: Syntax,
+    /// Edits to the file's expect sections, from `--update-expected`.
+    updates: Vec<(Range<usize>, String)>,
+    /// Output sections, from `--inline-output`.
+    recorded: Vec<(Range<usize>, String)>,
+    /// The same for a notebook's cells.
+    cells: ipynb::Outputs,
+    /// With `--changed-only`, the blocks that run, and why the others
+    /// don't.
+    unchanged: Option<(HashSet<usize>, String)>,
+    /// The file's recorded results, with `--record` or `--replay`.
+    recording: Option<replay::Recording>,
+    /// The recording's key and line of each block so far.
+    keys: Vec<(String, usize)>,
+}
+
+impl FileRun<'_> {
+    /// Runs `block`, given how the blocks it needs went, and returns how it
+    /// went.
+    fn block(&mut self, block: Block, needed: &[(needs::Prerequisite, Status)]) -> io::Result<Status> {
+        let (file_path, opts) = (self.file_path, self.opts);
+        if block.is_empty() && !opts.strict {
+            self.summary.empty += 1;
+            writeln!(
+                opts.console.err(),
+                "{}:{}: warning: {} is empty, skipping it",
+                file_path,
+                block.span.start_line,
+                block.label()
+            )?;
+            return Ok(Status::Skipped);
+        }
+        let name = block.name().map(|n| format!(" '{}'", n)).unwrap_or_default();
+        opts.log(format_args!(
+            "Extracted {} block{} (lines {}-{})",
+            block.lang, name, block.span.start_line, block.span.end_line
+        ));
+        opts.log(format_args!("Executing {} code:\n{}", block.lang, block.code));
+        for reporter in self.reporters.iter_mut() {
+            reporter.block_started(file_path, &block)?;
+        }
+        let key = dedup_key(&block, opts);
+        let duplicate = key
+            .as_ref()
+            .and_then(|key| self.seen.get(key))
+            .map(|(origin, original)| original.duplicate(origin.clone()));
+        let recorded_key = self.recording.as_ref().map(|_| replay::key(&block));
+        let replayed = match (&self.recording, &recorded_key, opts.replay) {
+            (Some(recording), Some(key), Some(_)) => recording.replay(key)?,
+            _ => None,
+        };
+        if let Some(key) = &recorded_key {
+            self.keys.push((key.clone(), block.span.start_line));
+        }
+        let skip = self.left_out(&block).or_else(|| skip_reason(&block, opts, needed));
+        let mut result = match (skip, duplicate, replayed) {
+            (Some(error), _, _) => executor::ExecutionResult::not_run(error),
+            (None, Some(result), _) => {
+                opts.log(format_args!("Reusing the result of an identical block"));
+                result
+            }
+            (None, None, Some(result)) => {
+                opts.log(format_args!("Replaying the result recorded for the block"));
+                result
+            }
+            (None, None, None) if opts.replay == Some(replay::Mode::Strict) => {
+                let recording = self.recording.as_ref().expect("--replay loads the recording");
+                executor::ExecutionResult::not_run(executor::ExecError {
+                    kind: ErrorKind::NotRecorded,
+                    message: format!(
+                        "{} has no result recorded in {}, and --replay=strict doesn't run it",
+                        block.label(),
+                        recording.path().display()
+                    ),
+                    output: None,
+                })
+            }
+            (None, None, None) => {
+                let mut output_error = None;
+                let reporters = &mut *self.reporters;
+                let mut sink = |stream, chunk: &[u8]| {
+                    for reporter in reporters.iter_mut() {
+                        if let Err(e) = reporter.output(file_path, &block, stream, chunk) {
+                            output_error.get_or_insert(e);
                         }
-                        code.push_str(code_line);
-                        code.push('\n');
-                        i += 1;
                     }
-                    if depth == 0 {
-                        let code_trimmed = code.trim().to_string();
-                        if verbose {
-                            println!("Extracted {} block", lang);
-                        }
-                        blocks.push((lang, code_trimmed));
-                    } else {
-                        if verbose {
-                            eprintln!("Unclosed block for {}", lang);
-                        }
+                };
+                let prebuilt = self.java.as_ref().and_then(|j| j.get(&block));
+                let dir = rundir::block_dir(&self.dir, &block)?;
+                let mut result = executor::execute_code(&block, opts, Path::new(file_path), &dir, prebuilt, &mut sink);
+                if let Some(e) = output_error {
+                    return Err(e);
+                }
+                if let Some(spill_dir) = &opts.spill_dir {
+                    if result.stdout.spilled().is_some() || result.stderr.spilled().is_some() {
+                        fs::create_dir_all(spill_dir)?;
+                        let stem = Path::new(file_path).file_name().unwrap_or_default().to_string_lossy();
+                        let name = |stream| spill_dir.join(format!("{}.{}.{}", stem, block.index, stream));
+                        result.stdout.persist(name("stdout"))?;
+                        result.stderr.persist(name("stderr"))?;
                     }
-                    continue;
                 }
+                if let Some(key) = key {
+                    let origin = Origin {
+                        file: file_path.to_string(),
+                        index: block.index,
+                    };
+                    self.seen.insert(key, (origin, result.clone()));
+                }
+                if let (Some(recording), Some(key), true) = (&mut self.recording, recorded_key, opts.record) {
+                    recording.record(key, &block, &result)?;
+                }
+                result
             }
+        };
+        if self.encoding == encoding::FileEncoding::Auto && block.code.contains(char::REPLACEMENT_CHARACTER) {
+            result
+                .warnings
+                .push("the code had bytes that aren't UTF-8, which were replaced with U+FFFD".to_string());
         }
-        i += 1;
+        expect::check(&block, &mut result, opts.diff_context, opts.expect_newlines);
+        if let Some((ErrorKind::ExpectMismatch, _)) = result.error {
+            if let Some(dir) = &opts.write_actual {
+                let stem = Path::new(file_path).file_name().unwrap_or_default().to_string_lossy();
+                let path = dir.join(format!("{}.{}.actual", stem, block.index));
+                fs::create_dir_all(dir)?;
+                result.stdout.write_to(&path)?;
+                opts.log(format_args!("Wrote actual output to {}", path.display()));
+            }
+            if opts.update_expected {
+                let expected = block.expected.as_ref().expect("mismatch implies a section");
+                self.updates.push((
+                    expected.body.start..expected.body.end,
+                    expect::indent(&result.stdout.bytes()?, &expected.indent),
+                ));
+                result.status = Status::Passed;
+                result.error = None;
+                writeln!(
+                    opts.console.err(),
+                    "{}:{}: updated expected output",
+                    file_path, block.span.start_line
+                )?;
+            }
+        }
+        if opts.inline_output && self.syntax == Syntax::Notebook {
+            self.cells.extend(ipynb::outputs(&result).map(|outputs| (block.span.start_line, outputs)));
+        } else if opts.inline_output {
+            self.recorded.extend(notebook::record(&block, &result, self.marker));
+        }
+        self.summary.add(file_path, &block, &result);
+        for reporter in self.reporters.iter_mut() {
+            reporter.block_finished(file_path, &block, &result)?;
+        }
+        Ok(result.status)
+    }
+
+    /// Why `--changed-only` leaves `block` out, if it does.
+    fn left_out(&self, block: &Block) -> Option<executor::ExecError> {
+        let (selected, reason) = self.unchanged.as_ref()?;
+        (!selected.contains(&block.index)).then(|| executor::ExecError {
+            kind: ErrorKind::Unchanged,
+            message: reason.clone(),
+            output: None,
+        })
     }
-    blocks
 }
 
-fn execute_code(lang: &str, code: &str, verbose: bool) -> Result<String, Box<dyn std::error::Error>> {
-    let dir = tempdir()?;
-    if verbose {
-        println!("Temp dir: {:?}", dir.path());
+/// Why `block` isn't run at all, if it isn't: its language isn't allowed, one
+/// of its `if-*` conditions doesn't hold, or a block it needs didn't pass.
+fn skip_reason(block: &Block, opts: &Options, needed: &[(needs::Prerequisite, Status)]) -> Option<executor::ExecError> {
+    let (kind, message) = if !opts.language_allowed(&block.lang) {
+        (ErrorKind::NotAllowed, format!("{} blocks aren't allowed by --allow-lang", block.lang))
+    } else if let Some(condition) = conditions::unmet(&block.params, &opts.tools) {
+        (ErrorKind::ConditionUnmet, condition.reason())
+    } else {
+        let (prerequisite, status) = needed.iter().find(|(_, status)| *status != Status::Passed)?;
+        let how = if *status == Status::Failed { "failed" } else { "was skipped" };
+        let message = format!(
+            "dependency failed: it needs {} (line {}), which {}",
+            prerequisite.name, prerequisite.line, how
+        );
+        (ErrorKind::DependencyFailed, message)
+    };
+    Some(executor::ExecError {
+        kind,
+        message,
+        output: None,
+    })
+}
+
+/// Copies `source` to `dest` with non-overlapping replacements spliced in,
+/// streaming so neither file is held in memory. Writing goes through a
+/// temporary file, so `dest` may be `source`. Text inserted at the end of a
+/// file without a final newline starts on a line of its own, and text
+/// replacing the end of such a file doesn't add one. Replacements are
+/// written in the file's `encoding`.
+fn rewrite(
+    source: &Path,
+    dest: &Path,
+    mut edits: Vec<(Range<usize>, String)>,
+    encoding: encoding::FileEncoding,
+) -> io::Result<()> {
+    edits.sort_by_key(|(range, _)| range.start);
+    let mut file = File::open(source)?;
+    let len = file.metadata()?.len() as usize;
+    let mut last = [b'\n'];
+    if len > 0 {
+        file.seek(SeekFrom::End(-1))?;
+        file.read_exact(&mut last)?;
+        file.rewind()?;
     }
-    match lang {
-        "rust" => execute_rust(code, &dir),
-        "java" => execute_java(code, &dir),
-        "python" => execute_python(code),
-        "go" => execute_go(code, &dir),
-        _ => Err(format!("Unsupported language: {}", lang).into()),
+    let final_newline = last[0] == b'\n';
+    let mut input = BufReader::new(file);
+    let dir = dest.parent().filter(|d| !d.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let mut temp = tempfile::NamedTempFile::new_in(dir)?;
+    let mut output = BufWriter::new(temp.as_file_mut());
+    let mut position = 0;
+    for (range, replacement) in edits {
+        io::copy(&mut input.by_ref().take((range.start - position) as u64), &mut output)?;
+        io::copy(&mut input.by_ref().take(range.len() as u64), &mut io::sink())?;
+        position = range.end;
+        let mut replacement = replacement.as_str();
+        if range.end == len && !final_newline {
+            if range.is_empty() {
+                output.write_all(b"\n")?;
+            } else {
+                replacement = replacement.trim_end_matches(['\r', '\n']);
+            }
+        }
+        output.write_all(&encoding.encode(replacement))?;
     }
+    io::copy(&mut input, &mut output)?;
+    output.flush()?;
+    drop(output);
+    fs::set_permissions(temp.path(), fs::metadata(source)?.permissions())?;
+    temp.persist(dest).map_err(|e| e.error)?;
+    Ok(())
 }
 
-fn execute_rust(code: &str, dir: &TempDir) -> Result<String, Box<dyn std::error::Error>> {
-    let file_path = dir.path().join("main.rs");
-    fs::write(&file_path, code)?;
-    let output = Command::new("rustc")
-        .arg(&file_path)
-        .arg("-o")
-        .arg(dir.path().join("a.out"))
-        .stderr(Stdio::piped())
-        .stdout(Stdio::piped())
-        .spawn()?
-        .wait_with_output()?;
-    if !output.status.success() {
-        return Err(String::from_utf8_lossy(&output.stderr).to_string().into());
+/// Writes an exported script to `path`, executable where that means something.
+fn write_script(path: &Path, script: &str) -> io::Result<()> {
+    fs::write(path, script)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o755))?;
     }
-    let run_output = Command::new(dir.path().join("a.out"))
-        .stderr(Stdio::piped())
-        .stdout(Stdio::piped())
-        .spawn()?
-        .wait_with_output()?;
-    if run_output.status.success() {
-        Ok(String::from_utf8_lossy(&run_output.stdout).to_string())
-    } else {
-        Err(String::from_utf8_lossy(&run_output.stderr).to_string().into())
-    }
-}
-
-fn execute_java(code: &str, dir: &TempDir) -> Result<String, Box<dyn std::error::Error>> {
-    let file_path = dir.path().join("Main.java");
-    fs::write(&file_path, code)?;
-    let output = Command::new("javac")
-        .arg(&file_path)
-        .stderr(Stdio::piped())
-        .stdout(Stdio::piped())
-        .spawn()?
-        .wait_with_output()?;
-    if !output.status.success() {
-        return Err(String::from_utf8_lossy(&output.stderr).to_string().into());
-    }
-    let run_output = Command::new("java")
-        .arg("-cp")
-        .arg(dir.path())
-        .arg("Main")
-        .stderr(Stdio::piped())
-        .stdout(Stdio::piped())
-        .spawn()?
-        .wait_with_output()?;
-    if run_output.status.success() {
-        Ok(String::from_utf8_lossy(&run_output.stdout).to_string())
-    } else {
-        Err(String::from_utf8_lossy(&run_output.stderr).to_string().into())
+    Ok(())
+}
+
+fn number(flag: &str, value: &str) -> Result<usize, CliError> {
+    value
+        .parse()
+        .map_err(|_| usage_error(format!("{} expects a number, got {}", flag, value)))
+}
+
+/// A size given in MB, such as `--max-disk 500`, in bytes.
+fn megabytes(flag: &str, value: &str) -> Result<u64, CliError> {
+    let megabytes = number(flag, value)? as u64;
+    Ok(megabytes.saturating_mul(1024 * 1024))
+}
+
+const USAGE: &str = "\
+Usage: translator [run] <hacker_file>... [options]
+       translator list-languages [--no-plugins]
+       translator doctor [<hacker_file>...] [--no-plugins]
+       translator cache clear [--cache-dir DIR]
+       translator cache verify <hacker_file>...
+       translator export-script <hacker_file>... [--out PATH] [--fail-fast] [options]
+       translator daemon [--socket PATH] [--idle-timeout SECS]
+       translator client [--socket PATH] [--shutdown] [<hacker_file>... [options]]
+
+Options:
+  --verbose          log extraction and execution details
+  --quiet            print only program output and errors
+  --raw              print each block's stdout byte for byte, without headers or
+                     a summary; errors still go to stderr
+  --prefix           start every line of output with [index:lang:name]
+  --timings          show compile and run time per block and language
+  --rusage           show the peak memory and user and system CPU time of each
+                     block's program
+  --encoding ENCODING
+                     how hacker files are decoded: utf8 (the default), latin1 or
+                     another encoding, or auto to replace bytes that aren't UTF-8
+  --output-encoding ENCODING
+                     what output that isn't UTF-8 is decoded from: auto (the
+                     default) for the console's code page on Windows, or an
+                     encoding; utf8 leaves it as it is
+  --header-format TEMPLATE
+                     line printed above each block's result, with placeholders
+                     {index} {lang} {name} {label} {file} {start} {end}
+                     {status} {exit_code} {duration} {timings} {rusage}; empty
+                     for none
+  --marker STRING    directive lines start with STRING instead of |>, as in
+                     `STRING translator:rust (`
+  --input-format FORMAT
+                     read every file as hacker, org or ipynb, instead of by
+                     its extension (.org and .ipynb, or else hacker)
+  --config PATH      read settings from PATH instead of the nearest translator.toml
+  --no-plugins       don't look for translator-exec-<lang> plugins
+  --cache-dir DIR    keep compiled blocks in DIR (default $XDG_CACHE_HOME/translator)
+  --no-cache         always compile, and don't store anything
+  --no-dedup         run identical blocks again instead of reusing the first result
+  --strict           fail empty blocks instead of skipping them
+  --deny-warnings    fail blocks whose compiler printed warnings
+  --inherit-stdin    let blocks read translator's stdin; by default it is closed
+  --repl-semantics   print the value of python blocks that are a single
+                     expression, like the interactive interpreter
+  --keep-temp        keep the run directory with every block's sources and builds
+  --spill-threshold BYTES
+                     write output beyond BYTES to disk (default 1048576, 0 = never)
+  --spill-dir DIR    keep spilled output in DIR; reports then refer to the files
+  --backend BACKEND  native (default), docker or podman to run every block in
+                     a container, ssh://[USER@]HOST[:PORT] to run it there, or
+                     wsl to run it in WSL (on Windows, blocks whose toolchain
+                     is missing run there anyway)
+  --yes, -y          run files outside trusted directories without asking
+  --allow-lang LANGS skip blocks in languages not in the comma-separated LANGS
+  --changed-only[=BASE]
+                     skip files that git diff BASE...HEAD doesn't touch
+                     (default origin/main)
+  --changed-blocks   with --changed-only, also skip blocks whose lines it
+                     doesn't touch
+  --record           keep each block's result in FILE.translator-cache
+  --replay[=strict]  reuse the results --record kept for blocks whose code
+                     hasn't changed, and run the others (with =strict, fail them)
+  --set NAME=VALUE   replace {{NAME}} in blocks with VALUE, over the file's
+                     '|> set: NAME=VALUE' lines
+  --audit-log PATH   append a line of JSON to PATH for every process started
+                     for a block, without its output
+  --no-network       run every step of a block without a network (Linux, or in
+                     containers)
+  --allow-dependency-network
+                     with --no-network, still let steps that fetch a block's
+                     dependencies use the network
+  --run-as USER      run blocks' programs as USER, which needs root (Unix);
+                     the cache is off
+  --allow-root       let --run-as run blocks as root
+  --max-file-size MB a block's program can't write a file larger than MB (Unix)
+  --max-compile-file-size MB
+                     the same for compile steps (default 4 times the above)
+  --max-procs N      a block's program can have N more processes and threads
+                     than its user already has (Unix; default 64, 0 = no limit)
+  --max-disk MB      warn about blocks whose directory ends up over MB
+  --deny-disk        with --max-disk, fail them instead
+  --sandbox fs       confine blocks with Landlock to the system and toolchain
+                     directories and their own (Linux 5.13+); fs=best-effort
+                     runs them unconfined, with a warning, where it can't
+  --sandbox-cmd CMD  start every compile and run step through CMD, such as
+                     'bwrap --bind {dir} {dir} --'; {dir} is the block's
+                     directory, {cache} the cache and {cmd} the step
+  --format FORMAT    human (default), json, ndjson, tap or gh-annotations
+                     (gh-annotations is the default when GITHUB_ACTIONS=true)
+  --output PATH      write the --format document to PATH
+  --report KIND=PATH also write a report; KIND is junit, markdown or html
+  --notify-url URL   POST the --format json document to URL when the run is over
+  --notify-cmd CMD   run CMD with that document on stdin when the run is over
+  --report-max-lines N
+                     truncate output in reports after N lines (default 100)
+  --diff-context N   context lines around expect mismatches (default 3)
+  --expect-newlines MODE
+                     how expect sections treat line endings: exact (default),
+                     trailing to ignore newlines at the end, or lenient to also
+                     take CRLF as LF
+  --update-expected  rewrite mismatching |> expect: sections with the actual output
+  --write-actual DIR save the actual output of mismatching blocks in DIR
+  --artifacts-dir DIR
+                     copy the files of artifacts= to DIR/<block name>
+                     (default ./translator-artifacts)
+  --inline-output    record each block's output in FILE.out.EXT after the block,
+                     or in the cells' outputs for a .ipynb notebook
+  --in-place         with --inline-output, rewrite the hacker file itself
+  --out PATH         with export-script, write the script to PATH instead of stdout
+  --fail-fast        with export-script, stop the script at the first failing block
+";
+
+/// Prints the version of every built-in toolchain, or why it's missing, and
+/// the same for WSL where blocks could run there. With hacker files, also
+/// checks that everything their blocks need is there and fails if not.
+fn doctor(files: &[String], opts: &Options) -> Result<u8, CliError> {
+    let checks = toolchain::check_all(&opts.tools);
+    let mut out = opts.console.out();
+    let print = |out: &mut dyn Write, check: &toolchain::Check| {
+        let program = match &check.source {
+            Some(source) => format!(" ({}, from {})", check.program, source),
+            None if check.program != check.tool => format!(" ({})", check.program),
+            None => String::new(),
+        };
+        match &check.version {
+            Ok(version) => writeln!(out, "{:<8} {:<7} {}{}", check.lang, check.tool, version, program),
+            Err(problem) => writeln!(out, "{:<8} {:<7} missing: {}{}", check.lang, check.tool, problem, program),
+        }
+    };
+    for check in &checks {
+        print(&mut *out, check)?;
+    }
+    // What isn't installed natively runs in WSL on Windows, and everything
+    // does with --backend wsl.
+    let native = !matches!(opts.backend, backend::Kind::Wsl);
+    let wsl_checks = match opts.backend {
+        backend::Kind::Wsl => Some(toolchain::check_wsl()),
+        backend::Kind::Native if cfg!(windows) && backend::Wsl::available() => Some(toolchain::check_wsl()),
+        _ => None,
+    };
+    let found = |tool: &str| {
+        let found = |checks: &[toolchain::Check]| checks.iter().any(|check| check.tool == tool && check.version.is_ok());
+        (native && found(&checks)) || wsl_checks.as_deref().is_some_and(found)
+    };
+    if let Some(wsl_checks) = &wsl_checks {
+        writeln!(out)?;
+        writeln!(out, "In WSL:")?;
+        for check in wsl_checks {
+            print(&mut *out, check)?;
+        }
+        writeln!(out)?;
+        // By the program that identifies each language's toolchain.
+        let mut firsts: Vec<&toolchain::Check> = checks.iter().collect();
+        firsts.dedup_by_key(|check| check.lang);
+        for first in firsts {
+            let natively = native && first.version.is_ok();
+            let in_wsl = wsl_checks.iter().any(|check| check.tool == first.tool && check.version.is_ok());
+            let runs = if natively {
+                "runs natively"
+            } else if in_wsl {
+                "runs in WSL"
+            } else {
+                "can't run"
+            };
+            writeln!(out, "{:<8} {}", first.lang, runs)?;
+        }
+    }
+    let mut missing = BTreeSet::new();
+    for file in files {
+        let marker = opts.marker(Path::new(file))?;
+        parse_file(Path::new(file), opts, &marker, |parsed| {
+            let Parsed::Block(block) = parsed else {
+                return Ok(());
+            };
+            if !languages::BUILTIN.contains(&block.lang.as_str()) {
+                if !opts.plugins || plugin::find(&block.lang).is_none() {
+                    missing.insert(format!("{} (no translator-exec-{} plugin)", block.lang, block.lang));
+                }
+                return Ok(());
+            }
+            for tool in toolchain::required(&block) {
+                if !found(tool) {
+                    missing.insert(format!("{} ({})", block.lang, tool));
+                }
+            }
+            Ok(())
+        })
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", file, e)))?;
     }
+    if missing.is_empty() {
+        return Ok(0);
+    }
+    writeln!(out)?;
+    writeln!(out, "Missing for {}:", files.join(", "))?;
+    for lang in missing {
+        writeln!(out, "  {}", lang)?;
+    }
+    Ok(1)
 }
 
-fn execute_python(code: &str) -> Result<String, Box<dyn std::error::Error>> {
-    let output = Command::new("python")
-        .arg("-c")
-        .arg(code)
-        .stderr(Stdio::piped())
-        .stdout(Stdio::piped())
-        .spawn()?
-        .wait_with_output()?;
-    if output.status.success() {
-        Ok(String::from_utf8_lossy(&output.stdout).to_string())
-    } else {
-        Err(String::from_utf8_lossy(&output.stderr).to_string().into())
-    }
-}
-
-fn execute_go(code: &str, dir: &TempDir) -> Result<String, Box<dyn std::error::Error>> {
-    let file_path = dir.path().join("main.go");
-    fs::write(&file_path, code)?;
-    let output = Command::new("go")
-        .arg("run")
-        .arg(&file_path)
-        .stderr(Stdio::piped())
-        .stdout(Stdio::piped())
-        .spawn()?
-        .wait_with_output()?;
-    if output.status.success() {
-        Ok(String::from_utf8_lossy(&output.stdout).to_string())
-    } else {
-        Err(String::from_utf8_lossy(&output.stderr).to_string().into())
+fn list_languages(opts: &Options) -> io::Result<()> {
+    let mut out = opts.console.out();
+    for lang in languages::BUILTIN {
+        writeln!(out, "{:<12} built-in", lang)?;
+    }
+    if !opts.plugins {
+        return writeln!(out, "(plugin discovery disabled by --no-plugins)");
+    }
+    for (lang, path) in plugin::discover() {
+        if languages::BUILTIN.contains(&lang.as_str()) {
+            writeln!(out, "{:<12} plugin {} (shadowed by built-in)", lang, path.display())?;
+        } else {
+            writeln!(out, "{:<12} plugin {}", lang, path.display())?;
+        }
+    }
+    Ok(())
+}
+
+/// `translator cache verify`: lists the blocks `--replay` would have to run,
+/// those whose code changed or that were never recorded, and fails if there
+/// are any.
+fn verify_recordings(files: &[String], opts: &Options) -> io::Result<u8> {
+    let mut out = opts.console.out();
+    let mut stale = 0;
+    for file in files {
+        let path = Path::new(file);
+        let recording = replay::Recording::load(path)?;
+        let marker = opts.marker(path)?;
+        let mut keys = Vec::new();
+        parse_file(path, opts, &marker, |parsed| {
+            let Parsed::Block(block) = parsed else {
+                return Ok(());
+            };
+            if block.is_empty() && !opts.strict {
+                return Ok(());
+            }
+            let key = replay::key(&block);
+            if !recording.contains(&key) {
+                stale += 1;
+                writeln!(out, "{}:{}: {} is stale", file, block.span.start_line, block.label())?;
+            }
+            keys.push(key);
+            Ok(())
+        })
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", file, e)))?;
+        let unused = recording.unused(&keys);
+        if unused > 0 {
+            writeln!(
+                out,
+                "{}: {} recorded {} no block has any more",
+                recording.path().display(),
+                unused,
+                if unused == 1 { "result" } else { "results" }
+            )?;
+        }
+    }
+    if stale > 0 {
+        let blocks = if stale == 1 { "block" } else { "blocks" };
+        writeln!(out, "{} stale {}; run with --record to record them", stale, blocks)?;
+        return Ok(1);
     }
+    writeln!(out, "Every block's result is recorded")?;
+    Ok(0)
 }
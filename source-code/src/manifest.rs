@@ -0,0 +1,110 @@
+//! Cargo manifests for rust blocks built in cargo mode. Dependencies come
+//! from a `deps="serde=1,rand=0.8"` parameter or, as with rust-script, from a
+//! manifest embedded in the block's leading doc comment:
+//!
+//! ```text
+//! //! ```cargo
+//! //! [dependencies]
+//! //! rand = "0.8"
+//! //! ```
+//! ```
+
+use toml::{Table, Value};
+
+use crate::flags;
+use crate::parser::Block;
+
+/// The package name every block is built as, so its binary can be found.
+pub const PACKAGE: &str = "block";
+
+/// Whether the block is built with cargo rather than bare rustc: asked for
+/// with `cargo`, or implied by dependencies.
+pub fn cargo_mode(block: &Block) -> bool {
+    block.lang == "rust"
+        && (block.params.get("cargo") == Some("true")
+            || block.params.get("deps").is_some()
+            || embedded(&block.code).is_some())
+}
+
+/// The text inside a ```` ```cargo ```` fence in the doc comment the code
+/// starts with.
+fn embedded(code: &str) -> Option<String> {
+    let mut lines = code
+        .lines()
+        .skip_while(|line| line.trim().is_empty())
+        .map_while(|line| line.trim_start().strip_prefix("//!"))
+        .map(|line| line.strip_prefix(' ').unwrap_or(line));
+    lines.find(|line| line.trim() == "```cargo")?;
+    let mut manifest = String::new();
+    for line in lines {
+        if line.trim() == "```" {
+            return Some(manifest);
+        }
+        manifest.push_str(line);
+        manifest.push('\n');
+    }
+    None
+}
+
+/// The block's `Cargo.toml`: its embedded manifest, if any, with the
+/// dependencies from `deps=` added and the package named [`PACKAGE`].
+pub fn generate(block: &Block) -> Result<String, String> {
+    let mut manifest = match embedded(&block.code) {
+        Some(text) => text
+            .parse::<Table>()
+            .map_err(|e| format!("the block's cargo manifest is invalid: {}", e.message()))?,
+        None => Table::new(),
+    };
+    let package = table(&mut manifest, "package")?;
+    package.insert("name".to_string(), PACKAGE.into());
+    package.entry("version").or_insert_with(|| "0.0.0".into());
+    match flags::rust_edition(block)? {
+        Some(edition) => {
+            package.insert("edition".to_string(), edition.into());
+        }
+        None => {
+            package.entry("edition").or_insert_with(|| "2021".into());
+        }
+    }
+    // Keeps the package out of any workspace the run directory is inside.
+    table(&mut manifest, "workspace")?;
+    if let Some(deps) = block.params.get("deps") {
+        let dependencies = table(&mut manifest, "dependencies")?;
+        for dep in deps.split(',').map(str::trim).filter(|dep| !dep.is_empty()) {
+            let (name, version) = match dep.split_once('=') {
+                Some((name, version)) => (name.trim(), version.trim()),
+                None => (dep, "*"),
+            };
+            if name.is_empty() || version.is_empty() {
+                return Err(format!("deps entries are NAME=VERSION, got {}", dep));
+            }
+            dependencies.insert(name.to_string(), version.into());
+        }
+    }
+    toml::to_string(&manifest).map_err(|e| e.to_string())
+}
+
+/// Whether a generated manifest has anything to fetch.
+pub fn has_dependencies(manifest: &str) -> bool {
+    let Ok(manifest) = manifest.parse::<Table>() else {
+        return false;
+    };
+    ["dependencies", "build-dependencies"]
+        .iter()
+        .any(|key| manifest.get(*key).and_then(Value::as_table).is_some_and(|deps| !deps.is_empty()))
+}
+
+/// The edition a generated manifest builds with.
+pub fn edition(manifest: &str) -> Option<String> {
+    let manifest = manifest.parse::<Table>().ok()?;
+    let edition = manifest.get("package")?.get("edition")?.as_str()?;
+    Some(edition.to_string())
+}
+
+fn table<'a>(manifest: &'a mut Table, key: &str) -> Result<&'a mut Table, String> {
+    manifest
+        .entry(key)
+        .or_insert_with(|| Value::Table(Table::new()))
+        .as_table_mut()
+        .ok_or_else(|| format!("`{}` in the block's cargo manifest must be a table", key))
+}
@@ -0,0 +1,172 @@
+//! Ordering blocks by `needs=`. A block with `needs=generate` runs after the
+//! block named `generate` in the same file, even one further down, and only
+//! if that passed; `needs="a, b"` names several. Blocks run in file order
+//! otherwise: one whose prerequisites haven't run yet is held back until
+//! they have.
+
+use std::collections::{HashMap, HashSet};
+use std::io;
+
+use crate::executor::Status;
+use crate::parser::Block;
+
+/// A block another one needs.
+#[derive(Clone, Debug)]
+pub struct Prerequisite {
+    pub index: usize,
+    pub name: String,
+    /// Where the block starts.
+    pub line: usize,
+}
+
+struct Node {
+    index: usize,
+    line: usize,
+    name: Option<String>,
+    needs: Vec<String>,
+}
+
+/// The blocks of a file and what they need, gathered before any of them
+/// runs.
+#[derive(Default)]
+pub struct Graph {
+    nodes: Vec<Node>,
+    /// Each block's prerequisites, by index, once [`Graph::check`] has
+    /// resolved the names.
+    edges: HashMap<usize, Vec<Prerequisite>>,
+}
+
+impl Graph {
+    pub fn add(&mut self, block: &Block) {
+        let needs = block.params.get("needs").unwrap_or_default();
+        self.nodes.push(Node {
+            index: block.index,
+            line: block.span.start_line,
+            name: block.name().map(str::to_string),
+            needs: needs.split(',').map(str::trim).filter(|name| !name.is_empty()).map(str::to_string).collect(),
+        });
+    }
+
+    /// Resolves every `needs=` to a block, and fails on names no block or
+    /// more than one has, and on blocks that end up needing themselves.
+    pub fn check(&mut self, file: &str) -> io::Result<()> {
+        let invalid = |line: usize, message: String| io::Error::new(io::ErrorKind::InvalidData, format!("{}:{}: {}", file, line, message));
+        let mut by_name: HashMap<&str, &Node> = HashMap::new();
+        for node in &self.nodes {
+            if let Some(name) = &node.name {
+                by_name.entry(name).or_insert(node);
+            }
+        }
+        let mut edges = HashMap::new();
+        for node in self.nodes.iter().filter(|node| !node.needs.is_empty()) {
+            let mut prerequisites = Vec::new();
+            for name in &node.needs {
+                let Some(needed) = by_name.get(name.as_str()) else {
+                    return Err(invalid(node.line, format!("needs={}, but no block is named {}", name, name)));
+                };
+                let other = self.nodes.iter().find(|other| other.name == needed.name && other.index != needed.index);
+                if let Some(other) = other {
+                    return Err(invalid(
+                        node.line,
+                        format!("needs={}, but the blocks at lines {} and {} are both named that", name, needed.line, other.line),
+                    ));
+                }
+                prerequisites.push(Prerequisite {
+                    index: needed.index,
+                    name: name.clone(),
+                    line: needed.line,
+                });
+            }
+            edges.insert(node.index, prerequisites);
+        }
+        self.edges = edges;
+        for node in &self.nodes {
+            if let Some(cycle) = self.cycle(node.index, &mut Vec::new()) {
+                let mut path = vec![format!("{} (line {})", node.name.as_deref().unwrap_or_default(), node.line)];
+                path.extend(cycle.iter().map(|p| format!("{} (line {})", p.name, p.line)));
+                return Err(invalid(node.line, format!("needs= makes a cycle: {}", path.join(" -> "))));
+            }
+        }
+        Ok(())
+    }
+
+    /// The prerequisites on a path from `index` back to a block already on
+    /// `path`, if there is one.
+    fn cycle(&self, index: usize, path: &mut Vec<usize>) -> Option<Vec<Prerequisite>> {
+        if path.contains(&index) {
+            return Some(Vec::new());
+        }
+        path.push(index);
+        for prerequisite in self.edges.get(&index).into_iter().flatten() {
+            if let Some(mut cycle) = self.cycle(prerequisite.index, path) {
+                cycle.insert(0, prerequisite.clone());
+                return Some(cycle);
+            }
+        }
+        path.pop();
+        None
+    }
+
+    /// `blocks` and every block they need, directly or not.
+    pub fn with_prerequisites(&self, mut blocks: HashSet<usize>) -> HashSet<usize> {
+        let mut pending: Vec<usize> = blocks.iter().copied().collect();
+        while let Some(index) = pending.pop() {
+            for prerequisite in self.prerequisites(index) {
+                if blocks.insert(prerequisite.index) {
+                    pending.push(prerequisite.index);
+                }
+            }
+        }
+        blocks
+    }
+
+    fn prerequisites(&self, index: usize) -> &[Prerequisite] {
+        self.edges.get(&index).map(Vec::as_slice).unwrap_or_default()
+    }
+}
+
+/// Hands blocks on in file order, except that a block waits for the blocks
+/// it needs.
+pub struct Order {
+    graph: Graph,
+    finished: HashMap<usize, Status>,
+    held: Vec<Block>,
+}
+
+impl Order {
+    pub fn new(graph: Graph) -> Self {
+        Order {
+            graph,
+            finished: HashMap::new(),
+            held: Vec::new(),
+        }
+    }
+
+    /// Gives `block` to `run` once its prerequisites have been, along with
+    /// how they went, and then any held-back blocks that were waiting for
+    /// it. `run` returns how the block went.
+    pub fn push(
+        &mut self,
+        block: Block,
+        run: &mut dyn FnMut(Block, Vec<(Prerequisite, Status)>) -> io::Result<Status>,
+    ) -> io::Result<()> {
+        self.held.push(block);
+        while let Some(position) = self.held.iter().position(|block| self.ready(block)) {
+            let block = self.held.remove(position);
+            let index = block.index;
+            let prerequisites = self
+                .graph
+                .prerequisites(index)
+                .iter()
+                .map(|prerequisite| (prerequisite.clone(), self.finished[&prerequisite.index]))
+                .collect();
+            let status = run(block, prerequisites)?;
+            self.finished.insert(index, status);
+        }
+        Ok(())
+    }
+
+    fn ready(&self, block: &Block) -> bool {
+        self.graph.prerequisites(block.index).iter().all(|prerequisite| self.finished.contains_key(&prerequisite.index))
+    }
+}
@@ -0,0 +1,58 @@
+//! `--inline-output`: recording each block's result in the hacker file
+//! itself, as an `|> output:` or `|> error:` section after the block.
+
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use crate::executor::{ExecutionResult, Status};
+use crate::expect;
+use crate::parser::Block;
+
+/// The edit that records `result` for `block`: it replaces the section
+/// recorded by an earlier run, or inserts one after the block and its
+/// expect section. Skipped blocks keep whatever they had. The section starts
+/// with the file's `marker`.
+pub fn record(block: &Block, result: &ExecutionResult, marker: &str) -> Option<(Range<usize>, String)> {
+    let (keyword, text) = match result.status {
+        Status::Passed => {
+            let mut text = result.stdout.bytes().ok()?.into_owned();
+            if !result.artifacts.is_empty() && !text.is_empty() && !text.ends_with(b"\n") {
+                text.push(b'\n');
+            }
+            for path in &result.artifacts {
+                text.extend_from_slice(format!("artifact: {}\n", path.display()).as_bytes());
+            }
+            ("output", text)
+        }
+        Status::Failed => {
+            let message = result.error.as_ref().map(|(_, m)| m.as_str()).unwrap_or("");
+            ("error", message.as_bytes().to_vec())
+        }
+        Status::Skipped => return None,
+    };
+    let leading = &block.indent;
+    let mut section = format!("{}{} {}: (\n", leading, marker, keyword);
+    section.push_str(&expect::indent(&text, &format!("{}    ", leading)));
+    section.push_str(leading);
+    section.push_str(")\n");
+    if block.crlf {
+        section = section.replace('\n', "\r\n");
+    }
+    let range = match (block.recorded, &block.expected) {
+        (Some(recorded), _) => recorded.start..recorded.end,
+        (None, Some(expected)) => expected.span.end..expected.span.end,
+        (None, None) => block.span.end..block.span.end,
+    };
+    Some((range, section))
+}
+
+/// Where `--inline-output` writes without `--in-place`: `notes.hack` becomes
+/// `notes.out.hack`.
+pub fn out_path(file: &Path) -> PathBuf {
+    let stem = file.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match file.extension() {
+        Some(ext) => format!("{}.out.{}", stem, ext.to_string_lossy()),
+        None => format!("{}.out", stem),
+    };
+    file.with_file_name(name)
+}
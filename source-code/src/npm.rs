@@ -0,0 +1,51 @@
+//! npm packages for javascript and typescript blocks, which run through a
+//! plugin. The packages in a block's `deps="lodash@4,axios"` parameter are
+//! installed by translator itself before the plugin starts, once per set of
+//! packages, and the plugin finds them through `NODE_PATH`.
+
+use std::env;
+use std::path::Path;
+
+use crate::parser::Block;
+
+/// The languages whose blocks get their `deps` installed with npm.
+pub const LANGUAGES: &[&str] = &["javascript", "typescript"];
+
+/// The package manager `TRANSLATOR_NPM` names, npm unless it names pnpm or
+/// yarn.
+pub fn manager() -> String {
+    env::var("TRANSLATOR_NPM").ok().filter(|npm| !npm.is_empty()).unwrap_or_else(|| "npm".to_string())
+}
+
+/// What `manager` installs packages with, quietly.
+pub fn install_args(manager: &str) -> &'static [&'static str] {
+    let name = Path::new(manager).file_stem().and_then(|name| name.to_str()).unwrap_or(manager);
+    match name {
+        "pnpm" => &["add", "--silent"],
+        "yarn" => &["add", "--silent", "--no-progress"],
+        _ => &["install", "--silent", "--no-audit", "--no-fund"],
+    }
+}
+
+/// The packages in the block's `deps=`, sorted so the same set always
+/// installs into the same directory. Empty for blocks in other languages.
+pub fn packages(block: &Block) -> Vec<String> {
+    if !LANGUAGES.contains(&block.lang.as_str()) {
+        return Vec::new();
+    }
+    let mut packages: Vec<String> = block
+        .params
+        .get("deps")
+        .unwrap_or("")
+        .split(',')
+        .map(str::trim)
+        .filter(|package| !package.is_empty())
+        .map(str::to_string)
+        .collect();
+    packages.sort();
+    packages.dedup();
+    packages
+}
+
+/// The `package.json` packages are installed next to.
+pub const PACKAGE_JSON: &str = "{ \"private\": true }\n";
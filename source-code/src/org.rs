@@ -0,0 +1,431 @@
+//! Extraction of babel source blocks from org files:
+//!
+//! ```text
+//! #+NAME: demo
+//! #+BEGIN_SRC rust :flags "-O"
+//! fn main() {}
+//! #+END_SRC
+//!
+//! #+RESULTS:
+//! : what it prints
+//! ```
+//!
+//! The language goes through the same aliases as a directive's, and each
+//! `:key value` header argument becomes the parameter `key=value`, except
+//! `:tangle no`, which leaves the block out, and `:var`, which is ignored.
+//! `#+NAME:` names the block. A `#+RESULTS:` section after it, as `: `
+//! lines or an example block, is its expected output. Blocks are found
+//! however they are indented, in lists and drawers alike.
+
+use crate::languages;
+use crate::parser::{self, Block, DiagnosticKind, Expected, LineParser, ParseDiagnostic, Span};
+
+struct OpenSrc {
+    lang: String,
+    params: Vec<(String, String)>,
+    /// Whether `:tangle no` leaves the block out.
+    skip: bool,
+    indent: String,
+    start_line: usize,
+    start: usize,
+    lines: Vec<String>,
+}
+
+/// The forms a `#+RESULTS:` section takes.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Form {
+    /// Nothing seen after the keyword yet.
+    Unknown,
+    /// Lines starting with `:`.
+    FixedWidth,
+    /// `#+BEGIN_EXAMPLE` to `#+END_EXAMPLE`.
+    Example,
+}
+
+struct OpenResults {
+    block: Block,
+    form: Form,
+    start_line: usize,
+    /// Where the `#+RESULTS:` line starts.
+    opening: usize,
+    /// Where the body starts, and where its last line so far ends.
+    start: usize,
+    end: usize,
+    /// Leading whitespace of the `#+RESULTS:` line.
+    leading: String,
+    lines: Vec<String>,
+}
+
+/// Incremental parser for org files, fed one line at a time like
+/// [`parser::Parser`].
+pub struct OrgParser {
+    line_no: usize,
+    end: usize,
+    crlf: bool,
+    next_index: usize,
+    /// The `#+NAME:` of the element that follows, if it comes next.
+    name: Option<String>,
+    open: Option<OpenSrc>,
+    pending: Option<Block>,
+    results: Option<OpenResults>,
+    diagnostics: Vec<ParseDiagnostic>,
+}
+
+impl Default for OrgParser {
+    fn default() -> Self {
+        OrgParser::new()
+    }
+}
+
+impl OrgParser {
+    pub fn new() -> Self {
+        OrgParser {
+            line_no: 0,
+            end: 0,
+            crlf: false,
+            next_index: 1,
+            name: None,
+            open: None,
+            pending: None,
+            results: None,
+            diagnostics: Vec::new(),
+        }
+    }
+
+    /// The body of a `#+RESULTS:` section, once it is there. What ends a
+    /// fixed-width section isn't part of it and is handled as usual.
+    fn results_line(&mut self, mut open: OpenResults, text: &str, here: Span) -> bool {
+        let trimmed = text.trim_start();
+        if open.form == Form::Unknown {
+            if keyword(trimmed, "#+begin_example").is_some() {
+                open.form = Form::Example;
+                open.start = here.end;
+                open.end = here.end;
+                self.results = Some(open);
+                return true;
+            }
+            if fixed_width(trimmed).is_none() {
+                self.close_results(open);
+                return false;
+            }
+            open.form = Form::FixedWidth;
+        }
+        match open.form {
+            Form::Example if keyword(trimmed, "#+end_example").is_some() => {
+                let (text, indent) = parser::dedent_lines(&open.lines);
+                let body = Span {
+                    start_line: open.start_line + 2,
+                    end_line: here.start_line - 1,
+                    start: open.start,
+                    end: open.end,
+                };
+                open.block.expected = Some(Expected {
+                    text,
+                    body,
+                    indent: indent.unwrap_or_else(|| open.leading.clone()),
+                    span: Span {
+                        start_line: open.start_line,
+                        end_line: here.end_line,
+                        start: open.opening,
+                        end: here.end,
+                    },
+                });
+                self.pending = Some(open.block);
+                true
+            }
+            Form::Example => {
+                open.lines.push(unescape(text));
+                open.end = here.end;
+                self.results = Some(open);
+                true
+            }
+            _ => match fixed_width(trimmed) {
+                Some(line) => {
+                    open.lines.push(line.to_string());
+                    open.end = here.end;
+                    self.results = Some(open);
+                    true
+                }
+                None => {
+                    self.close_results(open);
+                    false
+                }
+            },
+        }
+    }
+
+    /// Ends a fixed-width or empty `#+RESULTS:` section before the current
+    /// line, and holds its block back again.
+    fn close_results(&mut self, mut open: OpenResults) {
+        let mut text = String::new();
+        for line in &open.lines {
+            text.push_str(line);
+            text.push('\n');
+        }
+        let body = Span {
+            start_line: open.start_line + 1,
+            end_line: open.start_line + open.lines.len(),
+            start: open.start,
+            end: open.end,
+        };
+        open.block.expected = Some(Expected {
+            text,
+            body,
+            indent: format!("{}: ", open.leading),
+            span: Span {
+                start_line: open.start_line,
+                end_line: body.end_line,
+                start: open.opening,
+                end: open.end,
+            },
+        });
+        self.pending = Some(open.block);
+    }
+
+    /// A line of a source block's body; at `#+END_SRC` the block is done
+    /// and held back for a `#+RESULTS:` section.
+    fn body_line(&mut self, mut open: OpenSrc, text: &str, here: Span) {
+        if keyword(text.trim_start(), "#+end_src").is_none() {
+            open.lines.push(unescape(text));
+            self.open = Some(open);
+            return;
+        }
+        if open.lang.is_empty() || open.skip {
+            return;
+        }
+        let index = self.next_index;
+        self.next_index += 1;
+        self.pending = Some(Block {
+            index,
+            lang: open.lang,
+            params: open.params.into(),
+            code: parser::dedent(&open.lines.join("\n")),
+            span: Span {
+                start_line: open.start_line,
+                end_line: here.end_line,
+                start: open.start,
+                end: here.end,
+            },
+            indent: open.indent,
+            crlf: self.crlf,
+            expected: None,
+            recorded: None,
+        });
+    }
+
+    /// Any other line: a source block's opening, a `#+NAME:`, or text.
+    fn element_line(&mut self, text: &str, here: Span) {
+        let trimmed = text.trim_start();
+        let name = self.name.take();
+        if let Some(value) = keyword(trimmed, "#+name:") {
+            self.name = Some(value.trim().to_string());
+            return;
+        }
+        let Some(header) = keyword(trimmed, "#+begin_src") else {
+            return;
+        };
+        let mut words = words(header).into_iter();
+        let lang = words.next().map(|lang| languages::canonical(&lang)).unwrap_or_default();
+        if lang.is_empty() {
+            self.diagnose(
+                DiagnosticKind::EmptyLanguage,
+                here,
+                "source block has no language, skipping it".to_string(),
+            );
+        }
+        let mut params = Vec::new();
+        let mut skip = false;
+        // Switches such as `-n` come before the header arguments.
+        let mut words = words.skip_while(|word| !word.starts_with(':')).peekable();
+        while let Some(key) = words.next() {
+            let mut values = Vec::new();
+            while let Some(value) = words.next_if(|word| !word.starts_with(':')) {
+                values.push(value);
+            }
+            let key = key[1..].to_string();
+            let value = values.join(" ");
+            if key.is_empty() {
+                self.diagnose(DiagnosticKind::InvalidParam, here, "header argument without a name".to_string());
+            } else if key == "var" {
+                self.diagnose(
+                    DiagnosticKind::InvalidParam,
+                    here,
+                    format!("ignoring :var {}, which translator doesn't support", value),
+                );
+            } else if key == "tangle" && value == "no" {
+                skip = true;
+            } else {
+                params.push((key, if value.is_empty() { "true".to_string() } else { value }));
+            }
+        }
+        if let Some(name) = name.filter(|_| !params.iter().any(|(key, _)| key == "name")) {
+            params.push(("name".to_string(), name));
+        }
+        self.open = Some(OpenSrc {
+            lang,
+            params,
+            skip,
+            indent: text[..text.len() - trimmed.len()].to_string(),
+            start_line: here.start_line,
+            start: here.start,
+            lines: Vec::new(),
+        });
+    }
+}
+
+impl LineParser for OrgParser {
+    fn line(&mut self, raw: &str, offset: usize, len: usize) -> Option<Block> {
+        self.line_no += 1;
+        self.end = offset + len;
+        self.crlf = raw.ends_with("\r\n");
+        let text = raw.strip_suffix('\n').unwrap_or(raw);
+        let text = text.strip_suffix('\r').unwrap_or(text);
+        let here = Span {
+            start_line: self.line_no,
+            end_line: self.line_no,
+            start: offset,
+            end: self.end,
+        };
+        if let Some(open) = self.open.take() {
+            self.body_line(open, text, here);
+            return None;
+        }
+        if let Some(results) = self.results.take() {
+            if self.results_line(results, text, here) {
+                return None;
+            }
+        }
+        if self.pending.is_some() && text.trim().is_empty() {
+            return None;
+        }
+        let pending = self.pending.take();
+        if let Some(block) = pending {
+            if keyword(text.trim_start(), "#+results").is_some() {
+                self.results = Some(OpenResults {
+                    block,
+                    form: Form::Unknown,
+                    start_line: here.start_line,
+                    opening: here.start,
+                    start: here.end,
+                    end: here.end,
+                    leading: text[..text.len() - text.trim_start().len()].to_string(),
+                    lines: Vec::new(),
+                });
+                return None;
+            }
+            self.element_line(text, here);
+            return Some(block);
+        }
+        self.element_line(text, here);
+        None
+    }
+
+    fn finish(mut self) -> (Option<Block>, Vec<ParseDiagnostic>) {
+        if let Some(open) = self.results.take() {
+            if open.form == Form::Example {
+                let span = Span {
+                    start_line: open.start_line,
+                    end_line: self.line_no,
+                    start: open.opening,
+                    end: self.end,
+                };
+                self.diagnose(
+                    DiagnosticKind::UnclosedBlock,
+                    span,
+                    "unclosed example in #+RESULTS:, reached end of input".to_string(),
+                );
+                self.pending = Some(open.block);
+            } else {
+                self.close_results(open);
+            }
+        }
+        if let Some(open) = self.open.take() {
+            let span = Span {
+                start_line: open.start_line,
+                end_line: self.line_no,
+                start: open.start,
+                end: self.end,
+            };
+            let message = format!("unclosed {} source block, reached end of input", open.lang);
+            self.diagnose(DiagnosticKind::UnclosedBlock, span, message);
+        }
+        (self.pending.take(), self.diagnostics)
+    }
+
+    fn line_no(&self) -> usize {
+        self.line_no
+    }
+
+    fn diagnose(&mut self, kind: DiagnosticKind, span: Span, message: String) {
+        self.diagnostics.push(ParseDiagnostic { kind, span, message });
+    }
+
+    fn take_diagnostics(&mut self) -> Vec<ParseDiagnostic> {
+        std::mem::take(&mut self.diagnostics)
+    }
+}
+
+/// What follows `keyword` at the start of `line`, which org matches without
+/// regard to case. `#+results` also matches `#+RESULTS[hash]: name`.
+fn keyword<'a>(line: &'a str, keyword: &str) -> Option<&'a str> {
+    let head = line.get(..keyword.len())?;
+    if !head.eq_ignore_ascii_case(keyword) {
+        return None;
+    }
+    let rest = &line[keyword.len()..];
+    let word_ends = rest.is_empty() || rest.starts_with(char::is_whitespace) || keyword.ends_with(':');
+    if word_ends || (keyword == "#+results" && rest.starts_with(['[', ':'])) {
+        Some(rest)
+    } else {
+        None
+    }
+}
+
+/// The text of a fixed-width line, `: text` or a lone `:`.
+fn fixed_width(line: &str) -> Option<&str> {
+    let rest = line.strip_prefix(':')?;
+    if rest.is_empty() {
+        Some("")
+    } else {
+        rest.strip_prefix(' ')
+    }
+}
+
+/// A body line as written, without the comma org puts before lines that
+/// would otherwise start a heading or a keyword.
+fn unescape(line: &str) -> String {
+    let trimmed = line.trim_start();
+    match trimmed.strip_prefix(',') {
+        Some(rest) if rest.starts_with('*') || rest.starts_with("#+") => {
+            format!("{}{}", &line[..line.len() - trimmed.len()], rest)
+        }
+        _ => line.to_string(),
+    }
+}
+
+/// The words of a header line; double quotes group words and are dropped.
+fn words(text: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+    let mut quoted = false;
+    for c in text.chars() {
+        match c {
+            '"' => {
+                in_quotes = !in_quotes;
+                quoted = true;
+            }
+            c if c.is_whitespace() && !in_quotes => {
+                if !current.is_empty() || quoted {
+                    words.push(std::mem::take(&mut current));
+                }
+                quoted = false;
+            }
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() || quoted {
+        words.push(current);
+    }
+    words
+}
@@ -0,0 +1,1021 @@
+//! Extraction of `|> translator:<lang> (` blocks from hacker files.
+//!
+//! The parser is a line-driven state machine: it never looks back, keeps only
+//! the block currently being collected, and reports anything odd as a
+//! [`ParseDiagnostic`] instead of printing. It does not panic on any input and
+//! runs in time linear in the input size.
+
+use std::collections::BTreeMap;
+use std::io::{self, BufRead};
+use std::path::Path;
+
+use crate::encoding::{self, FileEncoding};
+use crate::{detect, languages};
+
+/// What directive lines start with unless `--marker` or the `marker` setting
+/// says otherwise.
+pub const MARKER: &str = "|>";
+
+/// The keywords of directive lines, each after the marker and a space:
+/// `|> translator:`, `|> expect:` and so on.
+const DIRECTIVE: &str = "translator:";
+const EXPECT: &str = "expect:";
+const OUTPUT: &str = "output:";
+const ERROR: &str = "error:";
+const SET: &str = "set:";
+
+/// A region of the source, as 1-based inclusive line numbers and a half-open
+/// byte range covering the same lines (including their line terminators).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Span {
+    pub start_line: usize,
+    pub end_line: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Directive parameters, e.g. `rust(name=demo, flags="-O")`, in the order
+/// they were written.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Params(Vec<(String, String)>);
+
+impl From<Vec<(String, String)>> for Params {
+    fn from(params: Vec<(String, String)>) -> Self {
+        Params(params)
+    }
+}
+
+impl Params {
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Block {
+    /// 1-based position among the blocks extracted from the same source.
+    pub index: usize,
+    pub lang: String,
+    pub params: Params,
+    pub code: String,
+    pub span: Span,
+    /// Leading whitespace of the directive line.
+    pub indent: String,
+    /// Whether the block's closing line ends in CRLF, so text written next
+    /// to it can match.
+    pub crlf: bool,
+    /// The `|> expect:` section following the block, if any.
+    pub expected: Option<Expected>,
+    /// The whole `|> output:` or `|> error:` section recorded for the block
+    /// by `--inline-output`, if any.
+    pub recorded: Option<Span>,
+}
+
+/// Expected stdout of a block, written as an indented section that ends at a
+/// line holding only `)`:
+///
+/// ```text
+/// |> expect: (
+///     2 + 2 = 4
+/// )
+/// ```
+///
+/// The shared indentation is removed and every line keeps its newline.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Expected {
+    pub text: String,
+    /// The lines between the section's opening and closing lines; empty when
+    /// the section is.
+    pub body: Span,
+    /// Indentation to use when rewriting the body.
+    pub indent: String,
+    /// The whole section, from its opening to its closing line.
+    pub span: Span,
+}
+
+impl Block {
+    pub fn name(&self) -> Option<&str> {
+        self.params.get("name")
+    }
+
+    /// Whether the block has nothing but whitespace in it. A block of
+    /// comments isn't empty.
+    pub fn is_empty(&self) -> bool {
+        self.code.trim().is_empty()
+    }
+
+    /// How reports refer to the block: `rust demo` or `rust block 3`.
+    pub fn label(&self) -> String {
+        match self.name() {
+            Some(name) => format!("{} {}", self.lang, name),
+            None => format!("{} block {}", self.lang, self.index),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DiagnosticKind {
+    /// End of input was reached before the block's closing paren.
+    UnclosedBlock,
+    /// The directive has no language; its body is skipped.
+    EmptyLanguage,
+    /// A directive line appeared inside another block's body and was treated
+    /// as code.
+    NestedDirective,
+    /// A parameter without a key, such as `(=x)`.
+    InvalidParam,
+    /// Text after the block's closing paren or after the directive's opening
+    /// paren, which is ignored.
+    TrailingText,
+    /// An `|> expect:`, `|> output:` or `|> error:` section that doesn't
+    /// directly follow a block.
+    OrphanSection,
+    /// Bytes that aren't UTF-8, replaced under `--encoding auto`.
+    InvalidUtf8,
+    /// An `|> set:` line that isn't `NAME=VALUE`.
+    InvalidVariable,
+    /// A block uses a `{{NAME}}` no `|> set:` or `--set` defines. Unlike the
+    /// others, this one is an error.
+    UndefinedVariable,
+    /// The language a `translator:auto` block was taken to be. Not a
+    /// problem: it's only reported with `--verbose`.
+    DetectedLanguage,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseDiagnostic {
+    pub kind: DiagnosticKind,
+    pub span: Span,
+    pub message: String,
+}
+
+/// What blocks are read from: hacker files with their directives, org files
+/// with their source blocks, or Jupyter notebooks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Syntax {
+    Hacker,
+    Org,
+    Notebook,
+}
+
+impl Syntax {
+    /// The syntax `--input-format` names.
+    pub fn parse(value: &str) -> Option<Syntax> {
+        match value {
+            "hacker" => Some(Syntax::Hacker),
+            "org" => Some(Syntax::Org),
+            "ipynb" => Some(Syntax::Notebook),
+            _ => None,
+        }
+    }
+
+    /// The syntax of `path` by its extension: `.org` and `.ipynb` files, and
+    /// hacker files whatever else they are called.
+    pub fn of(path: &Path) -> Syntax {
+        let extension = path.extension().map(|ext| ext.to_string_lossy().to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("org") => Syntax::Org,
+            Some("ipynb") => Syntax::Notebook,
+            _ => Syntax::Hacker,
+        }
+    }
+}
+
+/// A line-driven parser that [`parse_reader`] feeds: [`Parser`] for hacker
+/// files, or [`crate::org::OrgParser`].
+pub trait LineParser {
+    /// Feeds the next line, including its terminator if it has one, that
+    /// starts at byte `offset` and is `len` bytes long in the input. Returns
+    /// the block this line completes or releases.
+    fn line(&mut self, raw: &str, offset: usize, len: usize) -> Option<Block>;
+    /// Ends the input, returning the last block if it was still held back
+    /// and the diagnostics not yet taken.
+    fn finish(self) -> (Option<Block>, Vec<ParseDiagnostic>);
+    /// The number of the line fed last.
+    fn line_no(&self) -> usize;
+    fn diagnose(&mut self, kind: DiagnosticKind, span: Span, message: String);
+    /// The diagnostics collected since the last call.
+    fn take_diagnostics(&mut self) -> Vec<ParseDiagnostic>;
+}
+
+impl LineParser for Parser {
+    fn line(&mut self, raw: &str, offset: usize, len: usize) -> Option<Block> {
+        Parser::line(self, raw, offset, len)
+    }
+
+    fn finish(self) -> (Option<Block>, Vec<ParseDiagnostic>) {
+        Parser::finish(self)
+    }
+
+    fn line_no(&self) -> usize {
+        self.line_no
+    }
+
+    fn diagnose(&mut self, kind: DiagnosticKind, span: Span, message: String) {
+        Parser::diagnose(self, kind, span, message)
+    }
+
+    fn take_diagnostics(&mut self) -> Vec<ParseDiagnostic> {
+        std::mem::take(&mut self.diagnostics)
+    }
+}
+
+/// What [`parse_reader`] reports as it goes.
+pub enum Parsed {
+    Block(Box<Block>),
+    Diagnostic(ParseDiagnostic),
+}
+
+/// Extracts blocks from `reader` one line at a time, handing each block to
+/// `f` as soon as it is complete. Only the block being collected is kept in
+/// memory. Fails on I/O errors and on input `encoding` can't decode. Returns
+/// the encoding the input turned out to have: UTF-8 when it starts with a
+/// byte order mark, whatever `encoding` says. `parser` makes the blocks of
+/// the lines, `Parser::new(marker)` for a hacker file.
+pub fn parse_reader<R: BufRead>(
+    mut reader: R,
+    mut encoding: FileEncoding,
+    mut parser: impl LineParser,
+    mut f: impl FnMut(Parsed) -> io::Result<()>,
+) -> io::Result<FileEncoding> {
+    let mut raw = Vec::new();
+    let mut offset = 0;
+    loop {
+        raw.clear();
+        if reader.read_until(b'\n', &mut raw)? == 0 {
+            break;
+        }
+        let mut bytes = raw.as_slice();
+        if offset == 0 {
+            if let Some(rest) = bytes.strip_prefix(encoding::BOM) {
+                bytes = rest;
+                if let FileEncoding::Other(_) = encoding {
+                    encoding = FileEncoding::Utf8;
+                }
+            }
+        }
+        let line = encoding.decode(bytes).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "line {} is not valid UTF-8: {}; pass --encoding latin1 or --encoding auto to read it anyway",
+                    parser.line_no() + 1,
+                    e
+                ),
+            )
+        })?;
+        let block = parser.line(&line.text, offset, raw.len());
+        if !line.replaced.is_empty() {
+            let start = offset + raw.len() - bytes.len();
+            let offsets: Vec<String> = line.replaced.iter().map(|at| (start + at).to_string()).collect();
+            let span = Span {
+                start_line: parser.line_no(),
+                end_line: parser.line_no(),
+                start: offset,
+                end: offset + raw.len(),
+            };
+            let message = format!(
+                "line {} is not valid UTF-8, replaced the bytes at offsets {}",
+                parser.line_no(),
+                offsets.join(", ")
+            );
+            parser.diagnose(DiagnosticKind::InvalidUtf8, span, message);
+        }
+        offset += raw.len();
+        for diagnostic in parser.take_diagnostics() {
+            f(Parsed::Diagnostic(diagnostic))?;
+        }
+        if let Some(block) = block {
+            f(Parsed::Block(Box::new(block)))?;
+        }
+    }
+    let (last, diagnostics) = parser.finish();
+    for diagnostic in diagnostics {
+        f(Parsed::Diagnostic(diagnostic))?;
+    }
+    if let Some(block) = last {
+        f(Parsed::Block(Box::new(block)))?;
+    }
+    Ok(encoding)
+}
+
+/// Extracts every block of a hacker file held in memory, with the default
+/// marker, and what was odd about it. The binary streams files through
+/// [`parse_reader`]; this is for the tests and the fuzz target.
+#[cfg(any(test, fuzzing))]
+pub fn parse(input: &str) -> (Vec<Block>, Vec<ParseDiagnostic>) {
+    let mut blocks = Vec::new();
+    let mut diagnostics = Vec::new();
+    // Reading from memory can't fail, and UTF-8 input always decodes.
+    let _ = parse_reader(input.as_bytes(), FileEncoding::Utf8, Parser::new(MARKER), |parsed| {
+        match parsed {
+            Parsed::Block(block) => blocks.push(*block),
+            Parsed::Diagnostic(diagnostic) => diagnostics.push(diagnostic),
+        }
+        Ok(())
+    });
+    (blocks, diagnostics)
+}
+
+struct OpenBlock {
+    lang: String,
+    indent: String,
+    params: Params,
+    start_line: usize,
+    start: usize,
+    depth: usize,
+    code: String,
+}
+
+/// The kinds of section that can follow a block. `Output` and `Error` are
+/// written by `--inline-output` and only matter when it replaces them.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Section {
+    Expect,
+    Output,
+    Error,
+}
+
+impl Section {
+    fn keyword(self) -> &'static str {
+        match self {
+            Section::Expect => EXPECT,
+            Section::Output => OUTPUT,
+            Section::Error => ERROR,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Section::Expect => "expect",
+            Section::Output => "output",
+            Section::Error => "error",
+        }
+    }
+}
+
+struct OpenSection {
+    section: Section,
+    /// The block the section belongs to; `None` for an orphan section, whose
+    /// body is skipped.
+    block: Option<Block>,
+    start_line: usize,
+    /// Where the opening line starts.
+    opening: usize,
+    /// Where the body starts, just after the opening line.
+    start: usize,
+    indent: String,
+    lines: Vec<String>,
+}
+
+/// Incremental parser fed one line at a time through [`Parser::line`].
+///
+/// A finished block is held back until the next non-blank line shows whether
+/// a section such as `|> expect:` follows it.
+pub struct Parser {
+    /// Directive lines, `|> translator:` by default, and the openings of
+    /// sections, in that order.
+    directive: String,
+    sections: [(String, Section); 3],
+    /// `|> set:` lines.
+    set: String,
+    /// What `|> set:` lines have defined so far.
+    variables: BTreeMap<String, String>,
+    /// What `--set` defines, which beats the file.
+    overrides: BTreeMap<String, String>,
+    line_no: usize,
+    end: usize,
+    /// Whether the current line ends in CRLF.
+    crlf: bool,
+    next_index: usize,
+    open: Option<OpenBlock>,
+    pending: Option<Block>,
+    section: Option<OpenSection>,
+    diagnostics: Vec<ParseDiagnostic>,
+}
+
+impl Parser {
+    /// A parser for directive lines that start with `marker`.
+    pub fn new(marker: &str) -> Self {
+        let keyword = |keyword| format!("{} {}", marker, keyword);
+        Parser {
+            directive: keyword(DIRECTIVE),
+            sections: [Section::Expect, Section::Output, Section::Error].map(|section| (keyword(section.keyword()), section)),
+            set: keyword(SET),
+            variables: BTreeMap::new(),
+            overrides: BTreeMap::new(),
+            line_no: 0,
+            end: 0,
+            crlf: false,
+            next_index: 1,
+            open: None,
+            pending: None,
+            section: None,
+            diagnostics: Vec::new(),
+        }
+    }
+
+    /// The parser with `--set` variables, which `|> set:` lines can't
+    /// change.
+    pub fn with_variables(mut self, overrides: &BTreeMap<String, String>) -> Self {
+        self.overrides = overrides.clone();
+        self
+    }
+
+    /// Feeds the next line, including its terminator if it has one, that
+    /// starts at byte `offset` and is `len` bytes long in the input. Returns
+    /// the block this line completes or releases.
+    pub fn line(&mut self, raw: &str, offset: usize, len: usize) -> Option<Block> {
+        self.line_no += 1;
+        self.end = offset + len;
+        self.crlf = raw.ends_with("\r\n");
+        let text = raw.strip_suffix('\n').unwrap_or(raw);
+        let text = text.strip_suffix('\r').unwrap_or(text);
+        let here = Span {
+            start_line: self.line_no,
+            end_line: self.line_no,
+            start: offset,
+            end: self.end,
+        };
+        if let Some(section) = self.section.take() {
+            self.section_line(section, text, here);
+            return None;
+        }
+        if let Some(open) = self.open.take() {
+            self.pending = self.body_line(open, text, here);
+            return None;
+        }
+        if self.pending.is_some() && text.trim().is_empty() {
+            return None;
+        }
+        let pending = self.pending.take();
+        if let Some((section, rest)) = self.opening(text) {
+            if pending.is_none() {
+                self.diagnose(
+                    DiagnosticKind::OrphanSection,
+                    here,
+                    format!("{} section doesn't follow a block, skipping it", section.name()),
+                );
+            }
+            if rest.trim() != "(" {
+                let opening = format!("{} sections open with `{} (`", section.name(), self.section_line_start(section));
+                self.diagnose(DiagnosticKind::TrailingText, here, opening);
+            }
+            let leading = &text[..text.len() - text.trim_start().len()];
+            self.section = Some(OpenSection {
+                section,
+                block: pending,
+                start_line: here.start_line,
+                opening: here.start,
+                start: here.end,
+                indent: format!("{}    ", leading),
+                lines: Vec::new(),
+            });
+            return None;
+        }
+        self.directive_line(text, here);
+        pending
+    }
+
+    /// Recognizes a section's opening line, returning what follows the
+    /// keyword.
+    fn opening<'a>(&self, text: &'a str) -> Option<(Section, &'a str)> {
+        let text = text.trim();
+        self.sections
+            .iter()
+            .find_map(|(start, section)| text.strip_prefix(start.as_str()).map(|rest| (*section, rest)))
+    }
+
+    /// How `section` opens, such as `|> expect:`.
+    fn section_line_start(&self, section: Section) -> &str {
+        let (start, _) = self.sections.iter().find(|(_, s)| *s == section).expect("every section has an opening");
+        start
+    }
+
+    /// Collects a section's body. Once it closes, its block is held back
+    /// again in case another section follows.
+    fn section_line(&mut self, mut open: OpenSection, text: &str, here: Span) {
+        if text.trim() != ")" {
+            open.lines.push(text.to_string());
+            self.section = Some(open);
+            return;
+        }
+        let Some(mut block) = open.block else {
+            return;
+        };
+        let span = Span {
+            start_line: open.start_line,
+            end_line: here.end_line,
+            start: open.opening,
+            end: here.end,
+        };
+        match open.section {
+            Section::Expect => {
+                let (text, indent) = dedent_lines(&open.lines);
+                block.expected = Some(Expected {
+                    text,
+                    body: Span {
+                        start_line: open.start_line + 1,
+                        end_line: here.start_line - 1,
+                        start: open.start,
+                        end: here.start,
+                    },
+                    indent: indent.unwrap_or(open.indent),
+                    span,
+                });
+            }
+            Section::Output | Section::Error => block.recorded = Some(span),
+        }
+        self.pending = Some(block);
+    }
+
+    /// Ends the input, returning the last block if it was still held back
+    /// and every diagnostic collected so far.
+    pub fn finish(mut self) -> (Option<Block>, Vec<ParseDiagnostic>) {
+        let mut last = self.pending.take();
+        if let Some(open) = self.section.take() {
+            let span = Span {
+                start_line: open.start_line,
+                end_line: self.line_no,
+                start: open.opening,
+                end: self.end,
+            };
+            self.diagnose(
+                DiagnosticKind::UnclosedBlock,
+                span,
+                format!("unclosed {} section, reached end of input", open.section.name()),
+            );
+            last = open.block;
+        }
+        if let Some(open) = self.open.take() {
+            let span = Span {
+                start_line: open.start_line,
+                end_line: self.line_no,
+                start: open.start,
+                end: self.end,
+            };
+            let message = if open.lang.is_empty() {
+                "unclosed block without a language".to_string()
+            } else {
+                format!("unclosed {} block, reached end of input", open.lang)
+            };
+            self.diagnose(DiagnosticKind::UnclosedBlock, span, message);
+        }
+        (last, self.diagnostics)
+    }
+
+    fn diagnose(&mut self, kind: DiagnosticKind, span: Span, message: String) {
+        self.diagnostics.push(ParseDiagnostic { kind, span, message });
+    }
+
+    fn directive_line(&mut self, text: &str, here: Span) {
+        if let Some(definition) = text.trim().strip_prefix(self.set.as_str()) {
+            return self.set_line(definition.trim(), here);
+        }
+        let Some(directive) = text.trim().strip_prefix(self.directive.as_str()) else {
+            return;
+        };
+        let (lang, params, rest) = self.header(directive.trim(), here);
+        // Anything after the opening paren is ignored; the body starts on the
+        // next line.
+        let rest = rest.strip_prefix('(').unwrap_or(rest).trim();
+        if !rest.is_empty() {
+            self.diagnose(
+                DiagnosticKind::TrailingText,
+                here,
+                format!("ignoring text after the directive: {}", preview(rest)),
+            );
+        }
+        if lang.is_empty() {
+            self.diagnose(
+                DiagnosticKind::EmptyLanguage,
+                here,
+                "directive has no language, skipping its block".to_string(),
+            );
+        }
+        self.open = Some(OpenBlock {
+            lang,
+            indent: text[..text.len() - text.trim_start().len()].to_string(),
+            params,
+            start_line: here.start_line,
+            start: here.start,
+            depth: 1,
+            code: String::new(),
+        });
+    }
+
+    /// `NAME=VALUE` or `NAME="VALUE"` after `|> set:`.
+    fn set_line(&mut self, definition: &str, here: Span) {
+        let valid = definition.split_once('=').filter(|(name, _)| is_variable(name.trim()));
+        let Some((name, value)) = valid else {
+            let message = format!("{} expects NAME=VALUE, got {}", self.set, preview(definition));
+            return self.diagnose(DiagnosticKind::InvalidVariable, here, message);
+        };
+        let value = value.trim();
+        let value = match value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+            Some(quoted) => quoted.replace("\\\"", "\"").replace("\\\\", "\\"),
+            None => value.to_string(),
+        };
+        self.variables.insert(name.trim().to_string(), value);
+    }
+
+    /// `code` with every `{{NAME}}` replaced by its value, once a variable
+    /// is defined at all, and `{{{{` by `{{`. Fails on the first name that
+    /// isn't defined.
+    fn substitute(&self, code: String) -> Result<String, String> {
+        if self.variables.is_empty() && self.overrides.is_empty() {
+            return Ok(code);
+        }
+        let mut out = String::with_capacity(code.len());
+        let mut rest = code.as_str();
+        while let Some(at) = rest.find("{{") {
+            out.push_str(&rest[..at]);
+            rest = &rest[at..];
+            if let Some(after) = rest.strip_prefix("{{{{") {
+                out.push_str("{{");
+                rest = after;
+                continue;
+            }
+            let reference = rest[2..].split_once("}}").filter(|(name, _)| is_variable(name));
+            let Some((name, after)) = reference else {
+                out.push_str("{{");
+                rest = &rest[2..];
+                continue;
+            };
+            let value = self.overrides.get(name).or_else(|| self.variables.get(name));
+            let value = value.ok_or_else(|| format!("{{{{{}}}}}, which isn't defined by {} or --set", name, self.set))?;
+            out.push_str(value);
+            rest = after;
+        }
+        out.push_str(rest);
+        Ok(out)
+    }
+
+    /// Splits `rust(name=demo) (` into the language, its parameters and
+    /// what follows them.
+    fn header<'a>(&mut self, directive: &'a str, here: Span) -> (String, Params, &'a str) {
+        let lang_end = directive
+            .find(|c: char| c == '(' || c.is_whitespace())
+            .unwrap_or(directive.len());
+        let lang = languages::canonical(&directive[..lang_end]);
+        let mut rest = directive[lang_end..].trim_start();
+        let mut params = Params::default();
+        if let Some(inner) = rest.strip_prefix('(') {
+            if let Some(close) = find_group_end(inner) {
+                params = self.parse_params(&inner[..close], here);
+                rest = inner[close + 1..].trim_start();
+            }
+        }
+        (lang, params, rest)
+    }
+
+    fn body_line(&mut self, mut open: OpenBlock, text: &str, here: Span) -> Option<Block> {
+        if text.trim_start().starts_with(self.directive.as_str()) {
+            self.diagnose(
+                DiagnosticKind::NestedDirective,
+                here,
+                format!(
+                    "directive inside the block opened on line {}, treating it as code",
+                    open.start_line
+                ),
+            );
+        }
+        let mut close = None;
+        for (idx, c) in text.char_indices() {
+            match c {
+                '(' => open.depth += 1,
+                ')' => {
+                    open.depth -= 1;
+                    if open.depth == 0 {
+                        close = Some(idx);
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+        let Some(close) = close else {
+            open.code.push_str(text);
+            open.code.push('\n');
+            self.open = Some(open);
+            return None;
+        };
+        open.code.push_str(&text[..close]);
+        let trailing = text[close + 1..].trim();
+        if !trailing.is_empty() {
+            self.diagnose(
+                DiagnosticKind::TrailingText,
+                here,
+                format!("ignoring text after the closing paren: {}", preview(trailing)),
+            );
+        }
+        if open.lang.is_empty() {
+            return None;
+        }
+        let code = match self.substitute(dedent(&open.code)) {
+            Ok(code) => code,
+            Err(message) => {
+                let span = Span {
+                    start_line: open.start_line,
+                    end_line: here.end_line,
+                    start: open.start,
+                    end: here.end,
+                };
+                self.diagnose(DiagnosticKind::UndefinedVariable, span, format!("the {} block uses {}", open.lang, message));
+                return None;
+            }
+        };
+        let span = Span {
+            start_line: open.start_line,
+            end_line: here.end_line,
+            start: open.start,
+            end: here.end,
+        };
+        let mut lang = open.lang;
+        // A block that can't be told is left `auto`, which fails it.
+        if lang == detect::AUTO {
+            if let Ok(guess) = detect::detect(&code) {
+                let message = format!(
+                    "detected {} ({:.0}% confidence, from {})",
+                    guess.lang,
+                    guess.confidence * 100.0,
+                    guess.clues.join(", ")
+                );
+                self.diagnose(DiagnosticKind::DetectedLanguage, span, message);
+                lang = guess.lang;
+            }
+        }
+        let index = self.next_index;
+        self.next_index += 1;
+        Some(Block {
+            index,
+            lang,
+            params: open.params,
+            code,
+            span,
+            indent: open.indent,
+            crlf: self.crlf,
+            expected: None,
+            recorded: None,
+        })
+    }
+
+    /// Parses `key=value, key2="quoted, value", flag` into pairs. A bare key
+    /// is shorthand for `key=true`.
+    fn parse_params(&mut self, s: &str, here: Span) -> Params {
+        let mut items = Vec::new();
+        let mut current = String::new();
+        let mut in_quotes = false;
+        let mut escaped = false;
+        for c in s.chars() {
+            match c {
+                _ if escaped => {
+                    current.push(c);
+                    escaped = false;
+                }
+                '\\' if in_quotes => escaped = true,
+                '"' => in_quotes = !in_quotes,
+                ',' if !in_quotes => items.push(std::mem::take(&mut current)),
+                _ => current.push(c),
+            }
+        }
+        items.push(current);
+        let mut params = Vec::new();
+        for item in items {
+            let item = item.trim();
+            if item.is_empty() {
+                continue;
+            }
+            let (key, value) = match item.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => (item, "true"),
+            };
+            if key.is_empty() {
+                self.diagnose(
+                    DiagnosticKind::InvalidParam,
+                    here,
+                    format!("parameter without a name: {}", preview(item)),
+                );
+                continue;
+            }
+            params.push((key.to_string(), value.to_string()));
+        }
+        Params(params)
+    }
+}
+
+/// The language and parameters of a directive written without its marker
+/// and opening paren, such as `rust(name=demo)`, or what is wrong with it.
+pub fn header(directive: &str) -> Result<(String, Params), String> {
+    let mut parser = Parser::new(MARKER);
+    let here = Span {
+        start_line: 1,
+        end_line: 1,
+        start: 0,
+        end: directive.len(),
+    };
+    let (lang, params, rest) = parser.header(directive.trim(), here);
+    if let Some(diagnostic) = parser.diagnostics.first() {
+        return Err(diagnostic.message.clone());
+    }
+    if lang.is_empty() {
+        return Err("no language".to_string());
+    }
+    if !rest.is_empty() {
+        return Err(format!("unexpected text after the language: {}", preview(rest)));
+    }
+    Ok((lang, params))
+}
+
+/// Returns the index of the `)` closing a group whose `(` was just consumed,
+/// if it closes on the same line. Parens inside double quotes don't count.
+fn find_group_end(s: &str) -> Option<usize> {
+    let mut depth = 1;
+    let mut in_quotes = false;
+    let mut escaped = false;
+    for (idx, c) in s.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_quotes => escaped = true,
+            '"' => in_quotes = !in_quotes,
+            '(' if !in_quotes => depth += 1,
+            ')' if !in_quotes => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(idx);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Removes the indentation shared by all non-blank lines, plus leading and
+/// trailing blank lines, so indented python and go bodies stay valid.
+pub fn dedent(code: &str) -> String {
+    let indent = common_indent(code.lines());
+    let lines: Vec<&str> = code
+        .lines()
+        .map(|line| line.strip_prefix(indent).unwrap_or(line.trim_start()))
+        .collect();
+    lines.join("\n").trim_matches('\n').trim_end().to_string()
+}
+
+/// Removes the indentation shared by all non-blank lines, keeping every
+/// line and its newline. Also returns that indentation, if there was a
+/// non-blank line to take it from.
+pub fn dedent_lines(lines: &[String]) -> (String, Option<String>) {
+    let indent = common_indent(lines.iter().map(String::as_str));
+    let mut text = String::new();
+    for line in lines {
+        text.push_str(line.strip_prefix(indent).unwrap_or(line.trim_start()));
+        text.push('\n');
+    }
+    let indent = lines.iter().any(|l| !l.trim().is_empty()).then(|| indent.to_string());
+    (text, indent)
+}
+
+fn common_indent<'a>(lines: impl Iterator<Item = &'a str>) -> &'a str {
+    lines
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| &line[..line.len() - line.trim_start().len()])
+        .reduce(|common, indent| {
+            let shared = common
+                .char_indices()
+                .zip(indent.chars())
+                .find(|((_, a), b)| a != b)
+                .map_or(common.len().min(indent.len()), |((idx, _), _)| idx);
+            &common[..shared]
+        })
+        .unwrap_or("")
+}
+
+/// Whether `name` can be a variable: letters, digits and underscores, not
+/// starting with a digit.
+fn is_variable(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_') && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Keeps diagnostics readable when the offending text is a huge line.
+fn preview(text: &str) -> String {
+    const MAX: usize = 40;
+    match text.char_indices().nth(MAX) {
+        Some((idx, _)) => format!("{}...", &text[..idx]),
+        None => text.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn blocks_and_their_expectations() {
+        let (blocks, diagnostics) = parse("intro\n|> translator:rs (name=demo)\n    fn main() {}\n)\n|> expect: (\n    hi\n)\n");
+        assert!(diagnostics.is_empty());
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(blocks[0].lang, "rust");
+        assert_eq!(blocks[0].name(), Some("demo"));
+        assert_eq!(blocks[0].code, "fn main() {}");
+        assert_eq!(blocks[0].span.start_line, 2);
+        assert_eq!(blocks[0].expected.as_ref().map(|e| e.text.as_str()), Some("hi\n"));
+    }
+
+    #[test]
+    fn directive_on_the_last_line() {
+        let (blocks, diagnostics) = parse("text\n|> translator:python (");
+        assert!(blocks.is_empty());
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].kind, DiagnosticKind::UnclosedBlock);
+        assert_eq!(diagnostics[0].message, "unclosed python block, reached end of input");
+    }
+
+    #[test]
+    fn whitespace_for_a_language() {
+        let (blocks, diagnostics) = parse("|> translator:   (\nprint(1)\n)\n|> translator:python (\nprint(2)\n)\n");
+        let kinds: Vec<_> = diagnostics.iter().map(|d| d.kind).collect();
+        assert_eq!(kinds, [DiagnosticKind::EmptyLanguage]);
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(blocks[0].code, "print(2)");
+    }
+
+    #[test]
+    fn unclosed_block_swallows_later_directives() {
+        let (blocks, diagnostics) = parse("|> translator:go (\nfunc main() {\n|> translator:python (\nprint(1)\n");
+        assert!(blocks.is_empty());
+        let kinds: Vec<_> = diagnostics.iter().map(|d| d.kind).collect();
+        assert_eq!(kinds, [DiagnosticKind::NestedDirective, DiagnosticKind::UnclosedBlock]);
+        assert_eq!(diagnostics[1].span.start_line, 1);
+        assert_eq!(diagnostics[1].span.end_line, 4);
+    }
+
+    #[test]
+    fn unclosed_section() {
+        let (blocks, diagnostics) = parse("|> translator:python (\nprint(1)\n)\n|> expect: (\n1\n");
+        assert_eq!(blocks.len(), 1);
+        assert!(blocks[0].expected.is_none());
+        assert_eq!(diagnostics[0].kind, DiagnosticKind::UnclosedBlock);
+        assert_eq!(diagnostics[0].message, "unclosed expect section, reached end of input");
+    }
+
+    /// Lines that mean something to the parser, half-written ones and
+    /// arbitrary text.
+    fn line() -> impl Strategy<Value = String> {
+        prop_oneof![
+            Just("|> translator:rust (".to_string()),
+            Just("   |> translator:python (name=x, flags=\"-O\") trailing".to_string()),
+            Just("|> translator:auto (".to_string()),
+            Just("|> translator: (".to_string()),
+            Just("|> translator:".to_string()),
+            Just("|> translator:go (=x, a=\"unterminated".to_string()),
+            Just("|> expect: (".to_string()),
+            Just("|> output: (".to_string()),
+            Just("|> set: NAME=\"value\"".to_string()),
+            Just("|> set: 1=2".to_string()),
+            Just("{{NAME}} {{{{ {{".to_string()),
+            Just(")".to_string()),
+            Just("  )  ".to_string()),
+            Just("(".to_string()),
+            Just(String::new()),
+            "\\PC{0,30}",
+            any::<String>(),
+        ]
+    }
+
+    fn soup() -> impl Strategy<Value = String> {
+        (prop::collection::vec(line(), 0..40), prop::sample::select(vec!["\n", "\r\n", "\r"]))
+            .prop_map(|(lines, newline)| lines.join(newline))
+    }
+
+    proptest! {
+        #[test]
+        fn line_soup_never_panics(input in soup()) {
+            parse(&input);
+        }
+
+        #[test]
+        fn no_more_blocks_than_directives(input in soup()) {
+            let directives = input.lines().filter(|line| line.trim().starts_with("|> translator:")).count();
+            let (blocks, _) = parse(&input);
+            prop_assert!(blocks.len() <= directives, "{} blocks from {} directives", blocks.len(), directives);
+        }
+
+        #[test]
+        fn spans_stay_inside_the_input(input in soup()) {
+            let (blocks, diagnostics) = parse(&input);
+            let spans = blocks.iter().map(|b| b.span).chain(diagnostics.iter().map(|d| d.span));
+            for span in spans {
+                prop_assert!(span.start <= span.end && span.end <= input.len());
+                prop_assert!(span.start_line <= span.end_line);
+            }
+        }
+    }
+}
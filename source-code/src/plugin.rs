@@ -0,0 +1,206 @@
+//! External executors for languages translator doesn't know about.
+//!
+//! A plugin is any executable named `translator-exec-<lang>` on `PATH`. It is
+//! started inside the block's temp dir with the block's code on stdin, the
+//! language in `TRANSLATOR_LANG` and every directive parameter as a
+//! `TRANSLATOR_PARAM_<KEY>=<VALUE>` environment variable (key upper-cased,
+//! `-` and `.` mapped to `_`). A zero exit status means success; stdout is
+//! the block's output, and stderr is the error message otherwise.
+//!
+//! `TRANSLATOR_VERBOSE=1` is set under `--verbose`, and `TRANSLATOR_CACHE_DIR`
+//! names a directory the plugin may keep things in between runs unless
+//! `--no-cache` is given. For javascript and typescript blocks with `deps=`,
+//! translator installs the packages first and names their `node_modules` in
+//! `TRANSLATOR_NODE_MODULES`, and in front of `NODE_PATH`.
+//!
+//! A plugin with more to say writes a JSON object to the file named by
+//! `TRANSLATOR_RESULT_FILE` before it exits: [`Response`].
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus};
+
+use serde::Deserialize;
+
+use crate::executor::{ErrorKind, ExecError, Phases, RunOutput};
+use crate::parser::Block;
+use crate::Options;
+
+const PREFIX: &str = "translator-exec-";
+
+/// The file in the block's directory a plugin may write its result to.
+const RESULT_FILE: &str = ".translator-result.json";
+
+/// What a plugin may write to `TRANSLATOR_RESULT_FILE`, as in
+/// `{"status": "failed", "error": "2 of 5 tests failed", "warnings": []}`.
+/// Every field is optional, and fields translator doesn't know are an
+/// error, so a misspelt one doesn't go unnoticed.
+#[derive(Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Response {
+    /// Whether the block passed, over what the exit status says.
+    pub status: Option<Outcome>,
+    /// Why the block failed, shown instead of the plugin's stderr.
+    pub error: Option<String>,
+    /// Problems that didn't fail the block.
+    #[serde(default)]
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Passed,
+    Failed,
+}
+
+/// Languages are used verbatim in the executable name, so anything that
+/// could escape the `PATH` directory is refused.
+fn valid_lang(lang: &str) -> bool {
+    !lang.is_empty()
+        && lang
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '+')
+}
+
+fn is_executable(path: &Path) -> bool {
+    match fs::metadata(path) {
+        #[cfg(unix)]
+        Ok(meta) => {
+            use std::os::unix::fs::PermissionsExt;
+            meta.is_file() && meta.permissions().mode() & 0o111 != 0
+        }
+        #[cfg(not(unix))]
+        Ok(meta) => meta.is_file(),
+        Err(_) => false,
+    }
+}
+
+/// Looks up the plugin for `lang`, taking the first match on `PATH`.
+pub fn find(lang: &str) -> Option<PathBuf> {
+    if !valid_lang(lang) {
+        return None;
+    }
+    let path = env::var_os("PATH")?;
+    env::split_paths(&path)
+        .map(|dir| dir.join(format!("{}{}", PREFIX, lang)))
+        .find(|candidate| is_executable(candidate))
+}
+
+/// Lists every plugin reachable through `PATH`, keyed by language. Earlier
+/// `PATH` entries win, matching what [`find`] would pick.
+pub fn discover() -> BTreeMap<String, PathBuf> {
+    let mut plugins = BTreeMap::new();
+    let Some(path) = env::var_os("PATH") else {
+        return plugins;
+    };
+    for dir in env::split_paths(&path) {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            let Some(lang) = name.to_str().and_then(|n| n.strip_prefix(PREFIX)) else {
+                continue;
+            };
+            if valid_lang(lang) && is_executable(&entry.path()) {
+                plugins.entry(lang.to_string()).or_insert_with(|| entry.path());
+            }
+        }
+    }
+    plugins
+}
+
+fn env_key(key: &str) -> String {
+    let key: String = key
+        .chars()
+        .map(|c| match c {
+            '-' | '.' => '_',
+            c => c.to_ascii_uppercase(),
+        })
+        .collect();
+    format!("TRANSLATOR_PARAM_{}", key)
+}
+
+pub fn execute(
+    plugin: &Path,
+    block: &Block,
+    opts: &Options,
+    dir: &Path,
+    node_modules: Option<&Path>,
+    p: &mut Phases,
+) -> Result<RunOutput, ExecError> {
+    // A plugin may be installed among the caches, as in ~/.cargo/bin.
+    if let Some(installed) = plugin.parent() {
+        p.uses(installed);
+    }
+    let mut command = Command::new(plugin);
+    command
+        .current_dir(dir)
+        .env("TRANSLATOR_LANG", &block.lang);
+    if opts.verbose {
+        command.env("TRANSLATOR_VERBOSE", "1");
+    }
+    if let Some(cache) = &opts.cache {
+        command.env("TRANSLATOR_CACHE_DIR", cache.dir().join("plugins").join(&block.lang));
+    }
+    for (key, value) in block.params.iter() {
+        command.env(env_key(key), value);
+    }
+    if let Some(node_modules) = node_modules {
+        let mut paths = vec![node_modules.to_path_buf()];
+        paths.extend(env::var_os("NODE_PATH").iter().flat_map(env::split_paths));
+        // A path with the separator in it can't go in NODE_PATH, but the
+        // plugin still has TRANSLATOR_NODE_MODULES.
+        if let Ok(node_path) = env::join_paths(paths) {
+            command.env("NODE_PATH", node_path);
+        }
+        command.env("TRANSLATOR_NODE_MODULES", node_modules);
+    }
+    let result_file = dir.join(RESULT_FILE);
+    command.env("TRANSLATOR_RESULT_FILE", &result_file);
+    let output = p.run_with_input(&mut command, Some(block.code.as_bytes()))?;
+    let name = plugin.file_name().unwrap_or_default().to_string_lossy();
+    let response = match fs::read_to_string(&result_file) {
+        Ok(json) => serde_json::from_str::<Response>(&json).map_err(|e| e.to_string()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(output),
+        Err(e) => Err(e.to_string()),
+    };
+    let response = match response {
+        Ok(response) => response,
+        Err(problem) => {
+            return Err(ExecError {
+                kind: ErrorKind::PluginProtocol,
+                message: format!("{} wrote a result translator can't read: {}", name, problem),
+                output: Some(output),
+            })
+        }
+    };
+    p.warnings.extend(response.warnings);
+    let passed = match response.status {
+        Some(outcome) => outcome == Outcome::Passed,
+        None => output.status.success(),
+    };
+    match (passed, response.error) {
+        (true, _) if output.status.success() => Ok(output),
+        (true, _) => Ok(RunOutput {
+            status: ExitStatus::default(),
+            ..output
+        }),
+        (false, None) if !output.status.success() => Ok(output),
+        (false, error) => {
+            let message = error.unwrap_or_else(|| match output.stderr.preview().trim() {
+                "" => format!("{} reported the block as failed", name),
+                stderr => stderr.to_string(),
+            });
+            Err(ExecError {
+                kind: ErrorKind::NonZeroExit,
+                message,
+                output: Some(output),
+            })
+        }
+    }
+}
@@ -0,0 +1,148 @@
+//! Finding the interpreter for python blocks. Many systems only have
+//! `python3`, others only `python`, and Windows may only have the `py`
+//! launcher, so the names are tried in turn unless one is configured.
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::OnceLock;
+
+use crate::parser::Block;
+use crate::tools::{self, Tools};
+
+/// Names tried in order when nothing is configured, each with the arguments
+/// it needs before the script.
+const CANDIDATES: &[(&str, &[&str])] = &[
+    ("python3", &[]),
+    ("python", &[]),
+    #[cfg(windows)]
+    ("py", &["-3"]),
+];
+
+/// Prints what a virtualenv made by the interpreter depends on, so a venv
+/// isn't reused after the interpreter is upgraded or swapped.
+pub const IDENTITY: &str = "import sys; print(sys.version); print(sys.base_prefix)";
+
+/// Runs the code in its first argument the way the interactive interpreter
+/// would a single line: an expression has its value printed (unless it is
+/// `None`), anything else is executed as usual. The user's code runs in a
+/// clean `__main__` with the same `sys.argv` and file name as under `-c`,
+/// and the driver's own frame is left out of tracebacks.
+pub const DRIVER: &str = r#"
+def _driver():
+    import sys, traceback
+    source = sys.argv.pop(1)
+    namespace = sys.modules["__main__"].__dict__
+    del namespace["_driver"]
+    try:
+        expression = compile(source, "<string>", "eval", dont_inherit=True)
+    except SyntaxError:
+        expression = None
+    try:
+        if expression is None:
+            exec(compile(source, "<string>", "exec", dont_inherit=True), namespace)
+        else:
+            sys.displayhook(eval(expression, namespace))
+    except SystemExit:
+        raise
+    except BaseException as e:
+        traceback.print_exception(type(e), e, e.__traceback__.tb_next)
+        sys.exit(1)
+_driver()
+"#;
+
+/// Whether `stderr` is python refusing to compile the code rather than the
+/// code failing as it runs: a syntax error without a traceback above it.
+pub fn is_syntax_error(stderr: &str) -> bool {
+    if stderr.contains("Traceback (most recent call last):") {
+        return false;
+    }
+    let last = stderr.lines().rev().find(|line| !line.trim().is_empty()).unwrap_or("");
+    let name = last.split(':').next().unwrap_or("");
+    ["SyntaxError", "IndentationError", "TabError"].contains(&name)
+}
+
+/// Whether the block's value is printed when it is a single expression:
+/// `expr=true` or `expr=false`, or else `--repl-semantics`.
+pub fn auto_print(block: &Block, repl_semantics: bool) -> bool {
+    match block.params.get("expr") {
+        Some(value) => value == "true",
+        None => repl_semantics,
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Interpreter {
+    pub program: String,
+    pub args: Vec<String>,
+}
+
+impl Interpreter {
+    pub fn command(&self) -> Command {
+        let mut command = Command::new(&self.program);
+        command.args(&self.args);
+        command
+    }
+}
+
+/// The interpreter to use: the `python` override (`$TRANSLATOR_PYTHON` or
+/// `translator.toml`), or else the first candidate on the `PATH` that runs.
+/// The search is done once per process.
+pub fn interpreter(tools: &Tools) -> Result<Interpreter, String> {
+    if let Some((path, _)) = tools.get("python") {
+        return Ok(Interpreter {
+            program: path.to_string_lossy().into_owned(),
+            args: Vec::new(),
+        });
+    }
+    static FOUND: OnceLock<Option<Interpreter>> = OnceLock::new();
+    FOUND.get_or_init(search).clone().ok_or_else(|| {
+        let tried: Vec<&str> = CANDIDATES.iter().map(|(name, _)| *name).collect();
+        format!(
+            "no python interpreter found (tried {}); set TRANSLATOR_PYTHON or `python` under [tools] in translator.toml",
+            tried.join(", ")
+        )
+    })
+}
+
+/// The requirements in the block's `deps="requests,numpy>=1.26"` parameter,
+/// sorted so the same set always maps to the same virtualenv.
+pub fn requirements(block: &Block) -> Vec<String> {
+    let mut requirements: Vec<String> = block
+        .params
+        .get("deps")
+        .unwrap_or("")
+        .split(',')
+        .map(str::trim)
+        .filter(|requirement| !requirement.is_empty())
+        .map(str::to_string)
+        .collect();
+    requirements.sort();
+    requirements.dedup();
+    requirements
+}
+
+/// The interpreter inside the virtualenv at `venv`.
+pub fn venv_python(venv: &Path) -> PathBuf {
+    if cfg!(windows) {
+        venv.join("Scripts").join("python.exe")
+    } else {
+        venv.join("bin").join("python")
+    }
+}
+
+fn search() -> Option<Interpreter> {
+    CANDIDATES.iter().find_map(|(name, args)| {
+        let runs = tools::command(name)
+            .args(*args)
+            .arg("--version")
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success());
+        runs.then(|| Interpreter {
+            program: tools::command(name).get_program().to_string_lossy().into_owned(),
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+        })
+    })
+}
@@ -0,0 +1,224 @@
+//! `--record` and `--replay`: keeping each block's result in a file next to
+//! its document, `notes.hack.translator-cache`, so the document can be run
+//! again without running the blocks whose code didn't change. Results are
+//! keyed by a hash of the block's language, parameters and code. The file
+//! is pretty-printed JSON with the output split into lines, so it can be
+//! committed next to the document and reviewed like it.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+
+use crate::cache;
+use crate::executor::{ErrorKind, ExecutionResult, Status, Timings};
+use crate::parser::Block;
+
+/// What is appended to a document's name for its recording.
+pub const EXTENSION: &str = "translator-cache";
+
+/// The recording's format. Files of another version are started over.
+const VERSION: u32 = 1;
+
+/// What `--replay` does with blocks that have no recorded result.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// Runs them.
+    Run,
+    /// Fails them, from `--replay=strict`.
+    Strict,
+}
+
+#[derive(Serialize, Deserialize)]
+struct File {
+    version: u32,
+    blocks: Vec<Entry>,
+}
+
+/// One block's result. `line`, `language` and `name` are for people
+/// reading the file; what counts is the key.
+#[derive(Clone, Serialize, Deserialize)]
+struct Entry {
+    key: String,
+    line: usize,
+    language: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    status: Status,
+    exit_code: Option<i32>,
+    signal: Option<i32>,
+    stdout: Vec<String>,
+    stdout_encoding: String,
+    stderr: Vec<String>,
+    stderr_encoding: String,
+    error_kind: Option<ErrorKind>,
+    error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    compiler_warnings: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    warnings: Vec<String>,
+}
+
+/// The results recorded for one document.
+pub struct Recording {
+    path: PathBuf,
+    entries: HashMap<String, Entry>,
+}
+
+/// Where `document`'s results are recorded.
+pub fn path_for(document: &Path) -> PathBuf {
+    let mut name = document.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{}", EXTENSION));
+    document.with_file_name(name)
+}
+
+/// The key of `block`'s result: a hash of its language, its parameters in
+/// order and its code.
+pub fn key(block: &Block) -> String {
+    let mut parts = vec![block.lang.as_str(), block.code.as_str()];
+    for (key, value) in block.params.iter() {
+        parts.extend([key, value]);
+    }
+    cache::hash(&parts)
+}
+
+impl Recording {
+    /// The results recorded for `document`, none if there are none yet.
+    pub fn load(document: &Path) -> io::Result<Recording> {
+        let path = path_for(document);
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(io::Error::new(e.kind(), format!("{}: {}", path.display(), e))),
+        };
+        let mut entries = HashMap::new();
+        if !text.is_empty() {
+            let file: File = serde_json::from_str(&text)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), e)))?;
+            if file.version == VERSION {
+                entries.extend(file.blocks.into_iter().map(|entry| (entry.key.clone(), entry)));
+            }
+        }
+        Ok(Recording { path, entries })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    /// How many results no block among `keys` has.
+    pub fn unused(&self, keys: &[String]) -> usize {
+        self.entries.keys().filter(|key| !keys.contains(key)).count()
+    }
+
+    /// The result recorded under `key`, as if the block had just run in no
+    /// time.
+    pub fn replay(&self, key: &str) -> io::Result<Option<ExecutionResult>> {
+        let Some(entry) = self.entries.get(key) else {
+            return Ok(None);
+        };
+        let invalid = |e: base64::DecodeError| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("{}: line {}: {}", self.path.display(), entry.line, e))
+        };
+        Ok(Some(ExecutionResult {
+            status: entry.status,
+            exit_code: entry.exit_code,
+            signal: entry.signal,
+            duration: Duration::ZERO,
+            timings: Timings::default(),
+            stdout: decode(&entry.stdout, &entry.stdout_encoding).map_err(invalid)?.into(),
+            stderr: decode(&entry.stderr, &entry.stderr_encoding).map_err(invalid)?.into(),
+            error: entry.error_kind.zip(entry.error.clone()),
+            duplicate_of: None,
+            compiler_flags: Vec::new(),
+            language_version: None,
+            toolchain_version: None,
+            compiler_warnings: entry.compiler_warnings.clone(),
+            warnings: entry.warnings.clone(),
+            disk_bytes: None,
+            artifacts: Vec::new(),
+            usage: None,
+            replayed: true,
+        }))
+    }
+
+    /// Keeps `result` as `block`'s, as long as its program, or compiler, got
+    /// to run: a missing toolchain says nothing about the code. Compile
+    /// errors are kept even without an exit code, which blocks compiled
+    /// together in one JVM don't have.
+    pub fn record(&mut self, key: String, block: &Block, result: &ExecutionResult) -> io::Result<()> {
+        let compile_error = matches!(result.error, Some((ErrorKind::CompileError, _)));
+        if result.exit_code.is_none() && result.signal.is_none() && !compile_error {
+            return Ok(());
+        }
+        let (stdout, stdout_encoding) = encode(&result.stdout.bytes()?);
+        let (stderr, stderr_encoding) = encode(&result.stderr.bytes()?);
+        let entry = Entry {
+            key: key.clone(),
+            line: block.span.start_line,
+            language: block.lang.clone(),
+            name: block.name().map(str::to_string),
+            status: result.status,
+            exit_code: result.exit_code,
+            signal: result.signal,
+            stdout,
+            stdout_encoding: stdout_encoding.to_string(),
+            stderr,
+            stderr_encoding: stderr_encoding.to_string(),
+            error_kind: result.error.as_ref().map(|(kind, _)| *kind),
+            error: result.error.as_ref().map(|(_, message)| message.clone()),
+            compiler_warnings: result.compiler_warnings.clone(),
+            warnings: result.warnings.clone(),
+        };
+        self.entries.insert(key, entry);
+        Ok(())
+    }
+
+    /// Writes the results of `blocks`, the document's keys and lines in
+    /// order. Results no block has any more are left out.
+    pub fn save(&self, blocks: &[(String, usize)]) -> io::Result<()> {
+        let mut file = File {
+            version: VERSION,
+            blocks: Vec::new(),
+        };
+        for (key, line) in blocks {
+            if file.blocks.iter().any(|entry| entry.key == *key) {
+                continue;
+            }
+            if let Some(entry) = self.entries.get(key) {
+                file.blocks.push(Entry {
+                    line: *line,
+                    ..entry.clone()
+                });
+            }
+        }
+        let mut json = serde_json::to_string_pretty(&file)?;
+        json.push('\n');
+        fs::write(&self.path, json).map_err(|e| io::Error::new(e.kind(), format!("{}: {}", self.path.display(), e)))
+    }
+}
+
+/// Output as the lines of a JSON array, one array element per line of
+/// output, if it's UTF-8; as one base64 element otherwise.
+fn encode(bytes: &[u8]) -> (Vec<String>, &'static str) {
+    match std::str::from_utf8(bytes) {
+        Ok("") => (Vec::new(), "utf-8"),
+        Ok(text) => (text.split('\n').map(str::to_string).collect(), "utf-8"),
+        Err(_) => (vec![base64::engine::general_purpose::STANDARD.encode(bytes)], "base64"),
+    }
+}
+
+fn decode(lines: &[String], encoding: &str) -> Result<Vec<u8>, base64::DecodeError> {
+    match encoding {
+        "base64" => base64::engine::general_purpose::STANDARD.decode(lines.concat()),
+        _ => Ok(lines.join("\n").into_bytes()),
+    }
+}
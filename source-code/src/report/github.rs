@@ -0,0 +1,61 @@
+//! GitHub Actions workflow commands, which the runner turns into annotations
+//! on the hacker file in the run summary and the pull request diff.
+
+use std::io;
+
+use super::{Reporter, Summary};
+use crate::console::Console;
+use crate::executor::{ErrorKind, ExecutionResult, Status};
+use crate::parser::Block;
+
+pub struct GhAnnotations {
+    pub console: Console,
+}
+
+/// Escaping of a command's message.
+fn escape_data(text: &str) -> String {
+    text.replace('%', "%25").replace('\r', "%0D").replace('\n', "%0A")
+}
+
+/// Property values additionally can't contain the separators `:` and `,`.
+fn escape_property(text: &str) -> String {
+    escape_data(text).replace(':', "%3A").replace(',', "%2C")
+}
+
+impl Reporter for GhAnnotations {
+    fn block_finished(&mut self, file: &str, block: &Block, result: &ExecutionResult) -> io::Result<()> {
+        // A missing toolchain fails the block, but is the machine's problem
+        // rather than the code's.
+        // Blocks left out on purpose aren't annotated one by one: there can
+        // be many, and GitHub only shows so many annotations of a run.
+        let command = match (result.status, result.error.as_ref().map(|(kind, _)| *kind)) {
+            (Status::Passed, _) | (Status::Skipped, Some(ErrorKind::Unchanged | ErrorKind::ConditionUnmet)) => return Ok(()),
+            (Status::Failed, Some(ErrorKind::ToolchainMissing)) | (Status::Skipped, _) => "warning",
+            (Status::Failed, _) => "error",
+        };
+        let message = result.error.as_ref().map(|(_, m)| m.as_str()).unwrap_or("");
+        let first_line = message.lines().find(|l| !l.trim().is_empty()).unwrap_or("block failed");
+        let mut out = self.console.out();
+        writeln!(
+            out,
+            "::{} file={},line={},endLine={},title={}::{}",
+            command,
+            escape_property(file),
+            block.span.start_line,
+            block.span.end_line,
+            escape_property(&block.label()),
+            escape_data(first_line.trim_end())
+        )?;
+        out.flush()
+    }
+
+    fn finish(&mut self, summary: &Summary) -> io::Result<()> {
+        if summary.unchanged == 0 {
+            return Ok(());
+        }
+        let blocks = if summary.unchanged == 1 { "block" } else { "blocks" };
+        let mut out = self.console.out();
+        writeln!(out, "::notice title=translator::{} unchanged {} skipped by --changed-only", summary.unchanged, blocks)?;
+        out.flush()
+    }
+}
@@ -0,0 +1,144 @@
+//! The line the console output prints above each block's result, built from
+//! a `--header-format` template such as `[{lang}] block {index}: {status}`.
+
+use super::format_duration;
+use crate::executor::{ExecutionResult, Status};
+use crate::parser::Block;
+
+pub const DEFAULT: &str = "[{lang}] block {index} ({file}:{start}-{end}): {status}";
+/// What `--timings` uses when no template is given.
+pub const DEFAULT_TIMINGS: &str = "[{lang}] block {index} ({file}:{start}-{end}): {status} ({timings})";
+
+const FIELDS: &[(&str, Field)] = &[
+    ("index", Field::Index),
+    ("lang", Field::Lang),
+    ("name", Field::Name),
+    ("label", Field::Label),
+    ("file", Field::File),
+    ("start", Field::Start),
+    ("end", Field::End),
+    ("status", Field::Status),
+    ("exit_code", Field::ExitCode),
+    ("duration", Field::Duration),
+    ("timings", Field::Timings),
+    ("rusage", Field::Rusage),
+];
+
+#[derive(Clone, Copy)]
+enum Field {
+    Index,
+    Lang,
+    Name,
+    Label,
+    File,
+    Start,
+    End,
+    Status,
+    ExitCode,
+    Duration,
+    Timings,
+    Rusage,
+}
+
+enum Part {
+    Text(String),
+    Field(Field),
+}
+
+pub struct Header {
+    parts: Vec<Part>,
+}
+
+impl Header {
+    /// Parses a template; `{{` and `}}` stand for literal braces. Unknown
+    /// placeholders are an error so typos surface before anything runs.
+    pub fn parse(template: &str) -> Result<Header, String> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => return Err(format!("unclosed placeholder {{{}", name)),
+                        }
+                    }
+                    let Some((_, field)) = FIELDS.iter().find(|(n, _)| *n == name) else {
+                        let known: Vec<&str> = FIELDS.iter().map(|(n, _)| *n).collect();
+                        return Err(format!(
+                            "unknown placeholder {{{}}}, expected one of {}",
+                            name,
+                            known.join(", ")
+                        ));
+                    };
+                    if !text.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(Part::Field(*field));
+                }
+                '}' => return Err("unmatched `}`, write `}}` for a literal brace".to_string()),
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+        Ok(Header { parts })
+    }
+
+    /// An empty template turns headers off.
+    pub fn is_empty(&self) -> bool {
+        self.parts.is_empty()
+    }
+
+    pub fn render(&self, file: &str, block: &Block, result: &ExecutionResult) -> String {
+        let mut line = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => line.push_str(text),
+                Part::Field(field) => line.push_str(&value(*field, file, block, result)),
+            }
+        }
+        line
+    }
+}
+
+fn value(field: Field, file: &str, block: &Block, result: &ExecutionResult) -> String {
+    match field {
+        Field::Index => block.index.to_string(),
+        Field::Lang => block.lang.clone(),
+        Field::Name => block.name().unwrap_or("").to_string(),
+        Field::Label => block.label(),
+        Field::File => file.to_string(),
+        Field::Start => block.span.start_line.to_string(),
+        Field::End => block.span.end_line.to_string(),
+        Field::Status => match result.status {
+            Status::Passed => "passed",
+            Status::Failed => "failed",
+            Status::Skipped => "skipped",
+        }
+        .to_string(),
+        Field::ExitCode => result.exit_code.map_or_else(|| "-".to_string(), |code| code.to_string()),
+        Field::Duration => format_duration(result.duration),
+        Field::Timings => {
+            let phases: Vec<String> = [("compile", result.timings.compile), ("run", result.timings.run)]
+                .into_iter()
+                .filter_map(|(name, time)| time.map(|t| format!("{} {}", name, format_duration(t))))
+                .collect();
+            phases.join(", ")
+        }
+        Field::Rusage => result.usage.map_or_else(|| "-".to_string(), |usage| usage.to_string()),
+    }
+}
@@ -0,0 +1,229 @@
+//! A single self-contained HTML page: no external stylesheets, scripts or
+//! fonts, so the file can be attached to a ticket and opened anywhere.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use super::{format_duration, timestamp, truncate_lines, Reporter, Summary};
+use crate::executor::{ExecutionResult, Status};
+use crate::parser::Block;
+use crate::toolchain;
+use crate::tools::Tools;
+
+const STYLE: &str = "
+body { font-family: system-ui, sans-serif; margin: 2em auto; max-width: 70em; padding: 0 1em; color: #1f2328; }
+h1 { margin-bottom: 0.2em; }
+.meta { color: #59636e; }
+table { border-collapse: collapse; margin: 1em 0; }
+td, th { border: 1px solid #d1d9e0; padding: 0.3em 0.7em; text-align: left; }
+details { border: 1px solid #d1d9e0; border-radius: 6px; margin: 0.6em 0; padding: 0.4em 0.8em; }
+details.failed { border-color: #cf222e; }
+summary { cursor: pointer; }
+pre { background: #f6f8fa; padding: 0.7em; overflow-x: auto; border-radius: 4px; }
+pre.stderr { background: #fff5f5; }
+.badge { display: inline-block; min-width: 5em; text-align: center; border-radius: 1em; padding: 0 0.6em; color: #fff; font-size: 0.85em; }
+.badge.passed { background: #1a7f37; }
+.badge.failed { background: #cf222e; }
+.badge.skipped { background: #6e7781; }
+";
+
+pub struct Html {
+    path: PathBuf,
+    max_lines: usize,
+    files: Vec<FileEntry>,
+    tools: Tools,
+}
+
+struct FileEntry {
+    file: String,
+    blocks: Vec<BlockEntry>,
+}
+
+struct BlockEntry {
+    label: String,
+    lang: String,
+    lines: (usize, usize),
+    code: String,
+    status: Status,
+    duration: String,
+    stdout: String,
+    stderr: String,
+    compiler_warnings: Option<String>,
+    error: Option<String>,
+}
+
+impl Html {
+    pub fn new(path: PathBuf, max_lines: usize, tools: Tools) -> Self {
+        Html {
+            path,
+            max_lines,
+            files: Vec::new(),
+            tools,
+        }
+    }
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn status_name(status: Status) -> &'static str {
+    match status {
+        Status::Passed => "passed",
+        Status::Failed => "failed",
+        Status::Skipped => "skipped",
+    }
+}
+
+impl Reporter for Html {
+    fn file_started(&mut self, file: &str) -> io::Result<()> {
+        self.files.push(FileEntry {
+            file: file.to_string(),
+            blocks: Vec::new(),
+        });
+        Ok(())
+    }
+
+    fn block_finished(&mut self, file: &str, block: &Block, result: &ExecutionResult) -> io::Result<()> {
+        let entry = BlockEntry {
+            label: block.label(),
+            lang: block.lang.clone(),
+            lines: (block.span.start_line, block.span.end_line),
+            code: block.code.clone(),
+            status: result.status,
+            duration: result
+                .reuse_note(file)
+                .unwrap_or_else(|| format_duration(result.duration)),
+            stdout: result.stdout.preview().into_owned(),
+            stderr: result.stderr.preview().into_owned(),
+            compiler_warnings: result.compiler_warnings.clone(),
+            error: result.error.as_ref().map(|(_, m)| m.clone()),
+        };
+        self.files
+            .last_mut()
+            .expect("file_started comes first")
+            .blocks
+            .push(entry);
+        Ok(())
+    }
+
+    fn finish(&mut self, summary: &Summary) -> io::Result<()> {
+        let mut html = String::from("<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n");
+        html.push_str("<title>translator report</title>\n");
+        html.push_str(&format!("<style>{}</style>\n</head>\n<body>\n", STYLE));
+        html.push_str("<h1>translator report</h1>\n");
+        html.push_str(&format!(
+            "<p class=\"meta\">{} · {} blocks: {} passed, {} failed, {} skipped{} in {}</p>\n",
+            timestamp(),
+            summary.total,
+            summary.passed,
+            summary.failed,
+            summary.skipped,
+            summary.skip_notes(),
+            format_duration(summary.duration)
+        ));
+
+        let languages: BTreeSet<&str> = self
+            .files
+            .iter()
+            .flat_map(|f| f.blocks.iter().map(|b| b.lang.as_str()))
+            .collect();
+        html.push_str("<table>\n<tr><th>Language</th><th>Toolchain</th></tr>\n");
+        for lang in languages {
+            let version = toolchain::version(lang, &self.tools).unwrap_or_else(|| "not found".to_string());
+            html.push_str(&format!(
+                "<tr><td>{}</td><td>{}</td></tr>\n",
+                escape(lang),
+                escape(&version)
+            ));
+        }
+        html.push_str("</table>\n");
+
+        if self.files.len() > 1 {
+            html.push_str("<h2>Files</h2>\n<ul>\n");
+            for (n, file) in self.files.iter().enumerate() {
+                let failed = file.blocks.iter().filter(|b| b.status == Status::Failed).count();
+                html.push_str(&format!(
+                    "<li><a href=\"#file-{}\">{}</a> ({} blocks, {} failed)</li>\n",
+                    n + 1,
+                    escape(&file.file),
+                    file.blocks.len(),
+                    failed
+                ));
+            }
+            html.push_str("</ul>\n");
+        }
+
+        for (n, file) in self.files.iter().enumerate() {
+            html.push_str(&format!(
+                "<section id=\"file-{}\">\n<h2>{}</h2>\n",
+                n + 1,
+                escape(&file.file)
+            ));
+            for block in &file.blocks {
+                let status = status_name(block.status);
+                let open = if block.status == Status::Failed { " open" } else { "" };
+                html.push_str(&format!(
+                    "<details class=\"{}\"{}>\n<summary><span class=\"badge {}\">{}</span> {} \
+                     <span class=\"meta\">lines {}-{} · {}</span></summary>\n",
+                    status,
+                    open,
+                    status,
+                    status,
+                    escape(&block.label),
+                    block.lines.0,
+                    block.lines.1,
+                    block.duration
+                ));
+                html.push_str(&format!(
+                    "<pre><code class=\"language-{}\">{}</code></pre>\n",
+                    escape(&block.lang),
+                    escape(&block.code)
+                ));
+                if let Some(warnings) = &block.compiler_warnings {
+                    html.push_str(&format!(
+                        "<h4>Compiler warnings</h4>\n<pre class=\"stderr\">{}</pre>\n",
+                        escape(&truncate_lines(warnings, self.max_lines))
+                    ));
+                }
+                if !block.stdout.is_empty() {
+                    html.push_str(&format!(
+                        "<h4>stdout</h4>\n<pre>{}</pre>\n",
+                        escape(&truncate_lines(&block.stdout, self.max_lines))
+                    ));
+                }
+                if !block.stderr.is_empty() {
+                    html.push_str(&format!(
+                        "<h4>stderr</h4>\n<pre class=\"stderr\">{}</pre>\n",
+                        escape(&truncate_lines(&block.stderr, self.max_lines))
+                    ));
+                }
+                if let Some(error) = &block.error {
+                    if block.stderr.trim() != error.trim() {
+                        html.push_str(&format!(
+                            "<h4>{}</h4>\n<pre class=\"stderr\">{}</pre>\n",
+                            if block.status == Status::Skipped { "Skipped" } else { "Error" },
+                            escape(&truncate_lines(error, self.max_lines))
+                        ));
+                    }
+                }
+                html.push_str("</details>\n");
+            }
+            html.push_str("</section>\n");
+        }
+        html.push_str("</body>\n</html>\n");
+        fs::write(&self.path, html)
+    }
+}
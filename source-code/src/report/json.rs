@@ -0,0 +1,198 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use base64::Engine;
+use serde::ser::{Error, SerializeMap};
+use serde::{Serialize, Serializer};
+
+use super::{Reporter, Summary};
+use crate::capture::Captured;
+use crate::console::Console;
+use crate::executor::{ErrorKind, ExecutionResult, Origin, Status};
+use crate::parser::Block;
+use crate::tools::Tools;
+use crate::usage::Usage;
+use crate::{languages, toolchain};
+
+/// A single JSON document (see the README for the schema) written when the
+/// run finishes.
+pub struct Json {
+    out: Box<dyn Write>,
+    blocks: Vec<BlockRecord>,
+    tools: Tools,
+}
+
+#[derive(Serialize)]
+struct BlockRecord {
+    index: usize,
+    file: String,
+    language: String,
+    name: Option<String>,
+    start_line: usize,
+    end_line: usize,
+    status: Status,
+    exit_code: Option<i32>,
+    signal: Option<i32>,
+    duration_ms: u128,
+    compile_ms: Option<u128>,
+    run_ms: Option<u128>,
+    #[serde(flatten)]
+    stdout: StreamFields,
+    #[serde(flatten)]
+    stderr: StreamFields,
+    error_kind: Option<ErrorKind>,
+    error: Option<String>,
+    duplicate_of: Option<Origin>,
+    replayed: bool,
+    compiler_flags: Vec<String>,
+    language_version: Option<String>,
+    toolchain_version: Option<String>,
+    compiler_warnings: Option<String>,
+    warnings: Vec<String>,
+    disk_bytes: Option<u64>,
+    artifacts: Vec<PathBuf>,
+    rusage: Option<Usage>,
+}
+
+/// A captured stream as the `stdout`, `stdout_encoding` and `stdout_file`
+/// fields (or `stderr`...). Spilled output is read back only while the
+/// document is written, one stream at a time, unless its file is kept: then
+/// the file is referenced and the text is just its head and tail.
+struct StreamFields {
+    name: &'static str,
+    captured: Captured,
+}
+
+impl Serialize for StreamFields {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (text, encoding, file) = match self.captured.spilled() {
+            Some(spilled) if spilled.kept => (self.captured.preview().into_owned(), "utf-8", Some(&spilled.path)),
+            _ => {
+                let bytes = self.captured.bytes().map_err(S::Error::custom)?;
+                let (text, encoding) = encode(&bytes);
+                (text, encoding, None)
+            }
+        };
+        let mut map = serializer.serialize_map(Some(3))?;
+        map.serialize_entry(self.name, &text)?;
+        map.serialize_entry(&format!("{}_encoding", self.name), encoding)?;
+        map.serialize_entry(&format!("{}_file", self.name), &file)?;
+        map.end()
+    }
+}
+
+#[derive(Serialize)]
+struct Document<'a> {
+    /// `failed` if any block did, `passed` otherwise.
+    status: Status,
+    blocks: &'a [BlockRecord],
+    summary: &'a Summary,
+    /// The version of each built-in toolchain the blocks used, `None` when
+    /// it is missing.
+    toolchains: BTreeMap<&'a str, Option<String>>,
+}
+
+/// Captured output is kept verbatim: valid UTF-8 becomes a string, anything
+/// else is base64-encoded and flagged as such.
+pub(super) fn encode(bytes: &[u8]) -> (String, &'static str) {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => (text.to_string(), "utf-8"),
+        Err(_) => (base64::engine::general_purpose::STANDARD.encode(bytes), "base64"),
+    }
+}
+
+impl Json {
+    pub fn new(output: Option<&Path>, console: &Console, tools: Tools) -> io::Result<Self> {
+        let out: Box<dyn Write> = match output {
+            Some(path) => Box::new(File::create(path)?),
+            None => Box::new(console.out_writer()),
+        };
+        Ok(Json {
+            out,
+            blocks: Vec::new(),
+            tools,
+        })
+    }
+
+    /// A document that is only ever [rendered](Json::render), never
+    /// written out by [`Reporter::finish`].
+    pub(super) fn collect(tools: Tools) -> Self {
+        Json {
+            out: Box::new(io::sink()),
+            blocks: Vec::new(),
+            tools,
+        }
+    }
+
+    /// The document for the blocks so far and `summary`, in memory.
+    pub(super) fn render(&self, summary: &Summary) -> serde_json::Result<Vec<u8>> {
+        let mut json = serde_json::to_vec_pretty(&document(&self.blocks, &self.tools, summary))?;
+        json.push(b'\n');
+        Ok(json)
+    }
+}
+
+fn document<'a>(blocks: &'a [BlockRecord], tools: &Tools, summary: &'a Summary) -> Document<'a> {
+    // Only the toolchains the blocks used, which the cache has usually
+    // probed already.
+    let toolchains = summary
+        .languages
+        .keys()
+        .filter(|lang| languages::BUILTIN.contains(&lang.as_str()))
+        .map(|lang| (lang.as_str(), toolchain::version(lang, tools)))
+        .collect();
+    Document {
+        status: if summary.failed > 0 { Status::Failed } else { Status::Passed },
+        blocks,
+        summary,
+        toolchains,
+    }
+}
+
+impl Reporter for Json {
+    fn block_finished(&mut self, file: &str, block: &Block, result: &ExecutionResult) -> io::Result<()> {
+        self.blocks.push(BlockRecord {
+            index: block.index,
+            file: file.to_string(),
+            language: block.lang.clone(),
+            name: block.name().map(str::to_string),
+            start_line: block.span.start_line,
+            end_line: block.span.end_line,
+            status: result.status,
+            exit_code: result.exit_code,
+            signal: result.signal,
+            duration_ms: result.duration.as_millis(),
+            compile_ms: result.timings.compile.map(|d| d.as_millis()),
+            run_ms: result.timings.run.map(|d| d.as_millis()),
+            stdout: StreamFields {
+                name: "stdout",
+                captured: result.stdout.clone(),
+            },
+            stderr: StreamFields {
+                name: "stderr",
+                captured: result.stderr.clone(),
+            },
+            error_kind: result.error.as_ref().map(|(kind, _)| *kind),
+            error: result.error.as_ref().map(|(_, message)| message.clone()),
+            duplicate_of: result.duplicate_of.clone(),
+            replayed: result.replayed,
+            compiler_flags: result.compiler_flags.clone(),
+            language_version: result.language_version.clone(),
+            toolchain_version: result.toolchain_version.clone(),
+            compiler_warnings: result.compiler_warnings.clone(),
+            warnings: result.warnings.clone(),
+            disk_bytes: result.disk_bytes,
+            artifacts: result.artifacts.clone(),
+            rusage: result.usage,
+        });
+        Ok(())
+    }
+
+    fn finish(&mut self, summary: &Summary) -> io::Result<()> {
+        serde_json::to_writer_pretty(&mut self.out, &document(&self.blocks, &self.tools, summary))?;
+        writeln!(self.out)?;
+        self.out.flush()
+    }
+}
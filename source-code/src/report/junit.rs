@@ -0,0 +1,161 @@
+//! JUnit XML, the lowest common denominator of CI test reporting: every input
+//! file becomes a `<testsuite>` and every block a `<testcase>`.
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use super::{Reporter, Summary};
+use crate::executor::{ExecutionResult, Status};
+use crate::parser::Block;
+
+pub struct Junit {
+    path: PathBuf,
+    suites: Vec<Suite>,
+}
+
+struct Suite {
+    file: String,
+    cases: Vec<Case>,
+}
+
+struct Case {
+    name: String,
+    status: Status,
+    duration: Duration,
+    message: String,
+    stdout: String,
+    stderr: String,
+    warnings: String,
+}
+
+impl Junit {
+    pub fn new(path: PathBuf) -> Self {
+        Junit { path, suites: Vec::new() }
+    }
+}
+
+impl Reporter for Junit {
+    fn file_started(&mut self, file: &str) -> io::Result<()> {
+        self.suites.push(Suite {
+            file: file.to_string(),
+            cases: Vec::new(),
+        });
+        Ok(())
+    }
+
+    fn block_finished(&mut self, _file: &str, block: &Block, result: &ExecutionResult) -> io::Result<()> {
+        let name = match block.name() {
+            Some(name) => name.to_string(),
+            None => format!("{}#{}", block.lang, block.index),
+        };
+        let suite = self.suites.last_mut().expect("file_started comes first");
+        suite.cases.push(Case {
+            name,
+            status: result.status,
+            duration: result.duration,
+            message: result.error.as_ref().map(|(_, m)| m.clone()).unwrap_or_default(),
+            stdout: result.stdout.preview().into_owned(),
+            stderr: result.stderr.preview().into_owned(),
+            warnings: result.compiler_warnings.clone().unwrap_or_default(),
+        });
+        Ok(())
+    }
+
+    fn finish(&mut self, summary: &Summary) -> io::Result<()> {
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        xml.push_str(&format!(
+            "<testsuites name=\"translator\" tests=\"{}\" failures=\"{}\" skipped=\"{}\" time=\"{}\">\n",
+            summary.total,
+            summary.failed,
+            summary.skipped,
+            seconds(summary.duration)
+        ));
+        for suite in &self.suites {
+            let count = |status| suite.cases.iter().filter(|c| c.status == status).count();
+            let time: Duration = suite.cases.iter().map(|c| c.duration).sum();
+            xml.push_str(&format!(
+                "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" errors=\"0\" skipped=\"{}\" time=\"{}\">\n",
+                escape(&suite.file),
+                suite.cases.len(),
+                count(Status::Failed),
+                count(Status::Skipped),
+                seconds(time)
+            ));
+            for case in &suite.cases {
+                xml.push_str(&format!(
+                    "    <testcase classname=\"{}\" name=\"{}\" time=\"{}\"",
+                    escape(&suite.file),
+                    escape(&case.name),
+                    seconds(case.duration)
+                ));
+                if case.status == Status::Passed && case.stdout.is_empty() && case.stderr.is_empty() && case.warnings.is_empty() {
+                    xml.push_str("/>\n");
+                    continue;
+                }
+                xml.push_str(">\n");
+                let first_line = case.message.lines().next().unwrap_or("");
+                match case.status {
+                    Status::Passed => {}
+                    Status::Failed => {
+                        // The full compiler/runtime stderr is the failure body;
+                        // fall back to the error itself when nothing was printed.
+                        let body = if case.stderr.trim().is_empty() {
+                            &case.message
+                        } else {
+                            &case.stderr
+                        };
+                        xml.push_str(&format!(
+                            "      <failure message=\"{}\">{}</failure>\n",
+                            escape(first_line),
+                            escape(body)
+                        ));
+                    }
+                    Status::Skipped => {
+                        xml.push_str(&format!("      <skipped message=\"{}\"/>\n", escape(first_line)));
+                    }
+                }
+                if !case.stdout.is_empty() {
+                    xml.push_str(&format!("      <system-out>{}</system-out>\n", escape(&case.stdout)));
+                }
+                // There is only one system-err; the compiler's warnings lead.
+                let system_err = if case.warnings.is_empty() {
+                    case.stderr.clone()
+                } else {
+                    format!("Compiler warnings:\n{}\n{}", case.warnings, case.stderr)
+                };
+                if !system_err.is_empty() {
+                    xml.push_str(&format!("      <system-err>{}</system-err>\n", escape(&system_err)));
+                }
+                xml.push_str("    </testcase>\n");
+            }
+            xml.push_str("  </testsuite>\n");
+        }
+        xml.push_str("</testsuites>\n");
+        fs::write(&self.path, xml)
+    }
+}
+
+fn seconds(duration: Duration) -> String {
+    format!("{:.3}", duration.as_secs_f64())
+}
+
+/// Escapes text for use in attributes and element content. Control
+/// characters XML 1.0 can't represent at all are replaced.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if (c as u32) < 0x20 || c == '\u{FFFE}' || c == '\u{FFFF}' => escaped.push('\u{FFFD}'),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
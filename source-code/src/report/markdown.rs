@@ -0,0 +1,189 @@
+//! A Markdown report meant to be pasted into PR descriptions: a summary
+//! table followed by every block's source and captured output.
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use super::{format_duration, truncate_lines, Reporter, Summary};
+use crate::executor::{ExecutionResult, Status};
+use crate::parser::Block;
+use crate::usage::Usage;
+
+pub struct Markdown {
+    path: PathBuf,
+    max_lines: usize,
+    entries: Vec<Entry>,
+}
+
+struct Entry {
+    file: String,
+    label: String,
+    lang: String,
+    lines: (usize, usize),
+    code: String,
+    status: Status,
+    duration: String,
+    stdout: String,
+    stderr: String,
+    compiler_warnings: Option<String>,
+    error: Option<String>,
+    usage: Option<Usage>,
+}
+
+impl Markdown {
+    pub fn new(path: PathBuf, max_lines: usize) -> Self {
+        Markdown {
+            path,
+            max_lines,
+            entries: Vec::new(),
+        }
+    }
+
+    fn truncate(&self, text: &str) -> String {
+        truncate_lines(text, self.max_lines)
+    }
+}
+
+fn badge(status: Status) -> &'static str {
+    match status {
+        Status::Passed => "✅ passed",
+        Status::Failed => "❌ **failed**",
+        Status::Skipped => "⏭️ skipped",
+    }
+}
+
+/// Wraps `text` in a fence longer than any backtick run inside it.
+fn fenced(tag: &str, text: &str) -> String {
+    let mut longest = 0;
+    let mut run = 0;
+    for c in text.chars() {
+        run = if c == '`' { run + 1 } else { 0 };
+        longest = longest.max(run);
+    }
+    let fence = "`".repeat(longest.max(2) + 1);
+    let newline = if text.ends_with('\n') { "" } else { "\n" };
+    format!("{}{}\n{}{}{}\n", fence, tag, text, newline, fence)
+}
+
+/// The `--rusage` columns of a block's row, with `n/a` for what the
+/// platform didn't tell and `-` for blocks whose program didn't run.
+fn usage_cells(usage: Option<Usage>) -> String {
+    let columns = usage.map_or_else(|| ["-".to_string(), "-".to_string(), "-".to_string()], |usage| usage.columns());
+    columns.iter().map(|column| format!(" {} |", column)).collect()
+}
+
+fn cell(text: &str) -> String {
+    text.replace('|', "\\|")
+}
+
+/// GitHub-style heading anchor for `## {index}. {label}` headings.
+fn anchor(heading: &str) -> String {
+    heading
+        .chars()
+        .filter_map(|c| match c {
+            c if c.is_alphanumeric() => Some(c.to_ascii_lowercase()),
+            ' ' | '-' => Some('-'),
+            '_' => Some('_'),
+            _ => None,
+        })
+        .collect()
+}
+
+impl Reporter for Markdown {
+    fn block_finished(&mut self, file: &str, block: &Block, result: &ExecutionResult) -> io::Result<()> {
+        self.entries.push(Entry {
+            file: file.to_string(),
+            label: block.label(),
+            lang: block.lang.clone(),
+            lines: (block.span.start_line, block.span.end_line),
+            code: block.code.clone(),
+            status: result.status,
+            duration: result
+                .reuse_note(file)
+                .unwrap_or_else(|| format_duration(result.duration)),
+            stdout: result.stdout.preview().into_owned(),
+            stderr: result.stderr.preview().into_owned(),
+            compiler_warnings: result.compiler_warnings.clone(),
+            error: result.error.as_ref().map(|(_, m)| m.clone()),
+            usage: result.usage,
+        });
+        Ok(())
+    }
+
+    fn finish(&mut self, summary: &Summary) -> io::Result<()> {
+        let mut md = String::from("# translator report\n\n");
+        md.push_str(&format!(
+            "**{} blocks:** {} passed, {} failed, {} skipped{} in {}\n\n",
+            summary.total,
+            summary.passed,
+            summary.failed,
+            summary.skipped,
+            summary.skip_notes(),
+            format_duration(summary.duration)
+        ));
+        // Blocks only have usage under --rusage, and then all that ran do.
+        let rusage = self.entries.iter().any(|e| e.usage.is_some());
+        if rusage {
+            md.push_str("| # | Block | Language | Status | Duration | Max RSS | User CPU | Sys CPU |\n");
+            md.push_str("|---|-------|----------|--------|----------|---------|----------|---------|\n");
+        } else {
+            md.push_str("| # | Block | Language | Status | Duration |\n");
+            md.push_str("|---|-------|----------|--------|----------|\n");
+        }
+        let headings: Vec<String> = self
+            .entries
+            .iter()
+            .enumerate()
+            .map(|(n, e)| format!("{}. {}: {}", n + 1, e.file, e.label))
+            .collect();
+        for (n, (entry, heading)) in self.entries.iter().zip(&headings).enumerate() {
+            md.push_str(&format!(
+                "| {} | [{}](#{}) | {} | {} | {} |",
+                n + 1,
+                cell(&format!("{}: {}", entry.file, entry.label)),
+                anchor(heading),
+                cell(&entry.lang),
+                badge(entry.status),
+                entry.duration
+            ));
+            if rusage {
+                md.push_str(&usage_cells(entry.usage));
+            }
+            md.push('\n');
+        }
+        for (entry, heading) in self.entries.iter().zip(&headings) {
+            md.push_str(&format!("\n## {}\n\n", heading));
+            md.push_str(&format!(
+                "{} · lines {}-{} · {}\n\n",
+                badge(entry.status),
+                entry.lines.0,
+                entry.lines.1,
+                entry.duration
+            ));
+            md.push_str(&fenced(&entry.lang, &entry.code));
+            if let Some(warnings) = &entry.compiler_warnings {
+                md.push_str("\nCompiler warnings:\n\n");
+                md.push_str(&fenced("text", &self.truncate(warnings)));
+            }
+            if !entry.stdout.is_empty() {
+                md.push_str("\nOutput:\n\n");
+                md.push_str(&fenced("text", &self.truncate(&entry.stdout)));
+            }
+            match (entry.status, &entry.error) {
+                (Status::Failed, Some(error)) => {
+                    md.push_str("\n> [!CAUTION]\n> **This block failed.**\n\n");
+                    let details = if entry.stderr.trim().is_empty() { error } else { &entry.stderr };
+                    md.push_str(&fenced("text", &self.truncate(details)));
+                }
+                (Status::Skipped, Some(reason)) => md.push_str(&format!("\n_Skipped: {}_\n", reason.trim())),
+                _ if !entry.stderr.is_empty() => {
+                    md.push_str("\nStderr:\n\n");
+                    md.push_str(&fenced("text", &self.truncate(&entry.stderr)));
+                }
+                _ => {}
+            }
+        }
+        fs::write(&self.path, md)
+    }
+}
@@ -0,0 +1,463 @@
+//! Presentation of block results, either as they happen or as a document
+//! written once the run is over.
+
+mod github;
+mod header;
+mod html;
+mod json;
+mod junit;
+mod markdown;
+mod ndjson;
+mod notify;
+mod prefix;
+mod tap;
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::io::{self, IsTerminal};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+use crate::console::Console;
+use crate::executor::{ErrorKind, ExecutionResult, Status, Stream};
+use crate::languages;
+use crate::parser::Block;
+
+pub use github::GhAnnotations;
+pub use header::Header;
+pub use html::Html;
+pub use json::Json;
+pub use junit::Junit;
+pub use markdown::Markdown;
+pub use ndjson::Ndjson;
+pub use notify::Notify;
+pub use prefix::Prefix;
+pub use tap::Tap;
+
+pub trait Reporter {
+    fn file_started(&mut self, _file: &str) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn block_started(&mut self, _file: &str, _block: &Block) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// A chunk of what the block's program printed, while it runs.
+    fn output(&mut self, _file: &str, _block: &Block, _stream: Stream, _chunk: &[u8]) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn block_finished(&mut self, file: &str, block: &Block, result: &ExecutionResult) -> io::Result<()>;
+
+    fn finish(&mut self, summary: &Summary) -> io::Result<()>;
+}
+
+/// How many blocks [`Summary`] lists as the slowest of the run.
+const SLOWEST: usize = 3;
+
+#[derive(Debug, Default, Serialize)]
+pub struct Summary {
+    pub total: usize,
+    pub passed: usize,
+    pub failed: usize,
+    /// Failed blocks whose code didn't compile.
+    pub compile_errors: usize,
+    /// Failed blocks whose program, or compiler, was killed by a signal.
+    pub crashed: usize,
+    pub skipped: usize,
+    /// Blocks without code, which weren't run and aren't in `total`.
+    pub empty: usize,
+    /// Skipped blocks that `--changed-only` left out, which `skips` doesn't
+    /// list.
+    pub unchanged: usize,
+    /// Blocks whose result `--replay` reused, which count as passed or
+    /// failed as well.
+    pub replayed: usize,
+    #[serde(rename = "duration_ms", serialize_with = "millis")]
+    pub duration: Duration,
+    pub slowest: Vec<BlockRef>,
+    pub failures: Vec<BlockRef>,
+    /// Skipped blocks, with the reason.
+    pub skips: Vec<Skip>,
+    /// Time spent per language, split by phase.
+    pub languages: BTreeMap<String, LanguageTime>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct LanguageTime {
+    pub blocks: usize,
+    #[serde(rename = "compile_ms", serialize_with = "millis")]
+    pub compile: Duration,
+    #[serde(rename = "run_ms", serialize_with = "millis")]
+    pub run: Duration,
+}
+
+/// Identifies a block in the summary's lists.
+#[derive(Debug, Clone, Serialize)]
+pub struct BlockRef {
+    pub file: String,
+    pub line: usize,
+    pub label: String,
+    #[serde(rename = "duration_ms", serialize_with = "millis")]
+    pub duration: Duration,
+}
+
+/// A skipped block in the summary, and why it wasn't run.
+#[derive(Debug, Clone, Serialize)]
+pub struct Skip {
+    #[serde(flatten)]
+    pub block: BlockRef,
+    pub reason: String,
+}
+
+impl Summary {
+    pub fn add(&mut self, file: &str, block: &Block, result: &ExecutionResult) {
+        self.total += 1;
+        if result.replayed {
+            self.replayed += 1;
+        }
+        let entry = BlockRef {
+            file: file.to_string(),
+            line: block.span.start_line,
+            label: block.label(),
+            duration: result.duration,
+        };
+        match result.status {
+            Status::Passed => self.passed += 1,
+            Status::Failed => {
+                self.failed += 1;
+                if let Some((ErrorKind::CompileError, _)) = result.error {
+                    self.compile_errors += 1;
+                }
+                if result.signal.is_some() {
+                    self.crashed += 1;
+                }
+                self.failures.push(entry.clone());
+            }
+            Status::Skipped => {
+                self.skipped += 1;
+                if let Some((ErrorKind::Unchanged, _)) = result.error {
+                    self.unchanged += 1;
+                } else if let Some((_, message)) = &result.error {
+                    self.skips.push(Skip {
+                        block: entry.clone(),
+                        reason: message.lines().next().unwrap_or_default().to_string(),
+                    });
+                }
+            }
+        }
+        if result.status != Status::Skipped {
+            let time = self.languages.entry(block.lang.clone()).or_default();
+            time.blocks += 1;
+            time.compile += result.timings.compile.unwrap_or_default();
+            time.run += result.timings.run.unwrap_or_default();
+            let at = self.slowest.partition_point(|b| b.duration >= entry.duration);
+            // Replayed results took no time to speak of.
+            if at < SLOWEST && !result.replayed {
+                self.slowest.insert(at, entry);
+                self.slowest.truncate(SLOWEST);
+            }
+        }
+    }
+
+    /// What follows the number of skipped blocks on the summary line:
+    /// ` (398 by --changed-only)` when that filter left blocks out, so a
+    /// green run doesn't pass for a full one, `, 2 empty` when blocks were
+    /// skipped as empty and `, 5 replayed` when `--replay` ran fewer blocks
+    /// than passed or failed.
+    pub fn skip_notes(&self) -> String {
+        let mut notes = String::new();
+        if self.unchanged > 0 {
+            notes.push_str(&format!(" ({} by --changed-only)", self.unchanged));
+        }
+        if self.empty > 0 {
+            notes.push_str(&format!(", {} empty", self.empty));
+        }
+        if self.replayed > 0 {
+            notes.push_str(&format!(", {} replayed", self.replayed));
+        }
+        notes
+    }
+}
+
+/// Renders durations the way people read them: `34 ms`, `1.25 s`.
+pub fn format_duration(duration: Duration) -> String {
+    if duration.as_millis() < 1000 {
+        format!("{} ms", duration.as_millis())
+    } else {
+        format!("{:.2} s", duration.as_secs_f64())
+    }
+}
+
+/// Keeps the first `max_lines` lines of `text` and says how many were cut.
+pub fn truncate_lines(text: &str, max_lines: usize) -> String {
+    let total = text.lines().count();
+    if total <= max_lines {
+        return text.to_string();
+    }
+    let mut kept: String = text
+        .lines()
+        .take(max_lines)
+        .flat_map(|line| [line, "\n"])
+        .collect();
+    kept.push_str(&format!("… {} more lines truncated\n", total - max_lines));
+    kept
+}
+
+/// The current time as `YYYY-MM-DD HH:MM:SS UTC`.
+pub fn timestamp() -> String {
+    let (date, time, _) = utc(SystemTime::now());
+    format!("{} {} UTC", date, time)
+}
+
+/// `time` as `YYYY-MM-DDTHH:MM:SS.mmmZ`, as in RFC 3339.
+pub fn rfc3339(time: SystemTime) -> String {
+    let (date, time, millis) = utc(time);
+    format!("{}T{}.{:03}Z", date, time, millis)
+}
+
+/// The date, time of day and milliseconds of `time` in UTC.
+fn utc(time: SystemTime) -> (String, String, u32) {
+    let since = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since.as_secs();
+    let (days, rem) = (secs / 86_400, secs % 86_400);
+    // Civil-from-days, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (
+        format!("{:04}-{:02}-{:02}", year, month, day),
+        format!("{:02}:{:02}:{:02}", rem / 3600, rem % 3600 / 60, rem % 60),
+        since.subsec_millis(),
+    )
+}
+
+fn millis<S: serde::Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u128(duration.as_millis())
+}
+
+/// Whether to use ANSI colors on stderr: only for terminals, and never when
+/// `NO_COLOR` is set or the terminal is dumb.
+pub fn stderr_color() -> bool {
+    io::stderr().is_terminal() && color_allowed()
+}
+
+/// Like [`stderr_color`], for stdout.
+pub fn stdout_color() -> bool {
+    io::stdout().is_terminal() && color_allowed()
+}
+
+fn color_allowed() -> bool {
+    env::var_os("NO_COLOR").is_none() && env::var("TERM").map_or(true, |term| term != "dumb")
+}
+
+/// Colors the lines of a unified diff.
+fn colorize_diff(diff: &str) -> String {
+    diff.lines()
+        .map(|line| {
+            let color = match line.as_bytes().first() {
+                Some(b'-') if !line.starts_with("---") => "31",
+                Some(b'+') if !line.starts_with("+++") => "32",
+                Some(b'@') => "36",
+                Some(b'\\') => "2",
+                _ => return format!("{}\n", line),
+            };
+            format!("\x1b[{}m{}\x1b[0m\n", color, line)
+        })
+        .collect()
+}
+
+/// The classic console output: program output on stdout, problems on stderr,
+/// and a summary at the end. `quiet` leaves only the program output and
+/// errors, and `raw` only the exact bytes of the output, and errors on
+/// stderr.
+pub struct Human {
+    pub console: Console,
+    pub quiet: bool,
+    pub raw: bool,
+    pub timings: bool,
+    /// Sends what would go to stdout to stderr too, leaving stdout to
+    /// another format.
+    pub stderr_only: bool,
+    pub header: Header,
+    /// Names the block on every line of its output, from `--prefix`.
+    pub prefix: Option<Prefix>,
+    /// Whether stdout shows ANSI colors, like `console.color` for stderr.
+    pub stdout_color: bool,
+}
+
+impl Human {
+    pub fn default_header(timings: bool, rusage: bool) -> String {
+        let mut header = if timings { header::DEFAULT_TIMINGS } else { header::DEFAULT }.to_string();
+        if rusage {
+            header.push_str(" ({rusage})");
+        }
+        header
+    }
+
+    /// `text`, which the block's program or compiler printed, prefixed
+    /// with `--prefix`. `stderr` says where it is going.
+    fn program_text<'a>(&mut self, block: &Block, text: Cow<'a, str>, stderr: bool) -> Cow<'a, str> {
+        let color = if stderr || self.stderr_only { self.console.color } else { self.stdout_color };
+        match &mut self.prefix {
+            Some(prefix) => Cow::Owned(prefix.apply(block, &text, color)),
+            None => text,
+        }
+    }
+
+    fn print(&self, message: fmt::Arguments) -> io::Result<()> {
+        if self.stderr_only {
+            self.console.err().write_fmt(message)
+        } else {
+            self.console.out().write_fmt(message)
+        }
+    }
+}
+
+impl Reporter for Human {
+    fn block_finished(&mut self, file: &str, block: &Block, result: &ExecutionResult) -> io::Result<()> {
+        for warning in &result.warnings {
+            writeln!(self.console.err(), "{}:{}: warning: {}", file, block.span.start_line, warning)?;
+        }
+        if !self.quiet && !self.raw {
+            for path in &result.artifacts {
+                writeln!(self.console.err(), "{}:{}: artifact: {}", file, block.span.start_line, path.display())?;
+            }
+        }
+        if self.raw {
+            return match (&result.status, &result.error) {
+                (Status::Passed, _) => self.console.out().write_all(&result.stdout.bytes()?),
+                (_, Some((_, message))) => writeln!(self.console.err(), "{}", message),
+                (_, None) => Ok(()),
+            };
+        }
+        let header = (!self.header.is_empty()).then(|| {
+            let header = self.header.render(file, block, result);
+            match result.reuse_note(file) {
+                Some(note) => format!("{}, {}", header, note),
+                None => header,
+            }
+        });
+        match (&result.status, &result.error, header) {
+            (Status::Passed, _, Some(header)) if !self.quiet => {
+                let stdout = result.stdout.preview();
+                let note = if stdout.is_empty() || stdout.ends_with('\n') {
+                    ""
+                } else {
+                    "\n⏎ missing trailing newline\n"
+                };
+                let stdout = self.program_text(block, stdout, false);
+                self.print(format_args!("{}\n{}{}\n", header, stdout, note))?
+            }
+            (Status::Passed, _, _) => {
+                let stdout = self.program_text(block, result.stdout.preview(), false);
+                self.print(format_args!("{}", stdout))?
+            }
+            (_, error, header) => {
+                let message = match error {
+                    Some((ErrorKind::ExpectMismatch, message)) if self.console.color => colorize_diff(message),
+                    Some((ErrorKind::CompileError | ErrorKind::NonZeroExit | ErrorKind::Signal, message)) => {
+                        let message = format!("{}\n", message);
+                        self.program_text(block, Cow::Owned(message), true).into_owned()
+                    }
+                    Some((_, message)) => format!("{}\n", message),
+                    None => String::new(),
+                };
+                // What a crashed program printed before it died helps to
+                // tell where.
+                let mut partial = String::new();
+                if result.signal.is_some() {
+                    partial = result.stdout.preview().into_owned();
+                    if !partial.is_empty() && !partial.ends_with('\n') {
+                        partial.push('\n');
+                    }
+                    partial = self.program_text(block, Cow::Owned(partial), true).into_owned();
+                }
+                // A plugin doesn't say which of its steps failed.
+                let builtin = languages::BUILTIN.contains(&block.lang.as_str());
+                let phase = match error {
+                    Some((ErrorKind::CompileError, _)) => format!("[{}] Compile error:\n", block.lang),
+                    Some((ErrorKind::NonZeroExit | ErrorKind::Signal, _)) if builtin => {
+                        format!("[{}] Runtime error:\n", block.lang)
+                    }
+                    _ => String::new(),
+                };
+                match header {
+                    Some(header) => write!(self.console.err(), "{}\n{}{}{}", header, partial, phase, message)?,
+                    None => write!(self.console.err(), "{}{}{}", partial, phase, message)?,
+                }
+            }
+        }
+        // Under --deny-warnings they are the error already.
+        let denied = matches!(result.error, Some((ErrorKind::CompilerWarnings, _)));
+        if let Some(warnings) = result.compiler_warnings.as_ref().filter(|_| !self.quiet && !denied) {
+            self.print(format_args!("[{}] Compiler warnings:\n{}\n\n", block.lang, warnings.trim_end()))?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self, summary: &Summary) -> io::Result<()> {
+        if self.quiet || self.raw {
+            return Ok(());
+        }
+        let blocks = if summary.total == 1 { "block" } else { "blocks" };
+        self.print(format_args!(
+            "Summary: {} {}, {} passed, {} failed, {} skipped{} in {}\n",
+            summary.total,
+            blocks,
+            summary.passed,
+            summary.failed,
+            summary.skipped,
+            summary.skip_notes(),
+            format_duration(summary.duration)
+        ))?;
+        if !summary.slowest.is_empty() {
+            self.print(format_args!("Slowest:\n"))?;
+            for block in &summary.slowest {
+                self.print(format_args!(
+                    "  {:>9}  {}:{} {}\n",
+                    format_duration(block.duration),
+                    block.file,
+                    block.line,
+                    block.label
+                ))?;
+            }
+        }
+        if self.timings && !summary.languages.is_empty() {
+            self.print(format_args!("Time per language:\n"))?;
+            for (lang, time) in &summary.languages {
+                self.print(format_args!(
+                    "  {:<10} {:>3} blocks  compile {:>9}  run {:>9}\n",
+                    lang,
+                    time.blocks,
+                    format_duration(time.compile),
+                    format_duration(time.run)
+                ))?;
+            }
+        }
+        if !summary.failures.is_empty() {
+            self.print(format_args!("Failed:\n"))?;
+            for block in &summary.failures {
+                self.print(format_args!("  {}:{} {}\n", block.file, block.line, block.label))?;
+            }
+        }
+        if !summary.skips.is_empty() {
+            self.print(format_args!("Skipped:\n"))?;
+            for skip in &summary.skips {
+                let block = &skip.block;
+                self.print(format_args!("  {}:{} {}: {}\n", block.file, block.line, block.label, skip.reason))?;
+            }
+        }
+        Ok(())
+    }
+}
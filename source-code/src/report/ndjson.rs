@@ -0,0 +1,137 @@
+//! One JSON object per line on stdout for every event of the run, flushed as
+//! it happens so tools reading the pipe can follow along. The events are
+//! documented in the README.
+
+use std::io;
+use std::path::PathBuf;
+
+use serde::Serialize;
+
+use super::json::encode;
+use super::{Reporter, Summary};
+use crate::console::Console;
+use crate::executor::{ErrorKind, ExecutionResult, Origin, Status, Stream};
+use crate::parser::Block;
+use crate::usage::Usage;
+
+pub struct Ndjson {
+    console: Console,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum Event<'a> {
+    RunStarted {
+        files: &'a [String],
+    },
+    BlockStarted {
+        file: &'a str,
+        index: usize,
+        language: &'a str,
+        name: Option<&'a str>,
+        start_line: usize,
+        end_line: usize,
+    },
+    BlockOutputChunk {
+        file: &'a str,
+        index: usize,
+        stream: Stream,
+        data: String,
+        encoding: &'static str,
+    },
+    BlockFinished {
+        file: &'a str,
+        index: usize,
+        status: Status,
+        exit_code: Option<i32>,
+        signal: Option<i32>,
+        duration_ms: u128,
+        compile_ms: Option<u128>,
+        run_ms: Option<u128>,
+        error_kind: Option<ErrorKind>,
+        error: Option<&'a str>,
+        duplicate_of: Option<&'a Origin>,
+        replayed: bool,
+        compiler_flags: &'a [String],
+        language_version: Option<&'a str>,
+        toolchain_version: Option<&'a str>,
+        compiler_warnings: Option<&'a str>,
+        warnings: &'a [String],
+        disk_bytes: Option<u64>,
+        artifacts: &'a [PathBuf],
+        rusage: Option<&'a Usage>,
+    },
+    RunFinished {
+        summary: &'a Summary,
+    },
+}
+
+fn emit(console: &Console, event: &Event) -> io::Result<()> {
+    let mut line = serde_json::to_vec(event)?;
+    line.push(b'\n');
+    let mut out = console.out();
+    out.write_all(&line)?;
+    out.flush()
+}
+
+impl Ndjson {
+    pub fn new(files: &[String], console: Console) -> io::Result<Self> {
+        emit(&console, &Event::RunStarted { files })?;
+        Ok(Ndjson { console })
+    }
+}
+
+impl Reporter for Ndjson {
+    fn block_started(&mut self, file: &str, block: &Block) -> io::Result<()> {
+        emit(&self.console, &Event::BlockStarted {
+            file,
+            index: block.index,
+            language: &block.lang,
+            name: block.name(),
+            start_line: block.span.start_line,
+            end_line: block.span.end_line,
+        })
+    }
+
+    fn output(&mut self, file: &str, block: &Block, stream: Stream, chunk: &[u8]) -> io::Result<()> {
+        // A chunk can end in the middle of a UTF-8 sequence; it is then sent
+        // as base64 like any other non-text output.
+        let (data, encoding) = encode(chunk);
+        emit(&self.console, &Event::BlockOutputChunk {
+            file,
+            index: block.index,
+            stream,
+            data,
+            encoding,
+        })
+    }
+
+    fn block_finished(&mut self, file: &str, block: &Block, result: &ExecutionResult) -> io::Result<()> {
+        emit(&self.console, &Event::BlockFinished {
+            file,
+            index: block.index,
+            status: result.status,
+            exit_code: result.exit_code,
+            signal: result.signal,
+            duration_ms: result.duration.as_millis(),
+            compile_ms: result.timings.compile.map(|d| d.as_millis()),
+            run_ms: result.timings.run.map(|d| d.as_millis()),
+            error_kind: result.error.as_ref().map(|(kind, _)| *kind),
+            error: result.error.as_ref().map(|(_, message)| message.as_str()),
+            duplicate_of: result.duplicate_of.as_ref(),
+            replayed: result.replayed,
+            compiler_flags: &result.compiler_flags,
+            language_version: result.language_version.as_deref(),
+            toolchain_version: result.toolchain_version.as_deref(),
+            compiler_warnings: result.compiler_warnings.as_deref(),
+            warnings: &result.warnings,
+            disk_bytes: result.disk_bytes,
+            artifacts: &result.artifacts,
+            rusage: result.usage.as_ref(),
+        })
+    }
+
+    fn finish(&mut self, summary: &Summary) -> io::Result<()> {
+        emit(&self.console, &Event::RunFinished { summary })
+    }
+}
@@ -0,0 +1,163 @@
+//! `--notify-url` and `--notify-cmd`: once the run is over, the document
+//! `--format json` would print is POSTed to a URL and/or given to a command
+//! on its stdin, so a long run in the background can say that it's done.
+//! Delivery that fails is reported on stderr and doesn't change the run's
+//! exit code.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+use super::{Json, Reporter, Summary};
+use crate::console::Console;
+use crate::executor::ExecutionResult;
+use crate::parser::Block;
+use crate::tools::Tools;
+
+/// How long connecting, sending or waiting for the answer may take.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+pub struct Notify {
+    json: Json,
+    url: Option<String>,
+    command: Option<Vec<String>>,
+    console: Console,
+}
+
+impl Notify {
+    /// Sends the summary to `url`, an `http://` or `https://` URL, and to
+    /// `command`, split into words.
+    pub fn new(url: Option<String>, command: Option<Vec<String>>, console: Console, tools: Tools) -> Self {
+        Notify {
+            json: Json::collect(tools),
+            url,
+            command,
+            console,
+        }
+    }
+}
+
+impl Reporter for Notify {
+    fn block_finished(&mut self, file: &str, block: &Block, result: &ExecutionResult) -> io::Result<()> {
+        self.json.block_finished(file, block, result)
+    }
+
+    fn finish(&mut self, summary: &Summary) -> io::Result<()> {
+        let json = self.json.render(summary)?;
+        if let Some(url) = &self.url {
+            // One more try is enough for a server that was briefly away.
+            let mut delivered = post(url, &json);
+            if delivered.is_err() {
+                thread::sleep(Duration::from_secs(1));
+                delivered = post(url, &json);
+            }
+            if let Err(e) = delivered {
+                writeln!(self.console.err(), "warning: --notify-url {}: couldn't deliver the summary: {}", url, e)?;
+            }
+        }
+        if let Some(words) = &self.command {
+            let status = if summary.failed > 0 { "failed" } else { "passed" };
+            if let Err(e) = run(words, status, &json) {
+                writeln!(self.console.err(), "warning: --notify-cmd {}: {}", words[0], e)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// POSTs `body` as JSON to `url`. Plain HTTP is spoken here; HTTPS is left
+/// to curl.
+fn post(url: &str, body: &[u8]) -> io::Result<()> {
+    let Some(rest) = url.strip_prefix("http://") else {
+        return post_with_curl(url, body);
+    };
+    let rest = rest.split('#').next().unwrap_or_default();
+    let (authority, path) = match rest.find(['/', '?']) {
+        Some(at) if rest[at..].starts_with('/') => (&rest[..at], rest[at..].to_string()),
+        Some(at) => (&rest[..at], format!("/{}", &rest[at..])),
+        None => (rest, "/".to_string()),
+    };
+    let has_port = authority.rsplit_once(':').is_some_and(|(_, port)| port.parse::<u16>().is_ok());
+    let address = if has_port { authority.to_string() } else { format!("{}:80", authority) };
+    let address = address
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("{} has no address", authority)))?;
+    let mut stream = TcpStream::connect_timeout(&address, TIMEOUT)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    write!(
+        stream,
+        "POST {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: translator/{}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        path,
+        authority,
+        env!("CARGO_PKG_VERSION"),
+        body.len()
+    )?;
+    stream.write_all(body)?;
+    let mut status_line = String::new();
+    BufReader::new(stream).read_line(&mut status_line)?;
+    let status = status_line.trim_end();
+    match status.split(' ').nth(1) {
+        Some(code) if code.starts_with('2') => Ok(()),
+        Some(_) => Err(io::Error::other(format!("the server answered {}", status))),
+        None => Err(io::Error::new(io::ErrorKind::InvalidData, "the server's answer isn't HTTP")),
+    }
+}
+
+fn post_with_curl(url: &str, body: &[u8]) -> io::Result<()> {
+    let timeout = TIMEOUT.as_secs().to_string();
+    let mut child = Command::new("curl")
+        .args(["--silent", "--show-error", "--fail", "--max-time", &timeout])
+        .args(["--header", "Content-Type: application/json", "--data-binary", "@-", url])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => io::Error::new(e.kind(), "https:// URLs need curl, which isn't installed"),
+            _ => e,
+        })?;
+    // curl stops reading when it gives up on the URL, which it then says.
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    match stdin.write_all(body) {
+        Err(e) if e.kind() != io::ErrorKind::BrokenPipe => return Err(e),
+        _ => drop(stdin),
+    }
+    let output = child.wait_with_output()?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(String::from_utf8_lossy(&output.stderr).trim().to_string()))
+    }
+}
+
+/// Runs `words` with `json` on its stdin and `TRANSLATOR_STATUS` set to
+/// `status`, for commands that don't read it.
+fn run(words: &[String], status: &str, json: &[u8]) -> io::Result<()> {
+    let mut child = Command::new(&words[0])
+        .args(&words[1..])
+        .env("TRANSLATOR_STATUS", status)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    // A command that doesn't read its stdin closes the pipe early.
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    match stdin.write_all(json) {
+        Err(e) if e.kind() != io::ErrorKind::BrokenPipe => return Err(e),
+        _ => drop(stdin),
+    }
+    let output = child.wait_with_output()?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if stderr.trim().is_empty() {
+        Err(io::Error::other(output.status.to_string()))
+    } else {
+        Err(io::Error::other(format!("{}: {}", output.status, stderr.trim())))
+    }
+}
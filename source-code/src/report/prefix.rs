@@ -0,0 +1,40 @@
+//! `--prefix`: every line a block's program printed, on the console, starts
+//! with `[3:python:demo] ` naming the block, the way docker-compose names
+//! the service a log line came from.
+
+use crate::parser::Block;
+
+/// Colors the blocks take turns with.
+const COLORS: &[&str] = &["36", "33", "32", "35", "34", "31"];
+
+#[derive(Default)]
+pub struct Prefix {
+    /// The widest prefix so far, which the others are padded to.
+    width: usize,
+}
+
+impl Prefix {
+    /// `text` with the block's prefix before every line, in the block's color
+    /// when `color` is set.
+    pub fn apply(&mut self, block: &Block, text: &str, color: bool) -> String {
+        let mut label = format!("[{}:{}", block.index, block.lang);
+        if let Some(name) = block.name() {
+            label.push(':');
+            label.push_str(name);
+        }
+        label.push(']');
+        self.width = self.width.max(label.chars().count());
+        let label = format!("{:<width$} ", label, width = self.width);
+        let label = if color {
+            format!("\x1b[{}m{}\x1b[0m", COLORS[block.index % COLORS.len()], label)
+        } else {
+            label
+        };
+        let mut prefixed = String::new();
+        for line in text.split_inclusive('\n') {
+            prefixed.push_str(&label);
+            prefixed.push_str(line);
+        }
+        prefixed
+    }
+}
@@ -0,0 +1,78 @@
+//! TAP version 13 on stdout, for `prove` and other TAP harnesses. The plan is
+//! printed at the end so results can stream as blocks finish.
+
+use std::io;
+
+use super::{Reporter, Summary};
+use crate::console::Console;
+use crate::executor::{ExecutionResult, Status};
+use crate::parser::Block;
+
+pub struct Tap {
+    count: usize,
+    console: Console,
+}
+
+impl Tap {
+    pub fn new(console: Console) -> io::Result<Self> {
+        writeln!(console.out(), "TAP version 13")?;
+        Ok(Tap { count: 0, console })
+    }
+}
+
+/// `#` would start a directive, so it is escaped in descriptions.
+fn description(file: &str, block: &Block) -> String {
+    format!("{}: {}", file, block.label()).replace('#', "\\#")
+}
+
+fn yaml_block(key: &str, text: &str) -> String {
+    if text.is_empty() {
+        return format!("  {}: ''\n", key);
+    }
+    let mut yaml = format!("  {}: |\n", key);
+    for line in text.lines() {
+        yaml.push_str("    ");
+        yaml.push_str(line);
+        yaml.push('\n');
+    }
+    yaml
+}
+
+impl Reporter for Tap {
+    fn block_finished(&mut self, file: &str, block: &Block, result: &ExecutionResult) -> io::Result<()> {
+        self.count += 1;
+        let description = description(file, block);
+        let message = result.error.as_ref().map(|(_, m)| m.as_str()).unwrap_or("");
+        let mut out = self.console.out();
+        match result.status {
+            Status::Passed => writeln!(out, "ok {} - {}", self.count, description)?,
+            Status::Skipped => {
+                let reason = message.lines().next().unwrap_or("");
+                writeln!(out, "ok {} - {} # SKIP {}", self.count, description, reason)?
+            }
+            Status::Failed => {
+                writeln!(out, "not ok {} - {}", self.count, description)?;
+                writeln!(out, "  ---")?;
+                match result.exit_code {
+                    Some(code) => writeln!(out, "  exit_code: {}", code)?,
+                    None => writeln!(out, "  exit_code: ~")?,
+                }
+                if let Some(signal) = result.signal {
+                    writeln!(out, "  signal: {}", signal)?;
+                }
+                let stderr = result.stderr.preview();
+                let stderr = if stderr.trim().is_empty() { message } else { &stderr };
+                write!(out, "{}", yaml_block("stderr", stderr))?;
+                writeln!(out, "  ...")?;
+            }
+        }
+        if let Some(note) = result.reuse_note(file) {
+            writeln!(out, "# {}", note)?;
+        }
+        out.flush()
+    }
+
+    fn finish(&mut self, _summary: &Summary) -> io::Result<()> {
+        writeln!(self.console.out(), "1..{}", self.count)
+    }
+}
@@ -0,0 +1,98 @@
+//! The run directory: one temporary directory per run holding a directory
+//! per hacker file, which in turn holds one per block, e.g.
+//! `translator-run-XXXX/demo.hack/003-rust-parse-demo/`. Everything is
+//! removed when the run ends, unless `--keep-temp` keeps it for a look.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use tempfile::TempDir;
+
+use crate::parser::Block;
+
+/// Longest name part of a block's directory, so names stay readable.
+const MAX_SLUG: usize = 40;
+
+pub struct RunDir {
+    dir: TempDir,
+}
+
+impl RunDir {
+    pub fn new() -> io::Result<Self> {
+        let dir = tempfile::Builder::new().prefix("translator-run-").tempdir()?;
+        Ok(RunDir { dir })
+    }
+
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    /// Creates the directory for `file`, named after it. Files that share a
+    /// name get a numeric suffix.
+    pub fn file_dir(&self, file: &str) -> io::Result<PathBuf> {
+        let name = Path::new(file)
+            .file_name()
+            .map(|name| slug(&name.to_string_lossy()))
+            .filter(|name| !name.is_empty())
+            .unwrap_or_else(|| "file".to_string());
+        let mut n = 1;
+        loop {
+            let dir = match n {
+                1 => self.path().join(&name),
+                n => self.path().join(format!("{}-{}", name, n)),
+            };
+            match fs::create_dir(&dir) {
+                Ok(()) => return Ok(dir),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => n += 1,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Stops the directory from being removed, and returns where it is.
+    pub fn keep(self) -> PathBuf {
+        self.dir.keep()
+    }
+}
+
+/// Creates `003-rust` or, for a named block, `003-rust-parse-demo` in
+/// `file_dir`.
+pub fn block_dir(file_dir: &Path, block: &Block) -> io::Result<PathBuf> {
+    let mut name = format!("{:03}-{}", block.index, slug(&block.lang));
+    if let Some(label) = block.name().map(slug).filter(|label| !label.is_empty()) {
+        name.push('-');
+        name.push_str(&label);
+    }
+    let dir = file_dir.join(name);
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+/// The bytes the files beneath `dir` take, not following symlinks.
+pub fn size(dir: &Path) -> io::Result<u64> {
+    let mut total = 0;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        total += if metadata.is_dir() { size(&entry.path())? } else { metadata.len() };
+    }
+    Ok(total)
+}
+
+/// Lowercase letters, digits, dots and single dashes, safe in a path and in
+/// compiler messages.
+fn slug(text: &str) -> String {
+    let mut slug = String::new();
+    for c in text.chars() {
+        if c.is_ascii_alphanumeric() || c == '.' || c == '_' {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+        if slug.len() >= MAX_SLUG {
+            break;
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
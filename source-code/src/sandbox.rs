@@ -0,0 +1,766 @@
+//! Confinement of the steps that run natively, for hacker files that come
+//! from someone else. `--no-network` starts every step in an empty network
+//! namespace of its own, where only a loopback device that is down exists,
+//! so connecting anywhere fails at once. Only Linux has those.
+//!
+//! `--run-as USER` runs each block's program, though not its compiler,
+//! as another user, which translator can only switch to as root. The block's
+//! directory is given to the user first, and everything else in the run
+//! directory stays translator's, which removes it all at the end.
+//!
+//! `--sandbox fs` confines every step with Landlock (Linux 5.13 and later)
+//! to reading the system's directories, the toolchains and the cache, and
+//! to writing the block's directory. Compile steps may also write the cache
+//! and the toolchains' own caches.
+//!
+//! `--max-file-size` caps the size of every file a step writes: a write
+//! beyond it fails with `EFBIG`.
+//!
+//! `--sandbox-cmd` starts every step through a program such as bubblewrap
+//! or firejail instead, for confinement translator doesn't do itself.
+//!
+//! `--max-procs` caps how many more processes and threads a block's program
+//! and whatever it starts may have, so a fork bomb only fails to fork. The
+//! program also leads a process group of its own, which is killed once the
+//! program is done, or when translator gets Ctrl-C, so nothing it started
+//! is left running.
+
+use std::env;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+#[cfg(unix)]
+use std::sync::atomic::{AtomicI32, Ordering};
+#[cfg(unix)]
+use std::sync::Once;
+
+use serde::Serialize;
+
+use crate::flags;
+use crate::tools::{self, Tools};
+
+/// The kinds of step a block has, which get different permissions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Step {
+    Compile,
+    /// Fetching or installing dependencies, part of the compile phase.
+    Fetch,
+    /// The block's program.
+    Run,
+}
+
+/// Which steps of a block may use the network.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Network {
+    #[default]
+    All,
+    /// Only the steps that fetch dependencies, from
+    /// `--allow-dependency-network`.
+    Dependencies,
+    /// None, from `--no-network`.
+    None,
+}
+
+impl Network {
+    /// Whether `step` may use the network.
+    pub fn allows(self, step: Step) -> bool {
+        match self {
+            Network::All => true,
+            Network::Dependencies => step == Step::Fetch,
+            Network::None => false,
+        }
+    }
+}
+
+/// Makes sure steps can be isolated, so `--no-network` fails before any
+/// block runs rather than with each of them.
+pub fn check_network() -> io::Result<()> {
+    let mut probe = Command::new(std::env::current_exe()?);
+    probe.stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null());
+    isolate_network(&mut probe)?;
+    probe
+        .status()
+        .map(drop)
+        .map_err(|e| io::Error::new(e.kind(), format!("--no-network couldn't create a network namespace: {}", e)))
+}
+
+/// Starts `command` in a network namespace of its own. Without the
+/// privilege for that, it gets a user namespace as well, in which the user
+/// keeps their ids, the way `unshare -rn` does it.
+#[cfg(target_os = "linux")]
+pub fn isolate_network(command: &mut Command) -> io::Result<()> {
+    use std::os::unix::process::CommandExt;
+    // The hook runs between fork and exec, where nothing may allocate.
+    let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+    let uid_map = format!("{} {} 1\n", uid, uid);
+    let gid_map = format!("{} {} 1\n", gid, gid);
+    let hook = move || {
+        if unsafe { libc::unshare(libc::CLONE_NEWNET) } == 0 {
+            return Ok(());
+        }
+        if unsafe { libc::unshare(libc::CLONE_NEWUSER | libc::CLONE_NEWNET) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        write_proc(c"/proc/self/setgroups", b"deny")?;
+        write_proc(c"/proc/self/uid_map", uid_map.as_bytes())?;
+        write_proc(c"/proc/self/gid_map", gid_map.as_bytes())
+    };
+    unsafe { command.pre_exec(hook) };
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn isolate_network(_command: &mut Command) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "--no-network needs Linux network namespaces; use --backend docker here",
+    ))
+}
+
+#[cfg(target_os = "linux")]
+fn write_proc(path: &std::ffi::CStr, contents: &[u8]) -> io::Result<()> {
+    let fd = unsafe { libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let written = unsafe { libc::write(fd, contents.as_ptr().cast(), contents.len()) };
+    unsafe { libc::close(fd) };
+    if written < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// The user `--run-as` names.
+#[cfg(unix)]
+#[derive(Clone, Debug)]
+pub struct User {
+    pub name: String,
+    pub uid: u32,
+    gid: u32,
+    /// The user's supplementary groups, which replace translator's.
+    groups: Vec<libc::gid_t>,
+    home: std::ffi::OsString,
+}
+
+#[cfg(unix)]
+impl User {
+    /// Looks the user up by name, or by uid when `name` is a number, and
+    /// checks that translator may switch to it.
+    pub fn lookup(name: &str) -> io::Result<User> {
+        use std::ffi::{CStr, CString, OsStr};
+        use std::os::unix::ffi::OsStrExt;
+        let unknown = || io::Error::new(io::ErrorKind::NotFound, format!("--run-as: no user {}", name));
+        let c_name = CString::new(name).map_err(|_| unknown())?;
+        let mut entry: libc::passwd = unsafe { std::mem::zeroed() };
+        let mut buf = vec![0 as libc::c_char; 16 * 1024];
+        let mut found = std::ptr::null_mut();
+        let status = match name.parse::<libc::uid_t>() {
+            Ok(uid) => unsafe { libc::getpwuid_r(uid, &mut entry, buf.as_mut_ptr(), buf.len(), &mut found) },
+            Err(_) => unsafe { libc::getpwnam_r(c_name.as_ptr(), &mut entry, buf.as_mut_ptr(), buf.len(), &mut found) },
+        };
+        if status != 0 {
+            return Err(io::Error::from_raw_os_error(status));
+        }
+        if found.is_null() {
+            return Err(unknown());
+        }
+        let user_name = unsafe { CStr::from_ptr(entry.pw_name) };
+        let home = unsafe { CStr::from_ptr(entry.pw_dir) };
+        let mut groups = vec![0 as libc::gid_t; 256];
+        let mut count = groups.len() as libc::c_int;
+        #[cfg(target_os = "macos")]
+        let listed = unsafe {
+            libc::getgrouplist(user_name.as_ptr(), entry.pw_gid as libc::c_int, groups.as_mut_ptr().cast(), &mut count)
+        };
+        #[cfg(not(target_os = "macos"))]
+        let listed = unsafe { libc::getgrouplist(user_name.as_ptr(), entry.pw_gid, groups.as_mut_ptr(), &mut count) };
+        groups.truncate(if listed < 0 { 0 } else { count as usize });
+        if groups.is_empty() {
+            groups.push(entry.pw_gid);
+        }
+        let user = User {
+            name: user_name.to_string_lossy().into_owned(),
+            uid: entry.pw_uid,
+            gid: entry.pw_gid,
+            groups,
+            home: OsStr::from_bytes(home.to_bytes()).to_os_string(),
+        };
+        let euid = unsafe { libc::geteuid() };
+        if euid != 0 && euid != user.uid {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("--run-as {} needs translator to run as root", user.name),
+            ));
+        }
+        Ok(user)
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.uid == 0
+    }
+
+    /// Gives `dir`, and everything in it, to the user.
+    pub fn hand_over(&self, dir: &Path) -> io::Result<()> {
+        std::os::unix::fs::lchown(dir, Some(self.uid), Some(self.gid))?;
+        if !dir.is_symlink() && dir.is_dir() {
+            for entry in std::fs::read_dir(dir)? {
+                self.hand_over(&entry?.path())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Makes `command` switch to the user before it starts. If it can't,
+    /// starting it fails, rather than running it as translator.
+    pub fn apply(&self, command: &mut Command) {
+        use std::os::unix::process::CommandExt;
+        command.env("HOME", &self.home).env("USER", &self.name).env("LOGNAME", &self.name);
+        if unsafe { libc::geteuid() } == self.uid {
+            return;
+        }
+        let (uid, gid, groups) = (self.uid, self.gid, self.groups.clone());
+        let hook = move || {
+            // Groups first, while translator may still change them.
+            if unsafe { libc::setgroups(groups.len() as _, groups.as_ptr()) } != 0
+                || unsafe { libc::setgid(gid) } != 0
+                || unsafe { libc::setuid(uid) } != 0
+            {
+                return Err(io::Error::last_os_error());
+            }
+            if unsafe { libc::getuid() != uid || libc::geteuid() != uid } {
+                return Err(io::Error::from_raw_os_error(libc::EPERM));
+            }
+            Ok(())
+        };
+        unsafe { command.pre_exec(hook) };
+    }
+}
+
+/// Lets every user through `dir` to what is inside, without listing it, so
+/// blocks run as another user reach their directories in the run directory.
+#[cfg(unix)]
+pub fn allow_traverse(dir: &Path) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(dir, std::fs::Permissions::from_mode(0o711))
+}
+
+#[cfg(not(unix))]
+#[derive(Clone, Debug)]
+pub struct User {
+    pub name: String,
+}
+
+#[cfg(not(unix))]
+impl User {
+    pub fn lookup(_name: &str) -> io::Result<User> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "--run-as only works on Unix"))
+    }
+
+    pub fn is_root(&self) -> bool {
+        false
+    }
+
+    pub fn hand_over(&self, _dir: &Path) -> io::Result<()> {
+        Ok(())
+    }
+
+    pub fn apply(&self, _command: &mut Command) {}
+}
+
+#[cfg(not(unix))]
+pub fn allow_traverse(_dir: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Directories every step may read, where they exist.
+const SYSTEM_DIRS: &[&str] = &["/usr", "/lib", "/lib32", "/lib64", "/bin", "/sbin", "/etc", "/opt", "/nix", "/proc", "/sys"];
+
+/// `--sandbox fs` with the paths it opens up, beyond the block's directory.
+pub struct Landlock {
+    /// The newest Landlock ABI the kernel has, which decides the rights that
+    /// can be restricted.
+    abi: i64,
+    /// Readable by every step.
+    readable: Vec<PathBuf>,
+    /// Readable and writable by the steps that build the block, but not by
+    /// its program, which could read credentials and other projects' builds
+    /// in them.
+    compile_writable: Vec<PathBuf>,
+}
+
+impl Landlock {
+    /// The sandbox for a run using `cache`, or why the kernel can't have it.
+    pub fn new(cache: Option<&Path>) -> Result<Landlock, String> {
+        let abi = landlock_abi()?;
+        let home = env::var_os("HOME").map(PathBuf::from);
+        let var_or_home = |var: &str, dir: &str| env::var_os(var).map(PathBuf::from).or_else(|| home.as_ref().map(|home| home.join(dir)));
+        let mut readable: Vec<PathBuf> = SYSTEM_DIRS.iter().map(PathBuf::from).collect();
+        readable.extend(var_or_home("RUSTUP_HOME", ".rustup"));
+        readable.extend(["GOROOT", "JAVA_HOME"].into_iter().filter_map(env::var_os).map(PathBuf::from));
+        let mut compile_writable: Vec<PathBuf> = cache.map(Path::to_path_buf).into_iter().collect();
+        compile_writable.extend(var_or_home("CARGO_HOME", ".cargo"));
+        compile_writable.extend(var_or_home("GOPATH", "go"));
+        compile_writable.extend(var_or_home("XDG_CACHE_HOME", ".cache"));
+        compile_writable.extend(["GOCACHE", "GOMODCACHE"].into_iter().filter_map(env::var_os).map(PathBuf::from));
+        Ok(Landlock {
+            abi,
+            readable,
+            compile_writable,
+        })
+    }
+
+    /// Confines `command`, a `step` of the block whose directory is `dir`.
+    /// Temporary files go to `dir` as well. The directories of `tools`
+    /// become readable, and so do the cache `entries` the program uses.
+    pub fn apply(&self, command: &mut Command, step: Step, tools: &Tools, dir: &Path, entries: &[PathBuf]) -> io::Result<()> {
+        let mut readable = self.readable.clone();
+        readable.extend(toolchain_dirs(tools, &self.compile_writable));
+        readable.extend(entries.iter().cloned());
+        let mut writable = vec![dir.to_path_buf()];
+        if step != Step::Run {
+            writable.extend(self.compile_writable.iter().cloned());
+        }
+        command.env("TMPDIR", dir);
+        restrict(command, self.abi, &readable, &writable)
+    }
+}
+
+/// Where the programs behind the built-in languages are installed: for
+/// `/opt/go/bin/go`, `/opt/go`. Programs installed in one of `caches`, as
+/// rustup's are in `~/.cargo/bin`, only get their `bin` directory.
+fn toolchain_dirs(tools: &Tools, caches: &[PathBuf]) -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    for (tool, _) in tools::TOOLS.iter().chain(&[("python3", "")]) {
+        let program = match tools.get(tool) {
+            Some((path, _)) => Some(path.to_path_buf()),
+            None => tools::locate(Path::new(tool), None),
+        };
+        let Some(program) = program.and_then(|program| program.canonicalize().ok()) else {
+            continue;
+        };
+        let bin = program.parent().unwrap_or(&program);
+        let installed = bin.parent().unwrap_or(bin);
+        let cached = caches.iter().any(|cache| {
+            let cache = cache.canonicalize().unwrap_or_else(|_| cache.clone());
+            installed.starts_with(&cache) || cache.starts_with(installed)
+        });
+        dirs.push(if cached { bin } else { installed }.to_path_buf());
+    }
+    dirs
+}
+
+#[cfg(target_os = "linux")]
+mod landlock {
+    pub const CREATE_RULESET_VERSION: u32 = 1;
+    pub const RULE_PATH_BENEATH: libc::c_int = 1;
+
+    pub const EXECUTE: u64 = 1 << 0;
+    pub const WRITE_FILE: u64 = 1 << 1;
+    pub const READ_FILE: u64 = 1 << 2;
+    pub const READ_DIR: u64 = 1 << 3;
+    /// Every right of ABI 1, from `EXECUTE` to `MAKE_SYM`.
+    pub const ABI_1: u64 = (1 << 13) - 1;
+    pub const REFER: u64 = 1 << 13;
+    pub const TRUNCATE: u64 = 1 << 14;
+
+    #[repr(C)]
+    pub struct RulesetAttr {
+        pub handled_access_fs: u64,
+    }
+
+    #[repr(C, packed)]
+    pub struct PathBeneathAttr {
+        pub allowed_access: u64,
+        pub parent_fd: libc::c_int,
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn landlock_abi() -> Result<i64, String> {
+    let abi = unsafe {
+        libc::syscall(
+            libc::SYS_landlock_create_ruleset,
+            std::ptr::null::<landlock::RulesetAttr>(),
+            0usize,
+            landlock::CREATE_RULESET_VERSION,
+        )
+    };
+    if abi <= 0 {
+        let e = io::Error::last_os_error();
+        return Err(format!("Landlock isn't available ({}); it needs Linux 5.13 or later with Landlock enabled", e));
+    }
+    Ok(abi)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn landlock_abi() -> Result<i64, String> {
+    Err("Landlock only exists on Linux".to_string())
+}
+
+/// Makes `command` start under a Landlock ruleset that allows reading and
+/// executing beneath `readable`, and anything beneath `writable`.
+#[cfg(target_os = "linux")]
+fn restrict(command: &mut Command, abi: i64, readable: &[PathBuf], writable: &[PathBuf]) -> io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::process::CommandExt;
+    let mut handled = landlock::ABI_1;
+    if abi >= 2 {
+        handled |= landlock::REFER;
+    }
+    if abi >= 3 {
+        handled |= landlock::TRUNCATE;
+    }
+    let read = landlock::EXECUTE | landlock::READ_FILE | landlock::READ_DIR;
+    // Devices such as /dev/null and the terminal are written, not created.
+    let devices = [(PathBuf::from("/dev"), read | landlock::WRITE_FILE | (handled & landlock::TRUNCATE))];
+    let readable = readable.iter().map(|path| (path.clone(), read));
+    let writable = writable.iter().map(|path| (path.clone(), handled));
+    let mut rules = Vec::new();
+    for (path, access) in devices.into_iter().chain(readable).chain(writable) {
+        let path = CString::new(path.as_os_str().as_bytes())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "a path with a NUL byte"))?;
+        rules.push((path, access));
+    }
+    // The hook runs between fork and exec, where nothing may allocate.
+    let hook = move || {
+        let attr = landlock::RulesetAttr {
+            handled_access_fs: handled,
+        };
+        let size = std::mem::size_of::<landlock::RulesetAttr>();
+        let ruleset = unsafe { libc::syscall(libc::SYS_landlock_create_ruleset, &attr, size, 0u32) };
+        if ruleset < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let ruleset = ruleset as libc::c_int;
+        for (path, access) in &rules {
+            // Paths that don't exist have nothing to allow.
+            let fd = unsafe { libc::open(path.as_ptr(), libc::O_PATH | libc::O_CLOEXEC) };
+            if fd < 0 {
+                continue;
+            }
+            let rule = landlock::PathBeneathAttr {
+                allowed_access: *access,
+                parent_fd: fd,
+            };
+            // A rule the kernel refuses, e.g. directory rights on a file,
+            // only means less access.
+            unsafe { libc::syscall(libc::SYS_landlock_add_rule, ruleset, landlock::RULE_PATH_BENEATH, &rule, 0u32) };
+            unsafe { libc::close(fd) };
+        }
+        let restricted = unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) } == 0
+            && unsafe { libc::syscall(libc::SYS_landlock_restrict_self, ruleset, 0u32) } == 0;
+        let e = io::Error::last_os_error();
+        unsafe { libc::close(ruleset) };
+        if restricted {
+            Ok(())
+        } else {
+            Err(e)
+        }
+    };
+    unsafe { command.pre_exec(hook) };
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn restrict(_command: &mut Command, _abi: i64, _readable: &[PathBuf], _writable: &[PathBuf]) -> io::Result<()> {
+    unreachable!("Landlock::new fails elsewhere")
+}
+
+/// The program every native step is started through, from `--sandbox-cmd`
+/// or `sandbox_cmd` in `translator.toml`, with `{dir}` for the block's
+/// directory, `{cache}` for the cache, and `{cmd}` for the step's command,
+/// which otherwise goes at the end.
+#[derive(Clone, Debug)]
+pub struct Wrapper {
+    words: Vec<String>,
+}
+
+impl Wrapper {
+    /// Parses `template` like compiler flags. Fails unless the program it
+    /// starts with is installed.
+    pub fn new(template: &str) -> Result<Wrapper, String> {
+        let words = flags::split(template)?;
+        let Some(program) = words.first() else {
+            return Err("the command is empty".to_string());
+        };
+        if let Some(word) = words.iter().find(|word| word.contains("{cmd}") && *word != "{cmd}") {
+            return Err(format!("{{cmd}} has to be a word of its own, not part of {}", word));
+        }
+        if tools::locate(Path::new(program), None).is_none() {
+            return Err(format!("{} isn't installed", program));
+        }
+        Ok(Wrapper { words })
+    }
+
+    /// `command`, run in `dir` with the cache in `cache`, as an argument of
+    /// the wrapper. The wrapper inherits its environment and directory.
+    pub fn wrap(&self, command: &Command, dir: &Path, cache: Option<&Path>) -> Command {
+        let dir = dir.to_string_lossy();
+        // Without a cache, what would go in it goes in the block's directory.
+        let cache = cache.map_or_else(|| dir.clone(), Path::to_string_lossy);
+        let mut inner = vec![command.get_program().to_owned()];
+        inner.extend(command.get_args().map(ToOwned::to_owned));
+        let mut words = self.words.iter();
+        let mut wrapper = Command::new(words.next().expect("Wrapper::new checks it isn't empty"));
+        let mut placed = false;
+        for word in words {
+            if word == "{cmd}" {
+                wrapper.args(&inner);
+                placed = true;
+            } else {
+                wrapper.arg(word.replace("{dir}", &dir).replace("{cache}", &cache));
+            }
+        }
+        if !placed {
+            wrapper.args(&inner);
+        }
+        for (key, value) in command.get_envs() {
+            match value {
+                Some(value) => wrapper.env(key, value),
+                None => wrapper.env_remove(key),
+            };
+        }
+        if let Some(dir) = command.get_current_dir() {
+            wrapper.current_dir(dir);
+        }
+        wrapper
+    }
+}
+
+/// Resource limits for each native step, from `--max-file-size` and the
+/// like. Compile steps have limits of their own, since object files and
+/// the like can be large.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Limits {
+    /// The largest file the program may write, in bytes.
+    pub file_size: Option<u64>,
+    /// The same for compile steps.
+    pub compile_file_size: Option<u64>,
+    /// How many processes and threads the program may add to those its
+    /// user already has. Compilers aren't limited.
+    pub procs: Option<u64>,
+}
+
+/// `--max-procs` unless it's given: enough for a JVM's threads.
+pub const DEFAULT_PROCS: u64 = 64;
+
+impl Limits {
+    /// Whether no file size is limited.
+    pub fn is_empty(&self) -> bool {
+        self.file_size.is_none() && self.compile_file_size.is_none()
+    }
+
+    /// Applies the limits of `step` to `command`, which runs as `user` if
+    /// it's given.
+    #[cfg(unix)]
+    pub fn apply(&self, command: &mut Command, step: Step, user: Option<&User>) {
+        use std::os::unix::process::CommandExt;
+        let file_size = match step {
+            Step::Run => self.file_size,
+            Step::Compile | Step::Fetch => self.compile_file_size,
+        };
+        // The kernel counts every process of the user, so the limit starts
+        // from those it has now.
+        let procs = self.procs.filter(|_| step == Step::Run).map(|procs| {
+            let uid = user.map_or_else(|| unsafe { libc::getuid() }, |user| user.uid);
+            tasks_of(uid).saturating_add(procs)
+        });
+        if let Some(file_size) = file_size {
+            let hook = move || {
+                set_limit(libc::RLIMIT_FSIZE, file_size)?;
+                // Otherwise SIGXFSZ kills the program instead of its write
+                // failing.
+                unsafe { libc::signal(libc::SIGXFSZ, libc::SIG_IGN) };
+                Ok(())
+            };
+            unsafe { command.pre_exec(hook) };
+        }
+        if let Some(procs) = procs {
+            unsafe { command.pre_exec(move || set_limit(libc::RLIMIT_NPROC, procs)) };
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub fn apply(&self, _command: &mut Command, _step: Step, _user: Option<&User>) {}
+}
+
+/// What `setrlimit` takes to name a limit, which glibc has a type of its own for.
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+type Resource = libc::__rlimit_resource_t;
+#[cfg(all(unix, not(all(target_os = "linux", target_env = "gnu"))))]
+type Resource = libc::c_int;
+
+#[cfg(unix)]
+fn set_limit(resource: Resource, value: u64) -> io::Result<()> {
+    let limit = libc::rlimit {
+        rlim_cur: value as libc::rlim_t,
+        rlim_max: value as libc::rlim_t,
+    };
+    if unsafe { libc::setrlimit(resource, &limit) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// How many processes, or on Linux threads, `uid` has, as `RLIMIT_NPROC`
+/// counts them.
+#[cfg(target_os = "linux")]
+fn tasks_of(uid: u32) -> u64 {
+    let Ok(entries) = std::fs::read_dir("/proc") else {
+        return 0;
+    };
+    let mut tasks = 0;
+    for entry in entries.flatten() {
+        // Processes come and go while this looks; those that went are
+        // skipped.
+        let Ok(status) = std::fs::read_to_string(entry.path().join("status")) else {
+            continue;
+        };
+        let field = |name| status.lines().find_map(|line| line.strip_prefix(name)).map(str::trim);
+        let real_uid = field("Uid:").and_then(|ids| ids.split_whitespace().next()?.parse::<u32>().ok());
+        if real_uid == Some(uid) {
+            tasks += field("Threads:").and_then(|n| n.parse().ok()).unwrap_or(1);
+        }
+    }
+    tasks
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn tasks_of(uid: u32) -> u64 {
+    let output = Command::new("ps").args(["-U", &uid.to_string(), "-o", "pid="]).stderr(Stdio::null()).output();
+    output.map_or(0, |output| String::from_utf8_lossy(&output.stdout).lines().count() as u64)
+}
+
+/// The process groups of the programs running now, so that Ctrl-C can kill
+/// them; 0 is a free slot. Programs beyond the slots are only killed when
+/// they are done.
+#[cfg(unix)]
+static GROUPS: [AtomicI32; 64] = [const { AtomicI32::new(0) }; 64];
+
+/// Starts `command` as the leader of a process group of its own.
+#[cfg(unix)]
+pub fn own_group(command: &mut Command) {
+    use std::os::unix::process::CommandExt;
+    command.process_group(0);
+}
+
+#[cfg(not(unix))]
+pub fn own_group(_command: &mut Command) {}
+
+/// The process group a program started by [`own_group`] leads, killed with
+/// everything in it when this is dropped. On Windows it is a job object the
+/// program is put in, which what it starts joins too.
+#[cfg(unix)]
+pub struct Group(i32);
+
+#[cfg(unix)]
+impl Group {
+    /// The group `child` leads.
+    pub fn new(child: &Child) -> Group {
+        static HANDLER: Once = Once::new();
+        HANDLER.call_once(handle_interrupt);
+        let group = Group(child.id() as i32);
+        let free = |slot: &AtomicI32| slot.compare_exchange(0, group.0, Ordering::SeqCst, Ordering::SeqCst).is_ok();
+        GROUPS.iter().any(free);
+        group
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Group {
+    fn drop(&mut self) {
+        if let Some(slot) = GROUPS.iter().find(|slot| slot.load(Ordering::SeqCst) == self.0) {
+            slot.store(0, Ordering::SeqCst);
+        }
+        kill_group(self.0);
+    }
+}
+
+#[cfg(unix)]
+fn kill_group(group: i32) {
+    unsafe { libc::kill(-group, libc::SIGKILL) };
+}
+
+/// Makes Ctrl-C kill the programs' groups, which don't get it from the
+/// terminal, before translator goes the way it would have without them.
+#[cfg(unix)]
+fn handle_interrupt() {
+    extern "C" fn interrupted(signal: libc::c_int) {
+        // Only what's safe in a signal handler.
+        for slot in &GROUPS {
+            let group = slot.load(Ordering::SeqCst);
+            if group != 0 {
+                kill_group(group);
+            }
+        }
+        unsafe {
+            libc::signal(signal, libc::SIG_DFL);
+            libc::raise(signal);
+        }
+    }
+    let handler = interrupted as extern "C" fn(libc::c_int);
+    unsafe { libc::signal(libc::SIGINT, handler as libc::sighandler_t) };
+}
+
+/// The job handle, as a number so the group can go to another thread.
+#[cfg(windows)]
+pub struct Group(isize);
+
+#[cfg(windows)]
+impl Group {
+    /// A job for `child`, which is closed with the last handle to it, so
+    /// translator exiting on Ctrl-C takes it along. Without a job, only
+    /// `child` itself is waited for, as elsewhere.
+    pub fn new(child: &Child) -> Group {
+        use std::os::windows::io::AsRawHandle;
+        use windows_sys::Win32::System::JobObjects::{
+            AssignProcessToJobObject, CreateJobObjectW, JobObjectExtendedLimitInformation, SetInformationJobObject,
+            JOBOBJECT_EXTENDED_LIMIT_INFORMATION, JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE,
+        };
+        let job = unsafe { CreateJobObjectW(std::ptr::null(), std::ptr::null()) };
+        if job.is_null() {
+            return Group(0);
+        }
+        let mut limits = JOBOBJECT_EXTENDED_LIMIT_INFORMATION::default();
+        limits.BasicLimitInformation.LimitFlags = JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE;
+        unsafe {
+            SetInformationJobObject(
+                job,
+                JobObjectExtendedLimitInformation,
+                &limits as *const JOBOBJECT_EXTENDED_LIMIT_INFORMATION as *const std::ffi::c_void,
+                std::mem::size_of::<JOBOBJECT_EXTENDED_LIMIT_INFORMATION>() as u32,
+            );
+            AssignProcessToJobObject(job, child.as_raw_handle() as windows_sys::Win32::Foundation::HANDLE);
+        }
+        Group(job as isize)
+    }
+}
+
+#[cfg(windows)]
+impl Drop for Group {
+    fn drop(&mut self) {
+        use windows_sys::Win32::Foundation::{CloseHandle, HANDLE};
+        use windows_sys::Win32::System::JobObjects::TerminateJobObject;
+        if self.0 == 0 {
+            return;
+        }
+        let job = self.0 as HANDLE;
+        unsafe {
+            TerminateJobObject(job, 1);
+            CloseHandle(job);
+        }
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+pub struct Group;
+
+#[cfg(not(any(unix, windows)))]
+impl Group {
+    pub fn new(_child: &Child) -> Group {
+        Group
+    }
+}
@@ -0,0 +1,268 @@
+//! `translator export-script`: a POSIX shell script that runs the blocks of
+//! hacker files on a machine without translator. The executors take each
+//! block's steps as they would for a run, only written down instead of
+//! started, so the script runs the same compilers with the same arguments.
+//! It recreates every block's directory in a temporary one of its own from
+//! heredocs, runs the steps there in block order, and compares the output
+//! with the block's expect section, if it has one.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::conditions::Condition;
+use crate::executor::{self, Recorded, Status};
+use crate::needs::{self, Prerequisite};
+use crate::parser::{Block, Parsed};
+use crate::rundir::{self, RunDir};
+use crate::sandbox::Step;
+use crate::{parse_file, Options};
+
+/// The script being written, and what it needs to know to write commands.
+struct Script {
+    text: String,
+    /// The run directory the steps were recorded in, which becomes `$dir`.
+    root: String,
+    inherit_stdin: bool,
+    fail_fast: bool,
+    /// Which of the files the blocks are from, counting from 1, for the
+    /// `passed_FILE_INDEX` variables `needs=` checks.
+    file: usize,
+}
+
+/// The script for `files`. With `fail_fast`, it stops at the first block
+/// that fails; otherwise it runs them all and exits with 1 if any failed.
+pub fn export(files: &[String], opts: &Options, fail_fast: bool) -> io::Result<String> {
+    let run_dir = RunDir::new()?;
+    let mut script = Script {
+        text: String::new(),
+        root: run_dir.path().to_string_lossy().into_owned(),
+        inherit_stdin: opts.inherit_stdin,
+        fail_fast,
+        file: 0,
+    };
+    script.text.push_str(
+        "#!/bin/sh\n\
+         # Written by `translator export-script`; runs the blocks the way translator\n\
+         # would, with the same toolchains on the PATH.\n\
+         dir=$(mktemp -d) || exit 1\n\
+         trap 'rm -rf \"$dir\"' EXIT\n\
+         failed=0\n",
+    );
+    for file in files {
+        script.file += 1;
+        let file_dir = run_dir.file_dir(file)?;
+        let marker = opts.marker(Path::new(file))?;
+        let mut blocks = Vec::new();
+        let mut graph = needs::Graph::default();
+        parse_file(Path::new(file), opts, &marker, |parsed| {
+            if let Parsed::Block(block) = parsed {
+                graph.add(&block);
+                blocks.push(*block);
+            }
+            Ok(())
+        })
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", file, e)))?;
+        graph.check(file)?;
+        // The script decides what passed; the order is all that's needed here.
+        let mut order = needs::Order::new(graph);
+        for block in blocks {
+            order.push(block, &mut |block, needed| {
+                // As the default --header-format has it.
+                let heading = format!(
+                    "[{}] block {} ({}:{}-{})",
+                    block.lang, block.index, file, block.span.start_line, block.span.end_line
+                );
+                writeln!(script.text).unwrap();
+                if (block.is_empty() && !opts.strict) || !opts.language_allowed(&block.lang) {
+                    writeln!(script.text, "echo {}", quote(&format!("{}: skipped", heading))).unwrap();
+                    return Ok(Status::Skipped);
+                }
+                let dir = rundir::block_dir(&file_dir, &block)?;
+                let steps = executor::record_steps(&block, opts, Path::new(file), &dir).map_err(|e| {
+                    io::Error::other(format!("{}:{}: can't export {}: {}", file, block.span.start_line, block.label(), e.message))
+                })?;
+                let needed: Vec<_> = needed.into_iter().map(|(prerequisite, _)| prerequisite).collect();
+                script.block(&block, &heading, &steps, &dir, &needed)?;
+                Ok(Status::Passed)
+            })?;
+        }
+    }
+    script.text.push_str("exit $failed\n");
+    Ok(script.text)
+}
+
+impl Script {
+    /// Appends the part of the script that recreates the block's directory
+    /// and runs its `steps`, unless one of its conditions doesn't hold on
+    /// the machine the script runs on or a block it `needs` didn't pass.
+    fn block(&mut self, block: &Block, heading: &str, steps: &[Recorded], dir: &Path, needs: &[Prerequisite]) -> io::Result<()> {
+        self.materialize(dir)?;
+        let expected = block.expected.as_ref().map(|_| self.path(&dir.join(".expected")));
+        let stdout = self.path(&dir.join(".stdout"));
+        if let (Some(path), Some(section)) = (&expected, &block.expected) {
+            self.write_file(path, section.text.as_bytes());
+        }
+        self.text.push_str("ok=true\nskip=false\n");
+        for condition in Condition::all(&block.params) {
+            writeln!(
+                self.text,
+                "{} || {{ echo {}; ok=false; skip=true; }}",
+                condition.shell(),
+                quote(&condition.reason())
+            )
+            .unwrap();
+        }
+        for prerequisite in needs {
+            let message = format!(
+                "dependency failed: it needs {} (line {}), which didn't pass",
+                prerequisite.name, prerequisite.line
+            );
+            writeln!(
+                self.text,
+                "[ \"$passed_{}_{}\" = true ] || {{ echo {}; ok=false; skip=true; }}",
+                self.file,
+                prerequisite.index,
+                quote(&message)
+            )
+            .unwrap();
+        }
+        for (n, step) in steps.iter().enumerate() {
+            let stdin = match &step.input {
+                Some(input) => {
+                    let path = self.path(&dir.join(format!(".stdin-{}", n + 1)));
+                    self.write_file(&path, input);
+                    format!(" <{}", path)
+                }
+                None if self.inherit_stdin && step.step == Step::Run => String::new(),
+                None => " </dev/null".to_string(),
+            };
+            let command = self.command(step);
+            if expected.is_some() && step.step == Step::Run {
+                writeln!(self.text, "$ok && {{ ({}){} >{} || ok=false; cat {}; }}", command, stdin, stdout, stdout).unwrap();
+            } else {
+                writeln!(self.text, "$ok && {{ ({}){} || ok=false; }}", command, stdin).unwrap();
+            }
+        }
+        if let Some(path) = &expected {
+            writeln!(
+                self.text,
+                "$ok && ! cmp -s {} {} && {{ echo \"output doesn't match the expected output\"; ok=false; }}",
+                path, stdout
+            )
+            .unwrap();
+        }
+        writeln!(
+            self.text,
+            "if $ok; then passed_{}_{}=true; echo {}; elif $skip; then echo {}; else echo {}; {}; fi",
+            self.file,
+            block.index,
+            quote(&format!("{}: passed", heading)),
+            quote(&format!("{}: skipped", heading)),
+            quote(&format!("{}: failed", heading)),
+            if self.fail_fast { "exit 1" } else { "failed=1" }
+        )
+        .unwrap();
+        Ok(())
+    }
+
+    /// A step as a subshell command: into its directory, its variables set,
+    /// and the program started in the subshell's place.
+    fn command(&self, step: &Recorded) -> String {
+        let mut parts = Vec::new();
+        if let Some(cwd) = &step.cwd {
+            parts.push(format!("cd {}", self.path(cwd)));
+        }
+        let mut exec = vec!["exec".to_string()];
+        let mut set = Vec::new();
+        for (key, value) in &step.envs {
+            let key = key.to_string_lossy();
+            match value {
+                Some(value) => set.push(self.word(&format!("{}={}", key, value.to_string_lossy()))),
+                None => parts.push(format!("unset {}", key)),
+            }
+        }
+        if !set.is_empty() {
+            exec.push("env".to_string());
+            exec.extend(set);
+        }
+        exec.push(self.word(&step.program.to_string_lossy()));
+        exec.extend(step.args.iter().map(|arg| self.word(&arg.to_string_lossy())));
+        parts.push(exec.join(" "));
+        parts.join(" && ")
+    }
+
+    /// Recreates what the executor left in `dir`, the block's sources and
+    /// any directories it made, beneath the script's directory.
+    fn materialize(&mut self, dir: &Path) -> io::Result<()> {
+        writeln!(self.text, "mkdir -p {}", self.path(dir)).unwrap();
+        let mut entries: Vec<_> = fs::read_dir(dir)?.collect::<io::Result<_>>()?;
+        entries.sort_by_key(|entry| entry.file_name());
+        for entry in entries {
+            let path = entry.path();
+            if entry.file_type()?.is_dir() {
+                self.materialize(&path)?;
+            } else {
+                let word = self.path(&path);
+                self.write_file(&word, &fs::read(&path)?);
+            }
+        }
+        Ok(())
+    }
+
+    /// Appends commands that write `contents` to the file `path`, a shell
+    /// word. Text goes in a heredoc; anything else is spelled out in octal
+    /// escapes.
+    fn write_file(&mut self, path: &str, contents: &[u8]) {
+        let Ok(text) = std::str::from_utf8(contents) else {
+            writeln!(self.text, ": >{}", path).unwrap();
+            for chunk in contents.chunks(64) {
+                let escaped: String = chunk.iter().map(|byte| format!("\\{:03o}", byte)).collect();
+                writeln!(self.text, "printf '{}' >>{}", escaped, path).unwrap();
+            }
+            return;
+        };
+        let mut delimiter = "TRANSLATOR_EOF".to_string();
+        while text.lines().any(|line| line == delimiter) {
+            delimiter.push('_');
+        }
+        // A heredoc always ends in a newline; command substitution drops it
+        // again for text without one.
+        if text.is_empty() {
+            writeln!(self.text, ": >{}", path).unwrap();
+        } else if text.ends_with('\n') {
+            write!(self.text, "cat >{} <<'{}'\n{}{}\n", path, delimiter, text, delimiter).unwrap();
+        } else {
+            write!(self.text, "printf '%s' \"$(cat <<'{}'\n{}\n{}\n)\" >{}\n", delimiter, text, delimiter, path).unwrap();
+        }
+    }
+
+    fn path(&self, path: &Path) -> String {
+        self.word(&path.to_string_lossy())
+    }
+
+    /// `text` as one shell word, with the run directory in it replaced by
+    /// the script's directory.
+    fn word(&self, text: &str) -> String {
+        let parts: Vec<String> = text.split(self.root.as_str()).map(quote).collect();
+        let joined = parts.join("\"$dir\"");
+        // Quoting empty parts around the directory adds nothing.
+        let joined = joined.replace("''\"$dir\"", "\"$dir\"").replace("\"$dir\"''", "\"$dir\"");
+        if joined.is_empty() {
+            "''".to_string()
+        } else {
+            joined
+        }
+    }
+}
+
+/// `text` quoted for the shell, unless it needs no quotes.
+fn quote(text: &str) -> String {
+    let plain = text.chars().all(|c| c.is_ascii_alphanumeric() || "-_=.,:/+@%".contains(c));
+    if plain {
+        text.to_string()
+    } else {
+        format!("'{}'", text.replace('\'', "'\\''"))
+    }
+}
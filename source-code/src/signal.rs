@@ -0,0 +1,51 @@
+//! Signals that end a block's program, such as a segfault in an `unsafe`
+//! demo or the kernel's OOM killer. Only Unix has them; elsewhere a process
+//! always has an exit code.
+
+use std::process::ExitStatus;
+
+/// The signal that terminated the process, if one did.
+#[cfg(unix)]
+pub fn of(status: &ExitStatus) -> Option<i32> {
+    use std::os::unix::process::ExitStatusExt;
+    status.signal()
+}
+
+#[cfg(not(unix))]
+pub fn of(_status: &ExitStatus) -> Option<i32> {
+    None
+}
+
+/// `terminated by SIGSEGV (signal 11)`, or for SIGKILL, which is what the
+/// OOM killer sends, a hint at memory.
+pub fn describe(signal: i32) -> String {
+    let Some(name) = name(signal) else {
+        return format!("terminated by signal {}", signal);
+    };
+    if name == "SIGKILL" {
+        return format!("killed by {} (signal {}), possibly for running out of memory", name, signal);
+    }
+    format!("terminated by {} (signal {})", name, signal)
+}
+
+/// The name of a signal whose number is the same on Linux and macOS, or of
+/// SIGBUS, which isn't.
+fn name(signal: i32) -> Option<&'static str> {
+    const BUS: i32 = if cfg!(target_os = "linux") { 7 } else { 10 };
+    Some(match signal {
+        1 => "SIGHUP",
+        2 => "SIGINT",
+        3 => "SIGQUIT",
+        4 => "SIGILL",
+        5 => "SIGTRAP",
+        6 => "SIGABRT",
+        8 => "SIGFPE",
+        9 => "SIGKILL",
+        11 => "SIGSEGV",
+        13 => "SIGPIPE",
+        14 => "SIGALRM",
+        15 => "SIGTERM",
+        BUS => "SIGBUS",
+        _ => return None,
+    })
+}
@@ -0,0 +1,152 @@
+//! Probing of the compilers and interpreters behind the built-in languages.
+
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+
+use crate::backend;
+use crate::manifest;
+use crate::parser::Block;
+use crate::python;
+use crate::tools::{self, Tools};
+
+/// The programs the built-in languages start, by language, each with the
+/// argument that makes it print its version. The first one for a language
+/// is the one whose version identifies its toolchain. `python` stands for
+/// whichever interpreter [`python::interpreter`] picks.
+const TOOLS: &[(&str, &str, &str)] = &[
+    ("rust", "rustc", "--version"),
+    ("rust", "cargo", "--version"),
+    ("java", "javac", "-version"),
+    ("java", "java", "-version"),
+    ("java", "jshell", "--version"),
+    ("python", "python", "--version"),
+    ("go", "go", "version"),
+];
+
+/// Versions probed so far, by language and program; each toolchain is asked
+/// at most once per process.
+static VERSIONS: Mutex<BTreeMap<String, Option<String>>> = Mutex::new(BTreeMap::new());
+
+/// What `translator doctor` found out about one program.
+pub struct Check {
+    pub lang: &'static str,
+    pub tool: &'static str,
+    /// The command line that was probed, e.g. `python3`.
+    pub program: String,
+    /// Where an override of the program came from.
+    pub source: Option<String>,
+    /// The first line of its version, or why it couldn't be run.
+    pub version: Result<String, String>,
+}
+
+/// Probes every program of every built-in language.
+pub fn check_all(tools: &Tools) -> Vec<Check> {
+    TOOLS
+        .iter()
+        .map(|&(lang, tool, arg)| {
+            let command = match tool {
+                "python" => python::interpreter(tools).map(|interpreter| interpreter.command()),
+                _ => Ok(tools.command(tool)),
+            };
+            let (program, version) = match command {
+                Ok(mut command) => {
+                    let program = command.get_program().to_string_lossy().into_owned();
+                    (program, run(command.arg(arg)))
+                }
+                Err(message) => (tool.to_string(), Err(message)),
+            };
+            Check {
+                lang,
+                tool,
+                program,
+                source: tools.get(tool).map(|(_, source)| source.to_string()),
+                version,
+            }
+        })
+        .collect()
+}
+
+/// Probes the same programs in WSL, in the login shell blocks run in there.
+pub fn check_wsl() -> Vec<Check> {
+    TOOLS
+        .iter()
+        .map(|&(lang, tool, arg)| {
+            let program = if tool == "python" { "python3" } else { tool };
+            let mut command = Command::new(backend::WSL);
+            command.args(["-e", "bash", "-lc"]).arg(format!("{} {}", program, arg));
+            // The shell's status when it finds no such program.
+            let version = run(&mut command).map_err(|problem| {
+                if problem.ends_with(": 127") {
+                    "not found".to_string()
+                } else {
+                    problem
+                }
+            });
+            Check {
+                lang,
+                tool,
+                program: program.to_string(),
+                source: None,
+                version,
+            }
+        })
+        .collect()
+}
+
+/// Whether `tool`, one of [`TOOLS`], is installed on this machine.
+pub fn installed(tool: &str, tools: &Tools) -> bool {
+    match tool {
+        "python" => python::interpreter(tools).is_ok(),
+        _ => tools::locate(Path::new(tools.command(tool).get_program()), None).is_some(),
+    }
+}
+
+/// The tools from [`TOOLS`] that running `block` needs.
+pub fn required(block: &Block) -> Vec<&'static str> {
+    match block.lang.as_str() {
+        "rust" if manifest::cargo_mode(block) => vec!["rustc", "cargo"],
+        "rust" => vec!["rustc"],
+        "java" => vec!["javac", "java"],
+        "python" => vec!["python"],
+        "go" => vec!["go"],
+        _ => Vec::new(),
+    }
+}
+
+/// The command that reports a language's toolchain version.
+fn version_command(lang: &str, tools: &Tools) -> Option<Command> {
+    let &(_, tool, arg) = TOOLS.iter().find(|(tool_lang, _, _)| *tool_lang == lang)?;
+    let mut command = match tool {
+        "python" => python::interpreter(tools).ok()?.command(),
+        _ => tools.command(tool),
+    };
+    command.arg(arg);
+    Some(command)
+}
+
+/// The first line the toolchain prints about its version, or `None` if it is
+/// missing (or the language has no built-in toolchain).
+pub fn version(lang: &str, tools: &Tools) -> Option<String> {
+    let mut command = version_command(lang, tools)?;
+    let key = format!("{} {}", lang, command.get_program().to_string_lossy());
+    let mut versions = VERSIONS.lock().unwrap_or_else(|e| e.into_inner());
+    versions.entry(key).or_insert_with(|| run(&mut command).ok()).clone()
+}
+
+fn run(command: &mut Command) -> Result<String, String> {
+    let output = command.stdin(Stdio::null()).output().map_err(|e| match e.kind() {
+        io::ErrorKind::NotFound => "not found".to_string(),
+        _ => e.to_string(),
+    })?;
+    if !output.status.success() {
+        return Err(format!("exited with {}", output.status));
+    }
+    // Older javac and python print their version on stderr.
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let text = if stdout.trim().is_empty() { stderr } else { stdout };
+    Ok(text.lines().next().map(|line| line.trim().to_string()).unwrap_or_default())
+}
@@ -0,0 +1,168 @@
+//! The programs behind the built-in languages. Each is found on the `PATH`
+//! unless it is overridden, by a `TRANSLATOR_<TOOL>` environment variable or
+//! else by the `[tools]` table of `translator.toml`. Overrides are checked
+//! when the run starts, so a wrong one fails before any block does.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::config::Config;
+
+/// Every program that can be overridden, with its environment variable.
+pub const TOOLS: &[(&str, &str)] = &[
+    ("rustc", "TRANSLATOR_RUSTC"),
+    ("cargo", "TRANSLATOR_CARGO"),
+    ("javac", "TRANSLATOR_JAVAC"),
+    ("java", "TRANSLATOR_JAVA"),
+    ("jshell", "TRANSLATOR_JSHELL"),
+    ("python", "TRANSLATOR_PYTHON"),
+    ("go", "TRANSLATOR_GO"),
+];
+
+/// Where an override came from.
+#[derive(Clone, Debug)]
+pub enum Source {
+    Env(&'static str),
+    Config(PathBuf),
+    /// A block parameter, such as `version=3.11`.
+    Param(String),
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Source::Env(var) => write!(f, "{}", var),
+            Source::Config(path) => write!(f, "{}", path.display()),
+            Source::Param(param) => write!(f, "{}", param),
+        }
+    }
+}
+
+/// The overridden programs of a run; anything else comes from the `PATH`.
+#[derive(Clone, Debug, Default)]
+pub struct Tools {
+    overrides: BTreeMap<&'static str, (PathBuf, Source)>,
+}
+
+impl Tools {
+    /// No overrides: every program comes from the `PATH`.
+    pub const fn none() -> Tools {
+        Tools {
+            overrides: BTreeMap::new(),
+        }
+    }
+
+    /// Reads the overrides from the environment and `config`. Paths in the
+    /// config file are relative to its directory; a bare name is looked up on
+    /// the `PATH`. Fails on an override that doesn't exist.
+    pub fn resolve(config: &Config) -> io::Result<Tools> {
+        let mut overrides = BTreeMap::new();
+        for &(tool, var) in TOOLS {
+            let from_env = env::var_os(var)
+                .filter(|value| !value.is_empty())
+                .map(|value| (PathBuf::from(value), Source::Env(var), None));
+            let from_config = || {
+                let value = config.tool(tool)?;
+                let file = config.path.clone().unwrap_or_default();
+                Some((PathBuf::from(value), Source::Config(file), config.path.as_deref().and_then(Path::parent)))
+            };
+            let Some((value, source, base)) = from_env.or_else(from_config) else {
+                continue;
+            };
+            let path = locate(&value, base).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("{} (from {}) doesn't exist", value.display(), source),
+                )
+            })?;
+            overrides.insert(tool, (path, source));
+        }
+        Ok(Tools { overrides })
+    }
+
+    /// The overridden program for `tool`, and where the override came from.
+    pub fn get(&self, tool: &str) -> Option<(&Path, &Source)> {
+        self.overrides.get(tool).map(|(path, source)| (path.as_path(), source))
+    }
+
+    /// These tools with `overrides` on top, all from `source`.
+    pub fn with(&self, overrides: Vec<(&'static str, PathBuf)>, source: Source) -> Tools {
+        let mut tools = self.clone();
+        for (tool, path) in overrides {
+            tools.overrides.insert(tool, (path, source.clone()));
+        }
+        tools
+    }
+
+    /// A command for `tool`: the override, or else the one on the `PATH`.
+    /// The JDK's programs are also looked for in `$JAVA_HOME/bin`, since
+    /// installers on Windows often don't put them on the `PATH`.
+    pub fn command(&self, tool: &str) -> Command {
+        if let Some((path, _)) = self.get(tool) {
+            return Command::new(path);
+        }
+        if JDK_TOOLS.contains(&tool) && find_on_path(Path::new(tool)).is_none() {
+            if let Some(path) = java_home(tool) {
+                return Command::new(path);
+            }
+        }
+        command(tool)
+    }
+}
+
+/// The programs that come with a JDK.
+const JDK_TOOLS: &[&str] = &["javac", "java", "jshell"];
+
+/// `tool` in `$JAVA_HOME/bin`, if it is there.
+fn java_home(tool: &str) -> Option<PathBuf> {
+    let home = env::var_os("JAVA_HOME").filter(|home| !home.is_empty())?;
+    let path = Path::new(&home).join("bin").join(format!("{}{}", tool, env::consts::EXE_SUFFIX));
+    path.is_file().then_some(path)
+}
+
+/// A command for the program `name` on the `PATH`. Windows only looks for
+/// `name.exe` by itself, so there `name` is found with `PATHEXT` first, for
+/// the `.cmd` and `.bat` shims version managers install.
+pub fn command(name: &str) -> Command {
+    match find_on_path(Path::new(name)).filter(|_| cfg!(windows)) {
+        Some(path) => Command::new(path),
+        None => Command::new(name),
+    }
+}
+
+/// The file `value` names: a path, resolved against `base` when relative,
+/// or a name on the `PATH`.
+pub fn locate(value: &Path, base: Option<&Path>) -> Option<PathBuf> {
+    if value.components().count() == 1 {
+        return find_on_path(value);
+    }
+    let path = match base {
+        Some(base) if value.is_relative() => base.join(value),
+        _ => value.to_path_buf(),
+    };
+    path.is_file().then_some(path)
+}
+
+/// The file `name` is on the `PATH`. On Windows a name without an extension
+/// is tried with each one in `PATHEXT`, as the shell would.
+fn find_on_path(name: &Path) -> Option<PathBuf> {
+    let dirs = env::var_os("PATH")?;
+    let extensions = if cfg!(windows) && name.extension().is_none() {
+        let pathext = env::var("PATHEXT").unwrap_or_else(|_| ".COM;.EXE;.BAT;.CMD".to_string());
+        pathext.split(';').filter(|ext| !ext.is_empty()).map(str::to_string).collect()
+    } else {
+        vec![String::new()]
+    };
+    env::split_paths(&dirs).find_map(|dir| {
+        let path = dir.join(name);
+        extensions.iter().map(|ext| {
+            let mut path = path.clone().into_os_string();
+            path.push(ext);
+            PathBuf::from(path)
+        }).find(|path| path.is_file())
+    })
+}
@@ -0,0 +1,62 @@
+//! Whether a hacker file may run without asking. Files beneath a directory
+//! holding a `.translator-trust` file, or beneath one of the `trusted`
+//! directories of `translator.toml`, run as before; for any other file the
+//! user is shown what it would run and has to answer `y`, unless `--yes`
+//! was given. Without a terminal to ask on, only `--yes` runs it.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::io::{self, BufRead};
+use std::path::Path;
+
+use crate::config::Config;
+use crate::console::Console;
+
+/// The file that marks a directory, and everything beneath it, as trusted.
+pub const MARKER_FILE: &str = ".translator-trust";
+
+/// Whether `file` is beneath a trusted directory.
+pub fn is_trusted(file: &Path, config: &Config) -> io::Result<bool> {
+    let file = file.canonicalize()?;
+    let dir = file.parent().unwrap_or(&file);
+    if dir.ancestors().any(|dir| dir.join(MARKER_FILE).is_file()) {
+        return Ok(true);
+    }
+    let base = match config.path.as_deref().and_then(Path::parent) {
+        Some(base) => base.to_path_buf(),
+        None => env::current_dir()?,
+    };
+    let mut trusted = config.trusted.iter().filter_map(|dir| base.join(dir).canonicalize().ok());
+    Ok(trusted.any(|trusted| file.starts_with(trusted)))
+}
+
+/// Asks whether to run `file`'s blocks, counted by language in `blocks`.
+/// Fails without a terminal to ask on.
+pub fn confirm(file: &str, blocks: &BTreeMap<String, usize>, console: &Console) -> io::Result<bool> {
+    if !console.interactive {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "{} isn't in a trusted directory; pass --yes to run it anyway, or add a {} file to its directory",
+                file, MARKER_FILE
+            ),
+        ));
+    }
+    let total: usize = blocks.values().sum();
+    let langs: Vec<String> = blocks.iter().map(|(lang, count)| format!("{} ({})", lang, count)).collect();
+    let mut err = console.err();
+    write!(
+        err,
+        "{} isn't in a trusted directory. It has {} {} to run: {}. Run {}? [y/N] ",
+        file,
+        total,
+        if total == 1 { "block" } else { "blocks" },
+        langs.join(", "),
+        if total == 1 { "it" } else { "them" }
+    )?;
+    err.flush()?;
+    drop(err);
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_ascii_lowercase().as_str(), "y" | "yes"))
+}
@@ -0,0 +1,110 @@
+//! What a block's program used of the machine, for `--rusage`: its peak
+//! memory and the CPU time it spent in user and in system mode. The numbers
+//! are the operating system's for the process translator started, as it
+//! exits; on Unix that is `wait4`. Whatever a platform doesn't tell is
+//! `None` rather than zero.
+
+use std::fmt;
+use std::io;
+use std::process::{Child, ExitStatus};
+use std::time::Duration;
+
+use serde::{Serialize, Serializer};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Usage {
+    /// The largest the process's resident set got, in bytes.
+    #[serde(rename = "max_rss_bytes")]
+    pub max_rss: Option<u64>,
+    #[serde(rename = "user_ms", serialize_with = "millis")]
+    pub user: Option<Duration>,
+    #[serde(rename = "system_ms", serialize_with = "millis")]
+    pub system: Option<Duration>,
+}
+
+impl Usage {
+    /// Both runs together, as for a block whose program ran more than
+    /// once: the times add up and the peak is the higher one.
+    pub fn and(self, other: Usage) -> Usage {
+        let sum = |a: Option<Duration>, b: Option<Duration>| Some(a? + b?);
+        Usage {
+            max_rss: self.max_rss.zip(other.max_rss).map(|(a, b)| a.max(b)),
+            user: sum(self.user, other.user),
+            system: sum(self.system, other.system),
+        }
+    }
+
+    /// The peak memory, user time and system time as people read them,
+    /// `12.4 MB` and `310 ms`, with `n/a` for what isn't known.
+    pub fn columns(&self) -> [String; 3] {
+        let time = |time: Option<Duration>| time.map_or_else(|| "n/a".to_string(), crate::report::format_duration);
+        let rss = self.max_rss.map_or_else(
+            || "n/a".to_string(),
+            |bytes| format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0)),
+        );
+        [rss, time(self.user), time(self.system)]
+    }
+}
+
+/// `max RSS 12.4 MB, user 310 ms, sys 12 ms`.
+impl fmt::Display for Usage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let [rss, user, system] = self.columns();
+        write!(f, "max RSS {}, user {}, sys {}", rss, user, system)
+    }
+}
+
+fn millis<S: Serializer>(duration: &Option<Duration>, serializer: S) -> Result<S::Ok, S::Error> {
+    duration.map(|duration| duration.as_millis()).serialize(serializer)
+}
+
+/// Waits for `child` to exit, like [`Child::wait`], and returns what it
+/// used along with its status.
+#[cfg(unix)]
+pub fn wait(child: &mut Child) -> io::Result<(ExitStatus, Usage)> {
+    use std::os::unix::process::ExitStatusExt;
+    let pid = child.id() as libc::pid_t;
+    let mut status = 0;
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    while unsafe { libc::wait4(pid, &mut status, 0, &mut usage) } != pid {
+        let e = io::Error::last_os_error();
+        if e.kind() != io::ErrorKind::Interrupted {
+            return Err(e);
+        }
+    }
+    // macOS counts the peak in bytes, the others in kilobytes.
+    let unit = if cfg!(target_vendor = "apple") { 1 } else { 1024 };
+    let time = |time: libc::timeval| Duration::from_secs(time.tv_sec as u64) + Duration::from_micros(time.tv_usec as u64);
+    let usage = Usage {
+        max_rss: Some(usage.ru_maxrss as u64 * unit),
+        user: Some(time(usage.ru_utime)),
+        system: Some(time(usage.ru_stime)),
+    };
+    Ok((ExitStatus::from_raw(status), usage))
+}
+
+/// Waits for `child` to exit, like [`Child::wait`], and returns what it
+/// used along with its status. Windows gives the CPU times of a process
+/// that has exited, though not its peak memory.
+#[cfg(windows)]
+pub fn wait(child: &mut Child) -> io::Result<(ExitStatus, Usage)> {
+    use std::os::windows::io::AsRawHandle;
+    use windows_sys::Win32::Foundation::{FILETIME, HANDLE};
+    use windows_sys::Win32::System::Threading::GetProcessTimes;
+    let status = child.wait()?;
+    let [mut created, mut exited, mut kernel, mut user] = [FILETIME::default(); 4];
+    let known = unsafe { GetProcessTimes(child.as_raw_handle() as HANDLE, &mut created, &mut exited, &mut kernel, &mut user) } != 0;
+    // In units of 100 ns.
+    let time = |time: FILETIME| Duration::from_nanos(((time.dwHighDateTime as u64) << 32 | time.dwLowDateTime as u64) * 100);
+    let usage = Usage {
+        max_rss: None,
+        user: known.then(|| time(user)),
+        system: known.then(|| time(kernel)),
+    };
+    Ok((status, usage))
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn wait(child: &mut Child) -> io::Result<(ExitStatus, Usage)> {
+    Ok((child.wait()?, Usage::default()))
+}
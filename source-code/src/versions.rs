@@ -0,0 +1,204 @@
+//! Toolchains picked by a block's `version=`: `python(version=3.11)` runs
+//! under a python 3.11 even when the default `python3` is another one. The
+//! candidates are the `[versions]` table of `translator.toml`, then the
+//! places where several versions of a language tend to be installed side by
+//! side, then the toolchain the block would use anyway. The first whose
+//! version matches wins.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+use crate::parser::Block;
+use crate::toolchain;
+use crate::tools::{self, Source, Tools};
+
+/// The languages `version=` works for.
+pub const LANGS: &[&str] = &["python", "java", "go"];
+
+/// The toolchain found for a block's `version=`.
+pub struct Pinned {
+    /// The run's tools with the pinned programs in place.
+    pub tools: Tools,
+    /// Its full version, e.g. `3.11.7`.
+    pub version: String,
+    /// Where it is, unless it is the default toolchain.
+    pub program: Option<PathBuf>,
+}
+
+/// A place a toolchain may be, as the programs it overrides; none for the
+/// default toolchain.
+type Candidate = Vec<(&'static str, PathBuf)>;
+
+/// The block's `version=`, checked to be a version number of a language it
+/// works for.
+pub fn requested(block: &Block) -> Result<Option<&str>, String> {
+    let Some(version) = block.params.get("version") else {
+        return Ok(None);
+    };
+    if !LANGS.contains(&block.lang.as_str()) {
+        return Err(format!("version= only picks {} toolchains, not {}", LANGS.join(", "), block.lang));
+    }
+    let valid = version.split('.').all(|part| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit()));
+    if !valid {
+        return Err(format!("version={} isn't a version number like 3.11, 21 or 1.22", version));
+    }
+    Ok(Some(version))
+}
+
+/// Finds a `lang` toolchain of version `wanted`, which matches any version
+/// it is a prefix of: `3.11` is `3.11.7`, but not `3.1`.
+pub fn locate(lang: &str, wanted: &str, tools: &Tools, config: &Config) -> Result<Pinned, String> {
+    let mut found = Vec::new();
+    for candidate in candidates(lang, wanted, config) {
+        let program = candidate.first().map(|(_, path)| path.clone());
+        let source = Source::Param(format!("version={}", wanted));
+        let tools = tools.with(candidate, source);
+        let Some(version) = toolchain::version(lang, &tools).and_then(|line| number(lang, &line)) else {
+            continue;
+        };
+        if matches(&version, wanted) {
+            return Ok(Pinned { tools, version, program });
+        }
+        if !found.contains(&version) {
+            found.push(version);
+        }
+    }
+    let found = if found.is_empty() { "none".to_string() } else { found.join(", ") };
+    Err(format!("required {} {}, found {}", lang, wanted, found))
+}
+
+fn candidates(lang: &str, wanted: &str, config: &Config) -> Vec<Candidate> {
+    let base = config.path.as_deref().and_then(Path::parent);
+    let configured = config.versions.get(lang).and_then(|versions| versions.get(wanted));
+    let mut candidates = Vec::new();
+    match lang {
+        "python" => {
+            if let Some(path) = configured.and_then(|value| tools::locate(Path::new(value), base)) {
+                candidates.push(vec![("python", path)]);
+            }
+            let name = format!("python{}", wanted);
+            candidates.extend(tools::locate(Path::new(&name), None).map(|path| vec![("python", path)]));
+        }
+        "java" => {
+            let mut homes = Vec::new();
+            if let Some(home) = configured {
+                let home = Path::new(home);
+                homes.push(match base {
+                    Some(base) if home.is_relative() => base.join(home),
+                    _ => home.to_path_buf(),
+                });
+            }
+            // CI images often set JAVA_HOME_21_X64 and the like.
+            let prefix = format!("JAVA_HOME_{}_", wanted);
+            let mut vars: Vec<_> = env::vars_os()
+                .filter(|(var, _)| var.to_str().is_some_and(|var| var.starts_with(&prefix)))
+                .collect();
+            vars.sort();
+            homes.extend(vars.into_iter().map(|(_, home)| PathBuf::from(home)));
+            homes.extend(env::var_os("JAVA_HOME").map(PathBuf::from));
+            homes.extend(subdirs(Path::new("/usr/lib/jvm"), ""));
+            homes.extend(subdirs(Path::new("/Library/Java/JavaVirtualMachines"), "Contents/Home"));
+            candidates.extend(homes.iter().filter_map(|home| jdk(home)));
+        }
+        "go" => {
+            if let Some(path) = configured.and_then(|value| tools::locate(Path::new(value), base)) {
+                candidates.push(vec![("go", path)]);
+            }
+            // golang.org/dl installs a `go1.22.1` command per release, with
+            // the release itself in ~/sdk/go1.22.1.
+            let name = format!("go{}", wanted);
+            candidates.extend(on_path(&name).into_iter().map(|path| vec![("go", path)]));
+            if let Some(home) = env::var_os("HOME") {
+                let sdk = Path::new(&home).join("sdk");
+                let releases = subdirs(&sdk, "bin").into_iter().filter(|dir| {
+                    dir.parent().and_then(Path::file_name).is_some_and(|release| is_release(release.to_string_lossy().as_ref(), &name))
+                });
+                candidates.extend(releases.map(|bin| vec![("go", bin.join(exe("go")))]).filter(|c| c[0].1.is_file()));
+            }
+        }
+        _ => {}
+    }
+    // The default toolchain comes last, and only needs checking.
+    candidates.push(Vec::new());
+    candidates
+}
+
+/// The programs of the JDK at `home`, which needs at least javac and java.
+fn jdk(home: &Path) -> Option<Candidate> {
+    let bin = home.join("bin");
+    let programs: Candidate = ["javac", "java", "jshell"]
+        .into_iter()
+        .map(|tool| (tool, bin.join(exe(tool))))
+        .filter(|(_, path)| path.is_file())
+        .collect();
+    (programs.len() >= 2 && programs[0].0 == "javac").then_some(programs)
+}
+
+/// The commands on the `PATH` that are the release `name` (`go1.22`) or one
+/// of its patch releases (`go1.22.1`), in `PATH` order.
+fn on_path(name: &str) -> Vec<PathBuf> {
+    let Some(dirs) = env::var_os("PATH") else {
+        return Vec::new();
+    };
+    let mut found = Vec::new();
+    for dir in env::split_paths(&dirs) {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        let mut matching: Vec<PathBuf> = entries
+            .flatten()
+            .filter(|entry| is_release(entry.file_name().to_string_lossy().trim_end_matches(".exe"), name))
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file())
+            .collect();
+        matching.sort();
+        found.extend(matching);
+    }
+    found
+}
+
+fn is_release(candidate: &str, name: &str) -> bool {
+    candidate == name || candidate.strip_prefix(name).is_some_and(|rest| rest.starts_with('.'))
+}
+
+/// Each directory in `dir`, joined with `sub`, sorted by name.
+fn subdirs(dir: &Path, sub: &str) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut dirs: Vec<PathBuf> = entries.flatten().map(|entry| entry.path().join(sub)).filter(|path| path.is_dir()).collect();
+    dirs.sort();
+    dirs
+}
+
+fn exe(name: &str) -> String {
+    if cfg!(windows) {
+        format!("{}.exe", name)
+    } else {
+        name.to_string()
+    }
+}
+
+/// The version number in a toolchain's version line: `Python 3.11.7`,
+/// `javac 21.0.2` or `go version go1.22.1 linux/amd64`. Java 8 and older
+/// call themselves `1.8`, which is taken as `8`.
+pub fn number(lang: &str, line: &str) -> Option<String> {
+    let number = line.split_whitespace().find_map(|word| {
+        let word = word.trim_matches('"');
+        let word = word.strip_prefix("go").unwrap_or(word);
+        word.starts_with(|c: char| c.is_ascii_digit()).then_some(word)
+    })?;
+    let number = match (lang, number.strip_prefix("1.")) {
+        ("java", Some(legacy)) => legacy,
+        _ => number,
+    };
+    Some(number.to_string())
+}
+
+/// Whether `version` is `wanted` or one of its point releases.
+fn matches(version: &str, wanted: &str) -> bool {
+    let mut parts = version.split(['.', '_', '-', '+']);
+    wanted.split('.').all(|part| parts.next() == Some(part))
+}
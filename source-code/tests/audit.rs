@@ -0,0 +1,82 @@
+//! `--audit-log`: one JSON object per line for every process a block
+//! starts, which has to read back as written.
+
+mod common;
+
+use std::fs;
+
+use serde_json::Value;
+
+use common::{have, have_python, Dir};
+
+fn entries(path: &std::path::Path) -> Vec<Value> {
+    let log = fs::read_to_string(path).expect("the audit log was written");
+    assert!(log.ends_with('\n'), "last line isn't terminated: {:?}", log);
+    log.lines()
+        .map(|line| serde_json::from_str(line).unwrap_or_else(|e| panic!("not JSON ({}): {}", e, line)))
+        .collect()
+}
+
+#[test]
+fn every_process_gets_a_line() {
+    if !have_python() || !have("rustc") {
+        return;
+    }
+    let dir = Dir::new();
+    dir.write(
+        "doc.hacker",
+        "|> translator:rust(name=demo) (\nfn main() { println!(\"hi\"); }\n)\n|> translator:python (\nimport sys\nsys.exit(4)\n)\n",
+    );
+    let log = dir.path().join("audit.log");
+    let output = dir.run(&["--no-cache", "--audit-log", log.to_str().unwrap(), "doc.hacker"]);
+    assert_eq!(output.status.code(), Some(1), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let entries = entries(&log);
+    let steps: Vec<(u64, &str)> = entries
+        .iter()
+        .map(|entry| (entry["blocks"][0]["index"].as_u64().unwrap(), entry["step"].as_str().unwrap()))
+        .collect();
+    assert_eq!(steps, [(1, "compile"), (1, "run"), (2, "run")]);
+    for entry in &entries {
+        assert_eq!(entry["file"], "doc.hacker");
+        assert!(entry["time"].as_str().is_some_and(|time| time.ends_with('Z')), "{}", entry);
+        assert!(entry["argv"].as_array().is_some_and(|argv| !argv.is_empty()), "{}", entry);
+        assert!(entry["duration_ms"].is_u64(), "{}", entry);
+        assert!(entry["error"].is_null(), "{}", entry);
+        let block = &entry["blocks"][0];
+        assert_eq!(block["code_sha256"].as_str().map(str::len), Some(64), "{}", entry);
+    }
+    let rust = &entries[0]["blocks"][0];
+    assert_eq!((rust["lang"].as_str(), rust["name"].as_str()), (Some("rust"), Some("demo")));
+    assert_eq!((rust["start_line"].as_u64(), rust["end_line"].as_u64()), (Some(1), Some(3)));
+    assert_eq!(entries[0]["argv"][0].as_str().map(|argv0| argv0.contains("rustc")), Some(true));
+    assert_eq!(entries[1]["exit_code"], 0);
+    assert_eq!(entries[2]["exit_code"], 4);
+    assert!(entries[2]["blocks"][0]["name"].is_null());
+}
+
+#[test]
+fn log_is_appended_to() {
+    if !have_python() {
+        return;
+    }
+    let dir = Dir::new();
+    dir.write("doc.hacker", "|> translator:python (\nprint(1)\n)\n");
+    let log = dir.path().join("audit.log");
+    for _ in 0..2 {
+        dir.run(&["--audit-log", log.to_str().unwrap(), "doc.hacker"]);
+    }
+    assert_eq!(entries(&log).len(), 2);
+}
+
+#[test]
+fn log_that_cant_be_opened_stops_the_run() {
+    let dir = Dir::new();
+    dir.write("doc.hacker", "|> translator:python (\nprint(1)\n)\n");
+    let log = dir.path().join("missing").join("audit.log");
+    let output = dir.run(&["--audit-log", log.to_str().unwrap(), "doc.hacker"]);
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty(), "a block ran: {}", String::from_utf8_lossy(&output.stdout));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("--audit-log"), "{}", stderr);
+}
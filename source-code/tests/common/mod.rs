@@ -0,0 +1,108 @@
+//! What the tests that run translator on hacker files share. Each test
+//! writes its files to a directory of its own and runs the binary there.
+
+// Not every test file uses every helper.
+#![allow(dead_code)]
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+use serde_json::Value;
+use tempfile::TempDir;
+
+/// A directory holding the files of one test.
+pub struct Dir {
+    pub dir: TempDir,
+}
+
+impl Dir {
+    pub fn new() -> Dir {
+        Dir {
+            dir: tempfile::tempdir().expect("temp dir"),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    /// Writes `contents` to `name` in the directory and returns its path.
+    pub fn write(&self, name: &str, contents: impl AsRef<[u8]>) -> PathBuf {
+        let path = self.dir.path().join(name);
+        fs::write(&path, contents).expect("write test file");
+        path
+    }
+
+    /// translator, started in the directory, without the trust prompt and
+    /// with a cache of the directory's own.
+    pub fn translator(&self) -> Command {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_translator"));
+        command
+            .current_dir(self.dir.path())
+            .arg("--yes")
+            .arg("--cache-dir")
+            .arg(self.dir.path().join(".cache"))
+            .stdin(Stdio::null());
+        command
+    }
+
+    /// Runs translator with `args` and returns what it printed.
+    pub fn run(&self, args: &[&str]) -> Output {
+        self.translator().args(args).output().expect("run translator")
+    }
+
+    /// Runs translator with `args` and `--format json`, and returns the
+    /// document along with the exit code.
+    pub fn json(&self, args: &[&str]) -> (Value, i32) {
+        let output = self.translator().args(args).args(["--format", "json"]).output().expect("run translator");
+        let document = serde_json::from_slice(&output.stdout).unwrap_or_else(|e| {
+            panic!(
+                "stdout isn't JSON ({}):\n{}\nstderr:\n{}",
+                e,
+                String::from_utf8_lossy(&output.stdout),
+                String::from_utf8_lossy(&output.stderr)
+            )
+        });
+        (document, output.status.code().unwrap_or(-1))
+    }
+}
+
+/// Whether `program` can be run. Tests that need a missing toolchain
+/// return early, unless `TRANSLATOR_TEST_TOOLCHAINS=require` asks for them
+/// to fail, as CI does.
+pub fn have(program: &str) -> bool {
+    runs(program) || missing(program)
+}
+
+/// Like [`have`], for any of the names python goes by.
+pub fn have_python() -> bool {
+    ["python3", "python", "py"].into_iter().any(runs) || missing("python")
+}
+
+fn runs(program: &str) -> bool {
+    let version = if program == "go" { "version" } else { "--version" };
+    Command::new(program)
+        .arg(version)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+fn missing(program: &str) -> bool {
+    assert!(
+        env::var("TRANSLATOR_TEST_TOOLCHAINS").as_deref() != Ok("require"),
+        "{} isn't installed",
+        program
+    );
+    eprintln!("skipping: {} isn't installed", program);
+    false
+}
+
+/// The results of the blocks in a JSON document, in order.
+pub fn blocks(document: &Value) -> &[Value] {
+    document["blocks"].as_array().expect("blocks")
+}
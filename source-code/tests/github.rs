@@ -0,0 +1,76 @@
+//! `--format gh-annotations`: which blocks become annotations, and of what
+//! kind.
+
+mod common;
+
+use std::fs;
+use std::process::Command;
+
+use common::{have, Dir};
+
+/// The workflow commands translator printed.
+fn annotations(dir: &Dir, args: &[&str], path: Option<&std::path::Path>) -> Vec<String> {
+    let mut command = dir.translator();
+    command.args(["--format", "gh-annotations"]).args(args);
+    if let Some(path) = path {
+        command.env("PATH", path).env_remove("JAVA_HOME");
+    }
+    let output = command.output().unwrap();
+    String::from_utf8(output.stdout).unwrap().lines().map(str::to_string).collect()
+}
+
+#[test]
+fn failures_are_errors() {
+    if !have("rustc") {
+        return;
+    }
+    let dir = Dir::new();
+    dir.write("doc.hacker", "|> translator:rust (\nfn main() {}\n)\n|> translator:rust (\nfn main() { std::process::exit(3); }\n)\n");
+    let annotations = annotations(&dir, &["doc.hacker"], None);
+    assert_eq!(annotations.len(), 1, "{:?}", annotations);
+    assert!(annotations[0].starts_with("::error file=doc.hacker,line=4,endLine=6,title=rust block 2::"), "{}", annotations[0]);
+}
+
+#[test]
+fn missing_toolchain_is_a_warning() {
+    let dir = Dir::new();
+    fs::create_dir(dir.path().join("empty")).unwrap();
+    dir.write("doc.hacker", "|> translator:rust (\nfn main() {}\n)\n");
+    let annotations = annotations(&dir, &["doc.hacker"], Some(&dir.path().join("empty")));
+    assert_eq!(annotations.len(), 1, "{:?}", annotations);
+    assert!(annotations[0].starts_with("::warning file=doc.hacker,line=1,endLine=3,"), "{}", annotations[0]);
+}
+
+#[test]
+fn unmet_conditions_arent_annotated() {
+    let dir = Dir::new();
+    dir.write("doc.hacker", "|> translator:rust(if-os=plan9) (\nfn main() {}\n)\n");
+    assert_eq!(annotations(&dir, &["doc.hacker"], None), Vec::<String>::new());
+}
+
+/// Commits what's in `dir`.
+fn commit(dir: &Dir) {
+    for args in [&["add", "-A"][..], &["-c", "user.name=test", "-c", "user.email=test@example.com", "commit", "-qm", "doc"]] {
+        let status = Command::new("git").args(args).current_dir(dir.path()).status().unwrap();
+        assert!(status.success(), "git {:?}", args);
+    }
+}
+
+#[test]
+fn unchanged_blocks_get_one_notice() {
+    if !have("git") || !have("rustc") {
+        return;
+    }
+    let dir = Dir::new();
+    assert!(Command::new("git").args(["init", "-q"]).current_dir(dir.path()).status().unwrap().success());
+    let block = |code: &str| format!("|> translator:rust (\n{}\n)\n\n", code);
+    let unchanged = block("fn main() {}").repeat(3);
+    dir.write("doc.hacker", format!("{}{}", unchanged, block("fn main() { println!(\"before\"); }")));
+    commit(&dir);
+    dir.write("doc.hacker", format!("{}{}", unchanged, block("fn main() { std::process::exit(3); }")));
+    commit(&dir);
+    let annotations = annotations(&dir, &["--changed-only=HEAD~1", "--changed-blocks", "doc.hacker"], None);
+    assert_eq!(annotations.len(), 2, "{:?}", annotations);
+    assert!(annotations[0].starts_with("::error file=doc.hacker,line=13,"), "{}", annotations[0]);
+    assert_eq!(annotations[1], "::notice title=translator::3 unchanged blocks skipped by --changed-only");
+}
@@ -0,0 +1,46 @@
+//! Go blocks, which need go installed.
+
+mod common;
+
+use std::time::Instant;
+
+use common::{blocks, have, Dir};
+
+/// A block that needs a module from outside the standard library.
+const MODULE: &str = r#"|> translator:go(deps="github.com/google/uuid@v1.6.0") (
+package main
+
+import (
+	"fmt"
+
+	"github.com/google/uuid"
+)
+
+func main() {
+	fmt.Println(len(uuid.NewString()))
+}
+)
+"#;
+
+/// The second run reuses the resolved go.mod and go.sum, the module cache
+/// and the build, so it doesn't download or compile anything. Needs the
+/// network for the first run.
+#[test]
+#[ignore]
+fn module_block_is_faster_the_second_time() {
+    if !have("go") {
+        return;
+    }
+    let dir = Dir::new();
+    let file = dir.write("doc.hacker", MODULE);
+    let file = file.to_str().unwrap();
+    let mut durations = Vec::new();
+    for _ in 0..2 {
+        let start = Instant::now();
+        let (document, code) = dir.json(&[file]);
+        durations.push(start.elapsed());
+        assert_eq!(code, 0, "{}", document);
+        assert_eq!(blocks(&document)[0]["stdout"], "36\n");
+    }
+    assert!(durations[1] < durations[0], "first run {:?}, second {:?}", durations[0], durations[1]);
+}
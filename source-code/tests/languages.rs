@@ -0,0 +1,132 @@
+//! Each built-in language end to end: a block that passes, one that
+//! doesn't build and one whose program fails. A language whose toolchain
+//! isn't installed is skipped, unless `TRANSLATOR_TEST_TOOLCHAINS=require`.
+
+mod common;
+
+use common::{blocks, have, have_python, Dir};
+
+struct Language {
+    lang: &'static str,
+    hello: &'static str,
+    /// Doesn't compile, with its brackets balanced so the block ends
+    /// where it should.
+    broken: &'static str,
+    /// Exits with 3.
+    failing: &'static str,
+}
+
+const RUST: Language = Language {
+    lang: "rust",
+    hello: "fn main() {\n    println!(\"hello from rust\");\n}\n",
+    broken: "fn main() {\n    let x: i32 = \"three\";\n}\n",
+    failing: "fn main() {\n    std::process::exit(3);\n}\n",
+};
+
+const PYTHON: Language = Language {
+    lang: "python",
+    hello: "print('hello from python')\n",
+    broken: "def main:\n    pass\n",
+    failing: "import sys\nsys.exit(3)\n",
+};
+
+const JAVA: Language = Language {
+    lang: "java",
+    hello: "public class Hello {\n    public static void main(String[] args) {\n        System.out.println(\"hello from java\");\n    }\n}\n",
+    broken: "public class Broken {\n    public static void main(String[] args) {\n        int x = \"three\";\n    }\n}\n",
+    failing: "public class Failing {\n    public static void main(String[] args) {\n        System.exit(3);\n    }\n}\n",
+};
+
+const GO: Language = Language {
+    lang: "go",
+    hello: "package main\n\nimport \"fmt\"\n\nfunc main() {\n\tfmt.Println(\"hello from go\")\n}\n",
+    broken: "package main\n\nfunc main() {\n\tvar x int = \"three\"\n}\n",
+    failing: "package main\n\nimport \"os\"\n\nfunc main() {\n\tos.Exit(3)\n}\n",
+};
+
+fn check(language: &Language) {
+    let dir = Dir::new();
+    let mut file = String::new();
+    for code in [language.hello, language.broken, language.failing] {
+        file.push_str(&format!("|> translator:{} (\n{})\n\n", language.lang, code));
+    }
+    dir.write("doc.hacker", file);
+    let (document, code) = dir.json(&["doc.hacker"]);
+    assert_eq!(code, 2, "{}", document);
+    let results = blocks(&document);
+
+    assert_eq!(results[0]["status"], "passed", "{}", results[0]);
+    assert_eq!(results[0]["stdout"], format!("hello from {}\n", language.lang));
+
+    assert_eq!(results[1]["status"], "failed", "{}", results[1]);
+    assert_eq!(results[1]["error_kind"], "compile_error", "{}", results[1]);
+
+    assert_eq!(results[2]["status"], "failed", "{}", results[2]);
+    assert_eq!(results[2]["error_kind"], "non_zero_exit", "{}", results[2]);
+    assert_eq!(results[2]["exit_code"], 3);
+}
+
+#[test]
+fn rust() {
+    if have("rustc") {
+        check(&RUST);
+    }
+}
+
+#[test]
+fn python() {
+    if have_python() {
+        check(&PYTHON);
+    }
+}
+
+#[test]
+fn java() {
+    if have("javac") && have("java") {
+        check(&JAVA);
+    }
+}
+
+#[test]
+fn go() {
+    if have("go") {
+        check(&GO);
+    }
+}
+
+/// A JDK that only logs how it was called, and that isn't on the `PATH`.
+#[cfg(unix)]
+fn fake_jdk(dir: &Dir) -> std::path::PathBuf {
+    use std::os::unix::fs::PermissionsExt;
+    let javac = "#!/bin/sh\necho \"javac $*\" >> \"$JAVA_HOME/calls\"\n[ \"$1\" = -version ] && echo \"javac 99.0.1\"\nexit 0\n";
+    let java = "#!/bin/sh\necho \"java $*\" >> \"$JAVA_HOME/calls\"\necho \"java from JAVA_HOME\"\n";
+    std::fs::create_dir_all(dir.path().join("jdk/bin")).unwrap();
+    for (name, script) in [("javac", javac), ("java", java)] {
+        let path = dir.write(&format!("jdk/bin/{}", name), script);
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+    dir.path().join("jdk")
+}
+
+#[test]
+#[cfg(unix)]
+fn java_is_found_in_java_home() {
+    let dir = Dir::new();
+    let jdk = fake_jdk(&dir);
+    std::fs::create_dir(dir.path().join("empty")).unwrap();
+    dir.write("doc.hacker", format!("|> translator:java (\n{})\n", JAVA.hello));
+    let output = dir
+        .translator()
+        .args(["doc.hacker", "--format", "json"])
+        .env("PATH", dir.path().join("empty"))
+        .env("JAVA_HOME", &jdk)
+        .output()
+        .unwrap();
+    let document: serde_json::Value = serde_json::from_slice(&output.stdout).expect("JSON");
+    let results = blocks(&document);
+    assert_eq!(results[0]["status"], "passed", "{}", results[0]);
+    assert_eq!(results[0]["stdout"], "java from JAVA_HOME\n");
+    let calls = std::fs::read_to_string(jdk.join("calls")).unwrap();
+    assert!(calls.lines().any(|call| call.starts_with("javac -d ")), "{}", calls);
+    assert!(calls.lines().any(|call| call.starts_with("java -cp ")), "{}", calls);
+}
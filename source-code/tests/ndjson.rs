@@ -0,0 +1,86 @@
+//! `--format ndjson`: every line translator prints is a JSON event, even
+//! for blocks that fail or don't compile.
+
+mod common;
+
+use base64::Engine;
+use serde_json::Value;
+
+use common::{have, have_python, Dir};
+
+const FILE: &str = r#"|> translator:python (
+print("hello")
+)
+|> translator:python (
+import sys
+sys.stdout.buffer.write(b"\xff\n")
+sys.exit(3)
+)
+|> translator:rust (
+fn main() { let x: i32 = "a"; }
+)
+"#;
+
+/// The events translator printed, each line parsed on its own.
+fn events(stdout: &[u8]) -> Vec<Value> {
+    let stdout = std::str::from_utf8(stdout).expect("ndjson is UTF-8");
+    assert!(stdout.ends_with('\n'), "last event isn't terminated: {:?}", stdout);
+    stdout
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap_or_else(|e| panic!("not JSON ({}): {}", e, line)))
+        .collect()
+}
+
+#[test]
+fn every_line_is_an_event() {
+    if !have_python() || !have("rustc") {
+        return;
+    }
+    let dir = Dir::new();
+    dir.write("doc.hacker", FILE);
+    let output = dir.run(&["doc.hacker", "--format", "ndjson"]);
+    assert_eq!(output.status.code(), Some(2), "{}", String::from_utf8_lossy(&output.stderr));
+    let events = events(&output.stdout);
+
+    let kinds: Vec<&str> = events.iter().map(|event| event["event"].as_str().expect("event")).collect();
+    assert_eq!(kinds.first(), Some(&"run_started"));
+    assert_eq!(kinds.last(), Some(&"run_finished"));
+    assert_eq!(kinds.iter().filter(|kind| **kind == "block_started").count(), 3);
+
+    let finished: Vec<&Value> = events.iter().filter(|event| event["event"] == "block_finished").collect();
+    let outcomes: Vec<(&str, Option<&str>)> =
+        finished.iter().map(|event| (event["status"].as_str().unwrap(), event["error_kind"].as_str())).collect();
+    assert_eq!(
+        outcomes,
+        [("passed", None), ("failed", Some("non_zero_exit")), ("failed", Some("compile_error"))]
+    );
+    assert_eq!(finished[1]["exit_code"], 3);
+    assert!(finished[2]["error"].as_str().unwrap().contains("mismatched types"));
+
+    let summary = &events.last().unwrap()["summary"];
+    assert_eq!((summary["total"].as_u64(), summary["passed"].as_u64()), (Some(3), Some(1)));
+    assert_eq!((summary["failed"].as_u64(), summary["compile_errors"].as_u64()), (Some(2), Some(1)));
+}
+
+#[test]
+fn chunks_decode_to_the_output() {
+    if !have_python() {
+        return;
+    }
+    let dir = Dir::new();
+    dir.write("doc.hacker", FILE.split("|> translator:rust").next().unwrap());
+    let output = dir.run(&["doc.hacker", "--format", "ndjson"]);
+    let mut stdout = Vec::new();
+    for event in events(&output.stdout) {
+        if event["event"] != "block_output_chunk" || event["index"] != 2 || event["stream"] != "stdout" {
+            continue;
+        }
+        let data = event["data"].as_str().unwrap();
+        match event["encoding"].as_str() {
+            Some("base64") => stdout.extend(base64::engine::general_purpose::STANDARD.decode(data).unwrap()),
+            Some("utf-8") => stdout.extend(data.as_bytes()),
+            other => panic!("unknown encoding {:?}", other),
+        }
+    }
+    assert_eq!(stdout, b"\xff\n");
+}
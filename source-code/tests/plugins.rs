@@ -0,0 +1,175 @@
+//! The plugin protocol: what a `translator-exec-<lang>` plugin is given,
+//! and how what it gives back becomes the block's result.
+#![cfg(unix)]
+
+mod common;
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+use common::{blocks, Dir};
+
+/// Installs `script` as the plugin for `lang` in `dir/bin`.
+fn plugin(dir: &Dir, lang: &str, script: &str) {
+    let bin = dir.path().join("bin");
+    fs::create_dir_all(&bin).unwrap();
+    let path = bin.join(format!("translator-exec-{}", lang));
+    fs::write(&path, script).unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+}
+
+/// Runs `file` with the plugins in `dir/bin` first on `PATH`.
+fn run(dir: &Dir, file: &Path) -> (serde_json::Value, i32) {
+    let path = format!("{}:{}", dir.path().join("bin").display(), std::env::var("PATH").unwrap_or_default());
+    let output = dir
+        .translator()
+        .env("PATH", path)
+        .arg(file)
+        .args(["--format", "json"])
+        .output()
+        .unwrap();
+    let document = serde_json::from_slice(&output.stdout)
+        .unwrap_or_else(|e| panic!("{}: {}", e, String::from_utf8_lossy(&output.stderr)));
+    (document, output.status.code().unwrap_or(-1))
+}
+
+#[test]
+fn round_trip() {
+    let dir = Dir::new();
+    plugin(
+        &dir,
+        "echo",
+        r#"#!/bin/sh
+code=$(cat)
+printf '%s|%s|%s\n' "$TRANSLATOR_LANG" "$TRANSLATOR_PARAM_GREETING" "$code"
+printf '{"warnings": ["not %s"]}' "$TRANSLATOR_PARAM_SIDE_NOTE" > "$TRANSLATOR_RESULT_FILE"
+"#,
+    );
+    let file = dir.write(
+        "doc.hacker",
+        "|> translator:echo(greeting=hello, side-note=serious) (\nsome code\n)\n",
+    );
+    let (document, code) = run(&dir, &file);
+    let block = &blocks(&document)[0];
+    assert_eq!(code, 0, "{}", document);
+    assert_eq!(block["status"], "passed");
+    assert_eq!(block["stdout"], "echo|hello|some code\n");
+    assert_eq!(block["warnings"], serde_json::json!(["not serious"]));
+}
+
+#[test]
+fn result_file_decides_the_outcome() {
+    let dir = Dir::new();
+    plugin(
+        &dir,
+        "verdict",
+        r#"#!/bin/sh
+cat > /dev/null
+echo '{"status": "failed", "error": "2 of 5 checks failed"}' > "$TRANSLATOR_RESULT_FILE"
+"#,
+    );
+    let file = dir.write("doc.hacker", "|> translator:verdict (\nx\n)\n");
+    let (document, code) = run(&dir, &file);
+    let block = &blocks(&document)[0];
+    assert_eq!(code, 1);
+    assert_eq!(block["status"], "failed");
+    assert_eq!(block["error_kind"], "non_zero_exit");
+    assert_eq!(block["error"], "2 of 5 checks failed");
+}
+
+#[test]
+fn unknown_field_is_an_error() {
+    let dir = Dir::new();
+    plugin(
+        &dir,
+        "typo",
+        r#"#!/bin/sh
+cat > /dev/null
+echo '{"stauts": "passed"}' > "$TRANSLATOR_RESULT_FILE"
+"#,
+    );
+    let file = dir.write("doc.hacker", "|> translator:typo (\nx\n)\n");
+    let (document, _) = run(&dir, &file);
+    let block = &blocks(&document)[0];
+    assert_eq!(block["status"], "failed");
+    assert_eq!(block["error_kind"], "plugin_protocol");
+    let error = block["error"].as_str().unwrap();
+    assert!(error.contains("unknown field `stauts`"), "{}", error);
+}
+
+#[test]
+fn plugin_that_exits_early() {
+    let dir = Dir::new();
+    // It never reads the code, which is more than a pipe holds.
+    plugin(&dir, "quitter", "#!/bin/sh\necho giving up >&2\nexit 7\n");
+    let code = "x = 1\n".repeat(100_000);
+    let file = dir.write("doc.hacker", format!("|> translator:quitter (\n{})\n", code));
+    let (document, _) = run(&dir, &file);
+    let block = &blocks(&document)[0];
+    assert_eq!(block["status"], "failed");
+    assert_eq!(block["exit_code"], 7);
+    assert_eq!(block["error"].as_str().unwrap().trim(), "giving up");
+}
+
+/// A javascript plugin that prints where its packages are, next to an npm
+/// and a node that only pretend.
+fn fake_node(dir: &Dir) {
+    plugin(
+        dir,
+        "javascript",
+        "#!/bin/sh\ncat > /dev/null\necho \"$NODE_PATH\"\ncat \"$TRANSLATOR_NODE_MODULES/left-pad/index.js\"\n",
+    );
+    let bin = dir.path().join("bin");
+    for (name, script) in [
+        ("node", "#!/bin/sh\necho v20.0.0\n"),
+        (
+            "fake-npm",
+            "#!/bin/sh\necho \"$@\" >> \"$NPM_LOG\"\nmkdir -p node_modules/left-pad\necho installed > node_modules/left-pad/index.js\n",
+        ),
+    ] {
+        fs::write(bin.join(name), script).unwrap();
+        fs::set_permissions(bin.join(name), fs::Permissions::from_mode(0o755)).unwrap();
+    }
+}
+
+#[test]
+fn npm_deps_are_installed_once() {
+    let dir = Dir::new();
+    fake_node(&dir);
+    let log = dir.path().join("npm.log");
+    let file = dir.write("doc.hacker", "|> translator:javascript(deps=\"left-pad@1, left-pad@1\") (\nx\n)\n");
+    let path = format!("{}:{}", dir.path().join("bin").display(), std::env::var("PATH").unwrap_or_default());
+    for _ in 0..2 {
+        let output = dir
+            .translator()
+            .env("PATH", &path)
+            .env("TRANSLATOR_NPM", dir.path().join("bin/fake-npm"))
+            .env("NPM_LOG", &log)
+            .env_remove("NODE_PATH")
+            .args(["--no-dedup", "--format", "json"])
+            .arg(&file)
+            .output()
+            .unwrap();
+        let document: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        let block = &blocks(&document)[0];
+        assert_eq!(block["status"], "passed", "{}", document);
+        let stdout = block["stdout"].as_str().unwrap();
+        let (node_path, installed) = stdout.split_once('\n').unwrap();
+        assert!(node_path.starts_with(dir.path().join(".cache/npm").to_str().unwrap()), "{}", node_path);
+        assert!(node_path.ends_with("/node_modules"), "{}", node_path);
+        assert_eq!(installed, "installed\n");
+    }
+    assert_eq!(fs::read_to_string(&log).unwrap(), "install --silent --no-audit --no-fund left-pad@1\n");
+}
+
+#[test]
+fn javascript_needs_a_plugin() {
+    let dir = Dir::new();
+    let file = dir.write("doc.hacker", "|> translator:javascript (\nconsole.log(1)\n)\n");
+    let (document, _) = run(&dir, &file);
+    let block = &blocks(&document)[0];
+    assert_eq!(block["status"], "skipped");
+    assert_eq!(block["error_kind"], "unsupported");
+    assert!(block["error"].as_str().unwrap().contains("translator-exec-javascript"), "{}", block["error"]);
+}
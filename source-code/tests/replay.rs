@@ -0,0 +1,69 @@
+//! `--record` and `--replay`: which results are kept, and what replaying
+//! them gives back.
+
+mod common;
+
+use std::fs;
+
+use serde_json::Value;
+
+use common::{blocks, have, Dir};
+
+/// Two java blocks, which are compiled together in one JVM, the first of
+/// them with a compile error.
+const JAVA: &str = r#"|> translator:java (
+public class Broken { public static void main(String[] args) { int x = "s"; } }
+)
+|> translator:java (
+public class Hello { public static void main(String[] args) { System.out.println("hello"); } }
+)
+"#;
+
+/// The status and error kind of each recorded result, in order.
+fn recorded(dir: &Dir) -> Vec<(String, Value)> {
+    let text = fs::read_to_string(dir.path().join("doc.hacker.translator-cache")).expect("recording");
+    let recording: Value = serde_json::from_str(&text).unwrap();
+    let entries = recording["blocks"].as_array().unwrap();
+    entries.iter().map(|entry| (entry["status"].as_str().unwrap().to_string(), entry["error_kind"].clone())).collect()
+}
+
+#[test]
+fn compile_errors_are_recorded() {
+    if !have("javac") || !have("java") {
+        return;
+    }
+    let dir = Dir::new();
+    dir.write("doc.hacker", JAVA);
+    let (document, code) = dir.json(&["doc.hacker", "--record"]);
+    assert_eq!(code, 2, "{}", document);
+    assert_eq!(blocks(&document)[0]["error_kind"], "compile_error");
+    assert_eq!(
+        recorded(&dir),
+        [("failed".to_string(), Value::from("compile_error")), ("passed".to_string(), Value::Null)]
+    );
+
+    let (document, code) = dir.json(&["doc.hacker", "--replay=strict"]);
+    assert_eq!(code, 2, "{}", document);
+    let results = blocks(&document);
+    assert_eq!((&results[0]["status"], &results[0]["error_kind"]), (&Value::from("failed"), &Value::from("compile_error")));
+    assert!(results[0]["error"].as_str().unwrap().contains("incompatible types"), "{}", results[0]);
+    assert_eq!((&results[1]["status"], &results[1]["stdout"]), (&Value::from("passed"), &Value::from("hello\n")));
+    assert!(results.iter().all(|result| result["replayed"] == true));
+}
+
+#[test]
+fn missing_toolchain_isnt_recorded() {
+    let dir = Dir::new();
+    fs::create_dir(dir.path().join("empty")).unwrap();
+    dir.write("doc.hacker", "|> translator:rust (\nfn main() {}\n)\n");
+    let output = dir
+        .translator()
+        .args(["doc.hacker", "--record", "--format", "json"])
+        .env("PATH", dir.path().join("empty"))
+        .env_remove("JAVA_HOME")
+        .output()
+        .unwrap();
+    let document: Value = serde_json::from_slice(&output.stdout).expect("JSON");
+    assert_eq!(blocks(&document)[0]["error_kind"], "toolchain_missing", "{}", document);
+    assert_eq!(recorded(&dir), []);
+}
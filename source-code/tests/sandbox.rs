@@ -0,0 +1,236 @@
+//! Keeping blocks in line: network isolation, the filesystem sandbox,
+//! running as another user and process limits. These need privileges or kernel features CI machines
+//! don't all have, so they are `#[ignore]`d and check for them first.
+#![cfg(unix)]
+
+mod common;
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use common::{blocks, have_python, Dir};
+
+/// Whether this machine lets an unprivileged process have a network
+/// namespace of its own, which `--no-network` needs.
+#[cfg(target_os = "linux")]
+fn can_unshare() -> bool {
+    let status = Command::new("unshare").args(["--user", "--map-root-user", "--net", "true"]).stderr(Stdio::null()).status();
+    let available = status.is_ok_and(|status| status.success());
+    if !available {
+        eprintln!("skipping: no network namespaces here");
+    }
+    available
+}
+
+/// A server on the loopback interface that answers every request with 200,
+/// which a block in a network namespace of its own can't reach.
+#[cfg(target_os = "linux")]
+fn http_server() -> String {
+    use std::io::{Read, Write};
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            let _ = stream.read(&mut [0; 4096]);
+            let _ = stream.write_all(b"HTTP/1.0 200 OK\r\nContent-Length: 0\r\n\r\n");
+        }
+    });
+    url
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+#[ignore]
+fn http_request_fails_without_network() {
+    if !have_python() || !can_unshare() {
+        return;
+    }
+    let dir = Dir::new();
+    dir.write(
+        "doc.hacker",
+        format!(
+            "|> translator:python (\nimport urllib.request\nprint(urllib.request.urlopen('{}', timeout=10).status)\n)\n",
+            http_server()
+        ),
+    );
+    let (online, _) = dir.json(&["doc.hacker"]);
+    assert_eq!(blocks(&online)[0]["stdout"], "200\n", "{}", online);
+
+    let (offline, code) = dir.json(&["--no-network", "doc.hacker"]);
+    let block = &blocks(&offline)[0];
+    assert_eq!(code, 1);
+    assert_eq!(block["status"], "failed");
+    let error = block["error"].as_str().unwrap();
+    assert!(error.contains("urllib.error.URLError"), "{}", error);
+}
+
+/// Whether the kernel has Landlock, which `--sandbox fs` needs.
+#[cfg(target_os = "linux")]
+fn has_landlock() -> bool {
+    let abi = unsafe { libc::syscall(libc::SYS_landlock_create_ruleset, std::ptr::null::<u8>(), 0usize, 1u32) };
+    if abi <= 0 {
+        eprintln!("skipping: no Landlock here");
+    }
+    abi > 0
+}
+
+/// The caches compile steps write, cargo's with its credentials among them,
+/// are out of the program's reach. Its own build, from the cache the
+/// second time, isn't.
+#[cfg(target_os = "linux")]
+#[test]
+#[ignore]
+fn program_cant_read_the_caches() {
+    if !has_landlock() || !common::have("rustc") {
+        return;
+    }
+    let dir = Dir::new();
+    for cache in ["cargo-home", "xdg-cache"] {
+        fs::create_dir(dir.path().join(cache)).unwrap();
+    }
+    dir.write("cargo-home/credentials.toml", "[registry]\ntoken = \"secret\"\n");
+    dir.write(
+        "doc.hacker",
+        r#"|> translator:rust (
+fn main() {
+    for var in ["CARGO_HOME", "XDG_CACHE_HOME"] {
+        let dir = std::env::var(var).unwrap();
+        println!("{} {}", var, if std::fs::read_dir(dir).is_ok() { "readable" } else { "denied" });
+    }
+}
+)
+"#,
+    );
+    for run in ["built", "cached"] {
+        let output = dir
+            .translator()
+            .args(["--sandbox", "fs", "--format", "json", "doc.hacker"])
+            .env("CARGO_HOME", dir.path().join("cargo-home"))
+            .env("XDG_CACHE_HOME", dir.path().join("xdg-cache"))
+            .output()
+            .unwrap();
+        let document: serde_json::Value = serde_json::from_slice(&output.stdout).expect("JSON");
+        let block = &blocks(&document)[0];
+        assert_eq!(block["status"], "passed", "{}: {}", run, block);
+        assert_eq!(block["stdout"], "CARGO_HOME denied\nXDG_CACHE_HOME denied\n", "{}", run);
+    }
+}
+
+fn is_root() -> bool {
+    let root = unsafe { libc::geteuid() } == 0;
+    if !root {
+        eprintln!("skipping: only root can switch users");
+    }
+    root
+}
+
+/// A test directory with a `tmp` in it for the run directory, both of
+/// which another user can get through.
+fn shared_dir() -> (Dir, PathBuf) {
+    let dir = Dir::new();
+    let tmp = dir.path().join("tmp");
+    fs::create_dir(&tmp).unwrap();
+    for path in [dir.path(), tmp.as_path()] {
+        fs::set_permissions(path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+    (dir, tmp)
+}
+
+/// A rust block, so that the program is in the block's directory rather
+/// than an interpreter somewhere `nobody` may not reach.
+#[test]
+#[ignore]
+fn run_as_drops_the_uid_and_cleans_up() {
+    if !is_root() || !common::have("rustc") {
+        return;
+    }
+    let (dir, tmp) = shared_dir();
+    dir.write(
+        "doc.hacker",
+        r#"|> translator:rust (
+fn main() {
+    let id = std::process::Command::new("id").arg("-u").output().unwrap();
+    print!("{}", String::from_utf8_lossy(&id.stdout));
+    println!("{}", std::env::current_exe().unwrap().display());
+}
+)
+"#,
+    );
+    let nobody = Command::new("id").args(["-u", "nobody"]).output().unwrap();
+    let nobody = String::from_utf8(nobody.stdout).unwrap();
+    let output = dir.translator().env("TMPDIR", &tmp).args(["--run-as", "nobody", "--format", "json", "doc.hacker"]).output().unwrap();
+    let document: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let block = &blocks(&document)[0];
+    assert_eq!(block["status"], "passed", "{}", document);
+    let stdout = block["stdout"].as_str().unwrap();
+    let (uid, program) = stdout.split_once('\n').unwrap();
+    assert_eq!(uid, nobody.trim());
+    assert!(program.starts_with(tmp.to_str().unwrap()), "{}", program);
+    let left: Vec<_> = fs::read_dir(&tmp).unwrap().flatten().map(|entry| entry.path()).collect();
+    assert!(left.is_empty(), "left behind: {:?}", left);
+}
+
+#[test]
+fn run_as_root_needs_allow_root() {
+    let dir = Dir::new();
+    dir.write("doc.hacker", "|> translator:python (\nprint(1)\n)\n");
+    let output = dir.run(&["--run-as", "root", "doc.hacker"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("--allow-root"), "{}", stderr);
+}
+
+/// Starts up to 200 `sleep`s, which a shared machine can take, and says
+/// how far it got.
+const FORKS: &str = r#"|> translator:rust (
+fn main() {
+    let mut children = Vec::new();
+    while children.len() < 200 {
+        match std::process::Command::new("sleep").arg("60").spawn() {
+            Ok(child) => children.push(child),
+            Err(e) => return println!("stopped after {}: {:?}", children.len(), e.kind()),
+        }
+    }
+    println!("started {}", children.len());
+}
+)
+"#;
+
+/// The cap without `--max-procs`.
+const DEFAULT_PROCS: u64 = 64;
+
+/// Root isn't held to the limit, so as root the block runs as `nobody`.
+#[cfg(target_os = "linux")]
+#[test]
+#[ignore]
+fn fork_bomb_stops_at_max_procs() {
+    if !common::have("rustc") {
+        return;
+    }
+    let (dir, tmp) = shared_dir();
+    dir.write("doc.hacker", FORKS);
+    let run = |extra: &[&str]| {
+        let mut command = dir.translator();
+        command.env("TMPDIR", &tmp).args(["--format", "json", "doc.hacker"]).args(extra);
+        if unsafe { libc::geteuid() } == 0 {
+            command.args(["--run-as", "nobody"]);
+        }
+        let output = command.output().unwrap();
+        let document: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        blocks(&document)[0]["stdout"].as_str().unwrap_or_default().to_string()
+    };
+    let stopped_at = |stopped: String| {
+        assert!(stopped.ends_with(": WouldBlock\n"), "{}", stopped);
+        let forks: u64 = stopped
+            .strip_prefix("stopped after ")
+            .and_then(|rest| rest.split(':').next()?.parse().ok())
+            .unwrap_or_else(|| panic!("{}", stopped));
+        forks
+    };
+    let forks = stopped_at(run(&[]));
+    assert!(forks <= DEFAULT_PROCS, "stopped after {} by default", forks);
+    assert!(stopped_at(run(&["--max-procs", "20"])) <= 20);
+    assert_eq!(run(&["--max-procs", "0"]), "started 200\n", "0 lifts the limit");
+}
@@ -0,0 +1,90 @@
+//! Blocks get a closed stdin unless `--inherit-stdin` is given, so one
+//! that reads from it fails right away instead of waiting on the terminal.
+
+mod common;
+
+use std::io::Write;
+use std::process::{Output, Stdio};
+use std::time::{Duration, Instant};
+
+use common::{blocks, have, have_python, Dir};
+
+/// Runs `file` with the JSON report, failing the test if it takes long
+/// enough to suggest a block is waiting for input.
+fn run_quickly(dir: &Dir, args: &[&str]) -> (serde_json::Value, Duration) {
+    let start = Instant::now();
+    let (document, _) = dir.json(args);
+    (document, start.elapsed())
+}
+
+fn assert_fails_on_closed_stdin(lang: &str, code: &str) {
+    let dir = Dir::new();
+    dir.write("doc.hacker", format!("|> translator:{} (\n{})\n", lang, code));
+    let (document, elapsed) = run_quickly(&dir, &["doc.hacker"]);
+    assert!(elapsed < Duration::from_secs(60), "took {:?}", elapsed);
+    let block = &blocks(&document)[0];
+    assert_eq!(block["status"], "failed", "{}", document);
+    let error = block["error"].as_str().unwrap();
+    assert!(error.contains("stdin is closed; --inherit-stdin"), "{}", error);
+}
+
+#[test]
+fn python_input() {
+    if have_python() {
+        assert_fails_on_closed_stdin("python", "name = input()\nprint(name)\n");
+    }
+}
+
+#[test]
+fn java_scanner() {
+    if have("javac") {
+        assert_fails_on_closed_stdin(
+            "java",
+            "import java.util.Scanner;\npublic class Main {\n    public static void main(String[] args) {\n        System.out.println(new Scanner(System.in).nextLine());\n    }\n}\n",
+        );
+    }
+}
+
+#[test]
+fn rust_read_exact() {
+    if have("rustc") {
+        assert_fails_on_closed_stdin(
+            "rust",
+            "use std::io::Read;\nfn main() {\n    let mut byte = [0];\n    std::io::stdin().read_exact(&mut byte).unwrap();\n}\n",
+        );
+    }
+}
+
+#[test]
+fn other_failures_get_no_note() {
+    if !have_python() {
+        return;
+    }
+    let dir = Dir::new();
+    dir.write("doc.hacker", "|> translator:python (\nraise ValueError('unexpected EOF in the data')\n)\n");
+    let (document, _) = run_quickly(&dir, &["doc.hacker"]);
+    let error = blocks(&document)[0]["error"].as_str().unwrap().to_string();
+    assert!(!error.contains("--inherit-stdin"), "{}", error);
+}
+
+#[test]
+fn inherited_stdin_is_read() {
+    if !have_python() {
+        return;
+    }
+    let dir = Dir::new();
+    dir.write("doc.hacker", "|> translator:python (\nprint(input().upper())\n)\n");
+    let mut child = dir
+        .translator()
+        .args(["--inherit-stdin", "--format", "json", "doc.hacker"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(b"hello\n").unwrap();
+    let Output { stdout, .. } = child.wait_with_output().unwrap();
+    let document: serde_json::Value = serde_json::from_slice(&stdout).unwrap();
+    let block = &blocks(&document)[0];
+    assert_eq!(block["status"], "passed", "{}", document);
+    assert_eq!(block["stdout"], "HELLO\n");
+}
@@ -0,0 +1,142 @@
+//! Many translator runs at once, sharing a cache, with some of them
+//! interrupted the way a CI timeout or Ctrl-C would: no program a block
+//! started may outlive its run, and finished runs leave no temp dir behind.
+#![cfg(unix)]
+
+mod common;
+
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use std::process::{Child, ExitStatus, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use common::{have, have_python, Dir};
+
+const RUNS: usize = 24;
+
+/// A block that leaves a program running in the background, and writes its
+/// pid down.
+const BACKGROUND: &str = r#"|> translator:python (
+import os, subprocess
+child = subprocess.Popen(["sleep", "300"])
+with open(os.environ["PIDS"], "a") as pids:
+    pids.write(f"{child.pid}\n")
+print("started")
+)
+"#;
+
+/// Runs long enough to be interrupted.
+const STUCK: &str = r#"|> translator:python (
+import time
+print("waiting", flush=True)
+time.sleep(300)
+)
+"#;
+
+/// The same in every run, so they build into and read from the cache at
+/// the same time.
+const COMPILED: &str = "|> translator:rust (\nfn main() { println!(\"{}\", 6 * 7); }\n)\n";
+
+fn alive(pid: i32) -> bool {
+    // A zombie nobody reaps is as dead as it gets.
+    let zombie = fs::read_to_string(format!("/proc/{}/stat", pid))
+        .is_ok_and(|stat| stat.rsplit(')').next().is_some_and(|rest| rest.trim_start().starts_with('Z')));
+    !zombie && unsafe { libc::kill(pid, 0) == 0 }
+}
+
+fn pids(path: &Path) -> Vec<i32> {
+    let pids = fs::read_to_string(path).unwrap_or_default();
+    pids.lines().map(|pid| pid.parse().expect("pid")).collect()
+}
+
+/// Waits up to `timeout` for `child` to exit.
+fn wait_for(child: &mut Child, timeout: Duration) -> Option<ExitStatus> {
+    let deadline = Instant::now() + timeout;
+    while Instant::now() < deadline {
+        if let Some(status) = child.try_wait().unwrap() {
+            return Some(status);
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    None
+}
+
+/// Waits for `child` and reads what it printed. A run whose blocks left a
+/// program holding on to their output never finishes, and fails the test.
+fn finish(child: &mut Child) -> (Option<i32>, String) {
+    let Some(status) = wait_for(child, Duration::from_secs(60)) else {
+        let _ = child.kill();
+        panic!("a run didn't finish: something its blocks started is still running");
+    };
+    let mut stdout = String::new();
+    if let Some(mut pipe) = child.stdout.take() {
+        pipe.read_to_string(&mut stdout).unwrap();
+    }
+    (status.code(), stdout)
+}
+
+#[test]
+#[ignore]
+fn concurrent_runs_leave_nothing_behind() {
+    if !have_python() || !have("rustc") {
+        return;
+    }
+    let dir = Dir::new();
+    let pid_file = dir.path().join("pids");
+    let mut runs = Vec::new();
+    for run in 0..RUNS {
+        let interrupted = run % 3 == 0;
+        let tmp = dir.path().join(format!("tmp-{}", run));
+        fs::create_dir(&tmp).unwrap();
+        let mut contents = format!("{}{}", COMPILED, BACKGROUND);
+        if interrupted {
+            contents.push_str(STUCK);
+        }
+        let file = dir.write(&format!("run-{}.hacker", run), contents);
+        let child = dir
+            .translator()
+            .env("PIDS", &pid_file)
+            .env("TMPDIR", &tmp)
+            .arg(&file)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        runs.push((child, interrupted, tmp));
+    }
+
+    // Every run has started its background program before the
+    // interrupted ones are interrupted.
+    let deadline = Instant::now() + Duration::from_secs(120);
+    while pids(&pid_file).len() < RUNS {
+        assert!(Instant::now() < deadline, "only {} of {} blocks started", pids(&pid_file).len(), RUNS);
+        thread::sleep(Duration::from_millis(50));
+    }
+    for (child, interrupted, tmp) in &mut runs {
+        if *interrupted {
+            assert_eq!(wait_for(child, Duration::from_secs(1)), None, "an interrupted run finished on its own");
+            unsafe { libc::kill(child.id() as i32, libc::SIGINT) };
+            let status = wait_for(child, Duration::from_secs(10)).expect("interrupted run exits");
+            assert!(status.code().is_none(), "interrupted run exited with {}", status);
+        } else {
+            let (code, stdout) = finish(child);
+            assert_eq!(code, Some(0), "{}", stdout);
+            assert!(stdout.contains("42") && stdout.contains("started"), "{}", stdout);
+            let left: Vec<_> = fs::read_dir(&*tmp).unwrap().flatten().map(|entry| entry.path()).collect();
+            assert!(left.is_empty(), "left behind: {:?}", left);
+        }
+    }
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let mut leaked: Vec<i32> = pids(&pid_file);
+    while !leaked.is_empty() && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(50));
+        leaked.retain(|pid| alive(*pid));
+    }
+    for pid in &leaked {
+        unsafe { libc::kill(*pid, libc::SIGKILL) };
+    }
+    assert!(leaked.is_empty(), "still running: {:?}", leaked);
+}
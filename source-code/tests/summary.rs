@@ -0,0 +1,21 @@
+//! The summary line at the end of the console output.
+
+mod common;
+
+use common::Dir;
+
+/// The summary line for a file with `blocks` blocks, all of them skipped.
+fn summary(blocks: usize) -> String {
+    let dir = Dir::new();
+    dir.write("doc.hacker", "|> translator:rust(if-os=plan9) (\nfn main() {}\n)\n".repeat(blocks));
+    let output = dir.run(&["doc.hacker"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let line = stdout.lines().find(|line| line.starts_with("Summary: ")).unwrap_or_else(|| panic!("{}", stdout));
+    line.split(" in ").next().unwrap().to_string()
+}
+
+#[test]
+fn blocks_are_counted_in_words() {
+    assert_eq!(summary(1), "Summary: 1 block, 0 passed, 0 failed, 1 skipped");
+    assert_eq!(summary(2), "Summary: 2 blocks, 0 passed, 0 failed, 2 skipped");
+}